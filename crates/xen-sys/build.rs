@@ -1,11 +1,18 @@
 use std::{env, fs, path::PathBuf};
 
+/// Xen release the bundled bindings were generated for.
+const BINDINGS_VERSION: &str = "4.21";
+
 fn main() {
     let out_path =
         PathBuf::from(env::var("OUT_DIR").expect("Unable to get OUT_DIR environment variable"));
 
     let mut args = Vec::new();
-    let config = pkg_config::Config::new();
+    let mut config = pkg_config::Config::new();
+
+    // With `dlopen`, the libraries are loaded at runtime and only their
+    // headers are needed.
+    config.cargo_metadata(!cfg!(feature = "dlopen"));
 
     if env::var("DOCS_RS").is_ok() || env::var("XEN_SYS_USE_BINDINGS").is_ok()
     // || cfg!(feature = "bindings-4_21")
@@ -13,17 +20,28 @@ fn main() {
         let src = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
         let dst = PathBuf::from(env::var("OUT_DIR").unwrap());
 
-        fs::copy(src.join("bindings/xen-4.21.rs"), dst.join("bindings.rs"))
-            .expect("Failed to copy bindings.rs");
+        fs::copy(
+            src.join(format!("bindings/xen-{BINDINGS_VERSION}.rs")),
+            dst.join("bindings.rs"),
+        )
+        .expect("Failed to copy bindings.rs");
 
+        println!("cargo:rustc-env=XEN_SYS_XENCTRL_VERSION={BINDINGS_VERSION}");
         return;
     }
 
     if cfg!(feature = "xencontrol") {
-        config
+        let library = config
             .probe("xencontrol")
             .expect("Failed to locate xencontrol library");
         args.extend(["-D", "BINDGEN_XENCONTROL"]);
+
+        // libxenctrl has no stable ABI, its soname is `libxenctrl.so.X.Y`.
+        let version = library.version.split('.').take(2).collect::<Vec<_>>();
+        println!(
+            "cargo:rustc-env=XEN_SYS_XENCTRL_VERSION={}",
+            version.join(".")
+        );
     }
 
     if cfg!(feature = "xendevicemodel") {
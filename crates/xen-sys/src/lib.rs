@@ -6,3 +6,10 @@
 )]
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+/// Soname of the `libxenctrl` the bindings were generated for.
+///
+/// `libxenctrl` has no stable ABI, its soname changes with every Xen
+/// release and the bindings only match the release they were generated for.
+#[cfg(feature = "xencontrol")]
+pub const XENCTRL_SONAME: &str = concat!("libxenctrl.so.", env!("XEN_SYS_XENCTRL_VERSION"));
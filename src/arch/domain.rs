@@ -1,131 +1,184 @@
 use xen_sys::{
     __HVM_SAVE_TYPE_CPU, __HVM_SAVE_TYPE_LAPIC, __HVM_SAVE_TYPE_LAPIC_REGS, hvm_hw_cpu,
-    hvm_hw_lapic, hvm_hw_lapic_regs, hvm_save_descriptor, xc_domain_hvm_getcontext,
-    xc_domain_hvm_getcontext_partial, xc_domain_hvm_setcontext,
+    hvm_hw_lapic, hvm_hw_lapic_regs,
 };
 
-use super::x86::{Amd64, LocalApic, LocalApicRegisters, Registers};
-use crate::{VcpuId, XenDomain, XenError, xc_check_error};
+use super::x86::{
+    Amd64, ExtendedState, HvmContext, LocalApic, LocalApicRegisters, PageTableWalker,
+    RegisterFields, Registers,
+};
+use crate::{VcpuId, XenDomain, XenError, ctrl::XenPhysInfo, macros::as_bytes_mut};
 
 impl XenDomain<Amd64> {
     pub fn get_context_cpu(&self, vcpu: VcpuId) -> Result<Registers, XenError> {
-        let result = unsafe { std::mem::zeroed::<hvm_hw_cpu>() };
-
-        let rc = unsafe {
-            xc_domain_hvm_getcontext_partial(
-                self.interface.handle.0,
-                self.domain_id.0,
-                size_of_val(&__HVM_SAVE_TYPE_CPU::default().c) as u16,
-                vcpu.0,
-                &result as *const _ as *mut _,
-                size_of_val(&result) as u32,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        let mut result = unsafe { std::mem::zeroed::<hvm_hw_cpu>() };
+
+        self.interface.backend.domain_hvm_getcontext_partial(
+            self.domain_id,
+            size_of_val(&__HVM_SAVE_TYPE_CPU::default().c) as u16,
+            vcpu.0,
+            as_bytes_mut(&mut result),
+        )?;
         Ok(result.into())
     }
 
     pub fn get_context_lapic(&self, vcpu: VcpuId) -> Result<LocalApic, XenError> {
-        let result = unsafe { std::mem::zeroed::<hvm_hw_lapic>() };
-
-        let rc = unsafe {
-            xc_domain_hvm_getcontext_partial(
-                self.interface.handle.0,
-                self.domain_id.0,
-                size_of_val(&__HVM_SAVE_TYPE_LAPIC::default().c) as u16,
-                vcpu.0,
-                &result as *const _ as *mut _,
-                size_of_val(&result) as u32,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        let mut result = unsafe { std::mem::zeroed::<hvm_hw_lapic>() };
+
+        self.interface.backend.domain_hvm_getcontext_partial(
+            self.domain_id,
+            size_of_val(&__HVM_SAVE_TYPE_LAPIC::default().c) as u16,
+            vcpu.0,
+            as_bytes_mut(&mut result),
+        )?;
         Ok(result.into())
     }
 
     pub fn get_context_lapic_regs(&self, vcpu: VcpuId) -> Result<LocalApicRegisters, XenError> {
-        let result = unsafe { std::mem::zeroed::<hvm_hw_lapic_regs>() };
-
-        let rc = unsafe {
-            xc_domain_hvm_getcontext_partial(
-                self.interface.handle.0,
-                self.domain_id.0,
-                size_of_val(&__HVM_SAVE_TYPE_LAPIC_REGS::default().c) as u16,
-                vcpu.0,
-                &result as *const _ as *mut _,
-                size_of_val(&result) as u32,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        let mut result = unsafe { std::mem::zeroed::<hvm_hw_lapic_regs>() };
+
+        self.interface.backend.domain_hvm_getcontext_partial(
+            self.domain_id,
+            size_of_val(&__HVM_SAVE_TYPE_LAPIC_REGS::default().c) as u16,
+            vcpu.0,
+            as_bytes_mut(&mut result),
+        )?;
         Ok(result.into())
     }
 
-    pub fn set_context_cpu(&self, vcpu: VcpuId, registers: Registers) -> Result<(), XenError> {
-        self.pause()?;
-
-        // Get the context size.
-        let size = unsafe {
-            xc_domain_hvm_getcontext(
-                self.interface.handle.0,
-                self.domain_id.0,
-                std::ptr::null_mut(),
-                0,
-            )
-        };
-
-        if size <= 0 {
-            self.unpause()?;
+    /// Returns the full HVM context of the domain.
+    pub fn get_context(&self) -> Result<HvmContext, XenError> {
+        let backend = &self.interface.backend;
+
+        let size = backend.domain_hvm_getcontext(self.domain_id, None)?;
+        if size == 0 {
             return Err(XenError::Other("Failed to get context size"));
         }
 
-        // Allocate a buffer to hold the context.
-        let mut buffer = vec![0u8; size as usize];
-
-        // Get the context.
-        let rc = unsafe {
-            xc_domain_hvm_getcontext(
-                self.interface.handle.0,
-                self.domain_id.0,
-                buffer.as_mut_ptr(),
-                size as u32,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-
-        // Locate the CPU context.
-        let mut offset: u32 = 0;
-        let size = size as u32;
-        let hvm_save_code_cpu = size_of_val(&__HVM_SAVE_TYPE_CPU::default().c) as u16;
-
-        unsafe {
-            while offset < size {
-                let descriptor =
-                    buffer.as_ptr().offset(offset as isize) as *const hvm_save_descriptor;
-
-                offset += size_of::<hvm_save_descriptor>() as u32;
-
-                if (*descriptor).typecode == hvm_save_code_cpu && (*descriptor).instance == vcpu.0 {
-                    let vcpu_context = buffer.as_ptr().offset(offset as isize) as *mut hvm_hw_cpu;
-                    registers.copy_into(&mut *vcpu_context);
-                    break;
-                }
-
-                offset += (*descriptor).length;
-            }
+        let mut buffer = vec![0u8; size];
+        let size = backend.domain_hvm_getcontext(self.domain_id, Some(&mut buffer))?;
+        buffer.truncate(size);
+
+        HvmContext::parse(&buffer)
+    }
+
+    /// Replaces the full HVM context of the domain.
+    ///
+    /// The domain should be paused.
+    pub fn set_context(&self, context: &HvmContext) -> Result<(), XenError> {
+        self.interface
+            .backend
+            .domain_hvm_setcontext(self.domain_id, &context.to_bytes())
+    }
+
+    /// Writes all registers of a vCPU.
+    ///
+    /// See [`set_context_cpu_fields`](Self::set_context_cpu_fields).
+    pub fn set_context_cpu(&self, vcpu: VcpuId, registers: Registers) -> Result<(), XenError> {
+        self.set_context_cpu_fields(vcpu, &registers, RegisterFields::all())
+    }
+
+    /// Writes the selected registers of a vCPU, the others keep their
+    /// current value.
+    ///
+    /// The checks of [`Registers::validate_fields`] affected by `fields`
+    /// are performed on the resulting state before anything is written, as
+    /// an invalid state would crash the domain.
+    pub fn set_context_cpu_fields(
+        &self,
+        vcpu: VcpuId,
+        registers: &Registers,
+        fields: RegisterFields,
+    ) -> Result<(), XenError> {
+        let vmx = XenPhysInfo::from(self.interface.backend.physinfo()?).vmx();
+
+        self.update_context(|context| {
+            let cpu = context
+                .cpu_mut(vcpu)
+                .ok_or(XenError::Other("CPU record not found"))?;
+
+            let mut result = *cpu;
+            registers.copy_fields_into(&mut result, fields);
+            Registers::from(result).validate_fields(fields, vmx)?;
+
+            *cpu = result;
+            Ok(())
+        })
+    }
+
+    /// Returns the extended state of a vCPU from its `CPU_XSAVE` record.
+    pub fn get_context_xsave(&self, vcpu: VcpuId) -> Result<ExtendedState, XenError> {
+        self.get_context()?
+            .xsave(vcpu)
+            .ok_or(XenError::Other("CPU_XSAVE record not found"))?
+            .state()
+    }
+
+    /// Replaces the extended state of a vCPU in its `CPU_XSAVE` record.
+    ///
+    /// The domain is paused while its context is updated.
+    pub fn set_context_xsave(&self, vcpu: VcpuId, state: &ExtendedState) -> Result<(), XenError> {
+        self.update_context(|context| {
+            context
+                .xsave_mut(vcpu)
+                .ok_or(XenError::Other("CPU_XSAVE record not found"))?
+                .set_state(state)
+        })
+    }
+
+    /// Returns the extended state of a vCPU (`XEN_DOMCTL_getvcpuextstate`).
+    ///
+    /// Unlike [`get_context_xsave`](Self::get_context_xsave), only the state
+    /// of the single vCPU is transferred. The vCPU should be paused.
+    pub fn get_extended_state(&self, vcpu: VcpuId) -> Result<ExtendedState, XenError> {
+        let backend = &self.interface.backend;
+
+        let (xfeature_mask, size) = backend.vcpu_get_extstate(self.domain_id, vcpu, 0, None)?;
+        if size <= XSTATE_HEADER {
+            return Err(XenError::Other("vCPU has no extended state"));
         }
 
-        // Set the context.
-        let rc = unsafe {
-            xc_domain_hvm_setcontext(
-                self.interface.handle.0,
-                self.domain_id.0,
-                buffer.as_mut_ptr(),
-                size,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        let mut buffer = vec![0u8; size];
+        backend.vcpu_get_extstate(self.domain_id, vcpu, xfeature_mask, Some(&mut buffer))?;
 
-        self.unpause()?;
+        ExtendedState::from_xsave(
+            u64::from_le_bytes(buffer[0..8].try_into().unwrap()),
+            &buffer[XSTATE_HEADER..],
+        )
+    }
 
-        Ok(())
+    /// Returns a page table walker for the current paging state of a vCPU.
+    pub fn page_table_walker(&self, vcpu: VcpuId) -> Result<PageTableWalker, XenError> {
+        Ok(PageTableWalker::from(&self.get_context_cpu(vcpu)?))
+    }
+
+    /// Translates a linear address of a vCPU to a guest frame number, with
+    /// the page table walk done by libxenctrl.
+    ///
+    /// Unlike [`PageTableWalker::translate`], only the present bits are
+    /// checked and a failure does not report the fault.
+    pub fn translate_address(&self, vcpu: VcpuId, address: u64) -> Result<u64, XenError> {
+        self.interface
+            .backend
+            .translate_foreign_address(self.domain_id, vcpu, address)
+    }
+
+    /// Pauses the domain, applies `f` to its HVM context and writes the
+    /// context back.
+    fn update_context(
+        &self,
+        f: impl FnOnce(&mut HvmContext) -> Result<(), XenError>,
+    ) -> Result<(), XenError> {
+        let guard = self.paused()?;
+
+        let mut context = self.get_context()?;
+        f(&mut context)?;
+        self.set_context(&context)?;
+
+        guard.unpause()
     }
 }
+
+/// Size of the `xcr0` and `xcr0_accum` fields preceding the XSAVE area in
+/// the buffer of `XEN_DOMCTL_getvcpuextstate`.
+const XSTATE_HEADER: usize = 16;
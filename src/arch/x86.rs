@@ -1,5 +1,19 @@
+mod context;
+mod paging;
+mod validate;
+mod xsave;
+
 use xen_sys::{hvm_hw_cpu, hvm_hw_lapic, hvm_hw_lapic_regs};
 
+pub use self::{
+    context::{HvmContext, HvmContextRecord, HvmMsr, HvmRecord, HvmXsave},
+    paging::{
+        PageFault, PageFaultReason, PageSize, PageTableWalker, PagingMode, PhysicalMemory,
+        Translation, TranslationAccess,
+    },
+    xsave::{ExtendedState, FpuRegisters, X87Register, XsaveComponents},
+};
+
 pub struct Amd64;
 
 impl super::Architecture for Amd64 {
@@ -99,112 +113,239 @@ pub struct Registers {
     pub msr_tsc_aux: u64,
 }
 
+bitflags::bitflags! {
+    /// Fields of [`Registers`] to be written by
+    /// [`XenDomain::set_context_cpu_fields`](crate::XenDomain::set_context_cpu_fields).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct RegisterFields: u128 {
+        const RAX = 1 << 0;
+        const RBX = 1 << 1;
+        const RCX = 1 << 2;
+        const RDX = 1 << 3;
+        const RBP = 1 << 4;
+        const RSI = 1 << 5;
+        const RDI = 1 << 6;
+        const RSP = 1 << 7;
+        const R8 = 1 << 8;
+        const R9 = 1 << 9;
+        const R10 = 1 << 10;
+        const R11 = 1 << 11;
+        const R12 = 1 << 12;
+        const R13 = 1 << 13;
+        const R14 = 1 << 14;
+        const R15 = 1 << 15;
+        const RIP = 1 << 16;
+        const RFLAGS = 1 << 17;
+        const CR0 = 1 << 18;
+        const CR2 = 1 << 19;
+        const CR3 = 1 << 20;
+        const CR4 = 1 << 21;
+        const DR0 = 1 << 22;
+        const DR1 = 1 << 23;
+        const DR2 = 1 << 24;
+        const DR3 = 1 << 25;
+        const DR6 = 1 << 26;
+        const DR7 = 1 << 27;
+        const CS_BASE = 1 << 28;
+        const CS_LIMIT = 1 << 29;
+        const CS_SEL = 1 << 30;
+        const CS_ARBYTES = 1 << 31;
+        const DS_BASE = 1 << 32;
+        const DS_LIMIT = 1 << 33;
+        const DS_SEL = 1 << 34;
+        const DS_ARBYTES = 1 << 35;
+        const ES_BASE = 1 << 36;
+        const ES_LIMIT = 1 << 37;
+        const ES_SEL = 1 << 38;
+        const ES_ARBYTES = 1 << 39;
+        const FS_BASE = 1 << 40;
+        const FS_LIMIT = 1 << 41;
+        const FS_SEL = 1 << 42;
+        const FS_ARBYTES = 1 << 43;
+        const GS_BASE = 1 << 44;
+        const GS_LIMIT = 1 << 45;
+        const GS_SEL = 1 << 46;
+        const GS_ARBYTES = 1 << 47;
+        const SS_BASE = 1 << 48;
+        const SS_LIMIT = 1 << 49;
+        const SS_SEL = 1 << 50;
+        const SS_ARBYTES = 1 << 51;
+        const TR_BASE = 1 << 52;
+        const TR_LIMIT = 1 << 53;
+        const TR_SEL = 1 << 54;
+        const TR_ARBYTES = 1 << 55;
+        const LDTR_BASE = 1 << 56;
+        const LDTR_LIMIT = 1 << 57;
+        const LDTR_SEL = 1 << 58;
+        const LDTR_ARBYTES = 1 << 59;
+        const IDTR_BASE = 1 << 60;
+        const IDTR_LIMIT = 1 << 61;
+        const GDTR_BASE = 1 << 62;
+        const GDTR_LIMIT = 1 << 63;
+        const SYSENTER_CS = 1 << 64;
+        const SYSENTER_ESP = 1 << 65;
+        const SYSENTER_EIP = 1 << 66;
+        const SHADOW_GS = 1 << 67;
+        const MSR_FLAGS = 1 << 68;
+        const MSR_LSTAR = 1 << 69;
+        const MSR_STAR = 1 << 70;
+        const MSR_CSTAR = 1 << 71;
+        const MSR_SYSCALL_MASK = 1 << 72;
+        const MSR_EFER = 1 << 73;
+        const MSR_TSC_AUX = 1 << 74;
+
+        /// General purpose registers, without `RIP` and `RFLAGS`.
+        const GPRS = Self::RAX.bits() | Self::RBX.bits() | Self::RCX.bits() | Self::RDX.bits()
+            | Self::RBP.bits() | Self::RSI.bits() | Self::RDI.bits() | Self::RSP.bits()
+            | Self::R8.bits() | Self::R9.bits() | Self::R10.bits() | Self::R11.bits()
+            | Self::R12.bits() | Self::R13.bits() | Self::R14.bits() | Self::R15.bits();
+
+        /// Control registers.
+        const CONTROL = Self::CR0.bits() | Self::CR2.bits() | Self::CR3.bits() | Self::CR4.bits();
+
+        /// Debug registers.
+        const DEBUG = Self::DR0.bits() | Self::DR1.bits() | Self::DR2.bits() | Self::DR3.bits()
+            | Self::DR6.bits() | Self::DR7.bits();
+
+        /// Base, limit, selector and attributes of a segment register.
+        const CS = Self::CS_BASE.bits() | Self::CS_LIMIT.bits() | Self::CS_SEL.bits()
+            | Self::CS_ARBYTES.bits();
+        const DS = Self::DS_BASE.bits() | Self::DS_LIMIT.bits() | Self::DS_SEL.bits()
+            | Self::DS_ARBYTES.bits();
+        const ES = Self::ES_BASE.bits() | Self::ES_LIMIT.bits() | Self::ES_SEL.bits()
+            | Self::ES_ARBYTES.bits();
+        const FS = Self::FS_BASE.bits() | Self::FS_LIMIT.bits() | Self::FS_SEL.bits()
+            | Self::FS_ARBYTES.bits();
+        const GS = Self::GS_BASE.bits() | Self::GS_LIMIT.bits() | Self::GS_SEL.bits()
+            | Self::GS_ARBYTES.bits();
+        const SS = Self::SS_BASE.bits() | Self::SS_LIMIT.bits() | Self::SS_SEL.bits()
+            | Self::SS_ARBYTES.bits();
+        const TR = Self::TR_BASE.bits() | Self::TR_LIMIT.bits() | Self::TR_SEL.bits()
+            | Self::TR_ARBYTES.bits();
+        const LDTR = Self::LDTR_BASE.bits() | Self::LDTR_LIMIT.bits() | Self::LDTR_SEL.bits()
+            | Self::LDTR_ARBYTES.bits();
+
+        /// All segment registers, including `TR` and `LDTR`.
+        const SEGMENTS = Self::CS.bits() | Self::DS.bits() | Self::ES.bits() | Self::FS.bits()
+            | Self::GS.bits() | Self::SS.bits() | Self::TR.bits() | Self::LDTR.bits();
+
+        /// `IDTR` and `GDTR`.
+        const DESCRIPTOR_TABLES = Self::IDTR_BASE.bits() | Self::IDTR_LIMIT.bits()
+            | Self::GDTR_BASE.bits() | Self::GDTR_LIMIT.bits();
+
+        const SYSENTER = Self::SYSENTER_CS.bits() | Self::SYSENTER_ESP.bits()
+            | Self::SYSENTER_EIP.bits();
+
+        /// MSRs, including `SYSENTER` and the shadow `GS` base.
+        const MSRS = Self::SYSENTER.bits() | Self::SHADOW_GS.bits() | Self::MSR_FLAGS.bits()
+            | Self::MSR_LSTAR.bits() | Self::MSR_STAR.bits() | Self::MSR_CSTAR.bits()
+            | Self::MSR_SYSCALL_MASK.bits() | Self::MSR_EFER.bits() | Self::MSR_TSC_AUX.bits();
+
+        /// The fields written by `libvmi`, and by this crate before the
+        /// field mask was introduced.
+        const LIBVMI = Self::GPRS.bits() | Self::RIP.bits() | Self::RFLAGS.bits()
+            | Self::CONTROL.bits() | Self::FS_BASE.bits() | Self::GS_BASE.bits()
+            | Self::CS_ARBYTES.bits() | Self::SYSENTER.bits() | Self::MSR_LSTAR.bits()
+            | Self::MSR_EFER.bits() | Self::MSR_STAR.bits();
+    }
+}
+
 impl Registers {
+    /// Copies all fields into a CPU save record.
     pub fn copy_into(&self, value: &mut hvm_hw_cpu) {
-        value.rax = self.rax;
-        value.rbx = self.rbx;
-        value.rcx = self.rcx;
-        value.rdx = self.rdx;
-        value.rbp = self.rbp;
-        value.rsi = self.rsi;
-        value.rdi = self.rdi;
-        value.rsp = self.rsp;
-        value.r8 = self.r8;
-        value.r9 = self.r9;
-        value.r10 = self.r10;
-        value.r11 = self.r11;
-        value.r12 = self.r12;
-        value.r13 = self.r13;
-        value.r14 = self.r14;
-        value.r15 = self.r15;
-        value.rip = self.rip;
-        value.rflags = self.rflags;
-
-        value.cr0 = self.cr0;
-        value.cr2 = self.cr2;
-        value.cr3 = self.cr3;
-        value.cr4 = self.cr4;
-
-        // Copy just the fields as libvmi does.
-        value.fs_base = self.fs_base;
-        value.gs_base = self.gs_base;
-        value.cs_arbytes = self.cs_arbytes;
-        value.sysenter_cs = self.sysenter_cs;
-        value.sysenter_esp = self.sysenter_esp;
-        value.sysenter_eip = self.sysenter_eip;
-        value.msr_lstar = self.msr_lstar;
-        value.msr_efer = self.msr_efer;
-        value.msr_star = self.msr_star;
-
-        /////////////////////////////////////
-
-        /*
-        value.dr0 = self.dr0;
-        value.dr1 = self.dr1;
-        value.dr2 = self.dr2;
-        value.dr3 = self.dr3;
-        value.dr6 = self.dr6;
-        value.dr7 = self.dr7;
-
-        value.cs_base = self.cs_base;
-        value.cs_limit = self.cs_limit;
-        value.cs_sel = self.cs_sel;
-        value.cs_arbytes = self.cs_arbytes;
-
-        value.ds_base = self.ds_base;
-        value.ds_limit = self.ds_limit;
-        value.ds_sel = self.ds_sel;
-        value.ds_arbytes = self.ds_arbytes;
-
-        value.es_base = self.es_base;
-        value.es_limit = self.es_limit;
-        value.es_sel = self.es_sel;
-        value.es_arbytes = self.es_arbytes;
-
-        value.fs_base = self.fs_base;
-        value.fs_limit = self.fs_limit;
-        value.fs_sel = self.fs_sel;
-        value.fs_arbytes = self.fs_arbytes;
-
-        value.gs_base = self.gs_base;
-        value.gs_limit = self.gs_limit;
-        value.gs_sel = self.gs_sel;
-        value.gs_arbytes = self.gs_arbytes;
-
-        value.ss_base = self.ss_base;
-        value.ss_limit = self.ss_limit;
-        value.ss_sel = self.ss_sel;
-        value.ss_arbytes = self.ss_arbytes;
-
-        value.tr_base = self.tr_base;
-        value.tr_limit = self.tr_limit;
-        value.tr_sel = self.tr_sel;
-        value.tr_arbytes = self.tr_arbytes;
-
-        value.ldtr_base = self.ldtr_base;
-        value.ldtr_limit = self.ldtr_limit;
-        value.ldtr_sel = self.ldtr_sel;
-        value.ldtr_arbytes = self.ldtr_arbytes;
-
-        value.idtr_base = self.idtr_base;
-        value.idtr_limit = self.idtr_limit;
-
-        value.gdtr_base = self.gdtr_base;
-        value.gdtr_limit = self.gdtr_limit;
-
-        value.sysenter_cs = self.sysenter_cs;
-        value.sysenter_esp = self.sysenter_esp;
-        value.sysenter_eip = self.sysenter_eip;
-        value.shadow_gs = self.shadow_gs;
-
-        value.msr_flags = self.msr_flags;
-        value.msr_lstar = self.msr_lstar;
-        value.msr_star = self.msr_star;
-        value.msr_cstar = self.msr_cstar;
-        value.msr_syscall_mask = self.msr_syscall_mask;
-        value.msr_efer = self.msr_efer;
-        value.msr_tsc_aux = self.msr_tsc_aux;
-        */
+        self.copy_fields_into(value, RegisterFields::all());
+    }
+
+    /// Copies the selected fields into a CPU save record, leaving the other
+    /// fields of the record untouched.
+    pub fn copy_fields_into(&self, value: &mut hvm_hw_cpu, fields: RegisterFields) {
+        macro_rules! copy {
+            ($($field:ident => $flag:ident),* $(,)?) => {
+                $(
+                    if fields.contains(RegisterFields::$flag) {
+                        value.$field = self.$field;
+                    }
+                )*
+            };
+        }
+
+        copy! {
+            rax => RAX,
+            rbx => RBX,
+            rcx => RCX,
+            rdx => RDX,
+            rbp => RBP,
+            rsi => RSI,
+            rdi => RDI,
+            rsp => RSP,
+            r8 => R8,
+            r9 => R9,
+            r10 => R10,
+            r11 => R11,
+            r12 => R12,
+            r13 => R13,
+            r14 => R14,
+            r15 => R15,
+            rip => RIP,
+            rflags => RFLAGS,
+            cr0 => CR0,
+            cr2 => CR2,
+            cr3 => CR3,
+            cr4 => CR4,
+            dr0 => DR0,
+            dr1 => DR1,
+            dr2 => DR2,
+            dr3 => DR3,
+            dr6 => DR6,
+            dr7 => DR7,
+            cs_base => CS_BASE,
+            cs_limit => CS_LIMIT,
+            cs_sel => CS_SEL,
+            cs_arbytes => CS_ARBYTES,
+            ds_base => DS_BASE,
+            ds_limit => DS_LIMIT,
+            ds_sel => DS_SEL,
+            ds_arbytes => DS_ARBYTES,
+            es_base => ES_BASE,
+            es_limit => ES_LIMIT,
+            es_sel => ES_SEL,
+            es_arbytes => ES_ARBYTES,
+            fs_base => FS_BASE,
+            fs_limit => FS_LIMIT,
+            fs_sel => FS_SEL,
+            fs_arbytes => FS_ARBYTES,
+            gs_base => GS_BASE,
+            gs_limit => GS_LIMIT,
+            gs_sel => GS_SEL,
+            gs_arbytes => GS_ARBYTES,
+            ss_base => SS_BASE,
+            ss_limit => SS_LIMIT,
+            ss_sel => SS_SEL,
+            ss_arbytes => SS_ARBYTES,
+            tr_base => TR_BASE,
+            tr_limit => TR_LIMIT,
+            tr_sel => TR_SEL,
+            tr_arbytes => TR_ARBYTES,
+            ldtr_base => LDTR_BASE,
+            ldtr_limit => LDTR_LIMIT,
+            ldtr_sel => LDTR_SEL,
+            ldtr_arbytes => LDTR_ARBYTES,
+            idtr_base => IDTR_BASE,
+            idtr_limit => IDTR_LIMIT,
+            gdtr_base => GDTR_BASE,
+            gdtr_limit => GDTR_LIMIT,
+            sysenter_cs => SYSENTER_CS,
+            sysenter_esp => SYSENTER_ESP,
+            sysenter_eip => SYSENTER_EIP,
+            shadow_gs => SHADOW_GS,
+            msr_flags => MSR_FLAGS,
+            msr_lstar => MSR_LSTAR,
+            msr_star => MSR_STAR,
+            msr_cstar => MSR_CSTAR,
+            msr_syscall_mask => MSR_SYSCALL_MASK,
+            msr_efer => MSR_EFER,
+            msr_tsc_aux => MSR_TSC_AUX,
+        }
     }
 }
 
@@ -0,0 +1,593 @@
+use xen_sys::{
+    __HVM_SAVE_TYPE_CPU, __HVM_SAVE_TYPE_HEADER, __HVM_SAVE_TYPE_HPET, __HVM_SAVE_TYPE_IOAPIC,
+    __HVM_SAVE_TYPE_ISA_IRQ, __HVM_SAVE_TYPE_LAPIC, __HVM_SAVE_TYPE_LAPIC_REGS,
+    __HVM_SAVE_TYPE_MTRR, __HVM_SAVE_TYPE_PCI_IRQ, __HVM_SAVE_TYPE_PCI_LINK, __HVM_SAVE_TYPE_PIC,
+    __HVM_SAVE_TYPE_PIT, __HVM_SAVE_TYPE_PMTIMER, __HVM_SAVE_TYPE_RTC, __HVM_SAVE_TYPE_TSC_ADJUST,
+    __HVM_SAVE_TYPE_VIRIDIAN_DOMAIN, __HVM_SAVE_TYPE_VIRIDIAN_VCPU, __HVM_SAVE_TYPE_VMCE_VCPU,
+    CPU_MSR_CODE, CPU_XSAVE_CODE, hvm_hw_cpu, hvm_hw_hpet, hvm_hw_isa_irqs, hvm_hw_lapic,
+    hvm_hw_lapic_regs, hvm_hw_mtrr, hvm_hw_pci_irqs, hvm_hw_pci_link, hvm_hw_pit, hvm_hw_pmtimer,
+    hvm_hw_rtc, hvm_hw_vioapic, hvm_hw_vpic, hvm_save_descriptor, hvm_save_header, hvm_tsc_adjust,
+    hvm_viridian_domain_context, hvm_viridian_vcpu_context, hvm_vmce_vcpu,
+};
+
+use super::ExtendedState;
+use crate::{
+    VcpuId, XenError,
+    macros::{as_bytes, from_bytes},
+};
+
+/// Typecode of the record terminating an HVM context.
+const HVM_SAVE_CODE_END: u16 = 0;
+
+/// Size of the `xfeature_mask`, `xcr0` and `xcr0_accum` fields preceding the
+/// XSAVE area in the `CPU_XSAVE` record.
+const XSAVE_HEADER_SIZE: usize = 24;
+
+/// Size of the `count` field, padded to the alignment of the entries, in
+/// the `CPU_MSR` record.
+const MSR_HEADER_SIZE: usize = 8;
+
+/// Size of an entry of the `CPU_MSR` record.
+const MSR_ENTRY_SIZE: usize = 16;
+
+/// Returns the typecode of an HVM save type.
+macro_rules! hvm_save_code {
+    ($save_type:ident) => {
+        size_of_val(&$save_type::default().c) as u16
+    };
+}
+
+/// Defines [`HvmRecord`] with a variant for each fixed size record, in
+/// addition to the variable size ones.
+macro_rules! hvm_records {
+    ($($(#[$meta:meta])* $variant:ident($ty:ty) = $save_type:ident,)*) => {
+        /// A record of an HVM context.
+        ///
+        /// Fixed size records hold the structure saved by Xen.
+        #[derive(Clone)]
+        pub enum HvmRecord {
+            $($(#[$meta])* $variant($ty),)*
+
+            /// Extended processor state of a vCPU (`CPU_XSAVE`).
+            Xsave(HvmXsave),
+
+            /// MSRs of a vCPU (`CPU_MSR`).
+            Msr(Vec<HvmMsr>),
+
+            /// Terminates the context.
+            End,
+
+            /// A record unknown to this crate, or one whose layout differs
+            /// from the one known to this crate.
+            Unknown {
+                typecode: u16,
+                data: Vec<u8>,
+            },
+        }
+
+        // Not all of the structures saved by Xen implement `Debug`.
+        impl std::fmt::Debug for HvmRecord {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match self {
+                    $(Self::$variant(_) => f.write_str(concat!(stringify!($variant), "(..)")),)*
+                    Self::Xsave(xsave) => f.debug_tuple("Xsave").field(xsave).finish(),
+                    Self::Msr(msrs) => f.debug_tuple("Msr").field(msrs).finish(),
+                    Self::End => f.write_str("End"),
+                    Self::Unknown { typecode, data } => f
+                        .debug_struct("Unknown")
+                        .field("typecode", typecode)
+                        .field("length", &data.len())
+                        .finish(),
+                }
+            }
+        }
+
+        impl HvmRecord {
+            /// Returns the typecode of the record.
+            pub fn typecode(&self) -> u16 {
+                match self {
+                    $(Self::$variant(_) => hvm_save_code!($save_type),)*
+                    Self::Xsave(_) => CPU_XSAVE_CODE as u16,
+                    Self::Msr(_) => CPU_MSR_CODE as u16,
+                    Self::End => HVM_SAVE_CODE_END,
+                    Self::Unknown { typecode, .. } => *typecode,
+                }
+            }
+
+            /// Serializes the record, without its descriptor.
+            pub fn to_bytes(&self) -> Vec<u8> {
+                match self {
+                    $(Self::$variant(value) => as_bytes(value).to_vec(),)*
+                    Self::Xsave(xsave) => xsave.to_bytes(),
+                    Self::Msr(msrs) => {
+                        let mut result = vec![0; MSR_HEADER_SIZE];
+                        result[0..4].copy_from_slice(&(msrs.len() as u32).to_le_bytes());
+
+                        for msr in msrs {
+                            result.extend_from_slice(&msr.index.to_le_bytes());
+                            result.extend_from_slice(&[0; 4]);
+                            result.extend_from_slice(&msr.value.to_le_bytes());
+                        }
+
+                        result
+                    }
+                    Self::End => Vec::new(),
+                    Self::Unknown { data, .. } => data.clone(),
+                }
+            }
+
+            /// Parses the data of a record with the given typecode.
+            ///
+            /// Falls back to [`HvmRecord::Unknown`] unless the typed record
+            /// serializes back to exactly the same bytes.
+            fn parse(typecode: u16, data: &[u8]) -> Self {
+                let record = match typecode {
+                    $(
+                        typecode if typecode == hvm_save_code!($save_type) => {
+                            (data.len() == size_of::<$ty>())
+                                .then(|| Self::$variant(from_bytes(data)))
+                        }
+                    )*
+                    typecode if typecode == CPU_XSAVE_CODE as u16 => {
+                        HvmXsave::parse(data).map(Self::Xsave)
+                    }
+                    typecode if typecode == CPU_MSR_CODE as u16 => parse_msrs(data).map(Self::Msr),
+                    HVM_SAVE_CODE_END => data.is_empty().then_some(Self::End),
+                    _ => None,
+                };
+
+                match record {
+                    Some(record) if record.to_bytes() == data => record,
+                    _ => Self::Unknown {
+                        typecode,
+                        data: data.to_vec(),
+                    },
+                }
+            }
+        }
+    };
+}
+
+hvm_records! {
+    /// Describes the saved context (`HEADER`).
+    Header(hvm_save_header) = __HVM_SAVE_TYPE_HEADER,
+
+    /// Registers of a vCPU (`CPU`).
+    Cpu(hvm_hw_cpu) = __HVM_SAVE_TYPE_CPU,
+
+    /// One of the two 8259 interrupt controllers (`PIC`).
+    Pic(hvm_hw_vpic) = __HVM_SAVE_TYPE_PIC,
+
+    Ioapic(hvm_hw_vioapic) = __HVM_SAVE_TYPE_IOAPIC,
+
+    /// Local APIC of a vCPU (`LAPIC`).
+    Lapic(hvm_hw_lapic) = __HVM_SAVE_TYPE_LAPIC,
+
+    /// Register page of the local APIC of a vCPU (`LAPIC_REGS`).
+    LapicRegs(hvm_hw_lapic_regs) = __HVM_SAVE_TYPE_LAPIC_REGS,
+
+    PciIrq(hvm_hw_pci_irqs) = __HVM_SAVE_TYPE_PCI_IRQ,
+    IsaIrq(hvm_hw_isa_irqs) = __HVM_SAVE_TYPE_ISA_IRQ,
+    PciLink(hvm_hw_pci_link) = __HVM_SAVE_TYPE_PCI_LINK,
+    Pit(hvm_hw_pit) = __HVM_SAVE_TYPE_PIT,
+    Rtc(hvm_hw_rtc) = __HVM_SAVE_TYPE_RTC,
+    Hpet(hvm_hw_hpet) = __HVM_SAVE_TYPE_HPET,
+    PmTimer(hvm_hw_pmtimer) = __HVM_SAVE_TYPE_PMTIMER,
+
+    /// Memory type range registers of a vCPU (`MTRR`).
+    Mtrr(hvm_hw_mtrr) = __HVM_SAVE_TYPE_MTRR,
+
+    ViridianDomain(hvm_viridian_domain_context) = __HVM_SAVE_TYPE_VIRIDIAN_DOMAIN,
+    ViridianVcpu(hvm_viridian_vcpu_context) = __HVM_SAVE_TYPE_VIRIDIAN_VCPU,
+
+    /// `TSC_ADJUST` MSR of a vCPU.
+    TscAdjust(hvm_tsc_adjust) = __HVM_SAVE_TYPE_TSC_ADJUST,
+
+    /// Machine check state of a vCPU (`VMCE_VCPU`).
+    VmceVcpu(hvm_vmce_vcpu) = __HVM_SAVE_TYPE_VMCE_VCPU,
+}
+
+/// The `CPU_XSAVE` record of a vCPU.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HvmXsave {
+    /// Components supported by the host.
+    pub xfeature_mask: u64,
+
+    /// `XCR0` of the vCPU.
+    pub xcr0: u64,
+
+    /// All components the vCPU has ever enabled.
+    pub xcr0_accum: u64,
+
+    /// Standard format XSAVE area.
+    pub area: Vec<u8>,
+}
+
+impl std::fmt::Debug for HvmXsave {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HvmXsave")
+            .field("xfeature_mask", &self.xfeature_mask)
+            .field("xcr0", &self.xcr0)
+            .field("xcr0_accum", &self.xcr0_accum)
+            .field("area_size", &self.area.len())
+            .finish()
+    }
+}
+
+impl HvmXsave {
+    /// Parses the XSAVE area into its typed representation.
+    pub fn state(&self) -> Result<ExtendedState, XenError> {
+        ExtendedState::from_xsave(self.xcr0, &self.area)
+    }
+
+    /// Replaces the XSAVE area with the serialized `state`.
+    ///
+    /// The size of the area is kept, as Xen rejects records of a different
+    /// size.
+    pub fn set_state(&mut self, state: &ExtendedState) -> Result<(), XenError> {
+        let area = state.to_xsave();
+        if area.len() != self.area.len() {
+            return Err(XenError::Other("XSAVE area size mismatch"));
+        }
+
+        self.area = area;
+        Ok(())
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < XSAVE_HEADER_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            xfeature_mask: from_bytes(&data[0..8]),
+            xcr0: from_bytes(&data[8..16]),
+            xcr0_accum: from_bytes(&data[16..24]),
+            area: data[XSAVE_HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(XSAVE_HEADER_SIZE + self.area.len());
+        result.extend_from_slice(&self.xfeature_mask.to_le_bytes());
+        result.extend_from_slice(&self.xcr0.to_le_bytes());
+        result.extend_from_slice(&self.xcr0_accum.to_le_bytes());
+        result.extend_from_slice(&self.area);
+        result
+    }
+}
+
+/// An MSR of the `CPU_MSR` record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HvmMsr {
+    pub index: u32,
+    pub value: u64,
+}
+
+fn parse_msrs(data: &[u8]) -> Option<Vec<HvmMsr>> {
+    let count = u32::from_le_bytes(data.get(0..4)?.try_into().unwrap()) as usize;
+    let entries = data.get(MSR_HEADER_SIZE..)?;
+
+    if entries.len() != count * MSR_ENTRY_SIZE {
+        return None;
+    }
+
+    let result = entries
+        .chunks_exact(MSR_ENTRY_SIZE)
+        .map(|entry| HvmMsr {
+            index: from_bytes(&entry[0..4]),
+            value: from_bytes(&entry[8..16]),
+        })
+        .collect();
+
+    Some(result)
+}
+
+/// A record of an HVM context together with the instance it belongs to.
+#[derive(Debug, Clone)]
+pub struct HvmContextRecord {
+    /// vCPU or device the record belongs to, `0` for domain-wide records.
+    pub instance: u16,
+
+    pub record: HvmRecord,
+}
+
+/// The HVM context of a domain, as returned by `xc_domain_hvm_getcontext`.
+///
+/// Records the crate does not know about are preserved, so that a parsed
+/// context serializes back to the same bytes.
+#[derive(Debug, Default, Clone)]
+pub struct HvmContext {
+    /// Records in the order they are saved, normally starting with
+    /// [`HvmRecord::Header`] and ending with [`HvmRecord::End`].
+    pub records: Vec<HvmContextRecord>,
+
+    /// Bytes following the `END` record.
+    trailing: Vec<u8>,
+}
+
+impl HvmContext {
+    /// Parses an HVM context.
+    pub fn parse(buffer: &[u8]) -> Result<Self, XenError> {
+        let mut records = Vec::new();
+        let mut offset = 0;
+
+        while offset < buffer.len() {
+            let descriptor = buffer
+                .get(offset..offset + size_of::<hvm_save_descriptor>())
+                .map(from_bytes::<hvm_save_descriptor>)
+                .ok_or(XenError::Other("truncated HVM context descriptor"))?;
+            offset += size_of::<hvm_save_descriptor>();
+
+            let data = buffer
+                .get(offset..offset + descriptor.length as usize)
+                .ok_or(XenError::Other("truncated HVM context record"))?;
+            offset += descriptor.length as usize;
+
+            let record = HvmRecord::parse(descriptor.typecode, data);
+            let end = matches!(record, HvmRecord::End);
+
+            records.push(HvmContextRecord {
+                instance: descriptor.instance,
+                record,
+            });
+
+            if end {
+                break;
+            }
+        }
+
+        Ok(Self {
+            records,
+            trailing: buffer[offset..].to_vec(),
+        })
+    }
+
+    /// Serializes the context for `xc_domain_hvm_setcontext`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        for entry in &self.records {
+            let data = entry.record.to_bytes();
+            let descriptor = hvm_save_descriptor {
+                typecode: entry.record.typecode(),
+                instance: entry.instance,
+                length: data.len() as u32,
+            };
+
+            result.extend_from_slice(as_bytes(&descriptor));
+            result.extend_from_slice(&data);
+        }
+
+        result.extend_from_slice(&self.trailing);
+        result
+    }
+
+    /// Returns the header of the context.
+    pub fn header(&self) -> Option<&hvm_save_header> {
+        self.records.iter().find_map(|entry| match &entry.record {
+            HvmRecord::Header(header) => Some(header),
+            _ => None,
+        })
+    }
+
+    /// Returns the record with the given typecode and instance.
+    pub fn find(&self, typecode: u16, instance: u16) -> Option<&HvmRecord> {
+        self.records
+            .iter()
+            .find(|entry| entry.record.typecode() == typecode && entry.instance == instance)
+            .map(|entry| &entry.record)
+    }
+
+    /// Returns the record with the given typecode and instance.
+    pub fn find_mut(&mut self, typecode: u16, instance: u16) -> Option<&mut HvmRecord> {
+        self.records
+            .iter_mut()
+            .find(|entry| entry.record.typecode() == typecode && entry.instance == instance)
+            .map(|entry| &mut entry.record)
+    }
+
+    /// Returns the `CPU` record of a vCPU.
+    pub fn cpu(&self, vcpu: VcpuId) -> Option<&hvm_hw_cpu> {
+        match self.find(hvm_save_code!(__HVM_SAVE_TYPE_CPU), vcpu.0)? {
+            HvmRecord::Cpu(cpu) => Some(cpu),
+            _ => None,
+        }
+    }
+
+    /// Returns the `CPU` record of a vCPU.
+    pub fn cpu_mut(&mut self, vcpu: VcpuId) -> Option<&mut hvm_hw_cpu> {
+        match self.find_mut(hvm_save_code!(__HVM_SAVE_TYPE_CPU), vcpu.0)? {
+            HvmRecord::Cpu(cpu) => Some(cpu),
+            _ => None,
+        }
+    }
+
+    /// Returns the `CPU_XSAVE` record of a vCPU.
+    pub fn xsave(&self, vcpu: VcpuId) -> Option<&HvmXsave> {
+        match self.find(CPU_XSAVE_CODE as u16, vcpu.0)? {
+            HvmRecord::Xsave(xsave) => Some(xsave),
+            _ => None,
+        }
+    }
+
+    /// Returns the `CPU_XSAVE` record of a vCPU.
+    pub fn xsave_mut(&mut self, vcpu: VcpuId) -> Option<&mut HvmXsave> {
+        match self.find_mut(CPU_XSAVE_CODE as u16, vcpu.0)? {
+            HvmRecord::Xsave(xsave) => Some(xsave),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNKNOWN_CODE: u16 = 0x7f;
+
+    fn push_record(buffer: &mut Vec<u8>, typecode: u16, instance: u16, data: &[u8]) {
+        let descriptor = hvm_save_descriptor {
+            typecode,
+            instance,
+            length: data.len() as u32,
+        };
+
+        buffer.extend_from_slice(as_bytes(&descriptor));
+        buffer.extend_from_slice(data);
+    }
+
+    fn cpu_record(rax: u64) -> hvm_hw_cpu {
+        hvm_hw_cpu {
+            rax,
+            rip: 0xffff_f800_1234_5678,
+            cr0: 0x8005_0033,
+            ..Default::default()
+        }
+    }
+
+    fn msr_record(msrs: &[(u32, u64)]) -> Vec<u8> {
+        let mut data = vec![0; MSR_HEADER_SIZE];
+        data[0..4].copy_from_slice(&(msrs.len() as u32).to_le_bytes());
+
+        for (index, value) in msrs {
+            data.extend_from_slice(&index.to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        data
+    }
+
+    fn xsave_record() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x7u64.to_le_bytes());
+        data.extend_from_slice(&0x3u64.to_le_bytes());
+        data.extend_from_slice(&0x7u64.to_le_bytes());
+        data.extend((0..576).map(|i| i as u8));
+        data
+    }
+
+    fn context_blob() -> Vec<u8> {
+        let header = hvm_save_header {
+            magic: 0x5446_4d48,
+            version: 1,
+            changeset: 0x1234,
+            cpuid: 0x806e9,
+            gtsc_khz: 2_400_000,
+        };
+
+        let mut buffer = Vec::new();
+        push_record(
+            &mut buffer,
+            hvm_save_code!(__HVM_SAVE_TYPE_HEADER),
+            0,
+            as_bytes(&header),
+        );
+        push_record(
+            &mut buffer,
+            hvm_save_code!(__HVM_SAVE_TYPE_CPU),
+            0,
+            as_bytes(&cpu_record(1)),
+        );
+        push_record(
+            &mut buffer,
+            hvm_save_code!(__HVM_SAVE_TYPE_CPU),
+            1,
+            as_bytes(&cpu_record(2)),
+        );
+        push_record(
+            &mut buffer,
+            CPU_MSR_CODE as u16,
+            0,
+            &msr_record(&[(0xc000_0080, 0xd01), (0x174, 0x10)]),
+        );
+        push_record(&mut buffer, CPU_XSAVE_CODE as u16, 0, &xsave_record());
+        push_record(&mut buffer, UNKNOWN_CODE, 3, &[1, 2, 3, 4, 5]);
+        push_record(&mut buffer, HVM_SAVE_CODE_END, 0, &[]);
+        buffer.extend_from_slice(&[0xaa; 12]);
+        buffer
+    }
+
+    #[test]
+    fn round_trip() {
+        let blob = context_blob();
+        let context = HvmContext::parse(&blob).unwrap();
+
+        assert_eq!(context.records.len(), 7);
+        assert!(matches!(context.records[0].record, HvmRecord::Header(_)));
+        assert!(matches!(context.records[1].record, HvmRecord::Cpu(_)));
+        assert!(matches!(context.records[3].record, HvmRecord::Msr(_)));
+        assert!(matches!(context.records[4].record, HvmRecord::Xsave(_)));
+        assert!(matches!(
+            context.records[5].record,
+            HvmRecord::Unknown {
+                typecode: UNKNOWN_CODE,
+                ..
+            }
+        ));
+        assert!(matches!(context.records[6].record, HvmRecord::End));
+
+        assert_eq!(context.header().unwrap().gtsc_khz, 2_400_000);
+        assert_eq!(context.cpu(VcpuId(1)).unwrap().rax, 2);
+        assert_eq!(context.xsave(VcpuId(0)).unwrap().xcr0, 0x3);
+        assert_eq!(context.to_bytes(), blob);
+    }
+
+    #[test]
+    fn modified_records() {
+        let mut context = HvmContext::parse(&context_blob()).unwrap();
+        context.cpu_mut(VcpuId(0)).unwrap().rax = 0xdead_beef;
+        context.xsave_mut(VcpuId(0)).unwrap().xcr0 = 0x7;
+
+        let blob = context.to_bytes();
+        assert_eq!(blob.len(), context_blob().len());
+
+        let context = HvmContext::parse(&blob).unwrap();
+        assert_eq!(context.cpu(VcpuId(0)).unwrap().rax, 0xdead_beef);
+        assert_eq!(context.cpu(VcpuId(1)).unwrap().rax, 2);
+        assert_eq!(context.xsave(VcpuId(0)).unwrap().xcr0, 0x7);
+    }
+
+    #[test]
+    fn malformed_records() {
+        let cpu = cpu_record(1);
+        let mut msrs = msr_record(&[(0x174, 0x10)]);
+        msrs[0] = 2;
+
+        let mut blob = Vec::new();
+        push_record(
+            &mut blob,
+            hvm_save_code!(__HVM_SAVE_TYPE_CPU),
+            0,
+            &as_bytes(&cpu)[..size_of::<hvm_hw_cpu>() - 8],
+        );
+        push_record(&mut blob, CPU_MSR_CODE as u16, 0, &msrs);
+        push_record(&mut blob, CPU_XSAVE_CODE as u16, 0, &[0; 8]);
+        push_record(&mut blob, HVM_SAVE_CODE_END, 0, &[0]);
+
+        let context = HvmContext::parse(&blob).unwrap();
+        for (entry, typecode) in context.records.iter().zip([
+            hvm_save_code!(__HVM_SAVE_TYPE_CPU),
+            CPU_MSR_CODE as u16,
+            CPU_XSAVE_CODE as u16,
+            HVM_SAVE_CODE_END,
+        ]) {
+            assert!(
+                matches!(entry.record, HvmRecord::Unknown { typecode: code, .. } if code == typecode)
+            );
+        }
+
+        assert!(context.cpu(VcpuId(0)).is_none());
+        assert_eq!(context.to_bytes(), blob);
+    }
+
+    #[test]
+    fn truncated_context() {
+        let blob = context_blob();
+        let end = blob.len() - 12 - size_of::<hvm_save_descriptor>();
+
+        assert!(HvmContext::parse(&blob[..end - 3]).is_err());
+        assert!(HvmContext::parse(&blob[..4]).is_err());
+    }
+}
@@ -0,0 +1,785 @@
+use super::Registers;
+use crate::{XenError, consts::PAGE_SHIFT, ctrl::VmEventRegsX86};
+
+const CR0_WP: u64 = 1 << 16;
+const CR0_PG: u64 = 1 << 31;
+
+const CR4_PSE: u64 = 1 << 4;
+const CR4_PAE: u64 = 1 << 5;
+const CR4_LA57: u64 = 1 << 12;
+
+const EFER_LMA: u64 = 1 << 10;
+const EFER_NXE: u64 = 1 << 11;
+
+const PTE_PRESENT: u64 = 1 << 0;
+const PTE_WRITABLE: u64 = 1 << 1;
+const PTE_USER: u64 = 1 << 2;
+const PTE_PAGE_SIZE: u64 = 1 << 7;
+const PTE_NX: u64 = 1 << 63;
+
+/// Address bits of a 64-bit entry, for the architectural maximum of 52
+/// physical address bits.
+const PTE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Address bits of a 32-bit entry.
+const PTE32_ADDRESS_MASK: u64 = 0xffff_f000;
+
+/// Bits 62:52 of a PAE entry, which are reserved unlike in 4-level paging.
+const PAE_RESERVED_HIGH: u64 = 0x7ff0_0000_0000_0000;
+
+/// Reserved bits of a PAE page directory pointer table entry.
+const PDPTE_RESERVED: u64 = 0x1e6 | PTE_NX | PAE_RESERVED_HIGH;
+
+/// Paging mode of a vCPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PagingMode {
+    /// Paging is disabled, linear addresses are physical addresses.
+    Disabled,
+
+    /// 32-bit paging with 4K and 4M pages.
+    Legacy,
+
+    /// PAE paging with 4K and 2M pages.
+    Pae,
+
+    /// 4-level paging with 4K, 2M and 1G pages.
+    Ia32e,
+
+    /// 5-level paging with 4K, 2M and 1G pages.
+    La57,
+}
+
+impl PagingMode {
+    /// Derives the paging mode from `CR0`, `CR4` and `EFER`.
+    pub fn new(cr0: u64, cr4: u64, efer: u64) -> Self {
+        if cr0 & CR0_PG == 0 {
+            Self::Disabled
+        }
+        else if efer & EFER_LMA != 0 {
+            if cr4 & CR4_LA57 != 0 {
+                Self::La57
+            }
+            else {
+                Self::Ia32e
+            }
+        }
+        else if cr4 & CR4_PAE != 0 {
+            Self::Pae
+        }
+        else {
+            Self::Legacy
+        }
+    }
+
+    /// Returns the number of page table levels.
+    pub fn levels(self) -> u8 {
+        match self {
+            Self::Disabled => 0,
+            Self::Legacy => 2,
+            Self::Pae => 3,
+            Self::Ia32e => 4,
+            Self::La57 => 5,
+        }
+    }
+
+    /// Returns the width of a linear address in bits.
+    pub fn address_bits(self) -> u32 {
+        match self {
+            Self::Disabled | Self::Legacy | Self::Pae => 32,
+            Self::Ia32e => 48,
+            Self::La57 => 57,
+        }
+    }
+}
+
+impl From<&Registers> for PagingMode {
+    fn from(value: &Registers) -> Self {
+        Self::new(value.cr0, value.cr4, value.msr_efer)
+    }
+}
+
+impl From<&VmEventRegsX86> for PagingMode {
+    fn from(value: &VmEventRegsX86) -> Self {
+        Self::new(value.cr0, value.cr4, value.msr_efer)
+    }
+}
+
+/// Size of a page mapped by a page table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size4M,
+    Size1G,
+}
+
+impl PageSize {
+    /// Returns the size in bytes.
+    pub fn bytes(self) -> u64 {
+        match self {
+            Self::Size4K => 1 << 12,
+            Self::Size2M => 1 << 21,
+            Self::Size4M => 1 << 22,
+            Self::Size1G => 1 << 30,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Access a linear address is translated for.
+    ///
+    /// Without any flags, the translation is checked as a supervisor read.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct TranslationAccess: u8 {
+        const WRITE = 1 << 0;
+        const USER = 1 << 1;
+        const EXECUTE = 1 << 2;
+    }
+}
+
+/// Why a translation failed.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageFaultReason {
+    #[error("page is not present")]
+    NotPresent,
+
+    #[error("reserved bit is set")]
+    ReservedBit,
+
+    #[error("user access to a supervisor page")]
+    SupervisorPage,
+
+    #[error("write to a read-only page")]
+    ReadOnly,
+
+    #[error("instruction fetch from a no-execute page")]
+    NoExecute,
+}
+
+/// A failed translation, as the guest would observe it.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[error("page fault at {address:#x}: {reason}")]
+pub struct PageFault {
+    /// The linear address being translated.
+    pub address: u64,
+
+    /// The access the address was translated for.
+    pub access: TranslationAccess,
+
+    pub reason: PageFaultReason,
+
+    /// Level of the faulting entry, from 1 for the page table up to 5 for
+    /// the PML5 table.
+    pub level: u8,
+
+    /// Physical address of the faulting entry.
+    pub entry_address: u64,
+
+    /// Value of the faulting entry.
+    pub entry: u64,
+}
+
+impl PageFault {
+    /// Returns the error code the processor would push for the fault.
+    pub fn error_code(&self) -> u32 {
+        let mut result = 0;
+
+        if self.reason != PageFaultReason::NotPresent {
+            result |= 1 << 0;
+        }
+        if self.access.contains(TranslationAccess::WRITE) {
+            result |= 1 << 1;
+        }
+        if self.access.contains(TranslationAccess::USER) {
+            result |= 1 << 2;
+        }
+        if self.reason == PageFaultReason::ReservedBit {
+            result |= 1 << 3;
+        }
+        if self.access.contains(TranslationAccess::EXECUTE) {
+            result |= 1 << 4;
+        }
+
+        result
+    }
+}
+
+/// A successful translation of a linear address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Translation {
+    /// The translated linear address.
+    pub address: u64,
+
+    /// The guest physical address the linear address maps to.
+    pub physical_address: u64,
+
+    pub page_size: PageSize,
+
+    /// All levels allow writes.
+    pub writable: bool,
+
+    /// All levels allow user accesses.
+    pub user: bool,
+
+    /// No level forbids instruction fetches.
+    pub executable: bool,
+}
+
+impl Translation {
+    /// Returns the guest frame number of the physical address.
+    pub fn gfn(&self) -> u64 {
+        self.physical_address >> PAGE_SHIFT
+    }
+}
+
+/// Guest physical memory the page tables are read from.
+pub trait PhysicalMemory {
+    /// Reads `buffer.len()` bytes at the guest physical `address`.
+    fn read_physical(&self, address: u64, buffer: &mut [u8]) -> Result<(), XenError>;
+}
+
+/// The slice is the guest physical memory starting at address 0.
+impl PhysicalMemory for [u8] {
+    fn read_physical(&self, address: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        let data = usize::try_from(address)
+            .ok()
+            .and_then(|start| self.get(start..start.checked_add(buffer.len())?))
+            .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EFAULT))?;
+
+        buffer.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Translates linear addresses by walking the guest page tables.
+///
+/// Only the paging controls in `CR0`, `CR3`, `CR4` and `EFER` are taken into
+/// account. SMEP, SMAP and protection keys are not checked, and the
+/// physical address width is assumed to be 52 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageTableWalker {
+    pub mode: PagingMode,
+
+    /// Value of `CR3`.
+    pub root: u64,
+
+    /// `CR0.WP`, supervisor writes to read-only pages fault.
+    pub write_protect: bool,
+
+    /// `EFER.NXE`, the NX bit is honored.
+    pub no_execute: bool,
+
+    /// `CR4.PSE`, 32-bit paging may map 4M pages.
+    pub page_size_extensions: bool,
+}
+
+impl PageTableWalker {
+    /// Creates a walker from the paging control registers.
+    pub fn new(cr0: u64, cr3: u64, cr4: u64, efer: u64) -> Self {
+        Self {
+            mode: PagingMode::new(cr0, cr4, efer),
+            root: cr3,
+            write_protect: cr0 & CR0_WP != 0,
+            no_execute: efer & EFER_NXE != 0,
+            page_size_extensions: cr4 & CR4_PSE != 0,
+        }
+    }
+
+    /// Translates the linear `address` for `access`.
+    ///
+    /// A failed walk is reported as [`XenError::PageFault`], errors reading
+    /// the page tables are passed through.
+    pub fn translate<M>(
+        &self,
+        memory: &M,
+        address: u64,
+        access: TranslationAccess,
+    ) -> Result<Translation, XenError>
+    where
+        M: PhysicalMemory + ?Sized,
+    {
+        let bits = self.mode.address_bits();
+        let shift = 64 - bits;
+
+        match self.mode {
+            PagingMode::Ia32e | PagingMode::La57 => {
+                if ((address << shift) as i64 >> shift) as u64 != address {
+                    return Err(XenError::Other("Address is not canonical"));
+                }
+            }
+            _ => {
+                if address >> bits != 0 {
+                    return Err(XenError::Other("Address is above 4GiB"));
+                }
+            }
+        }
+
+        if self.mode == PagingMode::Disabled {
+            return Ok(Translation {
+                address,
+                physical_address: address,
+                page_size: PageSize::Size4K,
+                writable: true,
+                user: true,
+                executable: true,
+            });
+        }
+
+        let (entry_size, index_bits, mut table) = match self.mode {
+            PagingMode::Legacy => (4, 10, self.root & PTE32_ADDRESS_MASK),
+            PagingMode::Pae => (8, 9, self.root & 0xffff_ffe0),
+            _ => (8, 9, self.root & PTE_ADDRESS_MASK),
+        };
+
+        let mut writable = true;
+        let mut user = true;
+        let mut executable = true;
+
+        for level in (1..=self.mode.levels()).rev() {
+            let index =
+                (address >> (12 + index_bits * (level as u32 - 1))) & ((1 << index_bits) - 1);
+            let entry_address = table + index * entry_size;
+
+            let mut buffer = [0; 8];
+            memory.read_physical(entry_address, &mut buffer[..entry_size as usize])?;
+            let entry = u64::from_le_bytes(buffer);
+
+            let fault = |reason| PageFault {
+                address,
+                access,
+                reason,
+                level,
+                entry_address,
+                entry,
+            };
+
+            if entry & PTE_PRESENT == 0 {
+                return Err(fault(PageFaultReason::NotPresent).into());
+            }
+
+            if entry & self.reserved_bits(level, entry) != 0 {
+                return Err(fault(PageFaultReason::ReservedBit).into());
+            }
+
+            // PAE page directory pointers have no permission bits.
+            if self.mode != PagingMode::Pae || level != 3 {
+                writable &= entry & PTE_WRITABLE != 0;
+                user &= entry & PTE_USER != 0;
+            }
+
+            if self.no_execute && entry & PTE_NX != 0 {
+                executable = false;
+            }
+
+            let page_size = match level {
+                1 => PageSize::Size4K,
+                _ if !self.is_large_page(level, entry) => {
+                    table = match self.mode {
+                        PagingMode::Legacy => entry & PTE32_ADDRESS_MASK,
+                        _ => entry & PTE_ADDRESS_MASK,
+                    };
+                    continue;
+                }
+                2 if self.mode == PagingMode::Legacy => PageSize::Size4M,
+                2 => PageSize::Size2M,
+                _ => PageSize::Size1G,
+            };
+
+            if access.contains(TranslationAccess::USER) && !user {
+                return Err(fault(PageFaultReason::SupervisorPage).into());
+            }
+
+            if access.contains(TranslationAccess::WRITE)
+                && !writable
+                && (access.contains(TranslationAccess::USER) || self.write_protect)
+            {
+                return Err(fault(PageFaultReason::ReadOnly).into());
+            }
+
+            if access.contains(TranslationAccess::EXECUTE) && !executable {
+                return Err(fault(PageFaultReason::NoExecute).into());
+            }
+
+            let base = match page_size {
+                // PSE-36 keeps bits 39:32 of the address in bits 20:13.
+                PageSize::Size4M => (entry & 0xffc0_0000) | ((entry >> 13) & 0xff) << 32,
+                _ => entry & PTE_ADDRESS_MASK & !(page_size.bytes() - 1),
+            };
+
+            return Ok(Translation {
+                address,
+                physical_address: base | (address & (page_size.bytes() - 1)),
+                page_size,
+                writable,
+                user,
+                executable,
+            });
+        }
+
+        unreachable!("the walk ends at the page table")
+    }
+
+    /// Returns whether the entry at `level` maps a page instead of
+    /// referencing the next table.
+    fn is_large_page(&self, level: u8, entry: u64) -> bool {
+        if entry & PTE_PAGE_SIZE == 0 {
+            return false;
+        }
+
+        // Bit 7 of a page table entry selects the PAT.
+        match self.mode {
+            PagingMode::Legacy => self.page_size_extensions && level == 2,
+            PagingMode::Pae => level == 2,
+            _ => level == 2 || level == 3,
+        }
+    }
+
+    /// Returns the bits that must be clear in the present entry at `level`.
+    fn reserved_bits(&self, level: u8, entry: u64) -> u64 {
+        let nx = if self.no_execute { 0 } else { PTE_NX };
+        let large = self.is_large_page(level, entry);
+
+        match self.mode {
+            PagingMode::Disabled => 0,
+            PagingMode::Legacy if large => 1 << 21,
+            PagingMode::Legacy => 0,
+            PagingMode::Pae if level == 3 => PDPTE_RESERVED,
+            PagingMode::Pae if large => nx | PAE_RESERVED_HIGH | 0x001f_e000,
+            PagingMode::Pae => nx | PAE_RESERVED_HIGH,
+            PagingMode::Ia32e | PagingMode::La57 => match level {
+                4 | 5 => nx | PTE_PAGE_SIZE,
+                3 if large => nx | 0x3fff_e000,
+                2 if large => nx | 0x001f_e000,
+                _ => nx,
+            },
+        }
+    }
+}
+
+impl From<&Registers> for PageTableWalker {
+    fn from(value: &Registers) -> Self {
+        Self::new(value.cr0, value.cr3, value.cr4, value.msr_efer)
+    }
+}
+
+impl From<&VmEventRegsX86> for PageTableWalker {
+    fn from(value: &VmEventRegsX86) -> Self {
+        Self::new(value.cr0, value.cr3, value.cr4, value.msr_efer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: u64 = PTE_PRESENT;
+    const W: u64 = PTE_WRITABLE;
+    const U: u64 = PTE_USER;
+    const PS: u64 = PTE_PAGE_SIZE;
+
+    /// Guest physical memory holding one table per level, starting with
+    /// the root table at `0x1000`.
+    struct Tables {
+        mode: PagingMode,
+        memory: Vec<u8>,
+    }
+
+    impl Tables {
+        fn new(mode: PagingMode) -> Self {
+            Self {
+                mode,
+                memory: vec![0; 0x1000 * (mode.levels() as usize + 1)],
+            }
+        }
+
+        fn walker(&self) -> PageTableWalker {
+            PageTableWalker {
+                mode: self.mode,
+                root: 0x1000,
+                write_protect: true,
+                no_execute: true,
+                page_size_extensions: true,
+            }
+        }
+
+        fn entry_address(&self, address: u64, level: u8) -> u64 {
+            let (entry_size, index_bits) = match self.mode {
+                PagingMode::Legacy => (4, 10),
+                _ => (8, 9),
+            };
+
+            let table = 0x1000 * (1 + (self.mode.levels() - level) as u64);
+            let index =
+                (address >> (12 + index_bits * (level as u32 - 1))) & ((1 << index_bits) - 1);
+            table + index * entry_size
+        }
+
+        fn set(&mut self, address: u64, level: u8, entry: u64) {
+            let entry_address = self.entry_address(address, level) as usize;
+            let entry_size = match self.mode {
+                PagingMode::Legacy => 4,
+                _ => 8,
+            };
+
+            self.memory[entry_address..entry_address + entry_size]
+                .copy_from_slice(&entry.to_le_bytes()[..entry_size]);
+        }
+
+        /// Maps `address` with `leaf` at `leaf_level`, the entries above
+        /// reference the next table with `flags`.
+        fn map(&mut self, address: u64, leaf_level: u8, leaf: u64, flags: u64) {
+            for level in (leaf_level + 1..=self.mode.levels()).rev() {
+                let next = 0x1000 * (2 + (self.mode.levels() - level) as u64);
+                let flags = match (self.mode, level) {
+                    (PagingMode::Pae, 3) => P,
+                    _ => flags,
+                };
+
+                self.set(address, level, next | flags);
+            }
+
+            self.set(address, leaf_level, leaf);
+        }
+
+        fn translate(
+            &self,
+            address: u64,
+            access: TranslationAccess,
+        ) -> Result<Translation, XenError> {
+            self.walker().translate(&self.memory[..], address, access)
+        }
+
+        fn fault(&self, address: u64, access: TranslationAccess) -> PageFault {
+            match self.translate(address, access) {
+                Err(XenError::PageFault(fault)) => fault,
+                result => panic!("expected a page fault, got {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let tables = Tables::new(PagingMode::Disabled);
+        let translation = tables
+            .translate(0x1234_5678, TranslationAccess::EXECUTE)
+            .unwrap();
+
+        assert_eq!(translation.physical_address, 0x1234_5678);
+        assert!(
+            tables
+                .translate(1 << 32, TranslationAccess::empty())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn legacy() {
+        let mut tables = Tables::new(PagingMode::Legacy);
+        tables.map(0x0040_3123, 1, 0x0020_0000 | P | W, P | W);
+
+        let translation = tables
+            .translate(0x0040_3123, TranslationAccess::WRITE)
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x0020_0123);
+        assert_eq!(translation.page_size, PageSize::Size4K);
+
+        // Bit 7 of a page table entry is the PAT bit, not a page size.
+        tables.set(0x0040_3123, 1, 0x0020_0000 | P | PS);
+        let translation = tables
+            .translate(0x0040_3123, TranslationAccess::empty())
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x0020_0123);
+        assert_eq!(translation.page_size, PageSize::Size4K);
+
+        // 4M page with bits 39:32 of the address in bits 20:13.
+        tables.map(0x0080_1234, 2, 0x00c0_0000 | (0x12 << 13) | P | PS, 0);
+        let translation = tables
+            .translate(0x0080_1234, TranslationAccess::empty())
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x12_00c0_1234);
+        assert_eq!(translation.page_size, PageSize::Size4M);
+
+        tables.set(0x0080_1234, 2, 0x00c0_0000 | (1 << 21) | P | PS);
+        let fault = tables.fault(0x0080_1234, TranslationAccess::empty());
+        assert_eq!(fault.reason, PageFaultReason::ReservedBit);
+        assert_eq!(fault.level, 2);
+
+        // Without CR4.PSE, the page size bit is ignored.
+        let walker = PageTableWalker {
+            page_size_extensions: false,
+            ..tables.walker()
+        };
+        tables.set(0x0080_1234, 2, 0x2000 | P | PS);
+        tables.set(0x0080_1234, 1, 0x0050_0000 | P);
+        let translation = walker
+            .translate(&tables.memory[..], 0x0080_1234, TranslationAccess::empty())
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x0050_0234);
+    }
+
+    #[test]
+    fn pae() {
+        let mut tables = Tables::new(PagingMode::Pae);
+        tables.map(0xc000_5678, 1, 0x1234_5000 | P, P | W);
+
+        let translation = tables
+            .translate(0xc000_5678, TranslationAccess::empty())
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x1234_5678);
+        assert!(!translation.writable);
+
+        tables.map(0x4020_1234, 2, 0x8000_0000 | P | W | PS, P);
+        let translation = tables
+            .translate(0x4020_1234, TranslationAccess::WRITE)
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x8000_1234);
+        assert_eq!(translation.page_size, PageSize::Size2M);
+
+        // Writable bit of a page directory pointer is reserved.
+        tables.set(0x4020_1234, 3, 0x2000 | P | W);
+        let fault = tables.fault(0x4020_1234, TranslationAccess::empty());
+        assert_eq!(fault.reason, PageFaultReason::ReservedBit);
+        assert_eq!(fault.level, 3);
+        assert_eq!(fault.entry_address, 0x1008);
+    }
+
+    #[test]
+    fn ia32e() {
+        let address = 0xffff_f801_2345_6789;
+        let mut tables = Tables::new(PagingMode::Ia32e);
+
+        tables.map(address, 1, 0x0007_0000 | P | W | PS, P | W);
+        let translation = tables.translate(address, TranslationAccess::WRITE).unwrap();
+        assert_eq!(translation.physical_address, 0x0007_0789);
+        assert_eq!(translation.page_size, PageSize::Size4K);
+
+        tables.map(address, 2, 0x0020_0000 | P | W | PS, P | W);
+        let translation = tables.translate(address, TranslationAccess::WRITE).unwrap();
+        assert_eq!(translation.physical_address, 0x0025_6789);
+        assert_eq!(translation.page_size, PageSize::Size2M);
+
+        tables.map(address, 3, 0x4000_0000 | P | W | PS, P | W);
+        let translation = tables.translate(address, TranslationAccess::WRITE).unwrap();
+        assert_eq!(translation.physical_address, 0x6345_6789);
+        assert_eq!(translation.page_size, PageSize::Size1G);
+
+        // Misaligned 1G page.
+        tables.set(address, 3, 0x4000_2000 | P | PS);
+        let fault = tables.fault(address, TranslationAccess::empty());
+        assert_eq!(fault.reason, PageFaultReason::ReservedBit);
+        assert_eq!(fault.level, 3);
+
+        // No page size bit in the PML4.
+        tables.set(address, 4, 0x2000 | P | PS);
+        let fault = tables.fault(address, TranslationAccess::empty());
+        assert_eq!(fault.reason, PageFaultReason::ReservedBit);
+        assert_eq!(fault.level, 4);
+
+        assert!(
+            tables
+                .translate(0x0000_8000_0000_0000, TranslationAccess::empty())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn la57() {
+        let address = 0x00ff_0000_0000_1234;
+        let mut tables = Tables::new(PagingMode::La57);
+        tables.map(address, 1, 0x0009_0000 | P, P);
+
+        let translation = tables
+            .translate(address, TranslationAccess::empty())
+            .unwrap();
+        assert_eq!(translation.physical_address, 0x0009_0234);
+
+        let fault = tables.fault(0x00fe_0000_0000_1234, TranslationAccess::empty());
+        assert_eq!(fault.reason, PageFaultReason::NotPresent);
+        assert_eq!(fault.level, 5);
+        assert_eq!(fault.error_code(), 0);
+    }
+
+    #[test]
+    fn user_supervisor() {
+        let mut tables = Tables::new(PagingMode::Ia32e);
+
+        // The page is a user page, but its page directory is not.
+        tables.map(0x1000, 1, 0x0005_0000 | P | W | U, P | W | U);
+        tables.set(0x1000, 2, 0x4000 | P | W);
+
+        assert!(tables.translate(0x1000, TranslationAccess::WRITE).is_ok());
+
+        let fault = tables.fault(0x1000, TranslationAccess::USER);
+        assert_eq!(fault.reason, PageFaultReason::SupervisorPage);
+        assert_eq!(fault.level, 1);
+        assert_eq!(fault.error_code(), 0b101);
+    }
+
+    #[test]
+    fn read_only() {
+        let mut tables = Tables::new(PagingMode::Ia32e);
+        tables.map(0x1000, 1, 0x0005_0000 | P | U, P | W | U);
+
+        let fault = tables.fault(0x1000, TranslationAccess::USER | TranslationAccess::WRITE);
+        assert_eq!(fault.reason, PageFaultReason::ReadOnly);
+        assert_eq!(fault.error_code(), 0b111);
+
+        let fault = tables.fault(0x1000, TranslationAccess::WRITE);
+        assert_eq!(fault.reason, PageFaultReason::ReadOnly);
+
+        // Without CR0.WP, the supervisor can write to read-only pages.
+        let walker = PageTableWalker {
+            write_protect: false,
+            ..tables.walker()
+        };
+        assert!(
+            walker
+                .translate(&tables.memory[..], 0x1000, TranslationAccess::WRITE)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn no_execute() {
+        let mut tables = Tables::new(PagingMode::Ia32e);
+        tables.map(0x1000, 1, 0x0005_0000 | P, P | W);
+        tables.set(0x1000, 3, 0x3000 | P | W | PTE_NX);
+
+        let translation = tables
+            .translate(0x1000, TranslationAccess::empty())
+            .unwrap();
+        assert!(!translation.executable);
+
+        let fault = tables.fault(0x1000, TranslationAccess::EXECUTE);
+        assert_eq!(fault.reason, PageFaultReason::NoExecute);
+        assert_eq!(fault.error_code(), 0b10001);
+
+        // Without EFER.NXE, the NX bit is reserved.
+        let walker = PageTableWalker {
+            no_execute: false,
+            ..tables.walker()
+        };
+        match walker.translate(&tables.memory[..], 0x1000, TranslationAccess::empty()) {
+            Err(XenError::PageFault(fault)) => {
+                assert_eq!(fault.reason, PageFaultReason::ReservedBit);
+                assert_eq!(fault.level, 3);
+                assert_eq!(fault.error_code(), 0b1001);
+            }
+            result => panic!("expected a page fault, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn unreadable_tables() {
+        let tables = Tables::new(PagingMode::Ia32e);
+        let walker = PageTableWalker {
+            root: 0x10_0000,
+            ..tables.walker()
+        };
+
+        assert!(matches!(
+            walker.translate(&tables.memory[..], 0x1000, TranslationAccess::empty()),
+            Err(XenError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,618 @@
+use super::{RegisterFields, Registers};
+use crate::XenError;
+
+const RFLAGS_FIXED1: u64 = 1 << 1;
+const RFLAGS_RESERVED: u64 = !0x003f_7fd7;
+const RFLAGS_VM: u64 = 1 << 17;
+
+const CR0_PE: u64 = 1 << 0;
+const CR0_NW: u64 = 1 << 29;
+const CR0_CD: u64 = 1 << 30;
+const CR0_PG: u64 = 1 << 31;
+
+const CR4_PAE: u64 = 1 << 5;
+const CR4_LA57: u64 = 1 << 12;
+
+const EFER_LME: u64 = 1 << 8;
+const EFER_LMA: u64 = 1 << 10;
+
+/// Busy 16-bit TSS.
+const TYPE_TSS16_BUSY: u32 = 3;
+
+/// Busy 32-bit or 64-bit TSS.
+const TYPE_TSS_BUSY: u32 = 11;
+
+const TYPE_LDT: u32 = 2;
+const TYPE_ACCESSED: u32 = 1 << 0;
+const TYPE_READABLE: u32 = 1 << 1;
+const TYPE_CONFORMING: u32 = 1 << 2;
+const TYPE_CODE: u32 = 1 << 3;
+
+/// Segment attributes in the format saved by Xen.
+#[derive(Clone, Copy)]
+struct Attributes(u32);
+
+impl Attributes {
+    fn segment_type(self) -> u32 {
+        self.0 & 0xf
+    }
+
+    /// Code or data segment, as opposed to a system segment.
+    fn s(self) -> bool {
+        self.0 & (1 << 4) != 0
+    }
+
+    fn dpl(self) -> u32 {
+        (self.0 >> 5) & 3
+    }
+
+    fn present(self) -> bool {
+        self.0 & (1 << 7) != 0
+    }
+
+    fn l(self) -> bool {
+        self.0 & (1 << 9) != 0
+    }
+
+    fn db(self) -> bool {
+        self.0 & (1 << 10) != 0
+    }
+
+    fn g(self) -> bool {
+        self.0 & (1 << 11) != 0
+    }
+}
+
+/// Returns `Err` with the message if the condition does not hold and one
+/// of the fields it depends on is checked.
+macro_rules! ensure {
+    ($checked:expr, $fields:expr, $condition:expr, $message:expr) => {
+        if $checked.intersects($fields) && !$condition {
+            return Err(XenError::Other($message));
+        }
+    };
+}
+
+/// Checks the limit of a segment against its granularity.
+fn limit_matches_granularity(limit: u32, attributes: Attributes) -> bool {
+    if limit & 0xfff != 0xfff && attributes.g() {
+        return false;
+    }
+
+    if limit > 0xfffff && !attributes.g() {
+        return false;
+    }
+
+    true
+}
+
+impl Registers {
+    /// Checks the registers against the guest state checks performed on VM
+    /// entry on Intel VT-x, which would otherwise crash the domain when the
+    /// state is loaded.
+    ///
+    /// Segment attributes are expected in the format saved by Xen, where
+    /// bits 0-3 are the type, followed by S, DPL, P, AVL, L, D/B and G. A
+    /// segment that is not present is considered unusable.
+    pub fn validate(&self) -> Result<(), XenError> {
+        self.validate_fields(RegisterFields::all(), true)
+    }
+
+    /// Performs the checks of [`validate`](Self::validate) that depend on
+    /// one of `fields`, e.g. the ones affected by writing `fields`.
+    ///
+    /// The checks of the segment types, e.g. of the accessed bits, of the
+    /// busy `TR` and of the `SS` type, are only performed on VM entry on
+    /// Intel VT-x, and are skipped unless `vmx` is set.
+    pub fn validate_fields(&self, fields: RegisterFields, vmx: bool) -> Result<(), XenError> {
+        use RegisterFields as F;
+
+        let vmx_fields = match vmx {
+            true => fields,
+            false => F::empty(),
+        };
+
+        let canonical = |address: u64| {
+            let bits = if self.cr4 & CR4_LA57 != 0 { 57 } else { 48 };
+            let shift = 64 - bits;
+            ((address << shift) as i64 >> shift) as u64 == address
+        };
+
+        // Control registers and EFER.
+        ensure!(
+            fields,
+            F::CR0,
+            self.cr0 >> 32 == 0,
+            "CR0 reserved bits are set"
+        );
+        ensure!(
+            fields,
+            F::CR0,
+            self.cr0 & CR0_PG == 0 || self.cr0 & CR0_PE != 0,
+            "CR0.PG is set without CR0.PE"
+        );
+        ensure!(
+            fields,
+            F::CR0,
+            self.cr0 & CR0_NW == 0 || self.cr0 & CR0_CD != 0,
+            "CR0.NW is set without CR0.CD"
+        );
+
+        let long_mode = self.msr_efer & EFER_LMA != 0;
+        ensure!(
+            fields,
+            F::CR0 | F::MSR_EFER,
+            self.cr0 & CR0_PG == 0 || long_mode == (self.msr_efer & EFER_LME != 0),
+            "EFER.LMA does not match EFER.LME"
+        );
+        ensure!(
+            fields,
+            F::CR4 | F::MSR_EFER,
+            !long_mode || self.cr4 & CR4_PAE != 0,
+            "EFER.LMA is set without CR4.PAE"
+        );
+
+        // RFLAGS.
+        ensure!(
+            fields,
+            F::RFLAGS,
+            self.rflags & RFLAGS_RESERVED == 0 && self.rflags & RFLAGS_FIXED1 != 0,
+            "RFLAGS reserved bits are invalid"
+        );
+        ensure!(
+            fields,
+            F::RFLAGS | F::MSR_EFER,
+            !long_mode || self.rflags & RFLAGS_VM == 0,
+            "RFLAGS.VM is set in long mode"
+        );
+
+        // Segment registers.
+        let cs = Attributes(self.cs_arbytes);
+        let ss = Attributes(self.ss_arbytes);
+        let tr = Attributes(self.tr_arbytes);
+        let ldtr = Attributes(self.ldtr_arbytes);
+        let code64 = long_mode && cs.l();
+
+        ensure!(
+            fields,
+            F::CS_ARBYTES | F::MSR_EFER,
+            !(code64 && cs.db()),
+            "CS.L and CS.D are both set"
+        );
+        ensure!(
+            fields,
+            F::CS_LIMIT | F::CS_ARBYTES,
+            limit_matches_granularity(self.cs_limit, cs),
+            "CS limit does not match its granularity"
+        );
+        ensure!(
+            fields,
+            F::CS_BASE,
+            self.cs_base >> 32 == 0,
+            "CS base is invalid"
+        );
+
+        let protected_mode = self.cr0 & CR0_PE != 0 && self.rflags & RFLAGS_VM == 0;
+        let mode = F::CR0 | F::RFLAGS;
+
+        if protected_mode {
+            ensure!(
+                vmx_fields,
+                mode | F::CS_ARBYTES,
+                cs.present()
+                    && cs.s()
+                    && cs.segment_type() & (TYPE_CODE | TYPE_ACCESSED)
+                        == (TYPE_CODE | TYPE_ACCESSED),
+                "CS is not an accessed code segment"
+            );
+
+            if ss.present() {
+                ensure!(
+                    vmx_fields,
+                    mode | F::SS_ARBYTES,
+                    ss.s() && matches!(ss.segment_type(), 3 | 7),
+                    "SS is not an accessed writable data segment"
+                );
+
+                if cs.segment_type() & TYPE_CONFORMING == 0 {
+                    ensure!(
+                        vmx_fields,
+                        mode | F::CS_ARBYTES | F::SS_ARBYTES,
+                        cs.dpl() == ss.dpl(),
+                        "CS.DPL does not match SS.DPL"
+                    );
+                }
+                else {
+                    ensure!(
+                        vmx_fields,
+                        mode | F::CS_ARBYTES | F::SS_ARBYTES,
+                        cs.dpl() <= ss.dpl(),
+                        "CS.DPL is above SS.DPL"
+                    );
+                }
+            }
+        }
+
+        macro_rules! data_segment {
+            (
+                $name:literal,
+                $base:ident: $base_fields:expr,
+                $limit:ident: $limit_fields:expr,
+                $arbytes:ident: $arbytes_fields:expr,
+                $base_valid:expr
+            ) => {
+                let attributes = Attributes(self.$arbytes);
+
+                if attributes.present() {
+                    ensure!(
+                        fields,
+                        $limit_fields | $arbytes_fields,
+                        limit_matches_granularity(self.$limit, attributes),
+                        concat!($name, " limit does not match its granularity")
+                    );
+                    ensure!(
+                        fields,
+                        $base_fields | $arbytes_fields,
+                        $base_valid(self.$base),
+                        concat!($name, " base is invalid")
+                    );
+
+                    let segment_type = attributes.segment_type();
+                    ensure!(
+                        vmx_fields,
+                        mode | $arbytes_fields,
+                        !protected_mode
+                            || (attributes.s()
+                                && segment_type & TYPE_ACCESSED != 0
+                                && (segment_type & TYPE_CODE == 0
+                                    || segment_type & TYPE_READABLE != 0)),
+                        concat!($name, " is not an accessed data or readable code segment")
+                    );
+                }
+            };
+        }
+
+        let below_4g = |base: u64| base >> 32 == 0;
+        data_segment!(
+            "SS",
+            ss_base: F::SS_BASE,
+            ss_limit: F::SS_LIMIT,
+            ss_arbytes: F::SS_ARBYTES,
+            below_4g
+        );
+        data_segment!(
+            "DS",
+            ds_base: F::DS_BASE,
+            ds_limit: F::DS_LIMIT,
+            ds_arbytes: F::DS_ARBYTES,
+            below_4g
+        );
+        data_segment!(
+            "ES",
+            es_base: F::ES_BASE,
+            es_limit: F::ES_LIMIT,
+            es_arbytes: F::ES_ARBYTES,
+            below_4g
+        );
+        data_segment!(
+            "FS",
+            fs_base: F::FS_BASE | F::CR4,
+            fs_limit: F::FS_LIMIT,
+            fs_arbytes: F::FS_ARBYTES,
+            canonical
+        );
+        data_segment!(
+            "GS",
+            gs_base: F::GS_BASE | F::CR4,
+            gs_limit: F::GS_LIMIT,
+            gs_arbytes: F::GS_ARBYTES,
+            canonical
+        );
+
+        // System segments.
+        ensure!(
+            vmx_fields,
+            F::TR_ARBYTES | F::MSR_EFER,
+            tr.present()
+                && !tr.s()
+                && (tr.segment_type() == TYPE_TSS_BUSY
+                    || (!long_mode && tr.segment_type() == TYPE_TSS16_BUSY)),
+            "TR is not a busy TSS"
+        );
+        ensure!(
+            fields,
+            F::TR_SEL,
+            self.tr_sel & 4 == 0,
+            "TR selector references the LDT"
+        );
+        ensure!(
+            fields,
+            F::TR_LIMIT | F::TR_ARBYTES,
+            limit_matches_granularity(self.tr_limit, tr),
+            "TR limit does not match its granularity"
+        );
+        ensure!(
+            fields,
+            F::TR_BASE | F::CR4,
+            canonical(self.tr_base),
+            "TR base is not canonical"
+        );
+
+        if ldtr.present() {
+            ensure!(
+                vmx_fields,
+                F::LDTR_ARBYTES,
+                !ldtr.s() && ldtr.segment_type() == TYPE_LDT,
+                "LDTR is not an LDT"
+            );
+            ensure!(
+                fields,
+                F::LDTR_SEL | F::LDTR_ARBYTES,
+                self.ldtr_sel & 4 == 0,
+                "LDTR selector references the LDT"
+            );
+            ensure!(
+                fields,
+                F::LDTR_LIMIT | F::LDTR_ARBYTES,
+                limit_matches_granularity(self.ldtr_limit, ldtr),
+                "LDTR limit does not match its granularity"
+            );
+            ensure!(
+                fields,
+                F::LDTR_BASE | F::LDTR_ARBYTES | F::CR4,
+                canonical(self.ldtr_base),
+                "LDTR base is not canonical"
+            );
+        }
+
+        // Descriptor tables.
+        ensure!(
+            fields,
+            F::GDTR_BASE | F::CR4,
+            canonical(self.gdtr_base),
+            "GDTR base is not canonical"
+        );
+        ensure!(
+            fields,
+            F::GDTR_LIMIT,
+            self.gdtr_limit <= 0xffff,
+            "GDTR limit is above 64KiB"
+        );
+        ensure!(
+            fields,
+            F::IDTR_BASE | F::CR4,
+            canonical(self.idtr_base),
+            "IDTR base is not canonical"
+        );
+        ensure!(
+            fields,
+            F::IDTR_LIMIT,
+            self.idtr_limit <= 0xffff,
+            "IDTR limit is above 64KiB"
+        );
+
+        // RIP, debug registers and MSRs.
+        let rip_fields = F::RIP | F::CS_ARBYTES | F::MSR_EFER;
+        if code64 {
+            ensure!(
+                fields,
+                rip_fields | F::CR4,
+                canonical(self.rip),
+                "RIP is not canonical"
+            );
+        }
+        else {
+            ensure!(
+                fields,
+                rip_fields,
+                self.rip >> 32 == 0,
+                "RIP is above 4GiB outside of 64-bit mode"
+            );
+        }
+
+        ensure!(
+            fields,
+            F::DR7,
+            self.dr7 >> 32 == 0,
+            "DR7 reserved bits are set"
+        );
+        ensure!(
+            fields,
+            F::SYSENTER_ESP | F::CR4,
+            canonical(self.sysenter_esp),
+            "SYSENTER_ESP is not canonical"
+        );
+        ensure!(
+            fields,
+            F::SYSENTER_EIP | F::CR4,
+            canonical(self.sysenter_eip),
+            "SYSENTER_EIP is not canonical"
+        );
+        ensure!(
+            fields,
+            F::SHADOW_GS | F::CR4,
+            canonical(self.shadow_gs),
+            "shadow GS base is not canonical"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a change to a valid state.
+    type Modify = fn(&mut Registers);
+
+    /// Returns the state of a vCPU in 64-bit mode at CPL 0.
+    fn long_mode() -> Registers {
+        let data = 0xc93; // Accessed writable data, P, D/B, G.
+
+        Registers {
+            rip: 0xffff_f800_0000_1000,
+            rflags: RFLAGS_FIXED1,
+            cr0: 0x8000_0011,
+            cr4: CR4_PAE,
+            msr_efer: EFER_LMA | EFER_LME | 1,
+            cs_limit: 0xffff_ffff,
+            cs_sel: 0x10,
+            cs_arbytes: 0xa9b, // Accessed readable code, P, L, G.
+            ss_limit: 0xffff_ffff,
+            ss_sel: 0x18,
+            ss_arbytes: data,
+            ds_limit: 0xffff_ffff,
+            ds_arbytes: data,
+            es_limit: 0xffff_ffff,
+            es_arbytes: data,
+            fs_limit: 0xffff_ffff,
+            fs_arbytes: data,
+            gs_limit: 0xffff_ffff,
+            gs_arbytes: data,
+            tr_limit: 0x67,
+            tr_sel: 0x40,
+            tr_arbytes: 0x8b, // Busy TSS, P.
+            gdtr_base: 0xffff_f800_0010_0000,
+            gdtr_limit: 0x7f,
+            idtr_base: 0xffff_f800_0010_1000,
+            idtr_limit: 0xfff,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_state() {
+        let registers = long_mode();
+        assert!(registers.validate().is_ok());
+        assert!(
+            registers
+                .validate_fields(RegisterFields::all(), false)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn unrelated_fields() {
+        let mut registers = long_mode();
+        registers.tr_arbytes = 0x89; // Available TSS.
+        registers.dr7 = 1 << 32;
+        registers.rax = 0x1234;
+
+        assert!(registers.validate().is_err());
+        assert!(
+            registers
+                .validate_fields(RegisterFields::RAX | RegisterFields::RIP, true)
+                .is_ok()
+        );
+        assert!(
+            registers
+                .validate_fields(RegisterFields::TR_ARBYTES, true)
+                .is_err()
+        );
+        assert!(
+            registers
+                .validate_fields(RegisterFields::DR7, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn dependent_fields() {
+        // Writing CR0 is checked against the current EFER.
+        let mut registers = long_mode();
+        registers.msr_efer = EFER_LMA;
+        assert!(
+            registers
+                .validate_fields(RegisterFields::CR0, true)
+                .is_err()
+        );
+        assert!(registers.validate_fields(RegisterFields::RAX, true).is_ok());
+
+        // Canonical addresses depend on CR4.LA57.
+        let mut registers = long_mode();
+        registers.cr4 |= CR4_LA57;
+        registers.fs_base = 0x00ff_0000_0000_0000;
+        assert!(registers.validate().is_ok());
+
+        registers.cr4 &= !CR4_LA57;
+        assert!(
+            registers
+                .validate_fields(RegisterFields::CR4, true)
+                .is_err()
+        );
+        assert!(
+            registers
+                .validate_fields(RegisterFields::FS_BASE, true)
+                .is_err()
+        );
+        assert!(
+            registers
+                .validate_fields(RegisterFields::GS_BASE, true)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn vmx_checks() {
+        let cases: [Modify; 4] = [
+            // Data segment without the accessed bit.
+            |registers| registers.ds_arbytes &= !TYPE_ACCESSED,
+            // Available instead of busy TSS.
+            |registers| registers.tr_arbytes = 0x89,
+            // Read-only stack segment.
+            |registers| registers.ss_arbytes = 0xc91,
+            // Stack segment DPL differs from CS.DPL.
+            |registers| registers.ss_arbytes |= 3 << 5,
+        ];
+
+        for modify in cases {
+            let mut registers = long_mode();
+            modify(&mut registers);
+
+            assert!(registers.validate().is_err());
+            assert!(
+                registers
+                    .validate_fields(RegisterFields::all(), false)
+                    .is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_state() {
+        let cases: [(Modify, RegisterFields); 7] = [
+            (|registers| registers.cr0 &= !CR0_PE, RegisterFields::CR0),
+            (|registers| registers.cr4 = 0, RegisterFields::CR4),
+            (
+                |registers| registers.rflags = 1 << 15,
+                RegisterFields::RFLAGS,
+            ),
+            (
+                |registers| registers.cs_arbytes |= 1 << 10,
+                RegisterFields::CS_ARBYTES,
+            ),
+            (
+                |registers| registers.cs_limit = 0xfff0,
+                RegisterFields::CS_LIMIT,
+            ),
+            (
+                |registers| registers.rip = 0x0000_8000_0000_0000,
+                RegisterFields::RIP,
+            ),
+            // RIP is above 4GiB in compatibility mode.
+            (
+                |registers| registers.cs_arbytes &= !(1 << 9),
+                RegisterFields::CS_ARBYTES,
+            ),
+        ];
+
+        for (modify, fields) in cases {
+            let mut registers = long_mode();
+            modify(&mut registers);
+
+            assert!(registers.validate_fields(fields, true).is_err());
+            assert!(registers.validate_fields(fields, false).is_err());
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use crate::XenError;
+
+bitflags::bitflags! {
+    /// State components of the XSAVE area, as enabled in `XCR0`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct XsaveComponents: u64 {
+        const X87 = 1 << 0;
+        const SSE = 1 << 1;
+        const AVX = 1 << 2;
+        const BNDREGS = 1 << 3;
+        const BNDCSR = 1 << 4;
+        const OPMASK = 1 << 5;
+        const ZMM_HI256 = 1 << 6;
+        const HI16_ZMM = 1 << 7;
+        const PT = 1 << 8;
+        const PKRU = 1 << 9;
+    }
+}
+
+/// Size of the legacy (`FXSAVE`) region.
+const LEGACY_SIZE: usize = 512;
+
+/// Size of the legacy region followed by the XSAVE header.
+const HEADER_END: usize = LEGACY_SIZE + 64;
+
+/// Offset of `XSTATE_BV` in the XSAVE header.
+const XSTATE_BV: usize = LEGACY_SIZE;
+
+/// Offset of `XCOMP_BV` in the XSAVE header.
+const XCOMP_BV: usize = LEGACY_SIZE + 8;
+
+/// `XCOMP_BV` bit indicating the compacted format.
+const XCOMP_BV_COMPACTED: u64 = 1 << 63;
+
+/// Standard format offsets of the extended components.
+const AVX_OFFSET: usize = 576;
+const OPMASK_OFFSET: usize = 1088;
+const ZMM_HI256_OFFSET: usize = 1152;
+const HI16_ZMM_OFFSET: usize = 1664;
+
+/// Initial value of the x87 control word (`FNINIT`).
+const FCW_INIT: u16 = 0x037f;
+
+/// An 80-bit x87 register (`ST0`-`ST7`), also holding `MM0`-`MM7`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct X87Register(pub [u8; 10]);
+
+impl X87Register {
+    /// Returns the 64-bit significand, which is also the MMX register.
+    pub fn mantissa(&self) -> u64 {
+        u64::from_le_bytes(self.0[..8].try_into().unwrap())
+    }
+
+    /// Returns the sign bit and the 15-bit exponent.
+    pub fn sign_exponent(&self) -> u16 {
+        u16::from_le_bytes(self.0[8..].try_into().unwrap())
+    }
+}
+
+/// x87 FPU state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FpuRegisters {
+    /// Control word.
+    pub fcw: u16,
+
+    /// Status word.
+    pub fsw: u16,
+
+    /// Abridged tag word, bit `n` is set if physical register `n` is valid.
+    pub ftw: u8,
+
+    /// Opcode of the last non-control instruction.
+    pub fop: u16,
+
+    /// Instruction pointer of the last non-control instruction.
+    pub fip: u64,
+
+    /// Data pointer of the last non-control instruction.
+    pub fdp: u64,
+
+    /// Data registers, ordered by their stack position (`ST0` first).
+    pub st: [X87Register; 8],
+}
+
+/// Extended processor state of a vCPU saved by `XSAVE`.
+///
+/// Components not enabled in [`xcr0`](Self::xcr0) are `None`. Wider vector
+/// registers are split the same way as in the XSAVE area, e.g. `YMM0` is
+/// `xmm[0]` in its lower and `ymm_hi128[0]` in its upper half. Use
+/// [`ymm`](Self::ymm) and [`zmm`](Self::zmm) to access them as a whole.
+///
+/// Components without a typed representation (e.g. MPX or PKRU) are kept
+/// as they are when the state is written back.
+#[derive(Debug, Clone)]
+pub struct ExtendedState {
+    /// Components enabled by the guest.
+    pub xcr0: XsaveComponents,
+
+    pub fpu: FpuRegisters,
+    pub mxcsr: u32,
+    pub mxcsr_mask: u32,
+
+    /// `XMM0`-`XMM15`.
+    pub xmm: [u128; 16],
+
+    /// Upper halves of `YMM0`-`YMM15`.
+    pub ymm_hi128: Option<[u128; 16]>,
+
+    /// `K0`-`K7`.
+    pub opmask: Option<[u64; 8]>,
+
+    /// Upper 256 bits of `ZMM0`-`ZMM15`.
+    pub zmm_hi256: Option<[[u8; 32]; 16]>,
+
+    /// `ZMM16`-`ZMM31`.
+    pub hi16_zmm: Option<[[u8; 64]; 16]>,
+
+    /// The XSAVE area the state was parsed from.
+    area: Vec<u8>,
+}
+
+impl ExtendedState {
+    /// Parses a standard format XSAVE area of a vCPU with `xcr0`.
+    pub fn from_xsave(xcr0: u64, area: &[u8]) -> Result<Self, XenError> {
+        if area.len() < HEADER_END {
+            return Err(XenError::Other("XSAVE area is too small"));
+        }
+
+        if read_u64(area, XCOMP_BV) & XCOMP_BV_COMPACTED != 0 {
+            return Err(XenError::Other("compacted XSAVE format is not supported"));
+        }
+
+        let xcr0 = XsaveComponents::from_bits_retain(xcr0);
+        let xstate_bv = XsaveComponents::from_bits_retain(read_u64(area, XSTATE_BV));
+
+        // Returns the component if it is enabled, or `None` if it is not.
+        // A component missing from `XSTATE_BV` is in its initial state and
+        // its content in the area is undefined.
+        let component = |component: XsaveComponents,
+                         offset: usize,
+                         size: usize|
+         -> Result<Option<&[u8]>, XenError> {
+            if !xcr0.contains(component) {
+                return Ok(None);
+            }
+
+            if area.len() < offset + size {
+                return Err(XenError::Other("XSAVE area is too small"));
+            }
+
+            match xstate_bv.contains(component) {
+                true => Ok(Some(&area[offset..offset + size])),
+                false => Ok(Some(&[0; 1024][..size])),
+            }
+        };
+
+        let fpu = match xstate_bv.contains(XsaveComponents::X87) {
+            true => FpuRegisters {
+                fcw: read_u16(area, 0),
+                fsw: read_u16(area, 2),
+                ftw: area[4],
+                fop: read_u16(area, 6),
+                fip: read_u64(area, 8),
+                fdp: read_u64(area, 16),
+                st: std::array::from_fn(|index| {
+                    X87Register(area[32 + index * 16..][..10].try_into().unwrap())
+                }),
+            },
+            false => FpuRegisters {
+                fcw: FCW_INIT,
+                ..Default::default()
+            },
+        };
+
+        let xmm = match xstate_bv.contains(XsaveComponents::SSE) {
+            true => std::array::from_fn(|index| read_u128(area, 160 + index * 16)),
+            false => [0; 16],
+        };
+
+        let ymm_hi128 = component(XsaveComponents::AVX, AVX_OFFSET, 256)?
+            .map(|data| std::array::from_fn(|index| read_u128(data, index * 16)));
+
+        let opmask = component(XsaveComponents::OPMASK, OPMASK_OFFSET, 64)?
+            .map(|data| std::array::from_fn(|index| read_u64(data, index * 8)));
+
+        let zmm_hi256 = component(XsaveComponents::ZMM_HI256, ZMM_HI256_OFFSET, 512)?
+            .map(|data| std::array::from_fn(|index| data[index * 32..][..32].try_into().unwrap()));
+
+        let hi16_zmm = component(XsaveComponents::HI16_ZMM, HI16_ZMM_OFFSET, 1024)?
+            .map(|data| std::array::from_fn(|index| data[index * 64..][..64].try_into().unwrap()));
+
+        Ok(Self {
+            xcr0,
+            fpu,
+
+            // MXCSR is saved along with both SSE and AVX state, regardless
+            // of `XSTATE_BV`.
+            mxcsr: read_u32(area, 24),
+            mxcsr_mask: read_u32(area, 28),
+            xmm,
+            ymm_hi128,
+            opmask,
+            zmm_hi256,
+            hi16_zmm,
+            area: area.to_vec(),
+        })
+    }
+
+    /// Serializes the state back into the XSAVE area it was parsed from.
+    ///
+    /// All typed components are marked as present in `XSTATE_BV`.
+    pub fn to_xsave(&self) -> Vec<u8> {
+        let mut area = self.area.clone();
+        let mut xstate_bv = XsaveComponents::from_bits_retain(read_u64(&area, XSTATE_BV));
+
+        area[0..2].copy_from_slice(&self.fpu.fcw.to_le_bytes());
+        area[2..4].copy_from_slice(&self.fpu.fsw.to_le_bytes());
+        area[4] = self.fpu.ftw;
+        area[6..8].copy_from_slice(&self.fpu.fop.to_le_bytes());
+        area[8..16].copy_from_slice(&self.fpu.fip.to_le_bytes());
+        area[16..24].copy_from_slice(&self.fpu.fdp.to_le_bytes());
+        area[24..28].copy_from_slice(&self.mxcsr.to_le_bytes());
+        area[28..32].copy_from_slice(&self.mxcsr_mask.to_le_bytes());
+
+        for (index, register) in self.fpu.st.iter().enumerate() {
+            area[32 + index * 16..][..10].copy_from_slice(&register.0);
+        }
+
+        for (index, register) in self.xmm.iter().enumerate() {
+            area[160 + index * 16..][..16].copy_from_slice(&register.to_le_bytes());
+        }
+
+        xstate_bv |= XsaveComponents::X87 | XsaveComponents::SSE;
+
+        if let Some(ymm_hi128) = &self.ymm_hi128 {
+            for (index, register) in ymm_hi128.iter().enumerate() {
+                area[AVX_OFFSET + index * 16..][..16].copy_from_slice(&register.to_le_bytes());
+            }
+
+            xstate_bv |= XsaveComponents::AVX;
+        }
+
+        if let Some(opmask) = &self.opmask {
+            for (index, register) in opmask.iter().enumerate() {
+                area[OPMASK_OFFSET + index * 8..][..8].copy_from_slice(&register.to_le_bytes());
+            }
+
+            xstate_bv |= XsaveComponents::OPMASK;
+        }
+
+        if let Some(zmm_hi256) = &self.zmm_hi256 {
+            for (index, register) in zmm_hi256.iter().enumerate() {
+                area[ZMM_HI256_OFFSET + index * 32..][..32].copy_from_slice(register);
+            }
+
+            xstate_bv |= XsaveComponents::ZMM_HI256;
+        }
+
+        if let Some(hi16_zmm) = &self.hi16_zmm {
+            for (index, register) in hi16_zmm.iter().enumerate() {
+                area[HI16_ZMM_OFFSET + index * 64..][..64].copy_from_slice(register);
+            }
+
+            xstate_bv |= XsaveComponents::HI16_ZMM;
+        }
+
+        let xstate_bv = xstate_bv & self.xcr0;
+        area[XSTATE_BV..XSTATE_BV + 8].copy_from_slice(&xstate_bv.bits().to_le_bytes());
+        area
+    }
+
+    /// Returns `YMMn`, or `None` if AVX is not enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not lower than 16.
+    pub fn ymm(&self, index: usize) -> Option<[u8; 32]> {
+        let ymm_hi128 = self.ymm_hi128.as_ref()?;
+
+        let mut result = [0; 32];
+        result[..16].copy_from_slice(&self.xmm[index].to_le_bytes());
+        result[16..].copy_from_slice(&ymm_hi128[index].to_le_bytes());
+        Some(result)
+    }
+
+    /// Sets `YMMn`, i.e. `XMMn` and the upper half.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not lower than 16.
+    pub fn set_ymm(&mut self, index: usize, value: [u8; 32]) -> Result<(), XenError> {
+        let ymm_hi128 = self
+            .ymm_hi128
+            .as_mut()
+            .ok_or(XenError::Other("AVX is not enabled"))?;
+
+        self.xmm[index] = u128::from_le_bytes(value[..16].try_into().unwrap());
+        ymm_hi128[index] = u128::from_le_bytes(value[16..].try_into().unwrap());
+        Ok(())
+    }
+
+    /// Returns `ZMMn`, or `None` if AVX-512 is not enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not lower than 32.
+    pub fn zmm(&self, index: usize) -> Option<[u8; 64]> {
+        let mut result = [0; 64];
+
+        match index {
+            0..16 => {
+                result[..32].copy_from_slice(&self.ymm(index)?);
+                result[32..].copy_from_slice(&self.zmm_hi256.as_ref()?[index]);
+            }
+            16..32 => result.copy_from_slice(&self.hi16_zmm.as_ref()?[index - 16]),
+            _ => panic!("invalid ZMM register index {index}"),
+        }
+
+        Some(result)
+    }
+
+    /// Sets `ZMMn`, including the overlapping `XMMn` and `YMMn`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not lower than 32.
+    pub fn set_zmm(&mut self, index: usize, value: [u8; 64]) -> Result<(), XenError> {
+        const NOT_ENABLED: XenError = XenError::Other("AVX-512 is not enabled");
+
+        match index {
+            0..16 => {
+                if self.ymm_hi128.is_none() {
+                    return Err(NOT_ENABLED);
+                }
+
+                let zmm_hi256 = self.zmm_hi256.as_mut().ok_or(NOT_ENABLED)?;
+                zmm_hi256[index].copy_from_slice(&value[32..]);
+                self.set_ymm(index, value[..32].try_into().unwrap())
+            }
+            16..32 => {
+                let hi16_zmm = self.hi16_zmm.as_mut().ok_or(NOT_ENABLED)?;
+                hi16_zmm[index - 16] = value;
+                Ok(())
+            }
+            _ => panic!("invalid ZMM register index {index}"),
+        }
+    }
+}
+
+impl PartialEq for ExtendedState {
+    fn eq(&self, other: &Self) -> bool {
+        self.xcr0 == other.xcr0 && self.to_xsave() == other.to_xsave()
+    }
+}
+
+impl Eq for ExtendedState {}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of a standard format area with all AVX-512 components.
+    const AREA_SIZE: usize = HI16_ZMM_OFFSET + 1024;
+
+    const AVX512: XsaveComponents = XsaveComponents::X87
+        .union(XsaveComponents::SSE)
+        .union(XsaveComponents::AVX)
+        .union(XsaveComponents::OPMASK)
+        .union(XsaveComponents::ZMM_HI256)
+        .union(XsaveComponents::HI16_ZMM);
+
+    /// Returns an area filled with a byte pattern and an empty header
+    /// except for `xstate_bv`.
+    fn area(xstate_bv: XsaveComponents) -> Vec<u8> {
+        let mut area = (0..AREA_SIZE)
+            .map(|offset| (offset * 7 + offset / 251) as u8)
+            .collect::<Vec<_>>();
+
+        area[LEGACY_SIZE..HEADER_END].fill(0);
+        area[XSTATE_BV..XSTATE_BV + 8].copy_from_slice(&xstate_bv.bits().to_le_bytes());
+        area
+    }
+
+    fn bytes<const N: usize>() -> [u8; N] {
+        std::array::from_fn(|index| 0x80 | index as u8)
+    }
+
+    #[test]
+    fn round_trip() {
+        let area = area(AVX512);
+        let state = ExtendedState::from_xsave(AVX512.bits(), &area).unwrap();
+
+        assert_eq!(state.fpu.fcw, read_u16(&area, 0));
+        assert_eq!(state.fpu.fip, read_u64(&area, 8));
+        assert_eq!(state.fpu.st[7].0, area[32 + 7 * 16..][..10]);
+        assert_eq!(state.mxcsr, read_u32(&area, 24));
+        assert_eq!(state.xmm[15], read_u128(&area, 160 + 15 * 16));
+        assert_eq!(
+            state.ymm_hi128.unwrap()[1],
+            read_u128(&area, AVX_OFFSET + 16)
+        );
+        assert_eq!(
+            state.opmask.unwrap()[2],
+            read_u64(&area, OPMASK_OFFSET + 16)
+        );
+        assert_eq!(
+            state.zmm_hi256.unwrap()[3],
+            area[ZMM_HI256_OFFSET + 3 * 32..][..32]
+        );
+        assert_eq!(
+            state.hi16_zmm.unwrap()[4],
+            area[HI16_ZMM_OFFSET + 4 * 64..][..64]
+        );
+
+        let zmm = state.zmm(5).unwrap();
+        assert_eq!(zmm[..16], area[160 + 5 * 16..][..16]);
+        assert_eq!(zmm[16..32], area[AVX_OFFSET + 5 * 16..][..16]);
+        assert_eq!(zmm[32..], area[ZMM_HI256_OFFSET + 5 * 32..][..32]);
+
+        assert_eq!(state.to_xsave(), area);
+        assert_eq!(
+            ExtendedState::from_xsave(AVX512.bits(), &state.to_xsave()).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn initial_state() {
+        let xcr0 = XsaveComponents::X87 | XsaveComponents::SSE | XsaveComponents::AVX;
+        let area = area(XsaveComponents::empty());
+        let state = ExtendedState::from_xsave(xcr0.bits(), &area).unwrap();
+
+        assert_eq!(
+            state.fpu,
+            FpuRegisters {
+                fcw: FCW_INIT,
+                ..Default::default()
+            }
+        );
+        assert_eq!(state.xmm, [0; 16]);
+        assert_eq!(state.ymm_hi128, Some([0; 16]));
+        assert_eq!(state.opmask, None);
+        assert_eq!(state.zmm_hi256, None);
+
+        // MXCSR is valid regardless of `XSTATE_BV`.
+        assert_eq!(state.mxcsr, read_u32(&area, 24));
+
+        let area = state.to_xsave();
+        assert_eq!(read_u64(&area, XSTATE_BV), xcr0.bits());
+        assert_eq!(read_u16(&area, 0), FCW_INIT);
+        assert_eq!(read_u128(&area, 160), 0);
+        assert_eq!(read_u128(&area, AVX_OFFSET), 0);
+    }
+
+    #[test]
+    fn set_zmm() {
+        let mut state = ExtendedState::from_xsave(AVX512.bits(), &area(AVX512)).unwrap();
+
+        let value = bytes::<64>();
+        state.set_zmm(3, value).unwrap();
+        assert_eq!(state.xmm[3].to_le_bytes(), value[..16]);
+        assert_eq!(state.ymm(3).unwrap(), value[..32]);
+        assert_eq!(state.zmm(3).unwrap(), value);
+
+        state.set_zmm(20, value).unwrap();
+        assert_eq!(state.hi16_zmm.unwrap()[4], value);
+
+        let area = state.to_xsave();
+        assert_eq!(area[160 + 3 * 16..][..16], value[..16]);
+        assert_eq!(area[AVX_OFFSET + 3 * 16..][..16], value[16..32]);
+        assert_eq!(area[ZMM_HI256_OFFSET + 3 * 32..][..32], value[32..]);
+        assert_eq!(area[HI16_ZMM_OFFSET + 4 * 64..][..64], value);
+    }
+
+    #[test]
+    fn set_zmm_without_avx512() {
+        let xcr0 = XsaveComponents::X87 | XsaveComponents::SSE | XsaveComponents::AVX;
+        let mut state = ExtendedState::from_xsave(xcr0.bits(), &area(xcr0)).unwrap();
+        let xmm = state.xmm;
+
+        assert!(state.set_zmm(0, bytes()).is_err());
+        assert!(state.set_zmm(16, bytes()).is_err());
+        assert_eq!(state.xmm, xmm);
+
+        state.set_ymm(0, bytes()).unwrap();
+        assert_eq!(state.ymm(0).unwrap(), bytes::<32>());
+    }
+
+    #[test]
+    fn malformed_area() {
+        let area = area(AVX512);
+
+        let err = ExtendedState::from_xsave(AVX512.bits(), &area[..HEADER_END - 1]).unwrap_err();
+        assert!(matches!(err, XenError::Other("XSAVE area is too small")));
+
+        // The header fits, but the last component does not.
+        let err = ExtendedState::from_xsave(AVX512.bits(), &area[..AREA_SIZE - 1]).unwrap_err();
+        assert!(matches!(err, XenError::Other("XSAVE area is too small")));
+
+        let mut compacted = area.clone();
+        compacted[XCOMP_BV..XCOMP_BV + 8]
+            .copy_from_slice(&(XCOMP_BV_COMPACTED | AVX512.bits()).to_le_bytes());
+        let err = ExtendedState::from_xsave(AVX512.bits(), &compacted).unwrap_err();
+        assert!(matches!(
+            err,
+            XenError::Other("compacted XSAVE format is not supported")
+        ));
+    }
+}
@@ -0,0 +1,552 @@
+use std::collections::{BTreeMap, HashMap};
+
+use xen_sys::{
+    __HVM_SAVE_TYPE_CPU, __HVM_SAVE_TYPE_LAPIC, __HVM_SAVE_TYPE_LAPIC_REGS, CPU_XSAVE_CODE,
+    HVM_FILE_MAGIC, HVM_FILE_VERSION, HVM_PARAM_ALTP2M, XEN_DOMCTL_ALTP2M_mode_mask,
+    XEN_DOMCTL_CDF_hap, XEN_DOMCTL_CDF_hvm, XEN_DOMINF_hap, XEN_DOMINF_hvm_guest,
+    XEN_DOMINF_paused, XEN_DOMINF_running, XEN_DOMINF_shutdown, XEN_DOMINF_shutdownshift,
+    hvm_hw_cpu, hvm_hw_lapic, hvm_hw_lapic_regs, hvm_save_descriptor, hvm_save_header,
+    vm_event_regs_x86, xen_domctl_createdomain, xen_domctl_getdomaininfo,
+};
+
+use super::{errno, ring::MockRing};
+use crate::{
+    MemoryAccess, VcpuId, XenDomainId, XenError, XenX86EventType, XenX86ExceptionVector,
+    arch::x86::PhysicalMemory,
+    consts::{PAGE_SHIFT, PAGE_SIZE},
+    ctrl::{VmEvent, XenDomainConfig},
+    macros::{as_bytes, from_bytes},
+};
+
+pub(super) const HVM_SAVE_CODE_END: u16 = 0;
+pub(super) const HVM_SAVE_CODE_HEADER: u16 = 1;
+
+pub(super) fn hvm_save_code_cpu() -> u16 {
+    size_of_val(&__HVM_SAVE_TYPE_CPU::default().c) as u16
+}
+
+/// `XCR0` of the mock vCPUs: x87, SSE, AVX and AVX-512.
+pub(super) const MOCK_XCR0: u64 = 0xe7;
+
+/// Size of the standard format XSAVE area for [`MOCK_XCR0`], ending with
+/// the `Hi16_ZMM` component.
+const MOCK_XSAVE_SIZE: usize = 2688;
+
+/// Size of the `xfeature_mask`, `xcr0` and `xcr0_accum` fields preceding the
+/// XSAVE area in the `CPU_XSAVE` record.
+pub(super) const XSAVE_RECORD_HEADER: usize = 24;
+
+fn hvm_save_code_lapic() -> u16 {
+    size_of_val(&__HVM_SAVE_TYPE_LAPIC::default().c) as u16
+}
+
+fn hvm_save_code_lapic_regs() -> u16 {
+    size_of_val(&__HVM_SAVE_TYPE_LAPIC_REGS::default().c) as u16
+}
+
+/// Number of physical CPUs of the mock host.
+pub(super) const MOCK_NR_CPUS: u32 = 4;
+
+/// Number of NUMA nodes of the mock host.
+pub(super) const MOCK_NR_NODES: u32 = 1;
+
+/// Returns the CPU map of all physical CPUs.
+pub(super) fn online_cpus() -> Vec<u8> {
+    bitmap(MOCK_NR_CPUS)
+}
+
+/// Returns the node map of all NUMA nodes.
+pub(super) fn online_nodes() -> Vec<u8> {
+    bitmap(MOCK_NR_NODES)
+}
+
+fn bitmap(count: u32) -> Vec<u8> {
+    let mut result = vec![0; count.div_ceil(8) as usize];
+    for index in 0..count {
+        result[index as usize / 8] |= 1 << (index % 8);
+    }
+    result
+}
+
+/// Builds the `CPU_XSAVE` record of a vCPU in its initial state.
+fn xsave_record() -> Vec<u8> {
+    let mut result = vec![0; XSAVE_RECORD_HEADER + MOCK_XSAVE_SIZE];
+    result[0..8].copy_from_slice(&MOCK_XCR0.to_le_bytes());
+    result[8..16].copy_from_slice(&MOCK_XCR0.to_le_bytes());
+    result[16..24].copy_from_slice(&MOCK_XCR0.to_le_bytes());
+
+    let area = &mut result[XSAVE_RECORD_HEADER..];
+    area[0..2].copy_from_slice(&0x037fu16.to_le_bytes()); // FCW
+    area[24..28].copy_from_slice(&0x1f80u32.to_le_bytes()); // MXCSR
+    area[28..32].copy_from_slice(&0xffffu32.to_le_bytes()); // MXCSR_MASK
+    area[512..520].copy_from_slice(&0b11u64.to_le_bytes()); // XSTATE_BV
+    result
+}
+
+/// An event injected through the device model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockInjectedEvent {
+    pub vcpu: VcpuId,
+    pub vector: XenX86ExceptionVector,
+    pub event_type: XenX86EventType,
+    pub error_code: u32,
+    pub instruction_length: u8,
+    pub extra: u64,
+}
+
+#[derive(Debug)]
+pub(super) struct MockVcpu {
+    pub(super) paused: bool,
+    pub(super) view: u16,
+    pub(super) hard_affinity: Vec<u8>,
+    pub(super) soft_affinity: Vec<u8>,
+}
+
+impl Default for MockVcpu {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            view: 0,
+            hard_affinity: online_cpus(),
+            soft_affinity: online_cpus(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct MockView {
+    pub(super) default_access: MemoryAccess,
+    pub(super) access: HashMap<u64, MemoryAccess>,
+    pub(super) remap: HashMap<u64, u64>,
+}
+
+#[derive(Debug)]
+pub(super) struct MockMonitor {
+    pub(super) port: u32,
+    pub(super) ring: Option<MockRing>,
+    pub(super) reasons: u32,
+}
+
+/// Event channel port of a mock domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MockPort {
+    /// Waits for the domain to bind it.
+    Unbound(XenDomainId),
+
+    /// Bound to a local port of a control domain event channel.
+    Interdomain(XenDomainId, u32),
+}
+
+#[derive(Debug)]
+pub(super) struct MockDomain {
+    pub(super) max_pages: u64,
+    pub(super) ram: BTreeMap<u64, Box<[u8]>>,
+    pub(super) paused: u32,
+    pub(super) vcpus: Vec<MockVcpu>,
+    pub(super) records: BTreeMap<(u16, u16), Vec<u8>>,
+    pub(super) access_required: bool,
+    pub(super) mem_access: HashMap<u64, MemoryAccess>,
+    pub(super) altp2m: Option<BTreeMap<u16, MockView>>,
+    pub(super) monitor: Option<MockMonitor>,
+    pub(super) responses: Vec<VmEvent>,
+    pub(super) injected: Vec<MockInjectedEvent>,
+    pub(super) config: xen_domctl_createdomain,
+    pub(super) shutdown: Option<u32>,
+    pub(super) node_affinity: Vec<u8>,
+    pub(super) hvm_params: BTreeMap<u32, u64>,
+    pub(super) ports: BTreeMap<u32, MockPort>,
+
+    /// The domain was introduced to the store.
+    pub(super) introduced: bool,
+}
+
+impl MockDomain {
+    pub(super) fn new(pages: u64, vcpus: u16) -> Self {
+        let config = XenDomainConfig::hvm().max_vcpus(vcpus as u32).into();
+        let mut domain = Self::with_config(config);
+
+        domain.max_pages = pages;
+        domain.ram = (0..pages)
+            .map(|gfn| (gfn, vec![0; PAGE_SIZE as usize].into_boxed_slice()))
+            .collect();
+        domain.paused = 0;
+        domain.set_vcpus(vcpus);
+        domain
+    }
+
+    /// Creates a paused domain without memory and vCPUs, like
+    /// `xc_domain_create` does.
+    pub(super) fn with_config(config: xen_domctl_createdomain) -> Self {
+        Self {
+            max_pages: 0,
+            ram: BTreeMap::new(),
+            paused: 1,
+            vcpus: Vec::new(),
+            records: BTreeMap::new(),
+            access_required: false,
+            mem_access: HashMap::new(),
+            altp2m: None,
+            monitor: None,
+            responses: Vec::new(),
+            injected: Vec::new(),
+            config,
+            shutdown: None,
+            node_affinity: online_nodes(),
+            hvm_params: BTreeMap::from([(
+                HVM_PARAM_ALTP2M,
+                (config.altp2m.opts as u32 & XEN_DOMCTL_ALTP2M_mode_mask) as u64,
+            )]),
+            ports: BTreeMap::new(),
+            introduced: false,
+        }
+    }
+
+    /// Allocates the lowest free event channel port, starting at 1.
+    pub(super) fn alloc_port(&mut self, port: MockPort) -> u32 {
+        let number = (1..)
+            .find(|number| !self.ports.contains_key(number))
+            .expect("event channel ports exhausted");
+
+        self.ports.insert(number, port);
+        number
+    }
+
+    /// Brings the domain up to `vcpus` vCPUs.
+    pub(super) fn set_vcpus(&mut self, vcpus: u16) {
+        for vcpu in self.vcpus.len() as u16..vcpus {
+            // Same as `hvm_vcpu_reset_state`.
+            let cpu = hvm_hw_cpu {
+                rflags: 0x2,
+                cr0: 0x10,
+                cs_limit: 0xffff,
+                cs_arbytes: 0x9b,
+                ds_limit: 0xffff,
+                ds_arbytes: 0x93,
+                es_limit: 0xffff,
+                es_arbytes: 0x93,
+                fs_limit: 0xffff,
+                fs_arbytes: 0x93,
+                gs_limit: 0xffff,
+                gs_arbytes: 0x93,
+                ss_limit: 0xffff,
+                ss_arbytes: 0x93,
+                tr_limit: 0xffff,
+                tr_arbytes: 0x8b,
+                ldtr_limit: 0xffff,
+                ldtr_arbytes: 0x82,
+                gdtr_limit: 0xffff,
+                idtr_limit: 0xffff,
+                ..unsafe { std::mem::zeroed() }
+            };
+            let lapic = unsafe { std::mem::zeroed::<hvm_hw_lapic>() };
+            let lapic_regs = unsafe { std::mem::zeroed::<hvm_hw_lapic_regs>() };
+
+            self.records
+                .insert((hvm_save_code_cpu(), vcpu), as_bytes(&cpu).to_vec());
+            self.records
+                .insert((hvm_save_code_lapic(), vcpu), as_bytes(&lapic).to_vec());
+            self.records.insert(
+                (hvm_save_code_lapic_regs(), vcpu),
+                as_bytes(&lapic_regs).to_vec(),
+            );
+            self.records
+                .insert((CPU_XSAVE_CODE as u16, vcpu), xsave_record());
+            self.vcpus.push(MockVcpu::default());
+        }
+    }
+
+    pub(super) fn info(&self, domain_id: XenDomainId) -> xen_domctl_getdomaininfo {
+        let mut flags = 0;
+        if self.config.flags & XEN_DOMCTL_CDF_hvm != 0 {
+            flags |= XEN_DOMINF_hvm_guest;
+        }
+        if self.config.flags & XEN_DOMCTL_CDF_hap != 0 {
+            flags |= XEN_DOMINF_hap;
+        }
+
+        if let Some(reason) = self.shutdown {
+            flags |= XEN_DOMINF_shutdown | (reason << XEN_DOMINF_shutdownshift);
+        }
+        else if self.paused > 0 {
+            flags |= XEN_DOMINF_paused;
+        }
+        else {
+            flags |= XEN_DOMINF_running;
+        }
+
+        xen_domctl_getdomaininfo {
+            domain: domain_id.0 as u16,
+            flags,
+            tot_pages: self.ram.len() as u64,
+            max_pages: self.max_pages,
+            nr_online_vcpus: self.vcpus.len() as u32,
+            max_vcpu_id: self.vcpus.len().saturating_sub(1) as u32,
+            ssidref: self.config.ssidref,
+            handle: self.config.handle,
+            cpupool: self.config.cpupool_id,
+            arch_config: self.config.arch,
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn vcpu(&self, vcpu: VcpuId) -> Result<&MockVcpu, XenError> {
+        self.vcpus.get(vcpu.0 as usize).ok_or(errno(libc::EINVAL))
+    }
+
+    pub(super) fn vcpu_mut(&mut self, vcpu: VcpuId) -> Result<&mut MockVcpu, XenError> {
+        self.vcpus
+            .get_mut(vcpu.0 as usize)
+            .ok_or(errno(libc::EINVAL))
+    }
+
+    //
+    // Memory
+    //
+
+    /// Resolves a gfn through the altp2m remapping of `view`.
+    pub(super) fn translate(&self, view: u16, gfn: u64) -> u64 {
+        self.view(view)
+            .and_then(|view| view.remap.get(&gfn).copied())
+            .unwrap_or(gfn)
+    }
+
+    pub(super) fn read_physical(&self, gpa: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        let mut offset = 0;
+
+        while offset < buffer.len() {
+            let address = gpa + offset as u64;
+            let gfn = address >> PAGE_SHIFT;
+            let page_offset = (address & (PAGE_SIZE - 1)) as usize;
+            let size = std::cmp::min(buffer.len() - offset, PAGE_SIZE as usize - page_offset);
+
+            let page = self.ram.get(&gfn).ok_or(errno(libc::EFAULT))?;
+            buffer[offset..offset + size].copy_from_slice(&page[page_offset..page_offset + size]);
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn write_physical(&mut self, gpa: u64, data: &[u8]) -> Result<(), XenError> {
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let address = gpa + offset as u64;
+            let gfn = address >> PAGE_SHIFT;
+            let page_offset = (address & (PAGE_SIZE - 1)) as usize;
+            let size = std::cmp::min(data.len() - offset, PAGE_SIZE as usize - page_offset);
+
+            let page = self.ram.get_mut(&gfn).ok_or(errno(libc::EFAULT))?;
+            page[page_offset..page_offset + size].copy_from_slice(&data[offset..offset + size]);
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    //
+    // Permissions
+    //
+
+    pub(super) fn view(&self, view_id: u16) -> Option<&MockView> {
+        self.altp2m.as_ref().and_then(|views| views.get(&view_id))
+    }
+
+    pub(super) fn view_mut(&mut self, view_id: u16) -> Result<&mut MockView, XenError> {
+        self.altp2m
+            .as_mut()
+            .ok_or(errno(libc::EOPNOTSUPP))?
+            .get_mut(&view_id)
+            .ok_or(errno(libc::EINVAL))
+    }
+
+    pub(super) fn host_access(&self, gfn: u64) -> MemoryAccess {
+        self.mem_access
+            .get(&gfn)
+            .copied()
+            .unwrap_or(MemoryAccess::RWX)
+    }
+
+    /// Returns the access permissions of `gfn` in the view `view_id`.
+    pub(super) fn access(&self, view_id: u16, gfn: u64) -> MemoryAccess {
+        match self.view(view_id) {
+            Some(view) => view
+                .access
+                .get(&gfn)
+                .copied()
+                .unwrap_or(view.default_access),
+            None => self.host_access(gfn),
+        }
+    }
+
+    //
+    // HVM context
+    //
+
+    pub(super) fn record(&self, typecode: u16, instance: u16) -> Result<&[u8], XenError> {
+        self.records
+            .get(&(typecode, instance))
+            .map(Vec::as_slice)
+            .ok_or(errno(libc::ENOENT))
+    }
+
+    pub(super) fn cpu(&self, vcpu: VcpuId) -> Result<hvm_hw_cpu, XenError> {
+        Ok(from_bytes(self.record(hvm_save_code_cpu(), vcpu.0)?))
+    }
+
+    pub(super) fn set_cpu(&mut self, vcpu: VcpuId, cpu: &hvm_hw_cpu) -> Result<(), XenError> {
+        self.vcpu(vcpu)?;
+        self.records
+            .insert((hvm_save_code_cpu(), vcpu.0), as_bytes(cpu).to_vec());
+        Ok(())
+    }
+
+    /// Serializes the domain state the same way `xc_domain_hvm_getcontext`
+    /// does: a header record, all other records and an end marker.
+    pub(super) fn hvm_context(&self) -> Vec<u8> {
+        fn push(result: &mut Vec<u8>, typecode: u16, instance: u16, data: &[u8]) {
+            let descriptor = hvm_save_descriptor {
+                typecode,
+                instance,
+                length: data.len() as u32,
+            };
+
+            result.extend_from_slice(as_bytes(&descriptor));
+            result.extend_from_slice(data);
+        }
+
+        let header = hvm_save_header {
+            magic: HVM_FILE_MAGIC,
+            version: HVM_FILE_VERSION,
+            ..Default::default()
+        };
+
+        let mut result = Vec::new();
+        push(&mut result, HVM_SAVE_CODE_HEADER, 0, as_bytes(&header));
+        for (&(typecode, instance), data) in &self.records {
+            push(&mut result, typecode, instance, data);
+        }
+        push(&mut result, HVM_SAVE_CODE_END, 0, &[]);
+        result
+    }
+
+    pub(super) fn set_hvm_context(&mut self, buffer: &[u8]) -> Result<(), XenError> {
+        let mut records = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let data = buffer
+                .get(offset..offset + size_of::<hvm_save_descriptor>())
+                .ok_or(errno(libc::EINVAL))?;
+            let descriptor = from_bytes::<hvm_save_descriptor>(data);
+            offset += size_of::<hvm_save_descriptor>();
+
+            let data = buffer
+                .get(offset..offset + descriptor.length as usize)
+                .ok_or(errno(libc::EINVAL))?;
+            offset += descriptor.length as usize;
+
+            match descriptor.typecode {
+                HVM_SAVE_CODE_END => break,
+                HVM_SAVE_CODE_HEADER => {}
+                typecode => records.push(((typecode, descriptor.instance), data.to_vec())),
+            }
+        }
+
+        self.records.extend(records);
+        Ok(())
+    }
+
+    /// Builds the register block Xen attaches to every `vm_event` request.
+    pub(super) fn event_registers(&self, vcpu: VcpuId) -> Result<vm_event_regs_x86, XenError> {
+        let cpu = self.cpu(vcpu)?;
+
+        Ok(vm_event_regs_x86 {
+            rax: cpu.rax,
+            rcx: cpu.rcx,
+            rdx: cpu.rdx,
+            rbx: cpu.rbx,
+            rsp: cpu.rsp,
+            rbp: cpu.rbp,
+            rsi: cpu.rsi,
+            rdi: cpu.rdi,
+            r8: cpu.r8,
+            r9: cpu.r9,
+            r10: cpu.r10,
+            r11: cpu.r11,
+            r12: cpu.r12,
+            r13: cpu.r13,
+            r14: cpu.r14,
+            r15: cpu.r15,
+            rflags: cpu.rflags,
+            dr6: cpu.dr6,
+            dr7: cpu.dr7,
+            rip: cpu.rip,
+            cr0: cpu.cr0,
+            cr2: cpu.cr2,
+            cr3: cpu.cr3,
+            cr4: cpu.cr4,
+            sysenter_cs: cpu.sysenter_cs,
+            sysenter_esp: cpu.sysenter_esp,
+            sysenter_eip: cpu.sysenter_eip,
+            msr_efer: cpu.msr_efer,
+            msr_star: cpu.msr_star,
+            msr_lstar: cpu.msr_lstar,
+            gdtr_base: cpu.gdtr_base,
+            npt_base: 0,
+            vmtrace_pos: !0,
+            cs_base: cpu.cs_base as u32,
+            ss_base: cpu.ss_base as u32,
+            ds_base: cpu.ds_base as u32,
+            es_base: cpu.es_base as u32,
+            fs_base: cpu.fs_base,
+            gs_base: cpu.gs_base,
+            shadow_gs: cpu.shadow_gs,
+            gdtr_limit: cpu.gdtr_limit as u16,
+            cs_sel: cpu.cs_sel as u16,
+            ss_sel: cpu.ss_sel as u16,
+            ds_sel: cpu.ds_sel as u16,
+            es_sel: cpu.es_sel as u16,
+            fs_sel: cpu.fs_sel as u16,
+            gs_sel: cpu.gs_sel as u16,
+            ..Default::default()
+        })
+    }
+
+    /// Applies the registers of a `VM_EVENT_FLAG_SET_REGISTERS` response.
+    ///
+    /// Like Xen, only the general purpose registers, `rflags` and `rip` are
+    /// written back.
+    pub(super) fn apply_event_registers(
+        &mut self,
+        vcpu: VcpuId,
+        regs: &vm_event_regs_x86,
+    ) -> Result<(), XenError> {
+        let mut cpu = self.cpu(vcpu)?;
+        cpu.rax = regs.rax;
+        cpu.rcx = regs.rcx;
+        cpu.rdx = regs.rdx;
+        cpu.rbx = regs.rbx;
+        cpu.rsp = regs.rsp;
+        cpu.rbp = regs.rbp;
+        cpu.rsi = regs.rsi;
+        cpu.rdi = regs.rdi;
+        cpu.r8 = regs.r8;
+        cpu.r9 = regs.r9;
+        cpu.r10 = regs.r10;
+        cpu.r11 = regs.r11;
+        cpu.r12 = regs.r12;
+        cpu.r13 = regs.r13;
+        cpu.r14 = regs.r14;
+        cpu.r15 = regs.r15;
+        cpu.rflags = regs.rflags;
+        cpu.rip = regs.rip;
+        self.set_cpu(vcpu, &cpu)
+    }
+}
+
+impl PhysicalMemory for MockDomain {
+    fn read_physical(&self, address: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        MockDomain::read_physical(self, address, buffer)
+    }
+}
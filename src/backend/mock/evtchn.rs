@@ -0,0 +1,211 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{Mutex, PoisonError},
+};
+
+use xen_sys::NR_VIRQS;
+
+use super::{DOMID_FIRST_RESERVED, MockHypervisor, errno};
+use crate::{XenDomainId, XenError, backend::XenEventChannelBackend};
+
+/// What a local port of a mock event channel is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MockBinding {
+    Interdomain(XenDomainId, u32),
+    Unbound(XenDomainId),
+    Virq(u32),
+}
+
+#[derive(Debug, Default)]
+struct MockEventChannelState {
+    next_port: u32,
+    bindings: HashMap<u32, MockBinding>,
+    pending: VecDeque<u32>,
+    restricted: Option<XenDomainId>,
+}
+
+/// Event channel of the mock hypervisor.
+///
+/// Pending ports are signalled through an `eventfd` in semaphore mode, so
+/// the file descriptor can be polled just like `/dev/xen/evtchn`.
+#[derive(Debug)]
+pub struct MockEventChannel {
+    hypervisor: MockHypervisor,
+    eventfd: OwnedFd,
+    state: Mutex<MockEventChannelState>,
+}
+
+impl MockEventChannel {
+    pub(super) fn new(hypervisor: MockHypervisor) -> Result<Self, XenError> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_SEMAPHORE) };
+        if fd < 0 {
+            return Err(XenError::Io(std::io::Error::last_os_error()));
+        }
+
+        Ok(Self {
+            hypervisor,
+            eventfd: unsafe { OwnedFd::from_raw_fd(fd) },
+            state: Mutex::new(MockEventChannelState {
+                next_port: 1,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// Returns the local port bound to `remote_port` of `domain_id`.
+    pub(super) fn local_port(&self, domain_id: XenDomainId, remote_port: u32) -> Option<u32> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state
+            .bindings
+            .iter()
+            .find(|(_, binding)| **binding == MockBinding::Interdomain(domain_id, remote_port))
+            .map(|(local_port, _)| *local_port)
+    }
+
+    /// Returns the local ports bound to `virq`.
+    pub(super) fn virq_ports(&self, virq: u32) -> Vec<u32> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state
+            .bindings
+            .iter()
+            .filter(|(_, binding)| **binding == MockBinding::Virq(virq))
+            .map(|(local_port, _)| *local_port)
+            .collect()
+    }
+
+    /// Reserves a local port for `binding`, which the handle must be
+    /// allowed to bind.
+    fn reserve(&self, binding: MockBinding) -> Result<u32, XenError> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match (state.restricted, binding) {
+            (None, _) => {}
+            (Some(restricted), MockBinding::Interdomain(domain_id, _))
+                if restricted == domain_id => {}
+            (Some(_), _) => return Err(errno(libc::EACCES)),
+        }
+
+        let local_port = state.next_port;
+        state.next_port += 1;
+        Ok(local_port)
+    }
+
+    fn insert(&self, local_port: u32, binding: MockBinding) -> u32 {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.bindings.insert(local_port, binding);
+        local_port
+    }
+
+    /// Marks `local_port` as pending and wakes up a waiter.
+    pub(super) fn signal(&self, local_port: u32) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.pending.push_back(local_port);
+
+        let value = 1u64;
+        unsafe {
+            libc::write(
+                self.eventfd.as_raw_fd(),
+                &value as *const u64 as *const _,
+                size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl XenEventChannelBackend for MockEventChannel {
+    fn fd(&self) -> RawFd {
+        self.eventfd.as_raw_fd()
+    }
+
+    fn bind_interdomain(&self, domain_id: XenDomainId, remote_port: u32) -> Result<u32, XenError> {
+        let binding = MockBinding::Interdomain(domain_id, remote_port);
+        let local_port = self.reserve(binding)?;
+
+        // The channel state is not locked here, the hypervisor locks the
+        // channels when it signals them.
+        self.hypervisor
+            .connect_port(domain_id, remote_port, local_port)?;
+        Ok(self.insert(local_port, binding))
+    }
+
+    fn bind_unbound_port(&self, domain_id: XenDomainId) -> Result<u32, XenError> {
+        let binding = MockBinding::Unbound(domain_id);
+        let local_port = self.reserve(binding)?;
+        Ok(self.insert(local_port, binding))
+    }
+
+    fn bind_virq(&self, virq: u32) -> Result<u32, XenError> {
+        if virq >= NR_VIRQS {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let binding = MockBinding::Virq(virq);
+        let local_port = self.reserve(binding)?;
+        Ok(self.insert(local_port, binding))
+    }
+
+    fn restrict(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.restricted.is_some() {
+            return Err(errno(libc::EACCES));
+        }
+
+        if domain_id.0 == 0 || domain_id.0 >= DOMID_FIRST_RESERVED {
+            return Err(errno(libc::EINVAL));
+        }
+
+        state.restricted = Some(domain_id);
+        Ok(())
+    }
+
+    fn unbind(&self, port: u32) -> Result<(), XenError> {
+        let binding = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.bindings.remove(&port).ok_or(errno(libc::EINVAL))?
+        };
+
+        if let MockBinding::Interdomain(domain_id, remote_port) = binding {
+            self.hypervisor.disconnect_port(domain_id, remote_port);
+        }
+
+        Ok(())
+    }
+
+    fn notify(&self, port: u32) -> Result<(), XenError> {
+        let binding = {
+            let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            *state.bindings.get(&port).ok_or(errno(libc::EINVAL))?
+        };
+
+        match binding {
+            MockBinding::Interdomain(domain_id, _) => self.hypervisor.process_responses(domain_id),
+            MockBinding::Unbound(_) | MockBinding::Virq(_) => Ok(()),
+        }
+    }
+
+    fn pending(&self) -> Result<u32, XenError> {
+        let mut value = 0u64;
+        let rc = unsafe {
+            libc::read(
+                self.eventfd.as_raw_fd(),
+                &mut value as *mut u64 as *mut _,
+                size_of::<u64>(),
+            )
+        };
+
+        if rc < 0 {
+            return Err(XenError::Io(std::io::Error::last_os_error()));
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.pending.pop_front().ok_or(errno(libc::EAGAIN))
+    }
+
+    fn unmask(&self, port: u32) -> Result<(), XenError> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.bindings.contains_key(&port) {
+            true => Ok(()),
+            false => Err(errno(libc::EINVAL)),
+        }
+    }
+}
@@ -0,0 +1,1767 @@
+//! In-memory mock hypervisor.
+//!
+//! [`MockHypervisor`] implements every backend trait without touching Xen.
+//! It simulates guest RAM, `mem_access` permissions, altp2m views, vCPU
+//! registers (as HVM save records) and the `vm_event` ring, which makes it
+//! possible to drive monitor loops deterministically in tests:
+//!
+//! ```
+//! use xen::{
+//!     MemoryAccess, VcpuId, XenDomainId, arch::x86::Amd64, backend::mock::MockHypervisor,
+//! };
+//!
+//! # fn main() -> Result<(), xen::XenError> {
+//! let hypervisor = MockHypervisor::new();
+//! hypervisor.create_domain(XenDomainId(1), "guest", 256, 1)?;
+//!
+//! let domain = hypervisor.control()?.domain::<Amd64>(XenDomainId(1))?;
+//! let (monitor, mut ring) = domain.monitor()?;
+//! let channel = monitor.channel()?;
+//!
+//! domain.set_mem_access(0x10, MemoryAccess::R)?;
+//! assert!(!hypervisor.guest_access(XenDomainId(1), VcpuId(0), 0x10000, MemoryAccess::W)?);
+//!
+//! channel.wait()?;
+//! let event = ring.get_request();
+//! ring.put_response(event);
+//! channel.notify()?;
+//!
+//! assert!(!hypervisor.is_vcpu_paused(XenDomainId(1), VcpuId(0))?);
+//! # Ok(())
+//! # }
+//! ```
+
+mod domain;
+mod evtchn;
+mod ring;
+mod store;
+
+use std::{
+    alloc::Layout,
+    collections::{BTreeMap, HashMap},
+    ffi::c_void,
+    ptr::NonNull,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+use xen_sys::{
+    CPU_XSAVE_CODE, EVTCHNSTAT_closed, EVTCHNSTAT_interdomain, EVTCHNSTAT_unbound, HVM_NR_PARAMS,
+    MEM_ACCESS_RWX, SHUTDOWN_MAX, VM_EVENT_INTERFACE_VERSION, VM_EVENT_REASON_CPUID,
+    VM_EVENT_REASON_DEBUG_EXCEPTION, VM_EVENT_REASON_DESCRIPTOR_ACCESS,
+    VM_EVENT_REASON_EMUL_UNIMPLEMENTED, VM_EVENT_REASON_GUEST_REQUEST, VM_EVENT_REASON_INTERRUPT,
+    VM_EVENT_REASON_IO_INSTRUCTION, VM_EVENT_REASON_MEM_ACCESS, VM_EVENT_REASON_MOV_TO_MSR,
+    VM_EVENT_REASON_PRIVILEGED_CALL, VM_EVENT_REASON_SINGLESTEP,
+    VM_EVENT_REASON_SOFTWARE_BREAKPOINT, VM_EVENT_REASON_VMEXIT, VM_EVENT_REASON_WRITE_CTRLREG,
+    XEN_SYSCTL_PHYSCAP_directio, XEN_SYSCTL_PHYSCAP_hap, XEN_SYSCTL_PHYSCAP_hvm,
+    XEN_SYSCTL_PHYSCAP_vmtrace, XEN_VCPUAFFINITY_HARD, XEN_VCPUAFFINITY_SOFT, XENVER_capabilities,
+    XENVER_changeset, XENVER_compile_info, XENVER_extraversion, XENVER_pagesize,
+    XENVER_platform_parameters, XENVER_version, XS_PERM_NONE, evtchn_status, vm_event_st,
+    xen_domctl_createdomain, xen_domctl_getdomaininfo, xen_domctl_getvcpuinfo, xen_sysctl_physinfo,
+    xs_permissions,
+};
+
+pub use self::{domain::MockInjectedEvent, evtchn::MockEventChannel, store::MockStore};
+use self::{
+    domain::{
+        MOCK_NR_CPUS, MOCK_NR_NODES, MOCK_XCR0, MockDomain, MockMonitor, MockPort, MockView,
+        online_cpus, online_nodes,
+    },
+    ring::{MockRing, alloc_ring_page, free_ring_page},
+    store::MockNode,
+};
+use super::{
+    XenControlBackend, XenDeviceModelBackend, XenEventChannelBackend, XenForeignMemoryBackend,
+};
+use crate::{
+    MemoryAccess, VcpuId, XenControl, XenDomainId, XenError, XenForeignMemory, XenInterface,
+    XenStore, XenX86EventType, XenX86ExceptionVector,
+    arch::x86::{PageTableWalker, Registers, TranslationAccess},
+    consts::{INVALID_GFN, PAGE_SHIFT, PAGE_SIZE},
+    ctrl::{VmEvent, VmEventCtrlReg, VmEventFlag, VmEventMemAccess, VmEventReason},
+    evtchn::{Virq, XenEventChannel},
+    foreignmemory::XenForeignMemoryProtection,
+};
+
+fn errno(code: i32) -> XenError {
+    XenError::Io(std::io::Error::from_raw_os_error(code))
+}
+
+/// The domain the mock event channels belong to.
+const DOMID_CONTROL: XenDomainId = XenDomainId(0);
+
+/// First domain ID reserved for special purposes (`DOMID_FIRST_RESERVED`).
+const DOMID_FIRST_RESERVED: u32 = 0x7ff0;
+
+#[derive(Debug)]
+struct MockMapping {
+    domain_id: XenDomainId,
+    gfns: Vec<u64>,
+    writable: bool,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    domains: BTreeMap<XenDomainId, MockDomain>,
+    channels: Vec<Weak<MockEventChannel>>,
+    mappings: HashMap<usize, MockMapping>,
+    store: BTreeMap<String, MockNode>,
+    stores: Vec<Weak<MockStore>>,
+}
+
+impl MockState {
+    fn domain(&self, domain_id: XenDomainId) -> Result<&MockDomain, XenError> {
+        self.domains.get(&domain_id).ok_or(errno(libc::ESRCH))
+    }
+
+    fn domain_mut(&mut self, domain_id: XenDomainId) -> Result<&mut MockDomain, XenError> {
+        self.domains.get_mut(&domain_id).ok_or(errno(libc::ESRCH))
+    }
+
+    fn remove_domain(&mut self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.domains.remove(&domain_id).ok_or(errno(libc::ESRCH))?;
+        self.store_rm(&format!("/local/domain/{domain_id}"))?;
+        self.domain_exception();
+        Ok(())
+    }
+
+    /// Reports a changed domain state like Xen and xenstored do, through
+    /// `VIRQ_DOM_EXC` and the `@releaseDomain` watch.
+    fn domain_exception(&mut self) {
+        self.signal_virq(Virq::DomExc.into());
+        self.fire_watches("@releaseDomain");
+    }
+
+    /// Fires the watches of every store connection covering `path`, or
+    /// only the watches on `path` itself if `exact` is set.
+    fn fire_watches_with(&mut self, path: &str, exact: bool) {
+        self.stores.retain(|store| store.strong_count() > 0);
+
+        for store in self.stores.iter().filter_map(Weak::upgrade) {
+            store.fire(path, exact);
+        }
+    }
+
+    fn fire_watches(&mut self, path: &str) {
+        self.fire_watches_with(path, false);
+    }
+
+    /// Signals every event channel bound to `remote_port` of `domain_id`.
+    fn signal(&mut self, domain_id: XenDomainId, remote_port: u32) {
+        self.channels.retain(|channel| channel.strong_count() > 0);
+
+        for channel in self.channels.iter().filter_map(Weak::upgrade) {
+            if let Some(local_port) = channel.local_port(domain_id, remote_port) {
+                channel.signal(local_port);
+            }
+        }
+    }
+
+    /// Signals every event channel bound to `virq`.
+    fn signal_virq(&mut self, virq: u32) {
+        self.channels.retain(|channel| channel.strong_count() > 0);
+
+        for channel in self.channels.iter().filter_map(Weak::upgrade) {
+            for local_port in channel.virq_ports(virq) {
+                channel.signal(local_port);
+            }
+        }
+    }
+}
+
+/// In-memory hypervisor implementing all backend traits.
+///
+/// Cloning is cheap, all clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MockHypervisor {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockHypervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a control interface backed by this hypervisor.
+    pub fn interface(&self) -> XenInterface {
+        XenInterface::with_backend(Arc::new(self.clone()))
+    }
+
+    /// Returns a [`XenControl`] backed by this hypervisor.
+    pub fn control(&self) -> Result<XenControl, XenError> {
+        XenControl::attach(self.interface())
+    }
+
+    /// Returns a foreign memory interface backed by this hypervisor.
+    pub fn foreign_memory(&self) -> XenForeignMemory {
+        XenForeignMemory::with_backend(Arc::new(self.clone()))
+    }
+
+    /// Opens a store connection backed by this hypervisor.
+    pub fn store(&self) -> XenStore {
+        let store = Arc::new(MockStore::new(self.clone()));
+        self.lock().stores.push(Arc::downgrade(&store));
+        XenStore::with_backend(store)
+    }
+
+    /// Opens an event channel backed by this hypervisor.
+    pub fn event_channel(&self) -> Result<XenEventChannel, XenError> {
+        Ok(XenEventChannel::with_backend(
+            XenControlBackend::open_event_channel(self)?,
+        ))
+    }
+
+    /// Creates an HVM domain with `pages` pages of zeroed RAM starting at
+    /// gfn 0 and `vcpus` vCPUs.
+    ///
+    /// The domain is introduced to the store, with a home path
+    /// `/local/domain/<id>` owned by the domain and its name published
+    /// under `/local/domain/<id>/name`.
+    pub fn create_domain(
+        &self,
+        domain_id: XenDomainId,
+        name: &str,
+        pages: u64,
+        vcpus: u16,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+
+        if state.domains.contains_key(&domain_id) {
+            return Err(errno(libc::EEXIST));
+        }
+
+        let mut domain = MockDomain::new(pages, vcpus);
+        domain.introduced = true;
+        state.domains.insert(domain_id, domain);
+
+        let path = format!("/local/domain/{domain_id}");
+        state.store_mkdir(&path)?;
+        state.store.get_mut(&path).unwrap().permissions = vec![xs_permissions {
+            id: domain_id.0,
+            perms: XS_PERM_NONE,
+        }];
+        state.store_write(&format!("{path}/name"), name.as_bytes())?;
+        state.fire_watches("@introduceDomain");
+        Ok(())
+    }
+
+    /// Removes a domain together with its store entries.
+    pub fn destroy_domain(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.lock().remove_domain(domain_id)
+    }
+
+    /// Raises a virtual interrupt on every event channel bound to it.
+    pub fn send_virq(&self, virq: Virq) {
+        self.lock().signal_virq(virq.into());
+    }
+
+    /// Writes a store entry, creating its missing parents.
+    pub fn store_write(&self, path: &str, value: &str) -> Result<(), XenError> {
+        self.lock().store_write(path, value.as_bytes())
+    }
+
+    /// Reads guest physical memory.
+    pub fn read_physical(
+        &self,
+        domain_id: XenDomainId,
+        gpa: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), XenError> {
+        self.lock().domain(domain_id)?.read_physical(gpa, buffer)
+    }
+
+    /// Writes guest physical memory.
+    pub fn write_physical(
+        &self,
+        domain_id: XenDomainId,
+        gpa: u64,
+        data: &[u8],
+    ) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.write_physical(gpa, data)
+    }
+
+    /// Returns the registers of a vCPU.
+    pub fn registers(&self, domain_id: XenDomainId, vcpu: VcpuId) -> Result<Registers, XenError> {
+        Ok(self.lock().domain(domain_id)?.cpu(vcpu)?.into())
+    }
+
+    /// Sets the registers of a vCPU.
+    pub fn set_registers(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        registers: &Registers,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        let mut cpu = domain.cpu(vcpu)?;
+        registers.copy_into(&mut cpu);
+        domain.set_cpu(vcpu, &cpu)
+    }
+
+    /// Returns `true` if the domain is paused.
+    pub fn is_paused(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        Ok(self.lock().domain(domain_id)?.paused > 0)
+    }
+
+    /// Returns `true` if the vCPU waits for a `vm_event` response.
+    pub fn is_vcpu_paused(&self, domain_id: XenDomainId, vcpu: VcpuId) -> Result<bool, XenError> {
+        Ok(self.lock().domain(domain_id)?.vcpu(vcpu)?.paused)
+    }
+
+    /// Returns the altp2m view the vCPU currently runs in.
+    pub fn active_view(&self, domain_id: XenDomainId, vcpu: VcpuId) -> Result<u16, XenError> {
+        Ok(self.lock().domain(domain_id)?.vcpu(vcpu)?.view)
+    }
+
+    /// Returns the access permissions the vCPU currently has for `gfn`.
+    pub fn effective_access(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        gfn: u64,
+    ) -> Result<MemoryAccess, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+        let view = domain.vcpu(vcpu)?.view;
+        Ok(domain.access(view, domain.translate(view, gfn)))
+    }
+
+    /// Returns the bitmask of `VM_EVENT_REASON_*` bits the monitor has
+    /// enabled through the `xc_monitor_*` calls.
+    pub fn monitored_reasons(&self, domain_id: XenDomainId) -> Result<u32, XenError> {
+        let state = self.lock();
+        let monitor = state
+            .domain(domain_id)?
+            .monitor
+            .as_ref()
+            .ok_or(errno(libc::ENODEV))?;
+        Ok(monitor.reasons)
+    }
+
+    /// Simulates a guest access of `vcpu` to `gpa`.
+    ///
+    /// Returns `true` if the access is permitted. Otherwise, if a monitor is
+    /// enabled, a synchronous `mem_access` event is sent, the vCPU is paused
+    /// until it receives a response and `false` is returned.
+    pub fn guest_access(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        gpa: u64,
+        access: MemoryAccess,
+    ) -> Result<bool, XenError> {
+        let (view, gfn, permitted) = {
+            let state = self.lock();
+            let domain = state.domain(domain_id)?;
+            let view = domain.vcpu(vcpu)?.view;
+            let gfn = domain.translate(view, gpa >> PAGE_SHIFT);
+            let permitted = domain
+                .access(view, gfn)
+                .contains(access & MemoryAccess::RWX);
+            (view, gfn, permitted)
+        };
+
+        if permitted {
+            return Ok(true);
+        }
+
+        let mut flags = VmEventFlag::VCPU_PAUSED;
+        if view != 0 {
+            flags |= VmEventFlag::ALTERNATE_P2M;
+        }
+
+        self.send_event(
+            domain_id,
+            VmEvent {
+                flags,
+                reason: VmEventReason::MemoryAccess(VmEventMemAccess {
+                    gfn,
+                    offset: gpa & (PAGE_SIZE - 1),
+                    gla: 0,
+                    flags: access.bits() as u32 & MEM_ACCESS_RWX,
+                }),
+                vcpu_id: vcpu,
+                altp2m_idx: view,
+                options: None,
+                data: None,
+            },
+        )?;
+
+        Ok(false)
+    }
+
+    /// Places a request on the `vm_event` ring and signals the monitor
+    /// event channel.
+    ///
+    /// If `event.data` is `None`, the current registers of the vCPU are
+    /// attached, like Xen does. `VM_EVENT_FLAG_VCPU_PAUSED` pauses the vCPU
+    /// until a response arrives.
+    pub fn send_event(&self, domain_id: XenDomainId, event: VmEvent) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        let vcpu = event.vcpu_id;
+        let paused = event.flags.contains(VmEventFlag::VCPU_PAUSED);
+        let attach_registers = event.data.is_none();
+
+        let mut request = vm_event_st::from(event);
+        if attach_registers {
+            request.data.regs.x86 = domain.event_registers(vcpu)?;
+        }
+
+        let monitor = domain.monitor.as_mut().ok_or(errno(libc::ENODEV))?;
+        let port = monitor.port;
+        monitor
+            .ring
+            .as_mut()
+            .ok_or(errno(libc::ENODEV))?
+            .push_request(request)?;
+
+        if paused {
+            domain.vcpu_mut(vcpu)?.paused = true;
+        }
+
+        state.signal(domain_id, port);
+        Ok(())
+    }
+
+    /// Takes all responses processed so far.
+    pub fn take_responses(&self, domain_id: XenDomainId) -> Result<Vec<VmEvent>, XenError> {
+        Ok(std::mem::take(
+            &mut self.lock().domain_mut(domain_id)?.responses,
+        ))
+    }
+
+    /// Takes all events injected through the device model so far.
+    pub fn take_injected_events(
+        &self,
+        domain_id: XenDomainId,
+    ) -> Result<Vec<MockInjectedEvent>, XenError> {
+        Ok(std::mem::take(
+            &mut self.lock().domain_mut(domain_id)?.injected,
+        ))
+    }
+
+    /// Consumes responses from the ring and applies them to the vCPUs.
+    pub(crate) fn process_responses(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        loop {
+            let response = match domain.monitor.as_mut().and_then(|m| m.ring.as_mut()) {
+                Some(ring) => ring.pop_response(),
+                None => return Err(errno(libc::ENODEV)),
+            };
+
+            let Some(response) = response
+            else {
+                break;
+            };
+
+            let flags = VmEventFlag::from_bits_truncate(response.flags);
+            let vcpu = VcpuId(response.vcpu_id as u16);
+
+            if flags.contains(VmEventFlag::SET_REGISTERS) {
+                domain.apply_event_registers(vcpu, unsafe { &response.data.regs.x86 })?;
+            }
+
+            let response = VmEvent::from(response);
+
+            let state = domain.vcpu_mut(vcpu)?;
+            if flags.contains(VmEventFlag::ALTERNATE_P2M) {
+                state.view = response.altp2m_idx;
+            }
+            if flags.contains(VmEventFlag::VCPU_PAUSED) {
+                state.paused = false;
+            }
+
+            domain.responses.push(response);
+        }
+
+        Ok(())
+    }
+
+    /// Connects `remote_port` of `domain_id`, which must be unbound and
+    /// reserved for the control domain, to `local_port`.
+    fn connect_port(
+        &self,
+        domain_id: XenDomainId,
+        remote_port: u32,
+        local_port: u32,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let port = state
+            .domain_mut(domain_id)?
+            .ports
+            .get_mut(&remote_port)
+            .ok_or(errno(libc::EINVAL))?;
+
+        match port {
+            MockPort::Unbound(DOMID_CONTROL) => {
+                *port = MockPort::Interdomain(DOMID_CONTROL, local_port);
+                Ok(())
+            }
+            _ => Err(errno(libc::EINVAL)),
+        }
+    }
+
+    /// Makes `remote_port` of `domain_id` unbound again after the control
+    /// domain closed its end.
+    fn disconnect_port(&self, domain_id: XenDomainId, remote_port: u32) {
+        let mut state = self.lock();
+        let Ok(domain) = state.domain_mut(domain_id)
+        else {
+            return;
+        };
+
+        if let Some(port @ MockPort::Interdomain(..)) = domain.ports.get_mut(&remote_port) {
+            *port = MockPort::Unbound(DOMID_CONTROL);
+        }
+    }
+
+    fn set_monitor_reason(
+        &self,
+        domain_id: XenDomainId,
+        reason: u32,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let monitor = state
+            .domain_mut(domain_id)?
+            .monitor
+            .as_mut()
+            .ok_or(errno(libc::ENODEV))?;
+
+        match enable {
+            true => monitor.reasons |= 1 << reason,
+            false => monitor.reasons &= !(1 << reason),
+        }
+
+        Ok(())
+    }
+}
+
+impl XenControlBackend for MockHypervisor {
+    fn open_event_channel(&self) -> Result<Arc<dyn XenEventChannelBackend>, XenError> {
+        let channel = Arc::new(MockEventChannel::new(self.clone())?);
+        self.lock().channels.push(Arc::downgrade(&channel));
+        Ok(channel)
+    }
+
+    fn open_device_model(&self) -> Result<Arc<dyn XenDeviceModelBackend>, XenError> {
+        Ok(Arc::new(self.clone()))
+    }
+
+    #[expect(non_upper_case_globals)]
+    fn version(&self, command: u32, buffer: Option<&mut [u8]>) -> Result<i32, XenError> {
+        fn copy(buffer: Option<&mut [u8]>, value: &[u8]) -> Result<i32, XenError> {
+            let buffer = buffer.ok_or(errno(libc::EINVAL))?;
+            let size = std::cmp::min(buffer.len(), value.len());
+            buffer.fill(0);
+            buffer[..size].copy_from_slice(&value[..size]);
+            Ok(0)
+        }
+
+        match command {
+            XENVER_version => Ok((4 << 16) | 21),
+            XENVER_extraversion => copy(buffer, b".0-mock"),
+            XENVER_compile_info => copy(buffer, b"rustc"),
+            XENVER_capabilities => copy(buffer, b"xen-3.0-x86_64 hvm-3.0-x86_32 hvm-3.0-x86_64"),
+            XENVER_changeset => copy(buffer, b"mock"),
+            XENVER_platform_parameters => copy(buffer, &0xffff_8000_0000_0000u64.to_ne_bytes()),
+            XENVER_pagesize => Ok(PAGE_SIZE as i32),
+            _ => Err(errno(libc::EOPNOTSUPP)),
+        }
+    }
+
+    fn vm_event_get_version(&self) -> Result<u32, XenError> {
+        Ok(VM_EVENT_INTERFACE_VERSION)
+    }
+
+    fn physinfo(&self) -> Result<xen_sysctl_physinfo, XenError> {
+        let mut hw_cap = [0; 8];
+        hw_cap[1] = 1 << 5; // VMX
+
+        Ok(xen_sysctl_physinfo {
+            threads_per_core: 1,
+            cores_per_socket: 1,
+            nr_cpus: MOCK_NR_CPUS,
+            max_cpu_id: MOCK_NR_CPUS - 1,
+            nr_nodes: MOCK_NR_NODES,
+            capabilities: XEN_SYSCTL_PHYSCAP_hvm
+                | XEN_SYSCTL_PHYSCAP_hap
+                | XEN_SYSCTL_PHYSCAP_directio
+                | XEN_SYSCTL_PHYSCAP_vmtrace,
+            total_pages: self
+                .lock()
+                .domains
+                .values()
+                .map(|d| d.ram.len() as u64)
+                .sum(),
+            hw_cap,
+            ..Default::default()
+        })
+    }
+
+    fn domain_getinfolist(
+        &self,
+        first_domain: XenDomainId,
+        info: &mut [xen_domctl_getdomaininfo],
+    ) -> Result<usize, XenError> {
+        let state = self.lock();
+        let domains = state.domains.range(first_domain..);
+
+        let mut count = 0;
+        for (info, (&domain_id, domain)) in info.iter_mut().zip(domains) {
+            *info = domain.info(domain_id);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn domain_create(
+        &self,
+        domain_id: XenDomainId,
+        config: &mut xen_domctl_createdomain,
+    ) -> Result<XenDomainId, XenError> {
+        let mut state = self.lock();
+
+        let domain_id = match domain_id {
+            XenDomainId(0) => (1..DOMID_FIRST_RESERVED)
+                .map(XenDomainId)
+                .find(|domain_id| !state.domains.contains_key(domain_id))
+                .ok_or(errno(libc::ENOMEM))?,
+            domain_id if domain_id.0 >= DOMID_FIRST_RESERVED => return Err(errno(libc::EINVAL)),
+            domain_id if state.domains.contains_key(&domain_id) => {
+                return Err(errno(libc::EEXIST));
+            }
+            domain_id => domain_id,
+        };
+
+        if config.max_vcpus == 0 {
+            return Err(errno(libc::EINVAL));
+        }
+
+        state
+            .domains
+            .insert(domain_id, MockDomain::with_config(*config));
+        Ok(domain_id)
+    }
+
+    fn domain_destroy(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.lock().remove_domain(domain_id)
+    }
+
+    fn domain_shutdown(&self, domain_id: XenDomainId, reason: u32) -> Result<(), XenError> {
+        if reason > SHUTDOWN_MAX {
+            return Err(errno(libc::EINVAL));
+        }
+
+        // As in Xen, the first reason sticks until the domain is reset.
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+        if domain.shutdown.is_none() {
+            domain.shutdown = Some(reason);
+            state.domain_exception();
+        }
+
+        Ok(())
+    }
+
+    fn domain_soft_reset(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.shutdown = None;
+        Ok(())
+    }
+
+    fn domain_max_vcpus(&self, domain_id: XenDomainId, max: u32) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        if max > domain.config.max_vcpus || max > u16::MAX as u32 {
+            return Err(errno(libc::EINVAL));
+        }
+
+        domain.set_vcpus(max as u16);
+        Ok(())
+    }
+
+    fn domain_sethandle(&self, domain_id: XenDomainId, handle: [u8; 16]) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.config.handle = handle;
+        Ok(())
+    }
+
+    fn cpumap_size(&self) -> Result<usize, XenError> {
+        Ok(online_cpus().len())
+    }
+
+    fn nodemap_size(&self) -> Result<usize, XenError> {
+        Ok(online_nodes().len())
+    }
+
+    fn vcpu_getinfo(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+    ) -> Result<xen_domctl_getvcpuinfo, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+
+        if vcpu.0 as u32 >= domain.config.max_vcpus {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let v = domain
+            .vcpus
+            .get(vcpu.0 as usize)
+            .ok_or(errno(libc::ESRCH))?;
+        let running = domain.paused == 0 && domain.shutdown.is_none() && !v.paused;
+        let cpu = (0..MOCK_NR_CPUS)
+            .find(|&cpu| v.hard_affinity[cpu as usize / 8] & (1 << (cpu % 8)) != 0)
+            .unwrap_or_default();
+
+        Ok(xen_domctl_getvcpuinfo {
+            vcpu: vcpu.0 as u32,
+            online: 1,
+            blocked: 0,
+            running: running as u8,
+            cpu_time: 0,
+            cpu,
+        })
+    }
+
+    fn vcpu_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError> {
+        let state = self.lock();
+        let v = state.domain(domain_id)?.vcpu(vcpu)?;
+
+        let size = online_cpus().len();
+        if hard.len() < size || soft.len() < size {
+            return Err(errno(libc::EINVAL));
+        }
+
+        if flags & XEN_VCPUAFFINITY_HARD != 0 {
+            hard[..size].copy_from_slice(&v.hard_affinity);
+        }
+
+        if flags & XEN_VCPUAFFINITY_SOFT != 0 {
+            soft[..size].copy_from_slice(&v.soft_affinity);
+        }
+
+        Ok(())
+    }
+
+    fn vcpu_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let v = state.domain_mut(domain_id)?.vcpu_mut(vcpu)?;
+
+        let online = online_cpus();
+        if hard.len() < online.len() || soft.len() < online.len() {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let intersect = |map: &[u8], mask: &[u8]| -> Vec<u8> {
+            map.iter().zip(mask).map(|(map, mask)| map & mask).collect()
+        };
+
+        if flags & XEN_VCPUAFFINITY_HARD != 0 {
+            if intersect(hard, &online).iter().all(|&byte| byte == 0) {
+                return Err(errno(libc::EINVAL));
+            }
+
+            v.hard_affinity = hard[..online.len()].to_vec();
+        }
+
+        if flags & XEN_VCPUAFFINITY_SOFT != 0 {
+            v.soft_affinity = soft[..online.len()].to_vec();
+        }
+
+        // Report the effective affinity back, like Xen does.
+        let effective_hard = intersect(&v.hard_affinity, &online);
+        let effective_soft = intersect(&v.soft_affinity, &effective_hard);
+
+        if flags & XEN_VCPUAFFINITY_HARD != 0 {
+            hard[..online.len()].copy_from_slice(&effective_hard);
+        }
+
+        if flags & XEN_VCPUAFFINITY_SOFT != 0 {
+            soft[..online.len()].copy_from_slice(&effective_soft);
+        }
+
+        Ok(())
+    }
+
+    fn domain_node_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+
+        let size = domain.node_affinity.len();
+        if nodemap.len() < size {
+            return Err(errno(libc::EINVAL));
+        }
+
+        nodemap[..size].copy_from_slice(&domain.node_affinity);
+        Ok(())
+    }
+
+    fn domain_node_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        let online = online_nodes();
+        if nodemap.len() < online.len()
+            || nodemap
+                .iter()
+                .zip(&online)
+                .all(|(map, mask)| map & mask == 0)
+        {
+            return Err(errno(libc::EINVAL));
+        }
+
+        domain.node_affinity = nodemap[..online.len()].to_vec();
+        Ok(())
+    }
+
+    fn domain_maximum_gpfn(&self, domain_id: XenDomainId) -> Result<u64, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+        Ok(domain.ram.keys().next_back().copied().unwrap_or_default())
+    }
+
+    fn hvm_param_get(&self, domain_id: XenDomainId, param: u32) -> Result<u64, XenError> {
+        if param >= HVM_NR_PARAMS {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+        Ok(domain.hvm_params.get(&param).copied().unwrap_or_default())
+    }
+
+    fn hvm_param_set(
+        &self,
+        domain_id: XenDomainId,
+        param: u32,
+        value: u64,
+    ) -> Result<(), XenError> {
+        if param >= HVM_NR_PARAMS {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let mut state = self.lock();
+        state.domain_mut(domain_id)?.hvm_params.insert(param, value);
+        Ok(())
+    }
+
+    fn evtchn_alloc_unbound(
+        &self,
+        domain_id: XenDomainId,
+        remote_domain_id: XenDomainId,
+    ) -> Result<u32, XenError> {
+        let mut state = self.lock();
+        if remote_domain_id != DOMID_CONTROL {
+            state.domain(remote_domain_id)?;
+        }
+
+        let domain = state.domain_mut(domain_id)?;
+        Ok(domain.alloc_port(MockPort::Unbound(remote_domain_id)))
+    }
+
+    fn evtchn_status(&self, domain_id: XenDomainId, port: u32) -> Result<evtchn_status, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+
+        let mut status = unsafe { std::mem::zeroed::<evtchn_status>() };
+        status.dom = domain_id.0 as u16;
+        status.port = port;
+
+        match domain.ports.get(&port) {
+            None => status.status = EVTCHNSTAT_closed,
+            Some(MockPort::Unbound(remote_domain_id)) => {
+                status.status = EVTCHNSTAT_unbound;
+                status.u.unbound.dom = remote_domain_id.0 as u16;
+            }
+            Some(MockPort::Interdomain(remote_domain_id, remote_port)) => {
+                status.status = EVTCHNSTAT_interdomain;
+                status.u.interdomain.dom = remote_domain_id.0 as u16;
+                status.u.interdomain.port = *remote_port;
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn evtchn_reset(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let mut state = self.lock();
+        state.domain_mut(domain_id)?.ports.clear();
+        Ok(())
+    }
+
+    fn domain_pause(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.paused += 1;
+        Ok(())
+    }
+
+    fn domain_unpause(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+        domain.paused = domain.paused.checked_sub(1).ok_or(errno(libc::EINVAL))?;
+        Ok(())
+    }
+
+    fn get_mem_access(&self, domain_id: XenDomainId, gfn: u64) -> Result<MemoryAccess, XenError> {
+        Ok(self.lock().domain(domain_id)?.host_access(gfn))
+    }
+
+    fn set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        access: MemoryAccess,
+        first_gfn: u64,
+        nr: u32,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        for gfn in first_gfn..first_gfn + nr as u64 {
+            match access {
+                MemoryAccess::DEFAULT => domain.mem_access.remove(&gfn),
+                access => domain.mem_access.insert(gfn, access),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn domain_set_access_required(
+        &self,
+        domain_id: XenDomainId,
+        required: bool,
+    ) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.access_required = required;
+        Ok(())
+    }
+
+    fn domain_debug_control(
+        &self,
+        domain_id: XenDomainId,
+        _operation: u32,
+        vcpu: VcpuId,
+    ) -> Result<(), XenError> {
+        self.lock().domain(domain_id)?.vcpu(vcpu)?;
+        Ok(())
+    }
+
+    fn domain_setmaxmem(&self, domain_id: XenDomainId, max_memkb: u64) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.max_pages = max_memkb / (PAGE_SIZE / 1024);
+        Ok(())
+    }
+
+    fn domain_increase_reservation(
+        &self,
+        _domain_id: XenDomainId,
+        _extent_order: u32,
+        _mem_flags: u32,
+        _extents: &[u64],
+        _exact: bool,
+    ) -> Result<(), XenError> {
+        Err(errno(libc::EOPNOTSUPP))
+    }
+
+    fn domain_decrease_reservation(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        for &extent in extents {
+            for gfn in extent..extent + (1 << extent_order) {
+                if domain.ram.remove(&gfn).is_none() && exact {
+                    return Err(errno(libc::ENOENT));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn domain_populate_physmap(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        _mem_flags: u32,
+        extents: &[u64],
+        _exact: bool,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        for &extent in extents {
+            for gfn in extent..extent + (1 << extent_order) {
+                if domain.ram.len() as u64 >= domain.max_pages {
+                    return Err(errno(libc::ENOMEM));
+                }
+
+                domain
+                    .ram
+                    .entry(gfn)
+                    .or_insert_with(|| vec![0; PAGE_SIZE as usize].into_boxed_slice());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn domain_hvm_getcontext(
+        &self,
+        domain_id: XenDomainId,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<usize, XenError> {
+        let context = self.lock().domain(domain_id)?.hvm_context();
+
+        match buffer {
+            Some(buffer) if buffer.len() < context.len() => Err(errno(libc::ENOSPC)),
+            Some(buffer) => {
+                buffer[..context.len()].copy_from_slice(&context);
+                Ok(context.len())
+            }
+            None => Ok(context.len()),
+        }
+    }
+
+    fn domain_hvm_getcontext_partial(
+        &self,
+        domain_id: XenDomainId,
+        typecode: u16,
+        instance: u16,
+        buffer: &mut [u8],
+    ) -> Result<(), XenError> {
+        let state = self.lock();
+        let record = state.domain(domain_id)?.record(typecode, instance)?;
+
+        if buffer.len() < record.len() {
+            return Err(errno(libc::ENOSPC));
+        }
+
+        buffer[..record.len()].copy_from_slice(record);
+        Ok(())
+    }
+
+    fn domain_hvm_setcontext(&self, domain_id: XenDomainId, buffer: &[u8]) -> Result<(), XenError> {
+        self.lock().domain_mut(domain_id)?.set_hvm_context(buffer)
+    }
+
+    fn vcpu_get_extstate(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        xfeature_mask: u64,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<(u64, usize), XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+        domain.vcpu(vcpu)?;
+
+        // The record holds `xfeature_mask`, `xcr0`, `xcr0_accum` and the area,
+        // the extended state the same without `xfeature_mask`.
+        let record = &domain.record(CPU_XSAVE_CODE as u16, vcpu.0)?[8..];
+
+        match buffer {
+            Some(buffer) if buffer.len() != record.len() || xfeature_mask != MOCK_XCR0 => {
+                Err(errno(libc::EINVAL))
+            }
+            Some(buffer) => {
+                buffer.copy_from_slice(record);
+                Ok((xfeature_mask, record.len()))
+            }
+            None => Ok((MOCK_XCR0, record.len())),
+        }
+    }
+
+    fn translate_foreign_address(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        address: u64,
+    ) -> Result<u64, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+        let walker = PageTableWalker::from(&Registers::from(domain.cpu(vcpu)?));
+
+        match walker.translate(domain, address, TranslationAccess::empty()) {
+            Ok(translation) => Ok(translation.gfn()),
+            Err(XenError::PageFault(_)) => Err(errno(libc::EFAULT)),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn altp2m_get_domain_state(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        Ok(self.lock().domain(domain_id)?.altp2m.is_some())
+    }
+
+    fn altp2m_set_domain_state(&self, domain_id: XenDomainId, state: bool) -> Result<(), XenError> {
+        let mut guard = self.lock();
+        let domain = guard.domain_mut(domain_id)?;
+
+        match state {
+            true => {
+                domain.altp2m.get_or_insert_with(BTreeMap::new);
+            }
+            false => {
+                domain.altp2m = None;
+                for vcpu in &mut domain.vcpus {
+                    vcpu.view = 0;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn altp2m_create_view(
+        &self,
+        domain_id: XenDomainId,
+        default_access: MemoryAccess,
+    ) -> Result<u16, XenError> {
+        let mut state = self.lock();
+        let views = state
+            .domain_mut(domain_id)?
+            .altp2m
+            .as_mut()
+            .ok_or(errno(libc::EOPNOTSUPP))?;
+
+        let view_id = (1..u16::MAX)
+            .find(|view_id| !views.contains_key(view_id))
+            .ok_or(errno(libc::EBUSY))?;
+
+        views.insert(
+            view_id,
+            MockView {
+                default_access,
+                access: HashMap::new(),
+                remap: HashMap::new(),
+            },
+        );
+
+        Ok(view_id)
+    }
+
+    fn altp2m_destroy_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        if view_id == 0 || domain.vcpus.iter().any(|vcpu| vcpu.view == view_id) {
+            return Err(errno(libc::EBUSY));
+        }
+
+        domain
+            .altp2m
+            .as_mut()
+            .ok_or(errno(libc::EOPNOTSUPP))?
+            .remove(&view_id)
+            .ok_or(errno(libc::EINVAL))?;
+
+        Ok(())
+    }
+
+    fn altp2m_switch_to_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        if view_id != 0 {
+            domain.view_mut(view_id)?;
+        }
+        else if domain.altp2m.is_none() {
+            return Err(errno(libc::EOPNOTSUPP));
+        }
+
+        for vcpu in &mut domain.vcpus {
+            vcpu.view = view_id;
+        }
+
+        Ok(())
+    }
+
+    fn altp2m_get_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+    ) -> Result<MemoryAccess, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+
+        match view_id {
+            0 => Ok(domain.host_access(gfn)),
+            _ => {
+                let view = domain.view(view_id).ok_or(errno(libc::EINVAL))?;
+                view.access.get(&gfn).copied().ok_or(errno(libc::ESRCH))
+            }
+        }
+    }
+
+    fn altp2m_set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+        access: MemoryAccess,
+    ) -> Result<(), XenError> {
+        self.altp2m_set_mem_access_multi(domain_id, view_id, &[access], &[gfn])
+    }
+
+    fn altp2m_set_mem_access_multi(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        access: &[MemoryAccess],
+        gfns: &[u64],
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        let entries = match view_id {
+            0 => &mut domain.mem_access,
+            _ => &mut domain.view_mut(view_id)?.access,
+        };
+
+        for (&access, &gfn) in access.iter().zip(gfns) {
+            entries.insert(gfn, access);
+        }
+
+        Ok(())
+    }
+
+    fn altp2m_change_gfn(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        old_gfn: u64,
+        new_gfn: u64,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        // Passing INVALID_GFN as the new gfn removes the remapping.
+        if new_gfn != INVALID_GFN && !domain.ram.contains_key(&new_gfn) {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let view = domain.view_mut(view_id)?;
+        match new_gfn {
+            INVALID_GFN => view.remap.remove(&old_gfn),
+            _ => view.remap.insert(old_gfn, new_gfn),
+        };
+
+        Ok(())
+    }
+
+    fn monitor_enable(&self, domain_id: XenDomainId) -> Result<(NonNull<c_void>, u32), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+
+        if domain.monitor.is_some() {
+            return Err(errno(libc::EBUSY));
+        }
+
+        let ring_page = alloc_ring_page();
+        let ring_page_ptr = NonNull::new(ring_page as *mut c_void).ok_or(errno(libc::ENOMEM))?;
+        let port = domain.alloc_port(MockPort::Unbound(DOMID_CONTROL));
+
+        domain.monitor = Some(MockMonitor {
+            port,
+            ring: Some(MockRing::new(ring_page)),
+            reasons: 0,
+        });
+
+        Ok((ring_page_ptr, port))
+    }
+
+    fn monitor_disable(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+        let monitor = domain.monitor.take().ok_or(errno(libc::ENODEV))?;
+        domain.ports.remove(&monitor.port);
+
+        // Xen unpauses all vCPUs still waiting for a response.
+        for vcpu in &mut domain.vcpus {
+            vcpu.paused = false;
+        }
+
+        Ok(())
+    }
+
+    fn monitor_resume(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.process_responses(domain_id)
+    }
+
+    fn monitor_get_capabilities(&self, domain_id: XenDomainId) -> Result<u32, XenError> {
+        self.lock().domain(domain_id)?;
+
+        Ok([
+            VM_EVENT_REASON_MEM_ACCESS,
+            VM_EVENT_REASON_WRITE_CTRLREG,
+            VM_EVENT_REASON_MOV_TO_MSR,
+            VM_EVENT_REASON_SOFTWARE_BREAKPOINT,
+            VM_EVENT_REASON_SINGLESTEP,
+            VM_EVENT_REASON_GUEST_REQUEST,
+            VM_EVENT_REASON_DEBUG_EXCEPTION,
+            VM_EVENT_REASON_CPUID,
+            VM_EVENT_REASON_PRIVILEGED_CALL,
+            VM_EVENT_REASON_INTERRUPT,
+            VM_EVENT_REASON_DESCRIPTOR_ACCESS,
+            VM_EVENT_REASON_EMUL_UNIMPLEMENTED,
+            VM_EVENT_REASON_VMEXIT,
+            VM_EVENT_REASON_IO_INSTRUCTION,
+        ]
+        .iter()
+        .fold(0, |capabilities, reason| capabilities | (1 << reason)))
+    }
+
+    fn monitor_unmap_ring_page(&self, ring_page: NonNull<c_void>) {
+        let ring_page = ring_page.as_ptr() as usize;
+
+        let mut state = self.lock();
+        for domain in state.domains.values_mut() {
+            if let Some(monitor) = &mut domain.monitor
+                && monitor
+                    .ring
+                    .as_ref()
+                    .is_some_and(|ring| ring.ring_page == ring_page)
+            {
+                monitor.ring = None;
+            }
+        }
+
+        free_ring_page(ring_page);
+    }
+
+    fn monitor_write_ctrlreg(
+        &self,
+        domain_id: XenDomainId,
+        _index: VmEventCtrlReg,
+        enable: bool,
+        _sync: bool,
+        _bitmask: u64,
+        _onchangeonly: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_WRITE_CTRLREG, enable)
+    }
+
+    fn monitor_mov_to_msr(
+        &self,
+        domain_id: XenDomainId,
+        _msr: u32,
+        enable: bool,
+        _onchangeonly: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_MOV_TO_MSR, enable)
+    }
+
+    fn monitor_singlestep(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_SINGLESTEP, enable)
+    }
+
+    fn monitor_software_breakpoint(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_SOFTWARE_BREAKPOINT, enable)
+    }
+
+    fn monitor_descriptor_access(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_DESCRIPTOR_ACCESS, enable)
+    }
+
+    fn monitor_guest_request(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        _sync: bool,
+        _allow_userspace: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_GUEST_REQUEST, enable)
+    }
+
+    fn monitor_inguest_pagefault(
+        &self,
+        domain_id: XenDomainId,
+        _disable: bool,
+    ) -> Result<(), XenError> {
+        self.lock().domain(domain_id)?;
+        Ok(())
+    }
+
+    fn monitor_debug_exceptions(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        _sync: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_DEBUG_EXCEPTION, enable)
+    }
+
+    fn monitor_cpuid(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_CPUID, enable)
+    }
+
+    fn monitor_privileged_call(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_PRIVILEGED_CALL, enable)
+    }
+
+    fn monitor_emul_unimplemented(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_EMUL_UNIMPLEMENTED, enable)
+    }
+
+    fn monitor_vmexit(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        _sync: bool,
+    ) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_VMEXIT, enable)
+    }
+
+    fn monitor_io(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        self.set_monitor_reason(domain_id, VM_EVENT_REASON_IO_INSTRUCTION, enable)
+    }
+
+    fn monitor_emulate_each_rep(
+        &self,
+        domain_id: XenDomainId,
+        _enable: bool,
+    ) -> Result<(), XenError> {
+        self.lock().domain(domain_id)?;
+        Ok(())
+    }
+}
+
+/// Foreign mappings are snapshots of guest RAM. Writable mappings are
+/// written back when they are unmapped.
+impl XenForeignMemoryBackend for MockHypervisor {
+    fn map(
+        &self,
+        domain_id: XenDomainId,
+        protection: XenForeignMemoryProtection,
+        gfns: &[u64],
+        mut err: Option<&mut [i32]>,
+    ) -> Result<NonNull<c_void>, XenError> {
+        let state = self.lock();
+        let domain = state.domain(domain_id)?;
+
+        if gfns.is_empty() || err.as_ref().is_some_and(|err| err.len() != gfns.len()) {
+            return Err(errno(libc::EINVAL));
+        }
+
+        if err.is_none() && gfns.iter().any(|gfn| !domain.ram.contains_key(gfn)) {
+            return Err(errno(libc::ENOENT));
+        }
+
+        let layout = mapping_layout(gfns.len());
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).ok_or(errno(libc::ENOMEM))?;
+
+        for (index, gfn) in gfns.iter().enumerate() {
+            let result = match domain.ram.get(gfn) {
+                Some(page) => {
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(
+                            page.as_ptr(),
+                            ptr.as_ptr().add(index * PAGE_SIZE as usize),
+                            PAGE_SIZE as usize,
+                        );
+                    }
+                    0
+                }
+                None => -libc::ENOENT,
+            };
+
+            if let Some(err) = err.as_deref_mut() {
+                err[index] = result;
+            }
+        }
+
+        drop(state);
+
+        self.lock().mappings.insert(
+            ptr.as_ptr() as usize,
+            MockMapping {
+                domain_id,
+                gfns: gfns.to_vec(),
+                writable: protection.contains(XenForeignMemoryProtection::WRITE),
+            },
+        );
+
+        Ok(ptr.cast())
+    }
+
+    fn unmap(&self, ptr: NonNull<c_void>, pages: usize) {
+        let mut state = self.lock();
+        let Some(mapping) = state.mappings.remove(&(ptr.as_ptr() as usize))
+        else {
+            return;
+        };
+
+        if mapping.writable
+            && let Some(domain) = state.domains.get_mut(&mapping.domain_id)
+        {
+            for (index, gfn) in mapping.gfns.iter().enumerate() {
+                if let Some(page) = domain.ram.get_mut(gfn) {
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(
+                            (ptr.as_ptr() as *const u8).add(index * PAGE_SIZE as usize),
+                            page.as_mut_ptr(),
+                            PAGE_SIZE as usize,
+                        );
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            std::alloc::dealloc(ptr.as_ptr() as *mut u8, mapping_layout(pages));
+        }
+    }
+}
+
+fn mapping_layout(pages: usize) -> Layout {
+    Layout::from_size_align(pages * PAGE_SIZE as usize, PAGE_SIZE as usize).unwrap()
+}
+
+impl XenDeviceModelBackend for MockHypervisor {
+    fn inject_event(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        vector: XenX86ExceptionVector,
+        event_type: XenX86EventType,
+        error_code: u32,
+        instruction_length: u8,
+        extra: u64,
+    ) -> Result<(), XenError> {
+        let mut state = self.lock();
+        let domain = state.domain_mut(domain_id)?;
+        domain.vcpu(vcpu)?;
+
+        domain.injected.push(MockInjectedEvent {
+            vcpu,
+            vector,
+            event_type,
+            error_code,
+            instruction_length,
+            extra,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{XenDomain, arch::x86::Amd64};
+
+    fn setup() -> (MockHypervisor, XenDomain<Amd64>) {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 256, 1)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+
+        (hypervisor, domain)
+    }
+
+    fn event(flags: VmEventFlag, altp2m_idx: u16) -> VmEvent {
+        VmEvent {
+            flags,
+            reason: VmEventReason::GuestRequest,
+            vcpu_id: VcpuId(0),
+            altp2m_idx,
+            options: None,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn ring_full_and_wrap() {
+        let (hypervisor, domain) = setup();
+        let (monitor, mut ring) = domain.monitor().unwrap();
+
+        let mut size = 0;
+        let err = loop {
+            match hypervisor.send_event(XenDomainId(1), event(VmEventFlag::empty(), size)) {
+                Ok(()) => size += 1,
+                Err(err) => break err,
+            }
+        };
+
+        assert_eq!(err.errno(), Some(libc::EBUSY));
+        assert!(size.is_power_of_two());
+        assert_eq!(ring.unconsumed_requests(), size as usize);
+
+        // Consuming a request does not free its slot, only the response
+        // does.
+        let request = ring.get_request();
+        let err = hypervisor
+            .send_event(XenDomainId(1), event(VmEventFlag::empty(), size))
+            .unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EBUSY));
+
+        ring.put_response(request);
+        monitor.resume().unwrap();
+
+        // Keep the ring full while its indices wrap around a few times.
+        for index in size..size * 4 {
+            hypervisor
+                .send_event(XenDomainId(1), event(VmEventFlag::empty(), index))
+                .unwrap();
+            assert!(
+                hypervisor
+                    .send_event(XenDomainId(1), event(VmEventFlag::empty(), index))
+                    .is_err()
+            );
+
+            let request = ring.get_request();
+            assert_eq!(request.altp2m_idx, index - size + 1);
+            ring.put_response(request);
+            monitor.resume().unwrap();
+        }
+
+        assert_eq!(ring.unconsumed_requests(), size as usize - 1);
+        assert_eq!(
+            hypervisor.take_responses(XenDomainId(1)).unwrap().len(),
+            size as usize * 3 + 1
+        );
+    }
+
+    #[test]
+    fn effective_access_across_views() {
+        let (hypervisor, domain) = setup();
+        let effective_access = |gfn| {
+            hypervisor
+                .effective_access(XenDomainId(1), VcpuId(0), gfn)
+                .unwrap()
+        };
+
+        domain.set_mem_access(0x10, MemoryAccess::R).unwrap();
+
+        let altp2m = domain.altp2m().unwrap();
+        let view = altp2m.create_view(MemoryAccess::RW).unwrap();
+        view.set_mem_access(0x11, MemoryAccess::RX).unwrap();
+        view.change_gfn(0x12, 0x11).unwrap();
+
+        // The host view is in effect until the vCPU switches.
+        assert_eq!(effective_access(0x10), MemoryAccess::R);
+        assert_eq!(effective_access(0x11), MemoryAccess::RWX);
+        assert_eq!(effective_access(0x12), MemoryAccess::RWX);
+
+        view.switch().unwrap();
+        assert_eq!(effective_access(0x10), MemoryAccess::RW);
+        assert_eq!(effective_access(0x11), MemoryAccess::RX);
+        assert_eq!(effective_access(0x12), MemoryAccess::RX);
+
+        altp2m.reset_view().unwrap();
+        assert_eq!(effective_access(0x10), MemoryAccess::R);
+        assert_eq!(effective_access(0x12), MemoryAccess::RWX);
+    }
+
+    #[test]
+    fn unpause_on_response() {
+        let (hypervisor, domain) = setup();
+        let (monitor, mut ring) = domain.monitor().unwrap();
+
+        hypervisor
+            .send_event(XenDomainId(1), event(VmEventFlag::VCPU_PAUSED, 0))
+            .unwrap();
+        assert!(
+            hypervisor
+                .is_vcpu_paused(XenDomainId(1), VcpuId(0))
+                .unwrap()
+        );
+
+        // A response without the flag leaves the vCPU paused.
+        let request = ring.get_request();
+        ring.put_response(event(VmEventFlag::empty(), 0));
+        monitor.resume().unwrap();
+        assert!(
+            hypervisor
+                .is_vcpu_paused(XenDomainId(1), VcpuId(0))
+                .unwrap()
+        );
+
+        ring.put_response(request);
+        monitor.resume().unwrap();
+        assert!(
+            !hypervisor
+                .is_vcpu_paused(XenDomainId(1), VcpuId(0))
+                .unwrap()
+        );
+        assert!(!hypervisor.is_paused(XenDomainId(1)).unwrap());
+
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert!(responses[1].flags.contains(VmEventFlag::VCPU_PAUSED));
+    }
+
+    #[test]
+    fn map_with_short_err() {
+        let (hypervisor, _domain) = setup();
+        let mut err = [0; 1];
+
+        let result = hypervisor.foreign_memory().map(
+            XenDomainId(1),
+            XenForeignMemoryProtection::READ,
+            &[0x10, 0x11],
+            Some(&mut err),
+        );
+        assert_eq!(result.err().unwrap().errno(), Some(libc::EINVAL));
+    }
+}
@@ -0,0 +1,85 @@
+use std::alloc::Layout;
+
+use xen_sys::{vm_event_sring, vm_event_st};
+
+use super::errno;
+use crate::{XenError, consts::PAGE_SIZE};
+
+/// Allocates a zeroed, page-aligned ring page.
+pub(super) fn alloc_ring_page() -> usize {
+    unsafe { std::alloc::alloc_zeroed(ring_page_layout()) as usize }
+}
+
+/// Frees a page returned by [`alloc_ring_page`].
+pub(super) fn free_ring_page(ring_page: usize) {
+    unsafe { std::alloc::dealloc(ring_page as *mut u8, ring_page_layout()) }
+}
+
+fn ring_page_layout() -> Layout {
+    Layout::from_size_align(PAGE_SIZE as usize, PAGE_SIZE as usize).unwrap()
+}
+
+/// Front end of the `vm_event` ring, i.e. the side Xen is on.
+#[derive(Debug)]
+pub(super) struct MockRing {
+    pub(super) ring_page: usize,
+    req_prod_pvt: u32,
+    rsp_cons: u32,
+}
+
+impl MockRing {
+    pub(super) fn new(ring_page: usize) -> Self {
+        Self {
+            ring_page,
+            req_prod_pvt: 0,
+            rsp_cons: 0,
+        }
+    }
+
+    fn sring(&self) -> *mut vm_event_sring {
+        self.ring_page as *mut vm_event_sring
+    }
+
+    fn size(&self) -> u32 {
+        let sring = self.sring();
+        let page_size = PAGE_SIZE;
+        crate::__RING_SIZE!(sring, page_size)
+    }
+
+    pub(super) fn push_request(&mut self, request: vm_event_st) -> Result<(), XenError> {
+        let size = self.size();
+
+        if self.req_prod_pvt.wrapping_sub(self.rsp_cons) >= size {
+            return Err(errno(libc::EBUSY));
+        }
+
+        unsafe {
+            let sring = self.sring();
+            let ring = (*sring).ring.as_mut_slice(size as usize);
+            ring[(self.req_prod_pvt & (size - 1)) as usize].req = request;
+
+            self.req_prod_pvt = self.req_prod_pvt.wrapping_add(1);
+            crate::macros::wmb();
+            std::ptr::write_volatile(&mut (*sring).req_prod, self.req_prod_pvt);
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn pop_response(&mut self) -> Option<vm_event_st> {
+        let size = self.size();
+
+        unsafe {
+            let sring = self.sring();
+            let rsp_prod = std::ptr::read_volatile(&(*sring).rsp_prod);
+            if self.rsp_cons == rsp_prod {
+                return None;
+            }
+
+            let ring = (*sring).ring.as_slice(size as usize);
+            let response = ring[(self.rsp_cons & (size - 1)) as usize].rsp;
+            self.rsp_cons = self.rsp_cons.wrapping_add(1);
+            Some(response)
+        }
+    }
+}
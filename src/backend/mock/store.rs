@@ -0,0 +1,319 @@
+use std::{
+    collections::VecDeque,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{Mutex, PoisonError},
+};
+
+use xen_sys::{XS_PERM_NONE, xs_permissions};
+
+use super::{DOMID_CONTROL, MockHypervisor, MockState, errno};
+use crate::{XenDomainId, XenError, backend::XenStoreBackend, store::XenStore};
+
+/// Node of the mock store.
+#[derive(Debug, Clone)]
+pub(super) struct MockNode {
+    pub(super) value: Vec<u8>,
+    pub(super) permissions: Vec<xs_permissions>,
+}
+
+impl MockState {
+    pub(super) fn store_mkdir(&mut self, path: &str) -> Result<(), XenError> {
+        if self.store_create(path)? {
+            self.fire_watches(path);
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn store_write(&mut self, path: &str, value: &[u8]) -> Result<(), XenError> {
+        self.store_create(path)?;
+        self.store.get_mut(path).unwrap().value = value.to_vec();
+        self.fire_watches(path);
+        Ok(())
+    }
+
+    /// Creates `path` and its missing parents, returning `true` if `path`
+    /// was created. New nodes inherit the permissions of their parent,
+    /// like in xenstored.
+    fn store_create(&mut self, path: &str) -> Result<bool, XenError> {
+        check_path(path)?;
+
+        let mut permissions = vec![xs_permissions {
+            id: DOMID_CONTROL.0,
+            perms: XS_PERM_NONE,
+        }];
+
+        let mut created = false;
+        for (index, _) in path.match_indices('/').skip(1).chain([(path.len(), "")]) {
+            let node = self
+                .store
+                .entry(path[..index].to_owned())
+                .or_insert_with(|| {
+                    created = true;
+                    MockNode {
+                        value: Vec::new(),
+                        permissions: permissions.clone(),
+                    }
+                });
+
+            permissions = node.permissions.clone();
+        }
+
+        Ok(created)
+    }
+
+    /// Removes `path` and its children. Like in xenstored, removing a
+    /// missing node succeeds if its parent exists.
+    pub(super) fn store_rm(&mut self, path: &str) -> Result<(), XenError> {
+        check_path(path)?;
+        if path == "/" {
+            return Err(errno(libc::EINVAL));
+        }
+
+        if !self.store.contains_key(path) {
+            let parent = &path[..path.rfind('/').unwrap()];
+            return match parent.is_empty() || self.store.contains_key(parent) {
+                true => Ok(()),
+                false => Err(errno(libc::ENOENT)),
+            };
+        }
+
+        let removed = self
+            .store
+            .keys()
+            .filter(|node| XenStore::path_is_subpath(path, node))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Like xenstored, also fire the watches on the removed children.
+        for node in &removed {
+            self.store.remove(node);
+            if node != path {
+                self.fire_watches_with(node, true);
+            }
+        }
+
+        self.fire_watches(path);
+        Ok(())
+    }
+
+    fn store_node(&self, path: &str) -> Result<&MockNode, XenError> {
+        check_path(path)?;
+        self.store.get(path).ok_or(errno(libc::ENOENT))
+    }
+}
+
+/// Accepts absolute paths without empty components and NUL bytes.
+fn check_path(path: &str) -> Result<(), XenError> {
+    if !path.starts_with('/')
+        || path.contains("//")
+        || path.contains('\0')
+        || (path.len() > 1 && path.ends_with('/'))
+    {
+        return Err(errno(libc::EINVAL));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct MockStoreWatches {
+    watches: Vec<(String, String)>,
+    events: VecDeque<(String, String)>,
+    eventfd: Option<OwnedFd>,
+}
+
+/// Connection to the store of the mock hypervisor.
+///
+/// Entries are shared by all connections, watches are per connection.
+/// Pending watch events are signalled through an `eventfd` in semaphore
+/// mode, created the first time the descriptor is requested.
+#[derive(Debug)]
+pub struct MockStore {
+    hypervisor: MockHypervisor,
+    watches: Mutex<MockStoreWatches>,
+}
+
+impl MockStore {
+    pub(super) fn new(hypervisor: MockHypervisor) -> Self {
+        Self {
+            hypervisor,
+            watches: Mutex::new(MockStoreWatches::default()),
+        }
+    }
+
+    /// Queues an event for every watch covering `path`, or for the
+    /// watches on `path` itself if `exact` is set.
+    pub(super) fn fire(&self, path: &str, exact: bool) {
+        let mut watches = self.watches.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let tokens = watches
+            .watches
+            .iter()
+            .filter(|(watch, _)| watch == path || (!exact && is_watched(watch, path)))
+            .map(|(_, token)| token.clone())
+            .collect::<Vec<_>>();
+
+        for token in tokens {
+            push_event(&mut watches, path, token);
+        }
+    }
+}
+
+/// Returns `true` if a watch on `watch` reports changes of `path`.
+fn is_watched(watch: &str, path: &str) -> bool {
+    match watch.starts_with('@') {
+        true => watch == path,
+        false => XenStore::path_is_subpath(watch, path),
+    }
+}
+
+fn push_event(watches: &mut MockStoreWatches, path: &str, token: String) {
+    watches.events.push_back((path.to_owned(), token));
+
+    if let Some(eventfd) = &watches.eventfd {
+        let value = 1u64;
+        unsafe {
+            libc::write(
+                eventfd.as_raw_fd(),
+                &value as *const u64 as *const _,
+                size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl XenStoreBackend for MockStore {
+    fn directory(&self, path: &str) -> Result<Vec<String>, XenError> {
+        let state = self.hypervisor.lock();
+        if path != "/" {
+            state.store_node(path)?;
+        }
+
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        Ok(state
+            .store
+            .keys()
+            .filter_map(|key| key.strip_prefix(&prefix))
+            .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, XenError> {
+        Ok(self.hypervisor.lock().store_node(path)?.value.clone())
+    }
+
+    fn write(&self, path: &str, value: &[u8]) -> Result<(), XenError> {
+        self.hypervisor.lock().store_write(path, value)
+    }
+
+    fn mkdir(&self, path: &str) -> Result<(), XenError> {
+        self.hypervisor.lock().store_mkdir(path)
+    }
+
+    fn rm(&self, path: &str) -> Result<(), XenError> {
+        self.hypervisor.lock().store_rm(path)
+    }
+
+    fn get_permissions(&self, path: &str) -> Result<Vec<xs_permissions>, XenError> {
+        Ok(self.hypervisor.lock().store_node(path)?.permissions.clone())
+    }
+
+    fn set_permissions(&self, path: &str, permissions: &[xs_permissions]) -> Result<(), XenError> {
+        if permissions.is_empty() {
+            return Err(errno(libc::EINVAL));
+        }
+
+        let mut state = self.hypervisor.lock();
+        state.store_node(path)?;
+        state.store.get_mut(path).unwrap().permissions = permissions.to_vec();
+        state.fire_watches(path);
+        Ok(())
+    }
+
+    fn get_domain_path(&self, domain_id: XenDomainId) -> Result<String, XenError> {
+        Ok(format!("/local/domain/{domain_id}"))
+    }
+
+    fn is_domain_introduced(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        let state = self.hypervisor.lock();
+        Ok(state
+            .domains
+            .get(&domain_id)
+            .is_some_and(|domain| domain.introduced))
+    }
+
+    fn watch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        let mut watches = self.watches.lock().unwrap_or_else(PoisonError::into_inner);
+        if watches
+            .watches
+            .iter()
+            .any(|(watch, existing)| watch == path && existing == token)
+        {
+            return Err(errno(libc::EEXIST));
+        }
+
+        watches.watches.push((path.to_owned(), token.to_owned()));
+
+        // Like xenstored, fire the new watch once.
+        push_event(&mut watches, path, token.to_owned());
+        Ok(())
+    }
+
+    fn unwatch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        let mut watches = self.watches.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = watches
+            .watches
+            .iter()
+            .position(|(watch, existing)| watch == path && existing == token)
+            .ok_or(errno(libc::ENOENT))?;
+
+        watches.watches.remove(index);
+        Ok(())
+    }
+
+    fn watch_fd(&self) -> Result<RawFd, XenError> {
+        let mut watches = self.watches.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(eventfd) = &watches.eventfd {
+            return Ok(eventfd.as_raw_fd());
+        }
+
+        let pending = watches.events.len() as u32;
+        let fd = unsafe {
+            libc::eventfd(
+                pending,
+                libc::EFD_CLOEXEC | libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE,
+            )
+        };
+        if fd < 0 {
+            return Err(XenError::Io(std::io::Error::last_os_error()));
+        }
+
+        Ok(watches
+            .eventfd
+            .insert(unsafe { OwnedFd::from_raw_fd(fd) })
+            .as_raw_fd())
+    }
+
+    fn check_watch(&self) -> Result<Option<(String, String)>, XenError> {
+        let mut watches = self.watches.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(event) = watches.events.pop_front()
+        else {
+            return Ok(None);
+        };
+
+        if let Some(eventfd) = &watches.eventfd {
+            let mut value = 0u64;
+            unsafe {
+                libc::read(
+                    eventfd.as_raw_fd(),
+                    &mut value as *mut u64 as *mut _,
+                    size_of::<u64>(),
+                );
+            }
+        }
+
+        Ok(Some(event))
+    }
+}
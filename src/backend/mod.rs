@@ -0,0 +1,440 @@
+//! Backend abstraction over the Xen libraries.
+//!
+//! Every operation of [`XenInterface`], [`XenForeignMemory`],
+//! [`XenEventChannel`], [`XenStore`] and [`XenDeviceModel`] is routed through
+//! one of the traits below. The native handles (e.g. [`XenInterfaceHandle`])
+//! implement them on top of the Xen libraries and are used by default.
+//!
+//! With the `mock` feature enabled, `mock::MockHypervisor` provides an
+//! in-memory implementation of all of them.
+//!
+//! [`XenInterface`]: crate::XenInterface
+//! [`XenForeignMemory`]: crate::XenForeignMemory
+//! [`XenEventChannel`]: crate::evtchn::XenEventChannel
+//! [`XenStore`]: crate::XenStore
+//! [`XenDeviceModel`]: crate::XenDeviceModel
+//! [`XenInterfaceHandle`]: crate::ctrl::XenInterfaceHandle
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+use std::{ffi::c_void, os::fd::RawFd, ptr::NonNull, sync::Arc};
+
+use xen_sys::{
+    evtchn_status, xen_domctl_createdomain, xen_domctl_getdomaininfo, xen_domctl_getvcpuinfo,
+    xen_sysctl_physinfo, xs_permissions,
+};
+
+use crate::{
+    MemoryAccess, VcpuId, XenDomainId, XenError, XenX86EventType, XenX86ExceptionVector,
+    ctrl::VmEventCtrlReg, foreignmemory::XenForeignMemoryProtection,
+};
+
+/// Control operations (`libxenctrl`).
+pub trait XenControlBackend: std::fmt::Debug + Send + Sync {
+    /// Opens a new event channel backend.
+    fn open_event_channel(&self) -> Result<Arc<dyn XenEventChannelBackend>, XenError>;
+
+    /// Opens a new device model backend.
+    fn open_device_model(&self) -> Result<Arc<dyn XenDeviceModelBackend>, XenError>;
+
+    /// Issues `xc_version` with the `XENVER_*` `command`.
+    ///
+    /// Commands returning a structure copy it into `buffer`, which must be
+    /// at least as large as the structure. Returns the return code of the
+    /// call, which carries the result for `XENVER_version` and
+    /// `XENVER_pagesize`.
+    fn version(&self, command: u32, buffer: Option<&mut [u8]>) -> Result<i32, XenError>;
+
+    /// Returns the physical host information (`xc_physinfo`).
+    fn physinfo(&self) -> Result<xen_sysctl_physinfo, XenError>;
+
+    /// Returns the `VM_EVENT_INTERFACE_VERSION` of the hypervisor
+    /// (`xc_vm_event_get_version`).
+    fn vm_event_get_version(&self) -> Result<u32, XenError>;
+
+    /// Fills `info` with domains starting at `first_domain` and returns the
+    /// number of entries written.
+    fn domain_getinfolist(
+        &self,
+        first_domain: XenDomainId,
+        info: &mut [xen_domctl_getdomaininfo],
+    ) -> Result<usize, XenError>;
+
+    /// Creates a domain and returns its ID.
+    ///
+    /// A `domain_id` of `0` lets the hypervisor pick a free ID.
+    fn domain_create(
+        &self,
+        domain_id: XenDomainId,
+        config: &mut xen_domctl_createdomain,
+    ) -> Result<XenDomainId, XenError>;
+    fn domain_destroy(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+
+    /// Shuts the domain down with a `SHUTDOWN_*` `reason`.
+    fn domain_shutdown(&self, domain_id: XenDomainId, reason: u32) -> Result<(), XenError>;
+    fn domain_soft_reset(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+    fn domain_max_vcpus(&self, domain_id: XenDomainId, max: u32) -> Result<(), XenError>;
+    fn domain_sethandle(&self, domain_id: XenDomainId, handle: [u8; 16]) -> Result<(), XenError>;
+
+    /// Returns the size of a CPU map in bytes (`xc_get_cpumap_size`).
+    fn cpumap_size(&self) -> Result<usize, XenError>;
+
+    /// Returns the size of a node map in bytes (`xc_get_nodemap_size`).
+    fn nodemap_size(&self) -> Result<usize, XenError>;
+
+    fn vcpu_getinfo(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+    ) -> Result<xen_domctl_getvcpuinfo, XenError>;
+
+    /// Reads the affinity selected by the `XEN_VCPUAFFINITY_*` `flags`.
+    ///
+    /// Both maps must be [`cpumap_size`](Self::cpumap_size) bytes long.
+    fn vcpu_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError>;
+
+    /// Sets the affinity selected by the `XEN_VCPUAFFINITY_*` `flags` and
+    /// stores the effective affinity back into the maps.
+    ///
+    /// Both maps must be [`cpumap_size`](Self::cpumap_size) bytes long.
+    fn vcpu_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError>;
+
+    /// The map must be [`nodemap_size`](Self::nodemap_size) bytes long.
+    fn domain_node_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError>;
+
+    /// The map must be [`nodemap_size`](Self::nodemap_size) bytes long.
+    fn domain_node_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError>;
+
+    fn domain_maximum_gpfn(&self, domain_id: XenDomainId) -> Result<u64, XenError>;
+
+    /// Returns the `HVM_PARAM_*` parameter `param` (`xc_hvm_param_get`).
+    fn hvm_param_get(&self, domain_id: XenDomainId, param: u32) -> Result<u64, XenError>;
+
+    /// Sets the `HVM_PARAM_*` parameter `param` (`xc_hvm_param_set`).
+    fn hvm_param_set(&self, domain_id: XenDomainId, param: u32, value: u64)
+    -> Result<(), XenError>;
+
+    /// Allocates a port of `domain_id` for `remote_domain_id` to bind to
+    /// (`xc_evtchn_alloc_unbound`).
+    fn evtchn_alloc_unbound(
+        &self,
+        domain_id: XenDomainId,
+        remote_domain_id: XenDomainId,
+    ) -> Result<u32, XenError>;
+
+    /// Returns the status of `port` of `domain_id` (`xc_evtchn_status`).
+    fn evtchn_status(&self, domain_id: XenDomainId, port: u32) -> Result<evtchn_status, XenError>;
+
+    /// Closes all ports of `domain_id` (`xc_evtchn_reset`).
+    fn evtchn_reset(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+
+    fn domain_pause(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+    fn domain_unpause(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+
+    fn get_mem_access(&self, domain_id: XenDomainId, gfn: u64) -> Result<MemoryAccess, XenError>;
+    fn set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        access: MemoryAccess,
+        first_gfn: u64,
+        nr: u32,
+    ) -> Result<(), XenError>;
+
+    fn domain_set_access_required(
+        &self,
+        domain_id: XenDomainId,
+        required: bool,
+    ) -> Result<(), XenError>;
+    fn domain_debug_control(
+        &self,
+        domain_id: XenDomainId,
+        operation: u32,
+        vcpu: VcpuId,
+    ) -> Result<(), XenError>;
+    fn domain_setmaxmem(&self, domain_id: XenDomainId, max_memkb: u64) -> Result<(), XenError>;
+
+    fn domain_increase_reservation(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        mem_flags: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError>;
+    fn domain_decrease_reservation(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError>;
+    fn domain_populate_physmap(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        mem_flags: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError>;
+
+    /// Returns the size of the HVM context if `buffer` is `None`, otherwise
+    /// fills `buffer` and returns the number of bytes written.
+    fn domain_hvm_getcontext(
+        &self,
+        domain_id: XenDomainId,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<usize, XenError>;
+    fn domain_hvm_getcontext_partial(
+        &self,
+        domain_id: XenDomainId,
+        typecode: u16,
+        instance: u16,
+        buffer: &mut [u8],
+    ) -> Result<(), XenError>;
+    fn domain_hvm_setcontext(&self, domain_id: XenDomainId, buffer: &[u8]) -> Result<(), XenError>;
+
+    /// Reads the extended state of a vCPU (`xc_vcpu_get_extstate`).
+    ///
+    /// Without a `buffer`, returns the feature mask and the size of the
+    /// state. Otherwise both `xfeature_mask` and the buffer length must
+    /// match those, and the buffer receives `XCR0`, the accumulated `XCR0`
+    /// and the standard format XSAVE area.
+    fn vcpu_get_extstate(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        xfeature_mask: u64,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<(u64, usize), XenError>;
+
+    /// Translates a linear address of a vCPU to a guest frame number by
+    /// walking its page tables (`xc_translate_foreign_address`).
+    ///
+    /// Only the present bits are checked.
+    fn translate_foreign_address(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        address: u64,
+    ) -> Result<u64, XenError>;
+
+    fn altp2m_get_domain_state(&self, domain_id: XenDomainId) -> Result<bool, XenError>;
+    fn altp2m_set_domain_state(&self, domain_id: XenDomainId, state: bool) -> Result<(), XenError>;
+    fn altp2m_create_view(
+        &self,
+        domain_id: XenDomainId,
+        default_access: MemoryAccess,
+    ) -> Result<u16, XenError>;
+    fn altp2m_destroy_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError>;
+    fn altp2m_switch_to_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError>;
+    fn altp2m_get_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+    ) -> Result<MemoryAccess, XenError>;
+    fn altp2m_set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+        access: MemoryAccess,
+    ) -> Result<(), XenError>;
+    fn altp2m_set_mem_access_multi(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        access: &[MemoryAccess],
+        gfns: &[u64],
+    ) -> Result<(), XenError>;
+    fn altp2m_change_gfn(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        old_gfn: u64,
+        new_gfn: u64,
+    ) -> Result<(), XenError>;
+
+    /// Enables the monitor and returns the shared ring page together with the
+    /// remote event channel port.
+    fn monitor_enable(&self, domain_id: XenDomainId) -> Result<(NonNull<c_void>, u32), XenError>;
+    fn monitor_disable(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+    fn monitor_resume(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+    fn monitor_get_capabilities(&self, domain_id: XenDomainId) -> Result<u32, XenError>;
+
+    /// Releases a ring page previously returned by
+    /// [`monitor_enable`](Self::monitor_enable).
+    fn monitor_unmap_ring_page(&self, ring_page: NonNull<c_void>);
+
+    fn monitor_write_ctrlreg(
+        &self,
+        domain_id: XenDomainId,
+        index: VmEventCtrlReg,
+        enable: bool,
+        sync: bool,
+        bitmask: u64,
+        onchangeonly: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_mov_to_msr(
+        &self,
+        domain_id: XenDomainId,
+        msr: u32,
+        enable: bool,
+        onchangeonly: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_singlestep(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError>;
+    fn monitor_software_breakpoint(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_descriptor_access(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_guest_request(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+        allow_userspace: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_inguest_pagefault(
+        &self,
+        domain_id: XenDomainId,
+        disable: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_debug_exceptions(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_cpuid(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError>;
+    fn monitor_privileged_call(&self, domain_id: XenDomainId, enable: bool)
+    -> Result<(), XenError>;
+    fn monitor_emul_unimplemented(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_vmexit(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+    ) -> Result<(), XenError>;
+    fn monitor_io(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError>;
+    fn monitor_emulate_each_rep(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError>;
+}
+
+/// Foreign memory operations (`libxenforeignmemory`).
+pub trait XenForeignMemoryBackend: std::fmt::Debug + Send + Sync {
+    /// Maps `gfns` of a domain into a contiguous virtual address range.
+    ///
+    /// If `err` is provided, per-page errors are reported there and the call
+    /// only fails as a whole if the mapping itself could not be created.
+    fn map(
+        &self,
+        domain_id: XenDomainId,
+        protection: XenForeignMemoryProtection,
+        gfns: &[u64],
+        err: Option<&mut [i32]>,
+    ) -> Result<NonNull<c_void>, XenError>;
+
+    /// Unmaps a range previously returned by [`map`](Self::map).
+    fn unmap(&self, ptr: NonNull<c_void>, pages: usize);
+}
+
+/// Event channel operations (`libxenevtchn`).
+pub trait XenEventChannelBackend: std::fmt::Debug + Send + Sync {
+    /// Returns the file descriptor that becomes readable when a port is pending.
+    fn fd(&self) -> RawFd;
+
+    /// Binds to a remote port and returns the local port.
+    fn bind_interdomain(&self, domain_id: XenDomainId, remote_port: u32) -> Result<u32, XenError>;
+
+    /// Allocates a local port for `domain_id` to bind to.
+    fn bind_unbound_port(&self, domain_id: XenDomainId) -> Result<u32, XenError>;
+
+    /// Binds to the `VIRQ_*` interrupt `virq` and returns the local port.
+    fn bind_virq(&self, virq: u32) -> Result<u32, XenError>;
+
+    /// Restricts the handle to ports of `domain_id`.
+    fn restrict(&self, domain_id: XenDomainId) -> Result<(), XenError>;
+
+    fn unbind(&self, port: u32) -> Result<(), XenError>;
+    fn notify(&self, port: u32) -> Result<(), XenError>;
+
+    /// Blocks until a port is pending and returns it.
+    fn pending(&self) -> Result<u32, XenError>;
+    fn unmask(&self, port: u32) -> Result<(), XenError>;
+}
+
+/// XenStore operations (`libxenstore`).
+pub trait XenStoreBackend: std::fmt::Debug + Send + Sync {
+    fn directory(&self, path: &str) -> Result<Vec<String>, XenError>;
+    fn read(&self, path: &str) -> Result<Vec<u8>, XenError>;
+    fn write(&self, path: &str, value: &[u8]) -> Result<(), XenError>;
+    fn mkdir(&self, path: &str) -> Result<(), XenError>;
+    fn rm(&self, path: &str) -> Result<(), XenError>;
+    fn get_permissions(&self, path: &str) -> Result<Vec<xs_permissions>, XenError>;
+    fn set_permissions(&self, path: &str, permissions: &[xs_permissions]) -> Result<(), XenError>;
+    fn get_domain_path(&self, domain_id: XenDomainId) -> Result<String, XenError>;
+    fn is_domain_introduced(&self, domain_id: XenDomainId) -> Result<bool, XenError>;
+
+    /// Watches `path` and its children, reporting changes with `token`.
+    fn watch(&self, path: &str, token: &str) -> Result<(), XenError>;
+    fn unwatch(&self, path: &str, token: &str) -> Result<(), XenError>;
+
+    /// Returns a file descriptor that is readable while watch events are
+    /// pending (`xs_fileno`).
+    fn watch_fd(&self) -> Result<RawFd, XenError>;
+
+    /// Returns the path and token of the next pending watch event without
+    /// blocking.
+    fn check_watch(&self) -> Result<Option<(String, String)>, XenError>;
+}
+
+/// Device model operations (`libxendevicemodel`).
+pub trait XenDeviceModelBackend: std::fmt::Debug + Send + Sync {
+    #[expect(clippy::too_many_arguments)]
+    fn inject_event(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        vector: XenX86ExceptionVector,
+        event_type: XenX86EventType,
+        error_code: u32,
+        instruction_length: u8,
+        extra: u64,
+    ) -> Result<(), XenError>;
+}
@@ -1,8 +1,6 @@
 mod view;
-use xen_sys::{xc_altp2m_set_domain_state, xc_altp2m_switch_to_view};
-
 pub use self::view::XenAltP2MView;
-use crate::{MemoryAccess, XenDomainId, XenError, ctrl::XenInterface, xc_check_error};
+use crate::{MemoryAccess, XenDomainId, XenError, ctrl::XenInterface};
 pub struct XenAltP2M {
     interface: XenInterface,
     domain_id: XenDomainId,
@@ -10,8 +8,7 @@ pub struct XenAltP2M {
 
 impl XenAltP2M {
     pub(crate) fn new(interface: XenInterface, domain_id: XenDomainId) -> Result<Self, XenError> {
-        let rc = unsafe { xc_altp2m_set_domain_state(interface.handle.0, domain_id.0, true) };
-        xc_check_error!(interface.handle.0, rc);
+        interface.backend.altp2m_set_domain_state(domain_id, true)?;
         Ok(Self {
             interface,
             domain_id,
@@ -23,9 +20,9 @@ impl XenAltP2M {
     }
 
     pub fn reset_view(&self) -> Result<(), XenError> {
-        let rc = unsafe { xc_altp2m_switch_to_view(self.interface.handle.0, self.domain_id.0, 0) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .altp2m_switch_to_view(self.domain_id, 0)
     }
 }
 
@@ -33,8 +30,9 @@ impl Drop for XenAltP2M {
     fn drop(&mut self) {
         tracing::trace!(?self.domain_id, "disabling altp2m");
         let _ = self.reset_view();
-        unsafe {
-            xc_altp2m_set_domain_state(self.interface.handle.0, self.domain_id.0, false);
-        }
+        let _ = self
+            .interface
+            .backend
+            .altp2m_set_domain_state(self.domain_id, false);
     }
 }
@@ -1,9 +1,4 @@
-use xen_sys::{
-    xc_altp2m_change_gfn, xc_altp2m_create_view, xc_altp2m_destroy_view, xc_altp2m_get_mem_access,
-    xc_altp2m_set_mem_access, xc_altp2m_set_mem_access_multi, xc_altp2m_switch_to_view,
-};
-
-use crate::{MemoryAccess, XenDomainId, XenError, ctrl::XenInterface, xc_check_error};
+use crate::{MemoryAccess, XenDomainId, XenError, consts::INVALID_GFN, ctrl::XenInterface};
 
 pub struct XenAltP2MView {
     interface: XenInterface,
@@ -17,19 +12,9 @@ impl XenAltP2MView {
         domain_id: XenDomainId,
         default_access: MemoryAccess,
     ) -> Result<Self, XenError> {
-        let mut view_id = 0;
-        let rc = unsafe {
-            xc_altp2m_create_view(
-                interface.handle.0,
-                domain_id.0,
-                default_access.bits().into(),
-                &mut view_id,
-            )
-        };
-
-        if rc < 0 {
-            return Err(XenError::Io(std::io::Error::last_os_error()));
-        }
+        let view_id = interface
+            .backend
+            .altp2m_create_view(domain_id, default_access)?;
 
         tracing::trace!(domain_id = domain_id.0, view_id, "created altp2m view");
 
@@ -45,46 +30,21 @@ impl XenAltP2MView {
     }
 
     pub fn switch(&self) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_altp2m_switch_to_view(self.interface.handle.0, self.domain_id.0, self.view_id)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .altp2m_switch_to_view(self.domain_id, self.view_id)
     }
 
     pub fn get_mem_access(&self, gfn: u64) -> Result<MemoryAccess, XenError> {
-        let mut access = 0;
-        let rc = unsafe {
-            xc_altp2m_get_mem_access(
-                self.interface.handle.0,
-                self.domain_id.0,
-                self.view_id,
-                gfn,
-                &mut access,
-            )
-        };
-
-        // Preserve errno so callers can distinguish a lazy, unmaterialized
-        // altp2m entry (ESRCH) from other failures.
-        if rc < 0 {
-            return Err(XenError::Io(std::io::Error::last_os_error()));
-        }
-
-        Ok(MemoryAccess::from_bits_truncate(access as _))
+        self.interface
+            .backend
+            .altp2m_get_mem_access(self.domain_id, self.view_id, gfn)
     }
 
     pub fn set_mem_access(&self, gfn: u64, access: MemoryAccess) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_altp2m_set_mem_access(
-                self.interface.handle.0,
-                self.domain_id.0,
-                self.view_id,
-                gfn,
-                access.bits().into(),
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .altp2m_set_mem_access(self.domain_id, self.view_id, gfn, access)
     }
 
     pub fn set_mem_access_multi(
@@ -92,31 +52,31 @@ impl XenAltP2MView {
         access: &[MemoryAccess],
         gfns: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_altp2m_set_mem_access_multi(
-                self.interface.handle.0,
-                self.domain_id.0,
-                self.view_id,
-                access.as_ptr() as *mut u8,
-                gfns.as_ptr() as *mut u64,
-                std::cmp::min(access.len(), gfns.len()) as u32,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.altp2m_set_mem_access_multi(
+            self.domain_id,
+            self.view_id,
+            access,
+            gfns,
+        )
     }
 
+    /// Remaps `old_gfn` to the frame of `new_gfn` in this view, or removes
+    /// the remapping of `old_gfn` if `new_gfn` is [`INVALID_GFN`].
+    ///
+    /// Once remapped, both gfns are dropped from the page caches registered
+    /// for the domain.
     pub fn change_gfn(&self, old_gfn: u64, new_gfn: u64) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_altp2m_change_gfn(
-                self.interface.handle.0,
-                self.domain_id.0,
-                self.view_id,
-                old_gfn,
-                new_gfn,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        self.interface
+            .backend
+            .altp2m_change_gfn(self.domain_id, self.view_id, old_gfn, new_gfn)?;
+
+        for gfn in [old_gfn, new_gfn] {
+            if gfn != INVALID_GFN {
+                self.interface
+                    .invalidate_caches(self.domain_id, gfn..gfn + 1);
+            }
+        }
+
         Ok(())
     }
 }
@@ -129,8 +89,9 @@ impl Drop for XenAltP2MView {
             "destroying altp2m view"
         );
 
-        unsafe {
-            xc_altp2m_destroy_view(self.interface.handle.0, self.domain_id.0, self.view_id);
-        }
+        let _ = self
+            .interface
+            .backend
+            .altp2m_destroy_view(self.domain_id, self.view_id);
     }
 }
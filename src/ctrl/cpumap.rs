@@ -0,0 +1,108 @@
+macro_rules! bitmap {
+    ($(#[$meta:meta])* $name:ident, $item:literal) => {
+        $(#[$meta])*
+        ///
+        /// The map grows as needed, its size is adjusted to the one expected
+        /// by the hypervisor when it is passed to it.
+        #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+        pub struct $name(Vec<u8>);
+
+        impl $name {
+            /// Creates an empty map.
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Creates a map from its raw representation, where bit `n % 8`
+            /// of byte `n / 8` is set for each contained index `n`.
+            pub fn from_bytes(bytes: &[u8]) -> Self {
+                let mut result = Self(bytes.to_vec());
+                result.trim();
+                result
+            }
+
+            /// Returns the raw representation of the map.
+            pub fn as_bytes(&self) -> &[u8] {
+                &self.0
+            }
+
+            #[doc = concat!("Returns `true` if the ", $item, " is in the map.")]
+            pub fn contains(&self, index: u32) -> bool {
+                self.0
+                    .get(index as usize / 8)
+                    .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+            }
+
+            #[doc = concat!("Adds the ", $item, " to the map.")]
+            pub fn insert(&mut self, index: u32) {
+                let byte = index as usize / 8;
+                if byte >= self.0.len() {
+                    self.0.resize(byte + 1, 0);
+                }
+
+                self.0[byte] |= 1 << (index % 8);
+            }
+
+            #[doc = concat!("Removes the ", $item, " from the map.")]
+            pub fn remove(&mut self, index: u32) {
+                if let Some(byte) = self.0.get_mut(index as usize / 8) {
+                    *byte &= !(1 << (index % 8));
+                    self.trim();
+                }
+            }
+
+            /// Returns `true` if the map is empty.
+            pub fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+
+            /// Returns the number of entries in the map.
+            pub fn len(&self) -> usize {
+                self.0.iter().map(|byte| byte.count_ones() as usize).sum()
+            }
+
+            /// Returns the entries of the map in ascending order.
+            pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+                (0..self.0.len() as u32 * 8).filter(|&index| self.contains(index))
+            }
+
+            /// Returns the map resized to `size` bytes, or `None` if it
+            /// contains an entry that does not fit.
+            pub(crate) fn to_sized(&self, size: usize) -> Option<Vec<u8>> {
+                if self.0.len() > size {
+                    return None;
+                }
+
+                let mut result = self.0.clone();
+                result.resize(size, 0);
+                Some(result)
+            }
+
+            fn trim(&mut self) {
+                while self.0.last() == Some(&0) {
+                    self.0.pop();
+                }
+            }
+        }
+
+        impl FromIterator<u32> for $name {
+            fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+                let mut result = Self::new();
+                for index in iter {
+                    result.insert(index);
+                }
+                result
+            }
+        }
+    };
+}
+
+bitmap! {
+    /// Set of physical CPUs (`xc_cpumap_t`).
+    CpuMap, "CPU"
+}
+
+bitmap! {
+    /// Set of NUMA nodes (`xc_nodemap_t`).
+    NodeMap, "node"
+}
@@ -0,0 +1,229 @@
+use xen_sys::{
+    XEN_DOMCTL_ALTP2M_external, XEN_DOMCTL_ALTP2M_limited, XEN_DOMCTL_ALTP2M_mixed,
+    XEN_DOMCTL_CDF_hap, XEN_DOMCTL_CDF_hvm, XEN_DOMCTL_CDF_iommu, XEN_DOMCTL_CDF_nested_virt,
+    XEN_DOMCTL_CDF_oos_off, XEN_DOMCTL_CDF_s3_integrity, XEN_DOMCTL_CDF_trap_unmapped_accesses,
+    XEN_DOMCTL_CDF_vpmu, XEN_DOMCTL_CDF_xs_domain, XEN_DOMCTL_GRANT_version_mask, XEN_X86_EMU_ALL,
+    XEN_X86_EMU_VPCI, xen_domctl_createdomain,
+};
+
+use super::{XenDomainUuid, XenX86Emulation};
+use crate::XenDomainId;
+
+bitflags::bitflags! {
+    /// Flags of a domain to be created (`XEN_DOMCTL_CDF_*`).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct XenDomainCreateFlags: u32 {
+        /// The domain is an HVM (or PVH) guest.
+        const HVM = XEN_DOMCTL_CDF_hvm;
+
+        /// The domain uses hardware assisted paging.
+        const HAP = XEN_DOMCTL_CDF_hap;
+
+        const S3_INTEGRITY = XEN_DOMCTL_CDF_s3_integrity;
+
+        /// Disables out-of-sync shadow page tables.
+        const OOS_OFF = XEN_DOMCTL_CDF_oos_off;
+
+        /// The domain runs the xenstore service.
+        const XS_DOMAIN = XEN_DOMCTL_CDF_xs_domain;
+
+        /// Devices may be passed through to the domain.
+        const IOMMU = XEN_DOMCTL_CDF_iommu;
+
+        /// Nested virtualization is exposed to the domain.
+        const NESTED_VIRT = XEN_DOMCTL_CDF_nested_virt;
+
+        /// Performance counters are exposed to the domain.
+        const VPMU = XEN_DOMCTL_CDF_vpmu;
+
+        const TRAP_UNMAPPED_ACCESSES = XEN_DOMCTL_CDF_trap_unmapped_accesses;
+    }
+}
+
+/// Altp2m mode of a domain to be created (`XEN_DOMCTL_ALTP2M_*`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XenAltP2MMode {
+    /// Altp2m is not available.
+    #[default]
+    Disabled,
+
+    /// Views are controlled by both the guest and the toolstack.
+    Mixed,
+
+    /// Views are controlled by the toolstack only.
+    External,
+
+    /// The guest may only switch between views set up by the toolstack.
+    Limited,
+}
+
+impl From<XenAltP2MMode> for u16 {
+    fn from(value: XenAltP2MMode) -> Self {
+        let mode = match value {
+            XenAltP2MMode::Disabled => 0,
+            XenAltP2MMode::Mixed => XEN_DOMCTL_ALTP2M_mixed,
+            XenAltP2MMode::External => XEN_DOMCTL_ALTP2M_external,
+            XenAltP2MMode::Limited => XEN_DOMCTL_ALTP2M_limited,
+        };
+
+        mode as u16
+    }
+}
+
+/// Configuration of a domain to be created with
+/// [`XenControl::create_domain`](crate::XenControl::create_domain).
+///
+/// A domain is created paused, without memory and without vCPUs. Call
+/// [`XenDomain::set_max_vcpus`](crate::XenDomain::set_max_vcpus) and populate
+/// its memory before unpausing it.
+#[derive(Debug, Clone, Copy)]
+pub struct XenDomainConfig {
+    pub(crate) domain_id: XenDomainId,
+    pub(crate) inner: xen_domctl_createdomain,
+}
+
+impl XenDomainConfig {
+    /// Returns a configuration of an HVM guest using hardware assisted
+    /// paging and the emulated devices of a regular HVM guest.
+    pub fn hvm() -> Self {
+        Self::new(
+            XenDomainCreateFlags::HVM | XenDomainCreateFlags::HAP,
+            XenX86Emulation::from_bits_retain(XEN_X86_EMU_ALL & !XEN_X86_EMU_VPCI),
+        )
+    }
+
+    /// Returns a configuration of a PVH guest.
+    pub fn pvh() -> Self {
+        Self::new(
+            XenDomainCreateFlags::HVM | XenDomainCreateFlags::HAP,
+            XenX86Emulation::LAPIC,
+        )
+    }
+
+    /// Returns a configuration of a PV guest.
+    pub fn pv() -> Self {
+        Self::new(XenDomainCreateFlags::empty(), XenX86Emulation::empty())
+    }
+
+    fn new(flags: XenDomainCreateFlags, emulation_flags: XenX86Emulation) -> Self {
+        let mut inner = xen_domctl_createdomain {
+            flags: flags.bits(),
+            max_vcpus: 1,
+            max_evtchn_port: 1023,
+            max_grant_frames: -1,
+            max_maptrack_frames: -1,
+            grant_opts: 1,
+            ..Default::default()
+        };
+        inner.arch.emulation_flags = emulation_flags.bits();
+
+        Self {
+            domain_id: XenDomainId(0),
+            inner,
+        }
+    }
+
+    /// Requests a specific domain ID instead of letting Xen pick one.
+    pub fn domain_id(mut self, domain_id: XenDomainId) -> Self {
+        self.domain_id = domain_id;
+        self
+    }
+
+    pub fn flags(mut self, flags: XenDomainCreateFlags) -> Self {
+        self.inner.flags = flags.bits();
+        self
+    }
+
+    pub fn handle(mut self, handle: XenDomainUuid) -> Self {
+        self.inner.handle = handle.0;
+        self
+    }
+
+    /// Sets the security identifier of the domain (XSM).
+    pub fn ssidref(mut self, ssidref: u32) -> Self {
+        self.inner.ssidref = ssidref;
+        self
+    }
+
+    /// Sets the maximum number of vCPUs, `1` by default.
+    pub fn max_vcpus(mut self, max_vcpus: u32) -> Self {
+        self.inner.max_vcpus = max_vcpus;
+        self
+    }
+
+    /// Sets the highest event channel port, `1023` by default.
+    pub fn max_evtchn_port(mut self, max_evtchn_port: u32) -> Self {
+        self.inner.max_evtchn_port = max_evtchn_port;
+        self
+    }
+
+    /// Sets the maximum number of grant table and maptrack frames.
+    ///
+    /// `None` keeps the default of the hypervisor.
+    pub fn grant_frames(
+        mut self,
+        max_grant_frames: Option<u32>,
+        max_maptrack_frames: Option<u32>,
+    ) -> Self {
+        self.inner.max_grant_frames = max_grant_frames.map_or(-1, |frames| frames as i32);
+        self.inner.max_maptrack_frames = max_maptrack_frames.map_or(-1, |frames| frames as i32);
+        self
+    }
+
+    /// Sets the highest grant table version the guest may use, `1` by
+    /// default.
+    pub fn max_grant_version(mut self, version: u8) -> Self {
+        self.inner.grant_opts = (self.inner.grant_opts & !XEN_DOMCTL_GRANT_version_mask)
+            | (version as u32 & XEN_DOMCTL_GRANT_version_mask);
+        self
+    }
+
+    pub fn emulation_flags(mut self, emulation_flags: XenX86Emulation) -> Self {
+        self.inner.arch.emulation_flags = emulation_flags.bits();
+        self
+    }
+
+    /// Sets the `XEN_X86_MSR_*` flags.
+    pub fn misc_flags(mut self, misc_flags: u32) -> Self {
+        self.inner.arch.misc_flags = misc_flags;
+        self
+    }
+
+    pub fn altp2m(mut self, mode: XenAltP2MMode) -> Self {
+        self.inner.altp2m.opts = mode.into();
+        self
+    }
+
+    /// Sets the number of altp2m views, `0` keeps the default of the
+    /// hypervisor.
+    pub fn altp2m_views(mut self, count: u16) -> Self {
+        self.inner.altp2m.nr = count;
+        self
+    }
+
+    /// Sets the size of the processor trace buffer of each vCPU in bytes.
+    ///
+    /// Must be a power of two multiple of the page size, `0` disables
+    /// tracing.
+    pub fn vmtrace_size(mut self, size: u32) -> Self {
+        self.inner.vmtrace_size = size;
+        self
+    }
+
+    pub fn cpupool(mut self, cpupool: u32) -> Self {
+        self.inner.cpupool_id = cpupool;
+        self
+    }
+}
+
+impl Default for XenDomainConfig {
+    fn default() -> Self {
+        Self::hvm()
+    }
+}
+
+impl From<XenDomainConfig> for xen_domctl_createdomain {
+    fn from(value: XenDomainConfig) -> Self {
+        value.inner
+    }
+}
@@ -0,0 +1,407 @@
+use xen_sys::{
+    HVM_PARAM_ACPI_IOPORTS_LOCATION, HVM_PARAM_ACPI_S_STATE, HVM_PARAM_ALTP2M,
+    HVM_PARAM_BUFIOREQ_EVTCHN, HVM_PARAM_BUFIOREQ_PFN, HVM_PARAM_CALLBACK_IRQ,
+    HVM_PARAM_CALLBACK_TYPE_GSI, HVM_PARAM_CALLBACK_TYPE_PCI_INTX, HVM_PARAM_CALLBACK_TYPE_VECTOR,
+    HVM_PARAM_CONSOLE_EVTCHN, HVM_PARAM_CONSOLE_PFN, HVM_PARAM_DM_DOMAIN, HVM_PARAM_HPET_ENABLED,
+    HVM_PARAM_IDENT_PT, HVM_PARAM_IOREQ_PFN, HVM_PARAM_IOREQ_SERVER_PFN, HVM_PARAM_MCA_CAP,
+    HVM_PARAM_MONITOR_RING_PFN, HVM_PARAM_NESTEDHVM, HVM_PARAM_NR_IOREQ_SERVER_PAGES,
+    HVM_PARAM_PAE_ENABLED, HVM_PARAM_PAGING_RING_PFN, HVM_PARAM_SHARING_RING_PFN,
+    HVM_PARAM_STORE_EVTCHN, HVM_PARAM_STORE_PFN, HVM_PARAM_TIMER_MODE,
+    HVM_PARAM_TRIPLE_FAULT_REASON, HVM_PARAM_VIRIDIAN, HVM_PARAM_VM_GENERATION_ID_ADDR,
+    HVM_PARAM_VM86_TSS, HVM_PARAM_VM86_TSS_SIZED, HVM_PARAM_VPT_ALIGN, HVM_PARAM_X87_FIP_WIDTH,
+    HVMPTM_delay_for_missed_ticks, HVMPTM_no_delay_for_missed_ticks,
+    HVMPTM_no_missed_ticks_pending, HVMPTM_one_missed_tick_pending, HVMPV_apic_assist,
+    HVMPV_base_freq, HVMPV_cpu_hotplug, HVMPV_crash_ctl, HVMPV_ex_processor_masks, HVMPV_hcall_ipi,
+    HVMPV_hcall_remote_tlb_flush, HVMPV_no_freq, HVMPV_no_vp_limit, HVMPV_reference_tsc,
+    HVMPV_stimer, HVMPV_synic, HVMPV_time_ref_count, XEN_ALTP2M_disabled, XEN_ALTP2M_external,
+    XEN_ALTP2M_limited, XEN_ALTP2M_mixed,
+};
+
+use super::XenAltP2MMode;
+use crate::XenError;
+
+/// An HVM parameter of a domain (`HVM_PARAM_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HvmParam {
+    /// How event channel notifications are delivered to vCPU 0, see
+    /// [`HvmCallbackIrq`].
+    CallbackIrq,
+
+    /// Guest frame of the xenstore ring.
+    StorePfn,
+
+    /// Event channel port of the xenstore ring.
+    StoreEvtchn,
+
+    PaeEnabled,
+
+    /// Guest frame of the synchronous ioreq page of the default ioreq
+    /// server.
+    IoreqPfn,
+
+    /// Guest frame of the buffered ioreq page of the default ioreq server.
+    BufIoreqPfn,
+
+    /// Enabled Viridian enlightenments, see [`HvmViridianFeatures`].
+    Viridian,
+
+    /// See [`HvmTimerMode`].
+    TimerMode,
+
+    HpetEnabled,
+
+    /// Guest physical address of the identity map page table used by
+    /// unpaged real mode.
+    IdentPt,
+
+    /// Domain running the device model.
+    DmDomain,
+
+    /// ACPI sleep state of the domain.
+    AcpiSState,
+
+    /// Guest physical address of the TSS used for virtual 8086 mode.
+    Vm86Tss,
+
+    VptAlign,
+
+    /// Guest frame of the console ring.
+    ConsolePfn,
+
+    /// Event channel port of the console ring.
+    ConsoleEvtchn,
+
+    AcpiIoportsLocation,
+
+    /// Nested virtualization is enabled.
+    NestedHvm,
+
+    /// Event channel port of the buffered ioreq page of the default ioreq
+    /// server.
+    BufIoreqEvtchn,
+
+    /// Guest frame of the paging ring.
+    PagingRingPfn,
+
+    /// Guest frame of the monitor (`vm_event`) ring.
+    MonitorRingPfn,
+
+    /// Guest frame of the sharing ring.
+    SharingRingPfn,
+
+    /// Shutdown reason used when the domain triple faults, see
+    /// [`ShutdownReason`](crate::ctrl::ShutdownReason).
+    TripleFaultReason,
+
+    /// First guest frame reserved for ioreq servers.
+    IoreqServerPfn,
+
+    /// Number of guest frames reserved for ioreq servers.
+    NrIoreqServerPages,
+
+    VmGenerationIdAddr,
+
+    /// Altp2m mode of the domain, see
+    /// [`XenAltP2MMode`](crate::ctrl::XenAltP2MMode).
+    AltP2M,
+
+    X87FipWidth,
+
+    /// Guest physical address and size of the TSS used for virtual 8086
+    /// mode.
+    Vm86TssSized,
+
+    /// Machine check capabilities of the domain.
+    McaCap,
+
+    /// A parameter unknown to this crate.
+    Other(u32),
+}
+
+impl From<HvmParam> for u32 {
+    fn from(value: HvmParam) -> Self {
+        match value {
+            HvmParam::CallbackIrq => HVM_PARAM_CALLBACK_IRQ,
+            HvmParam::StorePfn => HVM_PARAM_STORE_PFN,
+            HvmParam::StoreEvtchn => HVM_PARAM_STORE_EVTCHN,
+            HvmParam::PaeEnabled => HVM_PARAM_PAE_ENABLED,
+            HvmParam::IoreqPfn => HVM_PARAM_IOREQ_PFN,
+            HvmParam::BufIoreqPfn => HVM_PARAM_BUFIOREQ_PFN,
+            HvmParam::Viridian => HVM_PARAM_VIRIDIAN,
+            HvmParam::TimerMode => HVM_PARAM_TIMER_MODE,
+            HvmParam::HpetEnabled => HVM_PARAM_HPET_ENABLED,
+            HvmParam::IdentPt => HVM_PARAM_IDENT_PT,
+            HvmParam::DmDomain => HVM_PARAM_DM_DOMAIN,
+            HvmParam::AcpiSState => HVM_PARAM_ACPI_S_STATE,
+            HvmParam::Vm86Tss => HVM_PARAM_VM86_TSS,
+            HvmParam::VptAlign => HVM_PARAM_VPT_ALIGN,
+            HvmParam::ConsolePfn => HVM_PARAM_CONSOLE_PFN,
+            HvmParam::ConsoleEvtchn => HVM_PARAM_CONSOLE_EVTCHN,
+            HvmParam::AcpiIoportsLocation => HVM_PARAM_ACPI_IOPORTS_LOCATION,
+            HvmParam::NestedHvm => HVM_PARAM_NESTEDHVM,
+            HvmParam::BufIoreqEvtchn => HVM_PARAM_BUFIOREQ_EVTCHN,
+            HvmParam::PagingRingPfn => HVM_PARAM_PAGING_RING_PFN,
+            HvmParam::MonitorRingPfn => HVM_PARAM_MONITOR_RING_PFN,
+            HvmParam::SharingRingPfn => HVM_PARAM_SHARING_RING_PFN,
+            HvmParam::TripleFaultReason => HVM_PARAM_TRIPLE_FAULT_REASON,
+            HvmParam::IoreqServerPfn => HVM_PARAM_IOREQ_SERVER_PFN,
+            HvmParam::NrIoreqServerPages => HVM_PARAM_NR_IOREQ_SERVER_PAGES,
+            HvmParam::VmGenerationIdAddr => HVM_PARAM_VM_GENERATION_ID_ADDR,
+            HvmParam::AltP2M => HVM_PARAM_ALTP2M,
+            HvmParam::X87FipWidth => HVM_PARAM_X87_FIP_WIDTH,
+            HvmParam::Vm86TssSized => HVM_PARAM_VM86_TSS_SIZED,
+            HvmParam::McaCap => HVM_PARAM_MCA_CAP,
+            HvmParam::Other(index) => index,
+        }
+    }
+}
+
+/// How event channel notifications are delivered to vCPU 0
+/// ([`HvmParam::CallbackIrq`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HvmCallbackIrq {
+    /// Notifications are not delivered.
+    #[default]
+    Disabled,
+
+    /// Notifications are delivered through a GSI, which must not be `0`.
+    Gsi(u64),
+
+    /// Notifications are delivered through the INTx line of a PCI device.
+    PciIntx {
+        segment: u16,
+        bus: u16,
+        devfn: u8,
+        intx: u8,
+    },
+
+    /// Notifications are delivered through an interrupt vector.
+    Vector(u8),
+}
+
+const CALLBACK_TYPE_SHIFT: u64 = 56;
+const CALLBACK_VALUE_MASK: u64 = (1 << CALLBACK_TYPE_SHIFT) - 1;
+
+impl TryFrom<u64> for HvmCallbackIrq {
+    type Error = XenError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value == 0 {
+            return Ok(Self::Disabled);
+        }
+
+        let data = value & CALLBACK_VALUE_MASK;
+        match (value >> CALLBACK_TYPE_SHIFT) as u32 {
+            HVM_PARAM_CALLBACK_TYPE_GSI => Ok(Self::Gsi(data)),
+            HVM_PARAM_CALLBACK_TYPE_PCI_INTX => Ok(Self::PciIntx {
+                segment: (data >> 32) as u16,
+                bus: (data >> 16) as u16,
+                devfn: (data >> 8) as u8,
+                intx: (data & 3) as u8,
+            }),
+            HVM_PARAM_CALLBACK_TYPE_VECTOR => Ok(Self::Vector(data as u8)),
+            _ => Err(XenError::Other("unknown callback IRQ type")),
+        }
+    }
+}
+
+impl From<HvmCallbackIrq> for u64 {
+    fn from(value: HvmCallbackIrq) -> Self {
+        let (callback_type, data) = match value {
+            HvmCallbackIrq::Disabled => return 0,
+            HvmCallbackIrq::Gsi(gsi) => (HVM_PARAM_CALLBACK_TYPE_GSI, gsi & CALLBACK_VALUE_MASK),
+            HvmCallbackIrq::PciIntx {
+                segment,
+                bus,
+                devfn,
+                intx,
+            } => (
+                HVM_PARAM_CALLBACK_TYPE_PCI_INTX,
+                (segment as u64) << 32
+                    | (bus as u64) << 16
+                    | (devfn as u64) << 8
+                    | (intx & 3) as u64,
+            ),
+            HvmCallbackIrq::Vector(vector) => (HVM_PARAM_CALLBACK_TYPE_VECTOR, vector as u64),
+        };
+
+        (callback_type as u64) << CALLBACK_TYPE_SHIFT | data
+    }
+}
+
+/// How missed timer ticks are delivered ([`HvmParam::TimerMode`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HvmTimerMode {
+    /// Missed ticks are delivered late, the guest time stays in sync with
+    /// the delivered ticks.
+    #[default]
+    DelayForMissedTicks,
+
+    /// Missed ticks are delivered late, the guest time is not delayed.
+    NoDelayForMissedTicks,
+
+    /// Missed ticks are dropped.
+    NoMissedTicksPending,
+
+    /// Missed ticks are collapsed into a single one.
+    OneMissedTickPending,
+}
+
+#[expect(non_upper_case_globals)]
+impl TryFrom<u64> for HvmTimerMode {
+    type Error = XenError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match u32::try_from(value) {
+            Ok(HVMPTM_delay_for_missed_ticks) => Ok(Self::DelayForMissedTicks),
+            Ok(HVMPTM_no_delay_for_missed_ticks) => Ok(Self::NoDelayForMissedTicks),
+            Ok(HVMPTM_no_missed_ticks_pending) => Ok(Self::NoMissedTicksPending),
+            Ok(HVMPTM_one_missed_tick_pending) => Ok(Self::OneMissedTickPending),
+            _ => Err(XenError::Other("unknown timer mode")),
+        }
+    }
+}
+
+impl From<HvmTimerMode> for u64 {
+    fn from(value: HvmTimerMode) -> Self {
+        let mode = match value {
+            HvmTimerMode::DelayForMissedTicks => HVMPTM_delay_for_missed_ticks,
+            HvmTimerMode::NoDelayForMissedTicks => HVMPTM_no_delay_for_missed_ticks,
+            HvmTimerMode::NoMissedTicksPending => HVMPTM_no_missed_ticks_pending,
+            HvmTimerMode::OneMissedTickPending => HVMPTM_one_missed_tick_pending,
+        };
+
+        mode as u64
+    }
+}
+
+bitflags::bitflags! {
+    /// Viridian enlightenments exposed to the domain (`HVMPV_*`,
+    /// [`HvmParam::Viridian`]).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct HvmViridianFeatures: u64 {
+        const BASE_FREQ = HVMPV_base_freq as u64;
+        const NO_FREQ = HVMPV_no_freq as u64;
+        const TIME_REF_COUNT = HVMPV_time_ref_count as u64;
+        const REFERENCE_TSC = HVMPV_reference_tsc as u64;
+        const HCALL_REMOTE_TLB_FLUSH = HVMPV_hcall_remote_tlb_flush as u64;
+        const APIC_ASSIST = HVMPV_apic_assist as u64;
+        const CRASH_CTL = HVMPV_crash_ctl as u64;
+        const SYNIC = HVMPV_synic as u64;
+        const STIMER = HVMPV_stimer as u64;
+        const HCALL_IPI = HVMPV_hcall_ipi as u64;
+        const EX_PROCESSOR_MASKS = HVMPV_ex_processor_masks as u64;
+        const NO_VP_LIMIT = HVMPV_no_vp_limit as u64;
+        const CPU_HOTPLUG = HVMPV_cpu_hotplug as u64;
+    }
+}
+
+#[expect(non_upper_case_globals)]
+impl TryFrom<u64> for XenAltP2MMode {
+    type Error = XenError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match u32::try_from(value) {
+            Ok(XEN_ALTP2M_disabled) => Ok(Self::Disabled),
+            Ok(XEN_ALTP2M_mixed) => Ok(Self::Mixed),
+            Ok(XEN_ALTP2M_external) => Ok(Self::External),
+            Ok(XEN_ALTP2M_limited) => Ok(Self::Limited),
+            _ => Err(XenError::Other("unknown altp2m mode")),
+        }
+    }
+}
+
+impl From<XenAltP2MMode> for u64 {
+    fn from(value: XenAltP2MMode) -> Self {
+        let mode = match value {
+            XenAltP2MMode::Disabled => XEN_ALTP2M_disabled,
+            XenAltP2MMode::Mixed => XEN_ALTP2M_mixed,
+            XenAltP2MMode::External => XEN_ALTP2M_external,
+            XenAltP2MMode::Limited => XEN_ALTP2M_limited,
+        };
+
+        mode as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_irq() {
+        let cases = [
+            (0, HvmCallbackIrq::Disabled),
+            (0x0000_0000_0000_0005, HvmCallbackIrq::Gsi(5)),
+            (
+                0x00ff_ffff_ffff_ffff,
+                HvmCallbackIrq::Gsi(0x00ff_ffff_ffff_ffff),
+            ),
+            (
+                0x0100_0000_0000_0000,
+                HvmCallbackIrq::PciIntx {
+                    segment: 0,
+                    bus: 0,
+                    devfn: 0,
+                    intx: 0,
+                },
+            ),
+            (
+                0x0100_1234_0056_1f03,
+                HvmCallbackIrq::PciIntx {
+                    segment: 0x1234,
+                    bus: 0x56,
+                    devfn: 0x1f,
+                    intx: 3,
+                },
+            ),
+            (0x0200_0000_0000_00f3, HvmCallbackIrq::Vector(0xf3)),
+        ];
+
+        for (value, irq) in cases {
+            assert_eq!(HvmCallbackIrq::try_from(value).unwrap(), irq, "{value:#x}");
+            assert_eq!(u64::from(irq), value, "{irq:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_callback_irq() {
+        assert!(HvmCallbackIrq::try_from(0xff00_0000_0000_0001).is_err());
+    }
+
+    #[test]
+    fn timer_mode() {
+        let cases = [
+            (0, HvmTimerMode::DelayForMissedTicks),
+            (1, HvmTimerMode::NoDelayForMissedTicks),
+            (2, HvmTimerMode::NoMissedTicksPending),
+            (3, HvmTimerMode::OneMissedTickPending),
+        ];
+
+        for (value, mode) in cases {
+            assert_eq!(HvmTimerMode::try_from(value).unwrap(), mode);
+            assert_eq!(u64::from(mode), value);
+        }
+
+        assert!(HvmTimerMode::try_from(4).is_err());
+        assert!(HvmTimerMode::try_from(1 << 32).is_err());
+    }
+
+    #[test]
+    fn altp2m_mode() {
+        let cases = [
+            (0, XenAltP2MMode::Disabled),
+            (1, XenAltP2MMode::Mixed),
+            (2, XenAltP2MMode::External),
+            (3, XenAltP2MMode::Limited),
+        ];
+
+        for (value, mode) in cases {
+            assert_eq!(XenAltP2MMode::try_from(value).unwrap(), mode);
+            assert_eq!(u64::from(mode), value);
+        }
+
+        assert!(XenAltP2MMode::try_from(4).is_err());
+        assert!(XenAltP2MMode::try_from(1 << 32).is_err());
+    }
+}
@@ -1,6 +1,181 @@
-use xen_sys::xen_domctl_getdomaininfo;
+use xen_sys::{
+    SHUTDOWN_crash, SHUTDOWN_poweroff, SHUTDOWN_reboot, SHUTDOWN_soft_reset, SHUTDOWN_suspend,
+    SHUTDOWN_watchdog, XEN_DOMINF_blocked, XEN_DOMINF_debugged, XEN_DOMINF_dying, XEN_DOMINF_hap,
+    XEN_DOMINF_hvm_guest, XEN_DOMINF_paused, XEN_DOMINF_running, XEN_DOMINF_shutdown,
+    XEN_DOMINF_shutdownmask, XEN_DOMINF_shutdownshift, XEN_DOMINF_xs_domain, XEN_X86_EMU_HPET,
+    XEN_X86_EMU_IOAPIC, XEN_X86_EMU_IOMMU, XEN_X86_EMU_LAPIC, XEN_X86_EMU_PIC, XEN_X86_EMU_PIT,
+    XEN_X86_EMU_PM, XEN_X86_EMU_RTC, XEN_X86_EMU_USE_PIRQ, XEN_X86_EMU_VGA, XEN_X86_EMU_VPCI,
+    xen_arch_domainconfig, xen_domain_handle_t, xen_domctl_getdomaininfo,
+};
 
+use crate::XenDomainId;
+
+bitflags::bitflags! {
+    /// State of a domain (`XEN_DOMINF_*`).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct XenDomainFlags: u32 {
+        /// The domain is being destroyed.
+        const DYING = XEN_DOMINF_dying;
+
+        /// The domain is an HVM guest.
+        const HVM_GUEST = XEN_DOMINF_hvm_guest;
+
+        /// The domain has shut down, see [`XenDomainInfo::shutdown_reason`].
+        const SHUTDOWN = XEN_DOMINF_shutdown;
+
+        /// The domain is paused by the toolstack.
+        const PAUSED = XEN_DOMINF_paused;
+
+        /// All vCPUs of the domain are blocked.
+        const BLOCKED = XEN_DOMINF_blocked;
+
+        /// At least one vCPU of the domain is running.
+        const RUNNING = XEN_DOMINF_running;
+
+        /// The domain is being debugged.
+        const DEBUGGED = XEN_DOMINF_debugged;
+
+        /// The domain runs the xenstore service.
+        const XS_DOMAIN = XEN_DOMINF_xs_domain;
+
+        /// The domain uses hardware assisted paging.
+        const HAP = XEN_DOMINF_hap;
+    }
+}
+
+/// Reason a domain has shut down (`SHUTDOWN_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShutdownReason {
+    /// The domain has powered off.
+    Poweroff,
+
+    /// The domain asked to be rebooted.
+    Reboot,
+
+    /// The domain has suspended itself.
+    Suspend,
+
+    /// The domain has crashed.
+    Crash,
+
+    /// The watchdog of the domain has fired.
+    Watchdog,
+
+    /// The domain asked for a soft reset.
+    SoftReset,
+
+    /// A reason unknown to this crate.
+    Unknown(u32),
+}
+
+#[expect(non_upper_case_globals)]
+impl From<u32> for ShutdownReason {
+    fn from(value: u32) -> Self {
+        match value {
+            SHUTDOWN_poweroff => Self::Poweroff,
+            SHUTDOWN_reboot => Self::Reboot,
+            SHUTDOWN_suspend => Self::Suspend,
+            SHUTDOWN_crash => Self::Crash,
+            SHUTDOWN_watchdog => Self::Watchdog,
+            SHUTDOWN_soft_reset => Self::SoftReset,
+            value => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<ShutdownReason> for u32 {
+    fn from(value: ShutdownReason) -> Self {
+        match value {
+            ShutdownReason::Poweroff => SHUTDOWN_poweroff,
+            ShutdownReason::Reboot => SHUTDOWN_reboot,
+            ShutdownReason::Suspend => SHUTDOWN_suspend,
+            ShutdownReason::Crash => SHUTDOWN_crash,
+            ShutdownReason::Watchdog => SHUTDOWN_watchdog,
+            ShutdownReason::SoftReset => SHUTDOWN_soft_reset,
+            ShutdownReason::Unknown(value) => value,
+        }
+    }
+}
+
+/// UUID of a domain (`xen_domain_handle_t`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XenDomainUuid(pub xen_domain_handle_t);
+
+impl XenDomainUuid {
+    /// Returns `true` if the UUID is all zeroes, i.e. it was never set.
+    pub fn is_nil(&self) -> bool {
+        self.0 == [0; 16]
+    }
+}
+
+impl std::fmt::Display for XenDomainUuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if matches!(index, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+bitflags::bitflags! {
+    /// Devices emulated by Xen for an x86 domain (`XEN_X86_EMU_*`).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct XenX86Emulation: u32 {
+        const LAPIC = XEN_X86_EMU_LAPIC;
+        const HPET = XEN_X86_EMU_HPET;
+        const PM = XEN_X86_EMU_PM;
+        const RTC = XEN_X86_EMU_RTC;
+        const IOAPIC = XEN_X86_EMU_IOAPIC;
+        const PIC = XEN_X86_EMU_PIC;
+        const VGA = XEN_X86_EMU_VGA;
+        const IOMMU = XEN_X86_EMU_IOMMU;
+        const PIT = XEN_X86_EMU_PIT;
+        const USE_PIRQ = XEN_X86_EMU_USE_PIRQ;
+        const VPCI = XEN_X86_EMU_VPCI;
+    }
+}
+
+/// Architecture specific configuration of a domain.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct XenArchDomainConfig {
+    pub emulation_flags: XenX86Emulation,
+
+    /// `XEN_X86_MSR_*` flags.
+    pub misc_flags: u32,
+}
+
+impl From<xen_arch_domainconfig> for XenArchDomainConfig {
+    fn from(value: xen_arch_domainconfig) -> Self {
+        Self {
+            emulation_flags: XenX86Emulation::from_bits_retain(value.emulation_flags),
+            misc_flags: value.misc_flags,
+        }
+    }
+}
+
+impl From<XenArchDomainConfig> for xen_arch_domainconfig {
+    fn from(value: XenArchDomainConfig) -> Self {
+        Self {
+            emulation_flags: value.emulation_flags.bits(),
+            misc_flags: value.misc_flags,
+        }
+    }
+}
+
+/// Information about a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct XenDomainInfo {
+    pub domain_id: XenDomainId,
+    pub flags: XenDomainFlags,
+
+    /// Reason of the shutdown, if the domain has shut down.
+    pub shutdown_reason: Option<ShutdownReason>,
+
     pub total_pages: u64,
     pub max_pages: u64,
     pub outstanding_pages: u64,
@@ -10,11 +185,76 @@ pub struct XenDomainInfo {
     pub cpu_time: u64,
     pub nr_online_vcpus: u32,
     pub max_vcpu_id: u16,
+
+    /// Security identifier of the domain (XSM).
+    pub ssidref: u32,
+
+    pub handle: XenDomainUuid,
+
+    /// CPU pool the domain belongs to.
+    pub cpupool: u32,
+
+    /// Width of the guest physical addresses.
+    pub gpaddr_bits: u8,
+
+    pub arch_config: XenArchDomainConfig,
+}
+
+impl XenDomainInfo {
+    /// Returns `true` if the domain is being destroyed.
+    pub fn is_dying(&self) -> bool {
+        self.flags.contains(XenDomainFlags::DYING)
+    }
+
+    /// Returns `true` if the domain has shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.flags.contains(XenDomainFlags::SHUTDOWN)
+    }
+
+    /// Returns `true` if the domain is paused.
+    pub fn is_paused(&self) -> bool {
+        self.flags.contains(XenDomainFlags::PAUSED)
+    }
+
+    /// Returns `true` if all vCPUs of the domain are blocked.
+    pub fn is_blocked(&self) -> bool {
+        self.flags.contains(XenDomainFlags::BLOCKED)
+    }
+
+    /// Returns `true` if at least one vCPU of the domain is running.
+    pub fn is_running(&self) -> bool {
+        self.flags.contains(XenDomainFlags::RUNNING)
+    }
+
+    /// Returns `true` if the domain is an HVM guest.
+    pub fn is_hvm(&self) -> bool {
+        self.flags.contains(XenDomainFlags::HVM_GUEST)
+    }
+
+    /// Returns `true` if the domain is a PV guest.
+    pub fn is_pv(&self) -> bool {
+        !self.is_hvm()
+    }
+
+    /// Returns `true` if the domain is being debugged.
+    pub fn is_debugged(&self) -> bool {
+        self.flags.contains(XenDomainFlags::DEBUGGED)
+    }
 }
 
 impl From<xen_domctl_getdomaininfo> for XenDomainInfo {
     fn from(value: xen_domctl_getdomaininfo) -> Self {
+        let flags = XenDomainFlags::from_bits_truncate(value.flags);
+        let shutdown_reason = flags.contains(XenDomainFlags::SHUTDOWN).then(|| {
+            ShutdownReason::from(
+                (value.flags >> XEN_DOMINF_shutdownshift) & XEN_DOMINF_shutdownmask,
+            )
+        });
+
         Self {
+            domain_id: XenDomainId(value.domain as u32),
+            flags,
+            shutdown_reason,
             total_pages: value.tot_pages,
             max_pages: value.max_pages,
             outstanding_pages: value.outstanding_pages,
@@ -24,6 +264,11 @@ impl From<xen_domctl_getdomaininfo> for XenDomainInfo {
             cpu_time: value.cpu_time,
             nr_online_vcpus: value.nr_online_vcpus,
             max_vcpu_id: value.max_vcpu_id as _,
+            ssidref: value.ssidref,
+            handle: XenDomainUuid(value.handle),
+            cpupool: value.cpupool,
+            gpaddr_bits: value.gpaddr_bits,
+            arch_config: value.arch_config.into(),
         }
     }
 }
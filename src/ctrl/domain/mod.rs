@@ -1,16 +1,31 @@
+mod config;
+mod hvm_param;
 mod info;
-use xen_sys::{
-    xc_domain_debug_control, xc_domain_decrease_reservation, xc_domain_decrease_reservation_exact,
-    xc_domain_getinfolist, xc_domain_increase_reservation, xc_domain_increase_reservation_exact,
-    xc_domain_maximum_gpfn, xc_domain_pause, xc_domain_populate_physmap,
-    xc_domain_populate_physmap_exact, xc_domain_set_access_required, xc_domain_setmaxmem,
-    xc_domain_unpause, xc_get_mem_access, xc_set_mem_access, xen_domctl_getdomaininfo,
-};
+mod pause;
+mod vcpu;
+use std::sync::{Arc, PoisonError};
+
+use xen_sys::{XEN_VCPUAFFINITY_HARD, XEN_VCPUAFFINITY_SOFT, xen_domctl_getdomaininfo};
 
-pub use self::info::XenDomainInfo;
+pub use self::{
+    config::{XenAltP2MMode, XenDomainConfig, XenDomainCreateFlags},
+    hvm_param::{HvmCallbackIrq, HvmParam, HvmTimerMode, HvmViridianFeatures},
+    info::{
+        ShutdownReason, XenArchDomainConfig, XenDomainFlags, XenDomainInfo, XenDomainUuid,
+        XenX86Emulation,
+    },
+    pause::XenDomainPauseGuard,
+    vcpu::{XenVcpuAffinity, XenVcpuInfo},
+};
 use crate::{
-    Architecture, MemoryAccess, VcpuId, XenAltP2M, XenDeviceModel, XenDomainId, XenError,
-    XenInterface, XenMonitor, ctrl::VmEventRing, xc_check_error,
+    Architecture, MemoryAccess, MonitorLoop, VcpuId, XenAltP2M, XenDeviceModel, XenDomainId,
+    XenError, XenInterface, XenMonitor,
+    ctrl::{
+        CpuMap, IntrospectionCapabilities, MonitorCapabilities, NodeMap, VmEventRing, XenPhysInfo,
+    },
+    error::ErrorKind,
+    evtchn::XenEventChannelStatus,
+    foreignmemory::{XenForeignMemory, XenForeignMemoryCache},
 };
 
 pub struct XenDomain<Arch>
@@ -39,92 +54,295 @@ where
     }
 
     pub fn info(&self) -> Result<XenDomainInfo, XenError> {
-        let mut info = xen_domctl_getdomaininfo::default();
-        let rc = unsafe {
-            xc_domain_getinfolist(
-                self.interface.handle.0,
-                self.domain_id.0,
-                1,
-                &mut info as *mut _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
+        let mut info = [xen_domctl_getdomaininfo::default()];
+        let count = self
+            .interface
+            .backend
+            .domain_getinfolist(self.domain_id, &mut info)?;
+
+        // `xc_domain_getinfolist` starts at the given domain, but returns the
+        // next existing one if the domain is gone.
+        let [info] = info;
+        if count == 0 || info.domain as u32 != self.domain_id.0 {
+            return Err(std::io::Error::from_raw_os_error(libc::ESRCH).into());
+        }
+
         Ok(info.into())
     }
 
+    /// Destroys the domain.
+    ///
+    /// The domain becomes dying and disappears once all its resources
+    /// (e.g. foreign mappings) are released.
+    pub fn destroy(&self) -> Result<(), XenError> {
+        self.interface.backend.domain_destroy(self.domain_id)
+    }
+
+    /// Shuts the domain down as if it did so itself with `reason`.
+    pub fn shutdown(&self, reason: ShutdownReason) -> Result<(), XenError> {
+        self.interface
+            .backend
+            .domain_shutdown(self.domain_id, reason.into())
+    }
+
+    /// Resets the domain after it has shut down with
+    /// [`ShutdownReason::SoftReset`], keeping its memory.
+    pub fn soft_reset(&self) -> Result<(), XenError> {
+        self.interface.backend.domain_soft_reset(self.domain_id)
+    }
+
+    /// Brings up `max` vCPUs, at most as many as the domain was created
+    /// with.
+    pub fn set_max_vcpus(&self, max: u32) -> Result<(), XenError> {
+        self.interface.backend.domain_max_vcpus(self.domain_id, max)
+    }
+
+    pub fn set_handle(&self, handle: XenDomainUuid) -> Result<(), XenError> {
+        self.interface
+            .backend
+            .domain_sethandle(self.domain_id, handle.0)
+    }
+
+    /// Returns information about all vCPUs of the domain.
+    pub fn vcpus(&self) -> Result<Vec<XenVcpuInfo>, XenError> {
+        let info = self.info()?;
+        let mut result = Vec::new();
+
+        for vcpu in 0..=info.max_vcpu_id {
+            match self.vcpu_info(VcpuId(vcpu)) {
+                Ok(info) => result.push(info),
+
+                // The vCPU has not been brought up yet.
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn vcpu_info(&self, vcpu: VcpuId) -> Result<XenVcpuInfo, XenError> {
+        Ok(self
+            .interface
+            .backend
+            .vcpu_getinfo(self.domain_id, vcpu)?
+            .into())
+    }
+
+    /// Returns the physical CPUs the vCPU may run on.
+    pub fn vcpu_affinity(&self, vcpu: VcpuId) -> Result<XenVcpuAffinity, XenError> {
+        let size = self.interface.backend.cpumap_size()?;
+        let mut hard = vec![0; size];
+        let mut soft = vec![0; size];
+
+        self.interface.backend.vcpu_getaffinity(
+            self.domain_id,
+            vcpu,
+            &mut hard,
+            &mut soft,
+            XEN_VCPUAFFINITY_HARD | XEN_VCPUAFFINITY_SOFT,
+        )?;
+
+        Ok(XenVcpuAffinity {
+            hard: CpuMap::from_bytes(&hard),
+            soft: CpuMap::from_bytes(&soft),
+        })
+    }
+
+    /// Sets the hard and/or the soft affinity of the vCPU, leaving the one
+    /// passed as `None` unchanged.
+    ///
+    /// Returns the effective affinity, i.e. the requested one restricted to
+    /// the online CPUs.
+    pub fn set_vcpu_affinity(
+        &self,
+        vcpu: VcpuId,
+        hard: Option<&CpuMap>,
+        soft: Option<&CpuMap>,
+    ) -> Result<XenVcpuAffinity, XenError> {
+        let size = self.interface.backend.cpumap_size()?;
+        let to_sized = |map: Option<&CpuMap>| match map {
+            Some(map) => map
+                .to_sized(size)
+                .ok_or(XenError::Other("CPU map exceeds the number of host CPUs")),
+            None => Ok(vec![0; size]),
+        };
+
+        let mut flags = 0;
+        if hard.is_some() {
+            flags |= XEN_VCPUAFFINITY_HARD;
+        }
+        if soft.is_some() {
+            flags |= XEN_VCPUAFFINITY_SOFT;
+        }
+
+        let mut hard = to_sized(hard)?;
+        let mut soft = to_sized(soft)?;
+        self.interface.backend.vcpu_setaffinity(
+            self.domain_id,
+            vcpu,
+            &mut hard,
+            &mut soft,
+            flags,
+        )?;
+
+        Ok(XenVcpuAffinity {
+            hard: CpuMap::from_bytes(&hard),
+            soft: CpuMap::from_bytes(&soft),
+        })
+    }
+
+    /// Returns the NUMA nodes the memory of the domain is preferably
+    /// allocated from.
+    pub fn node_affinity(&self) -> Result<NodeMap, XenError> {
+        let mut nodemap = vec![0; self.interface.backend.nodemap_size()?];
+        self.interface
+            .backend
+            .domain_node_getaffinity(self.domain_id, &mut nodemap)?;
+        Ok(NodeMap::from_bytes(&nodemap))
+    }
+
+    pub fn set_node_affinity(&self, nodes: &NodeMap) -> Result<(), XenError> {
+        let mut nodemap = nodes
+            .to_sized(self.interface.backend.nodemap_size()?)
+            .ok_or(XenError::Other("node map exceeds the number of host nodes"))?;
+        self.interface
+            .backend
+            .domain_node_setaffinity(self.domain_id, &mut nodemap)
+    }
+
     pub fn maximum_gpfn(&self) -> Result<u64, XenError> {
-        let mut gpfn = 0;
-        let rc =
-            unsafe { xc_domain_maximum_gpfn(self.interface.handle.0, self.domain_id.0, &mut gpfn) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(gpfn)
+        self.interface.backend.domain_maximum_gpfn(self.domain_id)
+    }
+
+    /// Returns the raw value of an HVM parameter.
+    pub fn hvm_param(&self, param: HvmParam) -> Result<u64, XenError> {
+        self.interface
+            .backend
+            .hvm_param_get(self.domain_id, param.into())
+    }
+
+    /// Sets the raw value of an HVM parameter.
+    pub fn set_hvm_param(&self, param: HvmParam, value: u64) -> Result<(), XenError> {
+        self.interface
+            .backend
+            .hvm_param_set(self.domain_id, param.into(), value)
+    }
+
+    pub fn callback_irq(&self) -> Result<HvmCallbackIrq, XenError> {
+        self.hvm_param(HvmParam::CallbackIrq)?.try_into()
+    }
+
+    pub fn set_callback_irq(&self, callback: HvmCallbackIrq) -> Result<(), XenError> {
+        self.set_hvm_param(HvmParam::CallbackIrq, callback.into())
+    }
+
+    pub fn altp2m_mode(&self) -> Result<XenAltP2MMode, XenError> {
+        self.hvm_param(HvmParam::AltP2M)?.try_into()
+    }
+
+    /// Switches the altp2m mode of the domain.
+    ///
+    /// Xen only allows this while altp2m is not active, and not at all on
+    /// versions where the mode is fixed at domain creation.
+    pub fn set_altp2m_mode(&self, mode: XenAltP2MMode) -> Result<(), XenError> {
+        self.set_hvm_param(HvmParam::AltP2M, mode.into())
+    }
+
+    pub fn timer_mode(&self) -> Result<HvmTimerMode, XenError> {
+        self.hvm_param(HvmParam::TimerMode)?.try_into()
+    }
+
+    pub fn set_timer_mode(&self, mode: HvmTimerMode) -> Result<(), XenError> {
+        self.set_hvm_param(HvmParam::TimerMode, mode.into())
+    }
+
+    pub fn viridian_features(&self) -> Result<HvmViridianFeatures, XenError> {
+        Ok(HvmViridianFeatures::from_bits_retain(
+            self.hvm_param(HvmParam::Viridian)?,
+        ))
+    }
+
+    pub fn set_viridian_features(&self, features: HvmViridianFeatures) -> Result<(), XenError> {
+        self.set_hvm_param(HvmParam::Viridian, features.bits())
+    }
+
+    /// Returns how the domain shuts down when it triple faults.
+    pub fn triple_fault_reason(&self) -> Result<ShutdownReason, XenError> {
+        Ok(ShutdownReason::from(
+            self.hvm_param(HvmParam::TripleFaultReason)? as u32,
+        ))
+    }
+
+    pub fn set_triple_fault_reason(&self, reason: ShutdownReason) -> Result<(), XenError> {
+        self.set_hvm_param(HvmParam::TripleFaultReason, u32::from(reason) as u64)
+    }
+
+    /// Allocates an event channel port of the domain for `remote_domain_id`
+    /// to bind to, e.g. with
+    /// [`XenEventChannel::bind_interdomain`](crate::evtchn::XenEventChannel::bind_interdomain).
+    pub fn alloc_unbound_port(&self, remote_domain_id: XenDomainId) -> Result<u32, XenError> {
+        self.interface
+            .backend
+            .evtchn_alloc_unbound(self.domain_id, remote_domain_id)
+    }
+
+    /// Returns what the event channel `port` of the domain is connected to.
+    pub fn event_channel_status(&self, port: u32) -> Result<XenEventChannelStatus, XenError> {
+        self.interface
+            .backend
+            .evtchn_status(self.domain_id, port)?
+            .try_into()
+    }
+
+    /// Closes all event channel ports of the domain.
+    pub fn reset_event_channels(&self) -> Result<(), XenError> {
+        self.interface.backend.evtchn_reset(self.domain_id)
     }
 
     pub fn pause(&self) -> Result<(), XenError> {
-        let rc = unsafe { xc_domain_pause(self.interface.handle.0, self.domain_id.0) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.domain_pause(self.domain_id)
     }
 
     pub fn unpause(&self) -> Result<(), XenError> {
-        let rc = unsafe { xc_domain_unpause(self.interface.handle.0, self.domain_id.0) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.domain_unpause(self.domain_id)
+    }
+
+    /// Pauses the domain until the returned guard is dropped.
+    ///
+    /// Guards nest: only the outermost guard of a domain pauses and unpauses
+    /// it, across all [`XenDomain`] instances sharing the same
+    /// [`XenInterface`].
+    pub fn paused(&self) -> Result<XenDomainPauseGuard<'_, Arch>, XenError> {
+        XenDomainPauseGuard::new(self)
     }
 
     pub fn get_mem_access(&self, gfn: u64) -> Result<MemoryAccess, XenError> {
-        let mut access = 0;
-        let rc = unsafe {
-            xc_get_mem_access(self.interface.handle.0, self.domain_id.0, gfn, &mut access)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(MemoryAccess::from_bits_truncate(access as _))
+        self.interface.backend.get_mem_access(self.domain_id, gfn)
     }
 
     pub fn set_mem_access(&self, gfn: u64, access: MemoryAccess) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_set_mem_access(
-                self.interface.handle.0,
-                self.domain_id.0,
-                access.bits().into(),
-                gfn,
-                1,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .set_mem_access(self.domain_id, access, gfn, 1)
     }
 
     pub fn set_access_required(&self, required: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_set_access_required(
-                self.interface.handle.0,
-                self.domain_id.0,
-                required.into(),
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .domain_set_access_required(self.domain_id, required)
     }
 
     pub fn debug_control(&self, vcpu: VcpuId, operation: u32) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_debug_control(
-                self.interface.handle.0,
-                self.domain_id.0,
-                operation,
-                vcpu.0.into(),
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .domain_debug_control(self.domain_id, operation, vcpu)
     }
 
     pub fn set_max_mem(&self, max_memkb: u64) -> Result<(), XenError> {
-        let rc =
-            unsafe { xc_domain_setmaxmem(self.interface.handle.0, self.domain_id.0, max_memkb) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .domain_setmaxmem(self.domain_id, max_memkb)
     }
 
     pub fn increase_reservation(
@@ -133,18 +351,13 @@ where
         mem_flags: u32,
         extents: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_increase_reservation(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                mem_flags,
-                extents.as_ptr() as *mut _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.domain_increase_reservation(
+            self.domain_id,
+            extent_order,
+            mem_flags,
+            extents,
+            false,
+        )
     }
 
     pub fn increase_reservation_exact(
@@ -153,32 +366,29 @@ where
         mem_flags: u32,
         extents: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_increase_reservation_exact(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                mem_flags,
-                extents.as_ptr() as *mut _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.domain_increase_reservation(
+            self.domain_id,
+            extent_order,
+            mem_flags,
+            extents,
+            true,
+        )
     }
 
+    /// Releases the extents from the domain.
+    ///
+    /// The released gfns are dropped from the page caches registered for the
+    /// domain.
     pub fn decrease_reservation(&self, extent_order: u32, extents: &[u64]) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_decrease_reservation(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                extents.as_ptr() as *mut _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        let result = self.interface.backend.domain_decrease_reservation(
+            self.domain_id,
+            extent_order,
+            extents,
+            false,
+        );
+
+        self.invalidate_extents(extent_order, extents);
+        result
     }
 
     pub fn decrease_reservation_exact(
@@ -186,17 +396,48 @@ where
         extent_order: u32,
         extents: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_decrease_reservation_exact(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                extents.as_ptr() as *mut _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        let result = self.interface.backend.domain_decrease_reservation(
+            self.domain_id,
+            extent_order,
+            extents,
+            true,
+        );
+
+        self.invalidate_extents(extent_order, extents);
+        result
+    }
+
+    /// Creates a cache of up to `capacity` read-only pages of the domain,
+    /// mapped through `foreignmemory`.
+    ///
+    /// The cache is kept coherent with changes to the physical memory map
+    /// made through this interface until it is dropped.
+    pub fn foreign_memory_cache(
+        &self,
+        foreignmemory: &XenForeignMemory,
+        capacity: usize,
+    ) -> XenForeignMemoryCache {
+        let cache = XenForeignMemoryCache::new(foreignmemory.clone(), self.domain_id, capacity);
+
+        self.interface
+            .caches
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::downgrade(&cache.shared));
+
+        cache
+    }
+
+    /// Drops the gfns of `extents` from the registered page caches.
+    ///
+    /// Called after the extents are released, also if releasing failed, as
+    /// some of them may have been released before the failure. Invalidating
+    /// before would let concurrent lookups cache the released frames again.
+    fn invalidate_extents(&self, extent_order: u32, extents: &[u64]) {
+        for &gfn in extents {
+            let end = gfn.saturating_add(1 << extent_order);
+            self.interface.invalidate_caches(self.domain_id, gfn..end);
+        }
     }
 
     pub fn populate_physmap(
@@ -205,18 +446,13 @@ where
         mem_flags: u32,
         extents: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_populate_physmap(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                mem_flags,
-                extents.as_ptr() as _,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.domain_populate_physmap(
+            self.domain_id,
+            extent_order,
+            mem_flags,
+            extents,
+            false,
+        )
     }
 
     pub fn populate_physmap_exact(
@@ -225,18 +461,42 @@ where
         mem_flags: u32,
         extents: &[u64],
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_domain_populate_physmap_exact(
-                self.interface.handle.0,
-                self.domain_id.0,
-                extents.len() as u64,
-                extent_order,
-                mem_flags,
-                extents.as_ptr() as _,
-            )
+        self.interface.backend.domain_populate_physmap(
+            self.domain_id,
+            extent_order,
+            mem_flags,
+            extents,
+            true,
+        )
+    }
+
+    /// Returns the introspection features available for the domain.
+    pub fn introspection_capabilities(&self) -> Result<IntrospectionCapabilities, XenError> {
+        let backend = &self.interface.backend;
+
+        // Xen refuses the query if altp2m is disabled for the domain or
+        // unsupported by the host.
+        let altp2m = match backend.altp2m_get_domain_state(self.domain_id) {
+            Ok(state) => state,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::InvalidInput | ErrorKind::NotSupported
+                ) =>
+            {
+                false
+            }
+            Err(err) => return Err(err),
         };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+
+        Ok(IntrospectionCapabilities {
+            monitor: MonitorCapabilities::from_bits_retain(
+                backend.monitor_get_capabilities(self.domain_id)?,
+            ),
+            altp2m,
+            vm_event_version: backend.vm_event_get_version()?,
+            vmtrace: XenPhysInfo::from(backend.physinfo()?).vmtrace(),
+        })
     }
 
     pub fn altp2m(&self) -> Result<XenAltP2M, XenError> {
@@ -247,7 +507,13 @@ where
         XenMonitor::new(self.interface.clone(), self.domain_id)
     }
 
+    /// Enables monitoring and creates a [`MonitorLoop`] for the events.
+    pub fn monitor_loop<'a>(&self) -> Result<MonitorLoop<'a>, XenError> {
+        let (monitor, ring) = self.monitor()?;
+        MonitorLoop::new(monitor, ring)
+    }
+
     pub fn device_model(&self) -> Result<XenDeviceModel, XenError> {
-        XenDeviceModel::new(self.domain_id)
+        XenDeviceModel::new(self.interface.backend.open_device_model()?, self.domain_id)
     }
 }
@@ -0,0 +1,130 @@
+use std::sync::PoisonError;
+
+use super::XenDomain;
+use crate::{Architecture, XenError};
+
+/// Keeps a domain paused until it is dropped, see [`XenDomain::paused`].
+pub struct XenDomainPauseGuard<'a, Arch>
+where
+    Arch: Architecture,
+{
+    domain: &'a XenDomain<Arch>,
+    active: bool,
+}
+
+impl<'a, Arch> XenDomainPauseGuard<'a, Arch>
+where
+    Arch: Architecture,
+{
+    pub(super) fn new(domain: &'a XenDomain<Arch>) -> Result<Self, XenError> {
+        let mut pauses = domain
+            .interface
+            .pauses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        match pauses.get_mut(&domain.domain_id) {
+            Some(count) => *count += 1,
+            None => {
+                domain.pause()?;
+                pauses.insert(domain.domain_id, 1);
+            }
+        }
+
+        Ok(Self {
+            domain,
+            active: true,
+        })
+    }
+
+    /// Releases the guard, unpausing the domain if this is the outermost
+    /// guard, and returns the error of the unpause if any.
+    pub fn unpause(mut self) -> Result<(), XenError> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<(), XenError> {
+        if !std::mem::take(&mut self.active) {
+            return Ok(());
+        }
+
+        let domain_id = self.domain.domain_id;
+        let mut pauses = self
+            .domain
+            .interface
+            .pauses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let count = pauses.get_mut(&domain_id).expect("pause count");
+
+        *count -= 1;
+        if *count > 0 {
+            return Ok(());
+        }
+
+        pauses.remove(&domain_id);
+        self.domain.unpause()
+    }
+}
+
+impl<Arch> Drop for XenDomainPauseGuard<'_, Arch>
+where
+    Arch: Architecture,
+{
+    fn drop(&mut self) {
+        if let Err(err) = self.release() {
+            tracing::error!(
+                domain_id = self.domain.domain_id.0,
+                %err,
+                "failed to unpause domain"
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{XenDomainId, arch::x86::Amd64, backend::mock::MockHypervisor};
+
+    #[test]
+    fn nested_guards() {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+
+        let outer = domain.paused().unwrap();
+        let inner = domain.paused().unwrap();
+        assert!(hypervisor.is_paused(XenDomainId(1)).unwrap());
+
+        inner.unpause().unwrap();
+        assert!(hypervisor.is_paused(XenDomainId(1)).unwrap());
+
+        drop(outer);
+        assert!(!hypervisor.is_paused(XenDomainId(1)).unwrap());
+    }
+
+    #[test]
+    fn failed_pause() {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+
+        hypervisor.destroy_domain(XenDomainId(1)).unwrap();
+        assert!(domain.paused().is_err());
+        assert!(domain.interface.pauses.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,48 @@
+use xen_sys::xen_domctl_getvcpuinfo;
+
+use crate::{VcpuId, ctrl::CpuMap};
+
+/// Information about a vCPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XenVcpuInfo {
+    pub vcpu: VcpuId,
+
+    /// The vCPU is brought up.
+    pub online: bool,
+
+    /// The vCPU is waiting for an event.
+    pub blocked: bool,
+
+    /// The vCPU is currently scheduled on [`cpu`](Self::cpu).
+    pub running: bool,
+
+    /// Total time the vCPU has run, in nanoseconds.
+    pub cpu_time: u64,
+
+    /// Physical CPU the vCPU runs, or last ran, on.
+    pub cpu: u32,
+}
+
+impl From<xen_domctl_getvcpuinfo> for XenVcpuInfo {
+    fn from(value: xen_domctl_getvcpuinfo) -> Self {
+        Self {
+            vcpu: VcpuId(value.vcpu as u16),
+            online: value.online != 0,
+            blocked: value.blocked != 0,
+            running: value.running != 0,
+            cpu_time: value.cpu_time,
+            cpu: value.cpu,
+        }
+    }
+}
+
+/// Physical CPUs a vCPU may run on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct XenVcpuAffinity {
+    /// CPUs the vCPU is restricted to.
+    pub hard: CpuMap,
+
+    /// CPUs the scheduler prefers to run the vCPU on, within
+    /// [`hard`](Self::hard).
+    pub soft: CpuMap,
+}
@@ -441,3 +441,28 @@ pub enum VmEventReason {
     /// IN/OUT Instruction executed
     IoInstruction(VmEventIo),
 }
+
+impl VmEventReason {
+    /// Returns the name of the reason, e.g. `"singlestep"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::MemoryAccess(_) => "memory access",
+            Self::MemorySharing(_) => "memory sharing",
+            Self::MemoryPaging(_) => "memory paging",
+            Self::WriteCtrlReg(_) => "control register write",
+            Self::MovToMsr(_) => "MSR write",
+            Self::SoftwareBreakpoint(_) => "software breakpoint",
+            Self::Singlestep(_) => "singlestep",
+            Self::GuestRequest => "guest request",
+            Self::DebugException(_) => "debug exception",
+            Self::Cpuid(_) => "CPUID",
+            Self::PrivilegedCall => "privileged call",
+            Self::Interrupt(_) => "interrupt",
+            Self::DescriptorAccess(_) => "descriptor access",
+            Self::EmulUnimplemented => "unimplemented emulation",
+            Self::VmExit(_) => "VMEXIT",
+            Self::IoInstruction(_) => "I/O instruction",
+        }
+    }
+}
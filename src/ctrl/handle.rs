@@ -1,41 +1,976 @@
-use xen_sys::{xc_interface, xc_interface_close, xc_interface_open, xentoollog_logger};
+use std::{
+    ffi::c_void,
+    ptr::NonNull,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
 
-use crate::XenError;
+use xen_sys::{
+    XENVER_capabilities, XENVER_changeset, XENVER_compile_info, XENVER_extraversion,
+    XENVER_pagesize, XENVER_platform_parameters, XENVER_version, evtchn_status, xc_interface,
+    xc_vcpu_extstate, xen_capabilities_info_t, xen_changeset_info_t, xen_compile_info,
+    xen_domctl_createdomain, xen_domctl_getdomaininfo, xen_domctl_getvcpuinfo, xen_extraversion_t,
+    xen_platform_parameters, xen_sysctl_physinfo, xentoollog_logger,
+};
 
-#[derive(Debug, Clone)]
-pub struct XenInterfaceHandle(pub(crate) *mut xc_interface);
+use crate::{
+    MemoryAccess, VcpuId, XenDomainId, XenError,
+    backend::{XenControlBackend, XenDeviceModelBackend, XenEventChannelBackend},
+    consts::PAGE_SIZE,
+    ctrl::VmEventCtrlReg,
+    devicemodel::XenDeviceModelHandle,
+    error::{XcError, XenErrorContext},
+    evtchn::XenEventChannelHandle,
+    ffi::xencontrol::{
+        self, xc_altp2m_change_gfn, xc_altp2m_create_view, xc_altp2m_destroy_view,
+        xc_altp2m_get_domain_state, xc_altp2m_get_mem_access, xc_altp2m_set_domain_state,
+        xc_altp2m_set_mem_access, xc_altp2m_set_mem_access_multi, xc_altp2m_switch_to_view,
+        xc_domain_create, xc_domain_debug_control, xc_domain_decrease_reservation,
+        xc_domain_decrease_reservation_exact, xc_domain_destroy, xc_domain_getinfolist,
+        xc_domain_hvm_getcontext, xc_domain_hvm_getcontext_partial, xc_domain_hvm_setcontext,
+        xc_domain_increase_reservation, xc_domain_increase_reservation_exact, xc_domain_max_vcpus,
+        xc_domain_maximum_gpfn, xc_domain_node_getaffinity, xc_domain_node_setaffinity,
+        xc_domain_pause, xc_domain_populate_physmap, xc_domain_populate_physmap_exact,
+        xc_domain_set_access_required, xc_domain_sethandle, xc_domain_setmaxmem,
+        xc_domain_shutdown, xc_domain_soft_reset, xc_domain_unpause, xc_evtchn_alloc_unbound,
+        xc_evtchn_reset, xc_evtchn_status, xc_get_cpumap_size, xc_get_mem_access,
+        xc_get_nodemap_size, xc_hvm_param_get, xc_hvm_param_set, xc_interface_close,
+        xc_interface_open, xc_monitor_cpuid, xc_monitor_debug_exceptions,
+        xc_monitor_descriptor_access, xc_monitor_disable, xc_monitor_emul_unimplemented,
+        xc_monitor_emulate_each_rep, xc_monitor_enable, xc_monitor_get_capabilities,
+        xc_monitor_guest_request, xc_monitor_inguest_pagefault, xc_monitor_io,
+        xc_monitor_mov_to_msr, xc_monitor_privileged_call, xc_monitor_resume,
+        xc_monitor_singlestep, xc_monitor_software_breakpoint, xc_monitor_vmexit,
+        xc_monitor_write_ctrlreg, xc_physinfo, xc_set_mem_access, xc_translate_foreign_address,
+        xc_vcpu_get_extstate, xc_vcpu_getaffinity, xc_vcpu_getinfo, xc_vcpu_setaffinity,
+        xc_version, xc_vm_event_get_version,
+    },
+    logger::logger_or_default,
+    xc_check_error,
+};
+
+/// Owned `xc_interface` handle.
+///
+/// libxenctrl keeps the last error in the handle itself, so a failing call
+/// and the subsequent `xc_get_last_error` must not interleave with calls
+/// made from other threads. The raw pointer is therefore only reachable
+/// through `lock`.
+#[derive(Debug)]
+pub struct XenInterfaceHandle(Mutex<*mut xc_interface>);
+
+// SAFETY: The handle is only ever accessed while holding the mutex.
+unsafe impl Send for XenInterfaceHandle {}
+unsafe impl Sync for XenInterfaceHandle {}
 
 impl XenInterfaceHandle {
     pub fn new() -> Result<Self, XenError> {
         Self::new_with_options(None, None, 0)
     }
 
+    /// Opens the handle with a custom `logger`.
+    ///
+    /// Messages of the library are forwarded to the global
+    /// [`TracingLogger`](crate::TracingLogger) if `logger` is `None`.
     pub fn new_with_options(
         logger: Option<&mut xentoollog_logger>,
         dombuild_logger: Option<&mut xentoollog_logger>,
         flags: u32,
     ) -> Result<Self, XenError> {
+        xencontrol::load()?;
+
         let handle = unsafe {
             xc_interface_open(
-                logger.map_or_else(std::ptr::null_mut, |p| p as *mut _),
+                logger_or_default(logger),
                 dombuild_logger.map_or_else(std::ptr::null_mut, |p| p as *mut _),
                 flags,
             )
         };
 
         if handle.is_null() {
-            return Err(XenError::Other("Failed to open Xen control interface"));
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xc_interface_open")).into(),
+            );
         }
 
-        Ok(Self(handle))
+        Ok(Self(Mutex::new(handle)))
+    }
+
+    /// Locks the handle for the duration of a call and its error check.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, *mut xc_interface> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
 impl Drop for XenInterfaceHandle {
     fn drop(&mut self) {
         tracing::trace!("closing Xen control interface");
+        let handle = *self.0.get_mut().unwrap_or_else(PoisonError::into_inner);
         unsafe {
-            xc_interface_close(self.0);
+            xc_interface_close(handle);
+        }
+    }
+}
+
+impl XenControlBackend for XenInterfaceHandle {
+    fn open_event_channel(&self) -> Result<Arc<dyn XenEventChannelBackend>, XenError> {
+        Ok(Arc::new(XenEventChannelHandle::new()?))
+    }
+
+    fn open_device_model(&self) -> Result<Arc<dyn XenDeviceModelBackend>, XenError> {
+        Ok(Arc::new(XenDeviceModelHandle::new()?))
+    }
+
+    #[expect(non_upper_case_globals)]
+    fn version(&self, command: u32, buffer: Option<&mut [u8]>) -> Result<i32, XenError> {
+        let required = match command {
+            XENVER_version | XENVER_pagesize => 0,
+            XENVER_extraversion => size_of::<xen_extraversion_t>(),
+            XENVER_compile_info => size_of::<xen_compile_info>(),
+            XENVER_capabilities => size_of::<xen_capabilities_info_t>(),
+            XENVER_changeset => size_of::<xen_changeset_info_t>(),
+            XENVER_platform_parameters => size_of::<xen_platform_parameters>(),
+            _ => return Err(std::io::Error::from_raw_os_error(libc::EOPNOTSUPP).into()),
+        };
+
+        let ptr = match buffer {
+            Some(buffer) if buffer.len() >= required => buffer.as_mut_ptr() as *mut c_void,
+            None if required == 0 => std::ptr::null_mut(),
+            _ => return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into()),
+        };
+
+        let xch = self.lock();
+        let rc = unsafe { xc_version(*xch, command as i32, ptr) };
+        xc_check_error!(*xch, rc, "xc_version");
+        Ok(rc)
+    }
+
+    fn physinfo(&self) -> Result<xen_sysctl_physinfo, XenError> {
+        let mut info = xen_sysctl_physinfo::default();
+        let xch = self.lock();
+        let rc = unsafe { xc_physinfo(*xch, &mut info) };
+        xc_check_error!(*xch, rc, "xc_physinfo");
+        Ok(info)
+    }
+
+    fn vm_event_get_version(&self) -> Result<u32, XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_vm_event_get_version(*xch) };
+        xc_check_error!(*xch, rc, "xc_vm_event_get_version");
+        Ok(rc as u32)
+    }
+
+    fn domain_getinfolist(
+        &self,
+        first_domain: XenDomainId,
+        info: &mut [xen_domctl_getdomaininfo],
+    ) -> Result<usize, XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_domain_getinfolist(*xch, first_domain.0, info.len() as u32, info.as_mut_ptr())
+        };
+        xc_check_error!(*xch, rc, "xc_domain_getinfolist", domain_id: first_domain);
+        Ok(rc as usize)
+    }
+
+    fn domain_create(
+        &self,
+        domain_id: XenDomainId,
+        config: &mut xen_domctl_createdomain,
+    ) -> Result<XenDomainId, XenError> {
+        let mut domid = domain_id.0;
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_create(*xch, &mut domid, config) };
+        xc_check_error!(*xch, rc, "xc_domain_create", domain_id: domain_id);
+        Ok(XenDomainId(domid))
+    }
+
+    fn domain_destroy(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_destroy(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_domain_destroy", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_shutdown(&self, domain_id: XenDomainId, reason: u32) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_shutdown(*xch, domain_id.0, reason as _) };
+        xc_check_error!(*xch, rc, "xc_domain_shutdown", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_soft_reset(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_soft_reset(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_domain_soft_reset", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_max_vcpus(&self, domain_id: XenDomainId, max: u32) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_max_vcpus(*xch, domain_id.0, max) };
+        xc_check_error!(*xch, rc, "xc_domain_max_vcpus", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_sethandle(
+        &self,
+        domain_id: XenDomainId,
+        mut handle: [u8; 16],
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_sethandle(*xch, domain_id.0, handle.as_mut_ptr()) };
+        xc_check_error!(*xch, rc, "xc_domain_sethandle", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn cpumap_size(&self) -> Result<usize, XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_get_cpumap_size(*xch) };
+        xc_check_error!(*xch, rc, "xc_get_cpumap_size");
+        Ok(rc as usize)
+    }
+
+    fn nodemap_size(&self) -> Result<usize, XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_get_nodemap_size(*xch) };
+        xc_check_error!(*xch, rc, "xc_get_nodemap_size");
+        Ok(rc as usize)
+    }
+
+    fn vcpu_getinfo(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+    ) -> Result<xen_domctl_getvcpuinfo, XenError> {
+        let mut info = xen_domctl_getvcpuinfo::default();
+        let xch = self.lock();
+        let rc = unsafe { xc_vcpu_getinfo(*xch, domain_id.0, vcpu.0.into(), &mut info) };
+        xc_check_error!(*xch, rc, "xc_vcpu_getinfo", domain_id: domain_id, vcpu: vcpu);
+        Ok(info)
+    }
+
+    fn vcpu_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError> {
+        let size = self.cpumap_size()?;
+        if hard.len() < size || soft.len() < size {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
+        }
+
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_vcpu_getaffinity(
+                *xch,
+                domain_id.0,
+                vcpu.0.into(),
+                hard.as_mut_ptr(),
+                soft.as_mut_ptr(),
+                flags,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_vcpu_getaffinity", domain_id: domain_id, vcpu: vcpu);
+        Ok(())
+    }
+
+    fn vcpu_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        hard: &mut [u8],
+        soft: &mut [u8],
+        flags: u32,
+    ) -> Result<(), XenError> {
+        let size = self.cpumap_size()?;
+        if hard.len() < size || soft.len() < size {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
+        }
+
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_vcpu_setaffinity(
+                *xch,
+                domain_id.0,
+                vcpu.0.into(),
+                hard.as_mut_ptr(),
+                soft.as_mut_ptr(),
+                flags,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_vcpu_setaffinity", domain_id: domain_id, vcpu: vcpu);
+        Ok(())
+    }
+
+    fn domain_node_getaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError> {
+        if nodemap.len() < self.nodemap_size()? {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
+        }
+
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_node_getaffinity(*xch, domain_id.0, nodemap.as_mut_ptr()) };
+        xc_check_error!(*xch, rc, "xc_domain_node_getaffinity", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_node_setaffinity(
+        &self,
+        domain_id: XenDomainId,
+        nodemap: &mut [u8],
+    ) -> Result<(), XenError> {
+        if nodemap.len() < self.nodemap_size()? {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
         }
+
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_node_setaffinity(*xch, domain_id.0, nodemap.as_mut_ptr()) };
+        xc_check_error!(*xch, rc, "xc_domain_node_setaffinity", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_maximum_gpfn(&self, domain_id: XenDomainId) -> Result<u64, XenError> {
+        let mut gpfn = 0;
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_maximum_gpfn(*xch, domain_id.0, &mut gpfn) };
+        xc_check_error!(*xch, rc, "xc_domain_maximum_gpfn", domain_id: domain_id);
+        Ok(gpfn)
+    }
+
+    fn hvm_param_get(&self, domain_id: XenDomainId, param: u32) -> Result<u64, XenError> {
+        let mut value = 0;
+
+        let xch = self.lock();
+        let rc = unsafe { xc_hvm_param_get(*xch, domain_id.0, param, &mut value) };
+        xc_check_error!(*xch, rc, "xc_hvm_param_get", domain_id: domain_id);
+        Ok(value)
+    }
+
+    fn hvm_param_set(
+        &self,
+        domain_id: XenDomainId,
+        param: u32,
+        value: u64,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_hvm_param_set(*xch, domain_id.0, param, value) };
+        xc_check_error!(*xch, rc, "xc_hvm_param_set", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn evtchn_alloc_unbound(
+        &self,
+        domain_id: XenDomainId,
+        remote_domain_id: XenDomainId,
+    ) -> Result<u32, XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_evtchn_alloc_unbound(*xch, domain_id.0, remote_domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_evtchn_alloc_unbound", domain_id: domain_id);
+        Ok(rc as u32)
+    }
+
+    fn evtchn_status(&self, domain_id: XenDomainId, port: u32) -> Result<evtchn_status, XenError> {
+        let mut status = unsafe { std::mem::zeroed::<evtchn_status>() };
+        status.dom = domain_id.0 as u16;
+        status.port = port;
+
+        let xch = self.lock();
+        let rc = unsafe { xc_evtchn_status(*xch, &mut status) };
+        xc_check_error!(*xch, rc, "xc_evtchn_status", domain_id: domain_id);
+        Ok(status)
+    }
+
+    fn evtchn_reset(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_evtchn_reset(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_evtchn_reset", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_pause(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_pause(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_domain_pause", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_unpause(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_unpause(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_domain_unpause", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn get_mem_access(&self, domain_id: XenDomainId, gfn: u64) -> Result<MemoryAccess, XenError> {
+        let mut access = 0;
+        let xch = self.lock();
+        let rc = unsafe { xc_get_mem_access(*xch, domain_id.0, gfn, &mut access) };
+        xc_check_error!(*xch, rc, "xc_get_mem_access", domain_id: domain_id, gfn: gfn);
+        Ok(MemoryAccess::from_bits_truncate(access as _))
+    }
+
+    fn set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        access: MemoryAccess,
+        first_gfn: u64,
+        nr: u32,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc =
+            unsafe { xc_set_mem_access(*xch, domain_id.0, access.bits().into(), first_gfn, nr) };
+        xc_check_error!(*xch, rc, "xc_set_mem_access", domain_id: domain_id, gfn: first_gfn);
+        Ok(())
+    }
+
+    fn domain_set_access_required(
+        &self,
+        domain_id: XenDomainId,
+        required: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_set_access_required(*xch, domain_id.0, required.into()) };
+        xc_check_error!(*xch, rc, "xc_domain_set_access_required", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_debug_control(
+        &self,
+        domain_id: XenDomainId,
+        operation: u32,
+        vcpu: VcpuId,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_debug_control(*xch, domain_id.0, operation, vcpu.0.into()) };
+        xc_check_error!(*xch, rc, "xc_domain_debug_control", domain_id: domain_id, vcpu: vcpu);
+        Ok(())
+    }
+
+    fn domain_setmaxmem(&self, domain_id: XenDomainId, max_memkb: u64) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_setmaxmem(*xch, domain_id.0, max_memkb) };
+        xc_check_error!(*xch, rc, "xc_domain_setmaxmem", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_increase_reservation(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        mem_flags: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError> {
+        let function = match exact {
+            false => xc_domain_increase_reservation,
+            true => xc_domain_increase_reservation_exact,
+        };
+        let operation = match exact {
+            false => "xc_domain_increase_reservation",
+            true => "xc_domain_increase_reservation_exact",
+        };
+
+        let xch = self.lock();
+        let rc = unsafe {
+            function(
+                *xch,
+                domain_id.0,
+                extents.len() as u64,
+                extent_order,
+                mem_flags,
+                extents.as_ptr() as *mut _,
+            )
+        };
+        xc_check_error!(*xch, rc, operation, domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_decrease_reservation(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError> {
+        let function = match exact {
+            false => xc_domain_decrease_reservation,
+            true => xc_domain_decrease_reservation_exact,
+        };
+        let operation = match exact {
+            false => "xc_domain_decrease_reservation",
+            true => "xc_domain_decrease_reservation_exact",
+        };
+
+        let xch = self.lock();
+        let rc = unsafe {
+            function(
+                *xch,
+                domain_id.0,
+                extents.len() as u64,
+                extent_order,
+                extents.as_ptr() as *mut _,
+            )
+        };
+        xc_check_error!(*xch, rc, operation, domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_populate_physmap(
+        &self,
+        domain_id: XenDomainId,
+        extent_order: u32,
+        mem_flags: u32,
+        extents: &[u64],
+        exact: bool,
+    ) -> Result<(), XenError> {
+        let function = match exact {
+            false => xc_domain_populate_physmap,
+            true => xc_domain_populate_physmap_exact,
+        };
+        let operation = match exact {
+            false => "xc_domain_populate_physmap",
+            true => "xc_domain_populate_physmap_exact",
+        };
+
+        let xch = self.lock();
+        let rc = unsafe {
+            function(
+                *xch,
+                domain_id.0,
+                extents.len() as u64,
+                extent_order,
+                mem_flags,
+                extents.as_ptr() as _,
+            )
+        };
+        xc_check_error!(*xch, rc, operation, domain_id: domain_id);
+        Ok(())
+    }
+
+    fn domain_hvm_getcontext(
+        &self,
+        domain_id: XenDomainId,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<usize, XenError> {
+        let (ptr, size) = match buffer {
+            Some(buffer) => (buffer.as_mut_ptr(), buffer.len() as u32),
+            None => (std::ptr::null_mut(), 0),
+        };
+
+        let xch = self.lock();
+        let rc = unsafe { xc_domain_hvm_getcontext(*xch, domain_id.0, ptr, size) };
+        xc_check_error!(*xch, rc, "xc_domain_hvm_getcontext", domain_id: domain_id);
+        Ok(rc as usize)
+    }
+
+    fn domain_hvm_getcontext_partial(
+        &self,
+        domain_id: XenDomainId,
+        typecode: u16,
+        instance: u16,
+        buffer: &mut [u8],
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_domain_hvm_getcontext_partial(
+                *xch,
+                domain_id.0,
+                typecode,
+                instance,
+                buffer.as_mut_ptr() as *mut _,
+                buffer.len() as u32,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_domain_hvm_getcontext_partial", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn vcpu_get_extstate(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        xfeature_mask: u64,
+        buffer: Option<&mut [u8]>,
+    ) -> Result<(u64, usize), XenError> {
+        let mut extstate = match buffer {
+            Some(buffer) => xc_vcpu_extstate {
+                xfeature_mask,
+                size: buffer.len() as u64,
+                buffer: buffer.as_mut_ptr() as *mut c_void,
+            },
+            None => xc_vcpu_extstate {
+                xfeature_mask: 0,
+                size: 0,
+                buffer: std::ptr::null_mut(),
+            },
+        };
+
+        let xch = self.lock();
+        let rc = unsafe { xc_vcpu_get_extstate(*xch, domain_id.0, vcpu.0.into(), &mut extstate) };
+        xc_check_error!(*xch, rc, "xc_vcpu_get_extstate", domain_id: domain_id, vcpu: vcpu);
+        Ok((extstate.xfeature_mask, extstate.size as usize))
+    }
+
+    fn translate_foreign_address(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        address: u64,
+    ) -> Result<u64, XenError> {
+        let xch = self.lock();
+        let gfn =
+            unsafe { xc_translate_foreign_address(*xch, domain_id.0, vcpu.0.into(), address) };
+
+        // libxenctrl reports failures as frame 0.
+        let rc = if gfn == 0 { -1 } else { 0 };
+        xc_check_error!(*xch, rc, "xc_translate_foreign_address", domain_id: domain_id, vcpu: vcpu);
+        Ok(gfn as u64)
+    }
+
+    fn domain_hvm_setcontext(&self, domain_id: XenDomainId, buffer: &[u8]) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_domain_hvm_setcontext(
+                *xch,
+                domain_id.0,
+                buffer.as_ptr() as *mut _,
+                buffer.len() as u32,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_domain_hvm_setcontext", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn altp2m_get_domain_state(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        let mut state = false;
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_get_domain_state(*xch, domain_id.0, &mut state) };
+        xc_check_error!(*xch, rc, "xc_altp2m_get_domain_state", domain_id: domain_id);
+        Ok(state)
+    }
+
+    fn altp2m_set_domain_state(&self, domain_id: XenDomainId, state: bool) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_set_domain_state(*xch, domain_id.0, state) };
+        xc_check_error!(*xch, rc, "xc_altp2m_set_domain_state", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn altp2m_create_view(
+        &self,
+        domain_id: XenDomainId,
+        default_access: MemoryAccess,
+    ) -> Result<u16, XenError> {
+        let mut view_id = 0;
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_altp2m_create_view(
+                *xch,
+                domain_id.0,
+                default_access.bits().into(),
+                &mut view_id,
+            )
+        };
+
+        xc_check_error!(*xch, rc, "xc_altp2m_create_view", domain_id: domain_id);
+        Ok(view_id)
+    }
+
+    fn altp2m_destroy_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_destroy_view(*xch, domain_id.0, view_id) };
+        xc_check_error!(*xch, rc, "xc_altp2m_destroy_view", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn altp2m_switch_to_view(&self, domain_id: XenDomainId, view_id: u16) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_switch_to_view(*xch, domain_id.0, view_id) };
+        xc_check_error!(*xch, rc, "xc_altp2m_switch_to_view", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn altp2m_get_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+    ) -> Result<MemoryAccess, XenError> {
+        let mut access = 0;
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_get_mem_access(*xch, domain_id.0, view_id, gfn, &mut access) };
+
+        // errno is preserved, so callers can tell a lazy, unmaterialized
+        // altp2m entry (ESRCH) from other failures.
+        xc_check_error!(*xch, rc, "xc_altp2m_get_mem_access", domain_id: domain_id, gfn: gfn);
+
+        Ok(MemoryAccess::from_bits_truncate(access as _))
+    }
+
+    fn altp2m_set_mem_access(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        gfn: u64,
+        access: MemoryAccess,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_altp2m_set_mem_access(*xch, domain_id.0, view_id, gfn, access.bits().into())
+        };
+        xc_check_error!(*xch, rc, "xc_altp2m_set_mem_access", domain_id: domain_id, gfn: gfn);
+        Ok(())
+    }
+
+    fn altp2m_set_mem_access_multi(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        access: &[MemoryAccess],
+        gfns: &[u64],
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_altp2m_set_mem_access_multi(
+                *xch,
+                domain_id.0,
+                view_id,
+                access.as_ptr() as *mut u8,
+                gfns.as_ptr() as *mut u64,
+                std::cmp::min(access.len(), gfns.len()) as u32,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_altp2m_set_mem_access_multi", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn altp2m_change_gfn(
+        &self,
+        domain_id: XenDomainId,
+        view_id: u16,
+        old_gfn: u64,
+        new_gfn: u64,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_altp2m_change_gfn(*xch, domain_id.0, view_id, old_gfn, new_gfn) };
+        xc_check_error!(*xch, rc, "xc_altp2m_change_gfn", domain_id: domain_id, gfn: old_gfn);
+        Ok(())
+    }
+
+    fn monitor_enable(&self, domain_id: XenDomainId) -> Result<(NonNull<c_void>, u32), XenError> {
+        let mut port: u32 = 0;
+        let xch = self.lock();
+        let ring_page = unsafe { xc_monitor_enable(*xch, domain_id.0, &mut port) };
+
+        match NonNull::new(ring_page) {
+            Some(ring_page) => Ok((ring_page, port)),
+            None => Err(unsafe {
+                XcError::last_error(
+                    *xch,
+                    -1,
+                    XenErrorContext {
+                        domain_id: Some(domain_id),
+                        ..XenErrorContext::new("xc_monitor_enable")
+                    },
+                )
+            }
+            .into()),
+        }
+    }
+
+    fn monitor_disable(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_disable(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_monitor_disable", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_resume(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_resume(*xch, domain_id.0) };
+        xc_check_error!(*xch, rc, "xc_monitor_resume", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_get_capabilities(&self, domain_id: XenDomainId) -> Result<u32, XenError> {
+        let mut capabilities = 0;
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_get_capabilities(*xch, domain_id.0, &mut capabilities) };
+        xc_check_error!(*xch, rc, "xc_monitor_get_capabilities", domain_id: domain_id);
+        Ok(capabilities)
+    }
+
+    fn monitor_unmap_ring_page(&self, ring_page: NonNull<c_void>) {
+        unsafe {
+            libc::munmap(ring_page.as_ptr(), PAGE_SIZE as usize);
+        }
+    }
+
+    fn monitor_write_ctrlreg(
+        &self,
+        domain_id: XenDomainId,
+        index: VmEventCtrlReg,
+        enable: bool,
+        sync: bool,
+        bitmask: u64,
+        onchangeonly: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe {
+            xc_monitor_write_ctrlreg(
+                *xch,
+                domain_id.0,
+                index as u16,
+                enable,
+                sync,
+                bitmask,
+                onchangeonly,
+            )
+        };
+        xc_check_error!(*xch, rc, "xc_monitor_write_ctrlreg", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_mov_to_msr(
+        &self,
+        domain_id: XenDomainId,
+        msr: u32,
+        enable: bool,
+        onchangeonly: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_mov_to_msr(*xch, domain_id.0, msr, enable, onchangeonly) };
+        xc_check_error!(*xch, rc, "xc_monitor_mov_to_msr", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_singlestep(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_singlestep(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_singlestep", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_software_breakpoint(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_software_breakpoint(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_software_breakpoint", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_descriptor_access(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_descriptor_access(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_descriptor_access", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_guest_request(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+        allow_userspace: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc =
+            unsafe { xc_monitor_guest_request(*xch, domain_id.0, enable, sync, allow_userspace) };
+        xc_check_error!(*xch, rc, "xc_monitor_guest_request", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_inguest_pagefault(
+        &self,
+        domain_id: XenDomainId,
+        disable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_inguest_pagefault(*xch, domain_id.0, disable) };
+        xc_check_error!(*xch, rc, "xc_monitor_inguest_pagefault", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_debug_exceptions(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_debug_exceptions(*xch, domain_id.0, enable, sync) };
+        xc_check_error!(*xch, rc, "xc_monitor_debug_exceptions", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_cpuid(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_cpuid(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_cpuid", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_privileged_call(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_privileged_call(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_privileged_call", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_emul_unimplemented(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_emul_unimplemented(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_emul_unimplemented", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_vmexit(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+        sync: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_vmexit(*xch, domain_id.0, enable, sync) };
+        xc_check_error!(*xch, rc, "xc_monitor_vmexit", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_io(&self, domain_id: XenDomainId, enable: bool) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_io(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_io", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn monitor_emulate_each_rep(
+        &self,
+        domain_id: XenDomainId,
+        enable: bool,
+    ) -> Result<(), XenError> {
+        let xch = self.lock();
+        let rc = unsafe { xc_monitor_emulate_each_rep(*xch, domain_id.0, enable) };
+        xc_check_error!(*xch, rc, "xc_monitor_emulate_each_rep", domain_id: domain_id);
+        Ok(())
     }
 }
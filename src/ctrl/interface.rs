@@ -1,17 +1,48 @@
-use std::rc::Rc;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError, Weak},
+};
 
 use super::XenInterfaceHandle;
-use crate::XenError;
+use crate::{XenDomainId, XenError, backend::XenControlBackend, foreignmemory::CacheShared};
 
 #[derive(Debug, Clone)]
 pub struct XenInterface {
-    pub(crate) handle: Rc<XenInterfaceHandle>,
+    pub(crate) backend: Arc<dyn XenControlBackend>,
+
+    /// Number of active pause guards per domain.
+    pub(crate) pauses: Arc<Mutex<HashMap<XenDomainId, usize>>>,
+
+    /// Page caches invalidated when the physical memory map of their domain
+    /// changes.
+    pub(crate) caches: Arc<Mutex<Vec<Weak<CacheShared>>>>,
 }
 
 impl XenInterface {
     pub fn new() -> Result<Self, XenError> {
-        Ok(Self {
-            handle: Rc::new(XenInterfaceHandle::new()?),
-        })
+        Ok(Self::with_backend(Arc::new(XenInterfaceHandle::new()?)))
+    }
+
+    /// Creates an interface that routes all control operations through
+    /// `backend`.
+    pub fn with_backend(backend: Arc<dyn XenControlBackend>) -> Self {
+        Self {
+            backend,
+            pauses: Arc::default(),
+            caches: Arc::default(),
+        }
+    }
+
+    /// Drops `gfns` of a domain from all registered page caches.
+    pub(crate) fn invalidate_caches(&self, domain_id: XenDomainId, gfns: Range<u64>) {
+        let mut caches = self.caches.lock().unwrap_or_else(PoisonError::into_inner);
+        caches.retain(|cache| cache.strong_count() > 0);
+
+        for cache in caches.iter().filter_map(Weak::upgrade) {
+            if cache.domain_id() == domain_id {
+                cache.invalidate_range(gfns.clone());
+            }
+        }
     }
 }
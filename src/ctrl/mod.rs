@@ -1,8 +1,16 @@
 mod altp2m;
 pub use self::altp2m::{XenAltP2M, XenAltP2MView};
 
+mod cpumap;
+pub use self::cpumap::{CpuMap, NodeMap};
+
 mod domain;
-pub use self::domain::{XenDomain, XenDomainInfo};
+pub use self::domain::{
+    HvmCallbackIrq, HvmParam, HvmTimerMode, HvmViridianFeatures, ShutdownReason, XenAltP2MMode,
+    XenArchDomainConfig, XenDomain, XenDomainConfig, XenDomainCreateFlags, XenDomainFlags,
+    XenDomainInfo, XenDomainPauseGuard, XenDomainUuid, XenVcpuAffinity, XenVcpuInfo,
+    XenX86Emulation,
+};
 
 mod event;
 pub use self::event::{
@@ -20,8 +28,26 @@ mod interface;
 pub use self::interface::XenInterface;
 
 mod monitor;
-pub use self::monitor::{VmEventRing, XenMonitor};
-use crate::{Architecture, XenDomainId, XenError};
+pub use self::monitor::{
+    IntrospectionCapabilities, MonitorCapabilities, MonitorLoop, MonitorLoopHandle,
+    MonitorResponse, VmEventRing, XenMonitor,
+};
+
+mod physinfo;
+pub use self::physinfo::{XenPhysCapabilities, XenPhysInfo};
+
+mod version;
+mod watcher;
+use xen_sys::xen_domctl_getdomaininfo;
+
+pub use self::{
+    version::{XenCompileInfo, XenVersion},
+    watcher::{DomainEvent, DomainWatcher},
+};
+use crate::{Architecture, XenDomainId, XenError, evtchn::XenEventChannel};
+
+/// Number of domains queried by a single `xc_domain_getinfolist` call.
+const DOMAIN_INFO_BATCH: usize = 64;
 
 pub struct XenControl {
     interface: XenInterface,
@@ -44,4 +70,69 @@ impl XenControl {
     {
         XenDomain::new(self.interface.clone(), id)
     }
+
+    /// Creates a new domain.
+    ///
+    /// The domain is created paused, see [`XenDomainConfig`].
+    pub fn create_domain<Arch>(&self, config: &XenDomainConfig) -> Result<XenDomain<Arch>, XenError>
+    where
+        Arch: Architecture,
+    {
+        let mut inner = config.inner;
+        let domain_id = self
+            .interface
+            .backend
+            .domain_create(config.domain_id, &mut inner)?;
+
+        XenDomain::new(self.interface.clone(), domain_id)
+    }
+
+    /// Returns information about all existing domains, ordered by their ID.
+    pub fn domains(&self) -> Result<Vec<XenDomainInfo>, XenError> {
+        let mut result = Vec::new();
+        let mut buffer = [xen_domctl_getdomaininfo::default(); DOMAIN_INFO_BATCH];
+        let mut first_domain = XenDomainId(0);
+
+        loop {
+            let count = self
+                .interface
+                .backend
+                .domain_getinfolist(first_domain, &mut buffer)?;
+
+            let batch = &buffer[..count.min(buffer.len())];
+            result.extend(batch.iter().map(|&info| XenDomainInfo::from(info)));
+
+            match batch.last() {
+                Some(last) if count == buffer.len() => {
+                    first_domain = XenDomainId(last.domain as u32 + 1);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the version of the running hypervisor.
+    pub fn version(&self) -> Result<XenVersion, XenError> {
+        XenVersion::query(&*self.interface.backend)
+    }
+
+    /// Returns the physical host information and capabilities.
+    pub fn physinfo(&self) -> Result<XenPhysInfo, XenError> {
+        Ok(self.interface.backend.physinfo()?.into())
+    }
+
+    /// Creates a [`DomainWatcher`] notified through `VIRQ_DOM_EXC`.
+    pub fn domain_watcher(&self) -> Result<DomainWatcher, XenError> {
+        DomainWatcher::new(self)
+    }
+
+    /// Opens an event channel of the same backend, e.g. to bind the
+    /// monitors of many domains with [`XenMonitor::bind`].
+    pub fn event_channel(&self) -> Result<XenEventChannel, XenError> {
+        Ok(XenEventChannel::with_backend(
+            self.interface.backend.open_event_channel()?,
+        ))
+    }
 }
@@ -0,0 +1,85 @@
+use xen_sys::{
+    VM_EVENT_REASON_CPUID, VM_EVENT_REASON_DEBUG_EXCEPTION, VM_EVENT_REASON_DESCRIPTOR_ACCESS,
+    VM_EVENT_REASON_EMUL_UNIMPLEMENTED, VM_EVENT_REASON_GUEST_REQUEST, VM_EVENT_REASON_INTERRUPT,
+    VM_EVENT_REASON_IO_INSTRUCTION, VM_EVENT_REASON_MEM_ACCESS, VM_EVENT_REASON_MEM_PAGING,
+    VM_EVENT_REASON_MEM_SHARING, VM_EVENT_REASON_MOV_TO_MSR, VM_EVENT_REASON_PRIVILEGED_CALL,
+    VM_EVENT_REASON_SINGLESTEP, VM_EVENT_REASON_SOFTWARE_BREAKPOINT, VM_EVENT_REASON_VMEXIT,
+    VM_EVENT_REASON_WRITE_CTRLREG,
+};
+
+use crate::ctrl::VmEventReason;
+
+bitflags::bitflags! {
+    /// Event reasons a domain can be monitored for, one bit per
+    /// `VM_EVENT_REASON_*`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct MonitorCapabilities: u32 {
+        const MEMORY_ACCESS = 1 << VM_EVENT_REASON_MEM_ACCESS;
+        const MEMORY_SHARING = 1 << VM_EVENT_REASON_MEM_SHARING;
+        const MEMORY_PAGING = 1 << VM_EVENT_REASON_MEM_PAGING;
+        const WRITE_CTRL_REG = 1 << VM_EVENT_REASON_WRITE_CTRLREG;
+        const MOV_TO_MSR = 1 << VM_EVENT_REASON_MOV_TO_MSR;
+        const SOFTWARE_BREAKPOINT = 1 << VM_EVENT_REASON_SOFTWARE_BREAKPOINT;
+        const SINGLESTEP = 1 << VM_EVENT_REASON_SINGLESTEP;
+        const GUEST_REQUEST = 1 << VM_EVENT_REASON_GUEST_REQUEST;
+        const DEBUG_EXCEPTION = 1 << VM_EVENT_REASON_DEBUG_EXCEPTION;
+        const CPUID = 1 << VM_EVENT_REASON_CPUID;
+        const PRIVILEGED_CALL = 1 << VM_EVENT_REASON_PRIVILEGED_CALL;
+        const INTERRUPT = 1 << VM_EVENT_REASON_INTERRUPT;
+        const DESCRIPTOR_ACCESS = 1 << VM_EVENT_REASON_DESCRIPTOR_ACCESS;
+        const EMUL_UNIMPLEMENTED = 1 << VM_EVENT_REASON_EMUL_UNIMPLEMENTED;
+        const VMEXIT = 1 << VM_EVENT_REASON_VMEXIT;
+        const IO_INSTRUCTION = 1 << VM_EVENT_REASON_IO_INSTRUCTION;
+    }
+}
+
+/// The capability needed to receive events of the reason, empty for
+/// [`VmEventReason::Unknown`].
+impl From<&VmEventReason> for MonitorCapabilities {
+    fn from(value: &VmEventReason) -> Self {
+        match value {
+            VmEventReason::Unknown => Self::empty(),
+            VmEventReason::MemoryAccess(_) => Self::MEMORY_ACCESS,
+            VmEventReason::MemorySharing(_) => Self::MEMORY_SHARING,
+            VmEventReason::MemoryPaging(_) => Self::MEMORY_PAGING,
+            VmEventReason::WriteCtrlReg(_) => Self::WRITE_CTRL_REG,
+            VmEventReason::MovToMsr(_) => Self::MOV_TO_MSR,
+            VmEventReason::SoftwareBreakpoint(_) => Self::SOFTWARE_BREAKPOINT,
+            VmEventReason::Singlestep(_) => Self::SINGLESTEP,
+            VmEventReason::GuestRequest => Self::GUEST_REQUEST,
+            VmEventReason::DebugException(_) => Self::DEBUG_EXCEPTION,
+            VmEventReason::Cpuid(_) => Self::CPUID,
+            VmEventReason::PrivilegedCall => Self::PRIVILEGED_CALL,
+            VmEventReason::Interrupt(_) => Self::INTERRUPT,
+            VmEventReason::DescriptorAccess(_) => Self::DESCRIPTOR_ACCESS,
+            VmEventReason::EmulUnimplemented => Self::EMUL_UNIMPLEMENTED,
+            VmEventReason::VmExit(_) => Self::VMEXIT,
+            VmEventReason::IoInstruction(_) => Self::IO_INSTRUCTION,
+        }
+    }
+}
+
+/// Introspection features available for a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntrospectionCapabilities {
+    /// Event reasons the domain can be monitored for.
+    pub monitor: MonitorCapabilities,
+
+    /// altp2m is enabled for the domain.
+    pub altp2m: bool,
+
+    /// `VM_EVENT_INTERFACE_VERSION` of the hypervisor.
+    pub vm_event_version: u32,
+
+    /// The host supports processor tracing of guests. Tracing is only
+    /// available for domains created with a
+    /// [`vmtrace_size`](crate::ctrl::XenDomainConfig::vmtrace_size).
+    pub vmtrace: bool,
+}
+
+impl IntrospectionCapabilities {
+    /// Returns `true` if events of all `capabilities` can be monitored.
+    pub fn supports(&self, capabilities: impl Into<MonitorCapabilities>) -> bool {
+        self.monitor.contains(capabilities.into())
+    }
+}
@@ -0,0 +1,573 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use super::{MonitorCapabilities, VmEventRing, XenMonitor};
+use crate::{
+    XenDomainId, XenError,
+    ctrl::{
+        VmEvent, VmEventData, VmEventEmulInsnData, VmEventEmulReadData, VmEventFastSinglestep,
+        VmEventFlag, VmEventFlagOptions, VmEventRegs, VmEventRegsX86,
+    },
+    evtchn::{XenEventChannelCancelHandle, XenEventChannelPort},
+};
+
+/// How a vCPU resumes after an event.
+///
+/// The default response only resumes the vCPU. The methods add flags and
+/// can be chained, e.g. `MonitorResponse::new().switch_view(0).toggle_singlestep()`.
+/// Unpausing the vCPU is handled by [`MonitorLoop`].
+#[derive(Debug, Default)]
+pub struct MonitorResponse {
+    flags: VmEventFlag,
+    altp2m_idx: u16,
+    options: Option<VmEventFlagOptions>,
+    data: Option<VmEventData>,
+}
+
+impl MonitorResponse {
+    /// Creates a response that only resumes the vCPU.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emulates the instruction that caused a memory access event.
+    pub fn emulate(mut self) -> Self {
+        self.flags |= VmEventFlag::EMULATE;
+        self
+    }
+
+    /// Emulates the instruction that caused a memory access event, with
+    /// writes disabled.
+    pub fn emulate_no_write(mut self) -> Self {
+        self.flags |= VmEventFlag::EMULATE_NO_WRITE;
+        self
+    }
+
+    /// Emulates the instruction, returning `data` to its reads.
+    pub fn emulate_read_data(mut self, data: VmEventEmulReadData) -> Self {
+        self.flags |= VmEventFlag::EMULATE | VmEventFlag::SET_EMUL_READ_DATA;
+        self.data = Some(VmEventData::EmulReadData(data));
+        self
+    }
+
+    /// Emulates `data` instead of the instruction in guest memory.
+    pub fn emulate_instruction(mut self, data: VmEventEmulInsnData) -> Self {
+        self.flags |= VmEventFlag::EMULATE | VmEventFlag::SET_EMUL_INSN_DATA;
+        self.data = Some(VmEventData::EmulInstructionData(data));
+        self
+    }
+
+    /// Denies a control register or MSR write.
+    pub fn deny(mut self) -> Self {
+        self.flags |= VmEventFlag::DENY;
+        self
+    }
+
+    /// Toggles singlestepping of the vCPU.
+    pub fn toggle_singlestep(mut self) -> Self {
+        self.flags |= VmEventFlag::TOGGLE_SINGLESTEP;
+        self
+    }
+
+    /// Executes a single instruction in the view `p2midx`, then switches
+    /// back to the current view.
+    pub fn fast_singlestep(mut self, p2midx: u16) -> Self {
+        self.flags |= VmEventFlag::FAST_SINGLESTEP;
+        self.options = Some(VmEventFlagOptions {
+            fast_singlestep: Some(VmEventFastSinglestep { p2midx }),
+        });
+        self
+    }
+
+    /// Resumes the vCPU in the altp2m view `view`.
+    pub fn switch_view(mut self, view: u16) -> Self {
+        self.flags |= VmEventFlag::ALTERNATE_P2M;
+        self.altp2m_idx = view;
+        self
+    }
+
+    /// Sets the general purpose registers, flags and instruction pointer
+    /// of the vCPU.
+    pub fn set_registers(mut self, registers: VmEventRegsX86) -> Self {
+        self.flags |= VmEventFlag::SET_REGISTERS;
+        self.data = Some(VmEventData::Registers(VmEventRegs::X86(registers)));
+        self
+    }
+
+    /// Requests the next interrupt to be delivered to the vCPU as an
+    /// event.
+    pub fn get_next_interrupt(mut self) -> Self {
+        self.flags |= VmEventFlag::GET_NEXT_INTERRUPT;
+        self
+    }
+
+    /// Returns the flags of the response.
+    pub fn flags(&self) -> VmEventFlag {
+        self.flags
+    }
+
+    /// Builds the response to `request`, unpausing the vCPU if the
+    /// request paused it.
+    pub fn into_event(self, request: VmEvent) -> VmEvent {
+        VmEvent {
+            flags: self.flags | (request.flags & VmEventFlag::VCPU_PAUSED),
+            reason: request.reason,
+            vcpu_id: request.vcpu_id,
+            altp2m_idx: self.altp2m_idx,
+            options: self.options,
+            data: self.data,
+        }
+    }
+}
+
+type Handler<'a> = Box<dyn FnMut(&VmEvent) -> Result<MonitorResponse, XenError> + Send + 'a>;
+
+/// Stops a [`MonitorLoop`].
+///
+/// Cloning is cheap, all clones stop the same loop.
+#[derive(Debug, Clone)]
+pub struct MonitorLoopHandle {
+    stopped: Arc<AtomicBool>,
+    cancel: XenEventChannelCancelHandle,
+}
+
+impl MonitorLoopHandle {
+    /// Makes [`MonitorLoop::run`] return once the events that are already
+    /// on the ring are answered, waking up the loop if it waits for an
+    /// event.
+    ///
+    /// The stop lasts until `run` is called again, until then
+    /// [`MonitorLoop::run_once`] and [`MonitorLoop::run_timeout`] return
+    /// without waiting.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        self.cancel.cancel();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+}
+
+/// Receives the events of a monitor and dispatches them to handlers.
+///
+/// Handlers are registered per event reason with [`on`](Self::on) and
+/// return the [`MonitorResponse`] for the event. Every event is answered,
+/// and a vCPU paused by an event is always unpaused: events without a
+/// handler are answered with [`MonitorResponse::new`], and so are events
+/// whose handler fails. Events left on the ring when the loop is dropped
+/// are answered the same way.
+///
+/// Handler errors are returned as [`XenError::Event`], naming the failed
+/// event. The loop can be run again afterwards.
+pub struct MonitorLoop<'a> {
+    // Dropped in declaration order: the port is unbound and the ring is
+    // unmapped before the monitor is disabled.
+    port: XenEventChannelPort,
+    ring: VmEventRing,
+    monitor: XenMonitor,
+    handlers: Vec<(MonitorCapabilities, Handler<'a>)>,
+    fallback: Option<Handler<'a>>,
+    handle: MonitorLoopHandle,
+}
+
+impl<'a> MonitorLoop<'a> {
+    /// Creates a loop for the monitor and its ring, binding the monitor
+    /// event channel.
+    pub fn new(monitor: XenMonitor, ring: VmEventRing) -> Result<Self, XenError> {
+        let port = monitor.channel()?;
+        let handle = MonitorLoopHandle {
+            stopped: Arc::new(AtomicBool::new(false)),
+            cancel: port.cancel_handle(),
+        };
+
+        Ok(Self {
+            port,
+            ring,
+            monitor,
+            handlers: Vec::new(),
+            fallback: None,
+            handle,
+        })
+    }
+
+    pub fn domain_id(&self) -> XenDomainId {
+        self.monitor.domain_id()
+    }
+
+    /// Returns the monitor, e.g. to enable or disable events while the
+    /// loop runs.
+    pub fn monitor(&self) -> &XenMonitor {
+        &self.monitor
+    }
+
+    pub fn port(&self) -> &XenEventChannelPort {
+        &self.port
+    }
+
+    /// Returns a handle that stops the loop, e.g. from a handler or
+    /// another thread.
+    pub fn handle(&self) -> MonitorLoopHandle {
+        self.handle.clone()
+    }
+
+    /// Handles events of the `reasons` with `handler`, e.g.
+    /// [`MonitorCapabilities::SINGLESTEP`] or
+    /// `MonitorCapabilities::SINGLESTEP | MonitorCapabilities::CPUID`.
+    ///
+    /// Handlers registered later take precedence for the reasons they
+    /// share with earlier ones.
+    pub fn on(
+        &mut self,
+        reasons: MonitorCapabilities,
+        handler: impl FnMut(&VmEvent) -> Result<MonitorResponse, XenError> + Send + 'a,
+    ) -> &mut Self {
+        self.handlers.push((reasons, Box::new(handler)));
+        self
+    }
+
+    /// Handles events no other handler is registered for, including
+    /// events of unknown reasons.
+    pub fn on_unhandled(
+        &mut self,
+        handler: impl FnMut(&VmEvent) -> Result<MonitorResponse, XenError> + Send + 'a,
+    ) -> &mut Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Handles events until the loop is stopped through its
+    /// [`handle`](Self::handle) or a handler fails.
+    ///
+    /// A stopped loop can be run again, stops requested before `run` is
+    /// called are discarded.
+    pub fn run(&mut self) -> Result<(), XenError> {
+        self.handle.cancel.reset();
+        self.handle.stopped.store(false, Ordering::Release);
+
+        while !self.handle.is_stopped() {
+            self.run_once()?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for events unless some are already on the ring, and handles
+    /// them.
+    ///
+    /// Returns the number of handled events, zero if the loop was stopped
+    /// while waiting.
+    pub fn run_once(&mut self) -> Result<usize, XenError> {
+        if !self.ring.has_unconsumed_requests() {
+            match self.port.wait() {
+                Ok(()) => {}
+                Err(err) if self.is_cancelled(&err) => return Ok(0),
+                Err(err) => return Err(err),
+            }
+        }
+
+        self.dispatch()
+    }
+
+    /// Like [`run_once`](Self::run_once), but waits at most `timeout`.
+    pub fn run_timeout(&mut self, timeout: Duration) -> Result<usize, XenError> {
+        if !self.ring.has_unconsumed_requests() {
+            match self.port.wait_timeout(timeout) {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(err) if self.is_cancelled(&err) => return Ok(0),
+                Err(err) => return Err(err),
+            }
+        }
+
+        self.dispatch()
+    }
+
+    /// Handles the events on the ring without waiting.
+    ///
+    /// Returns the number of handled events. After a handler fails, the
+    /// failed event is answered and the remaining events are left on the
+    /// ring for the next call.
+    pub fn dispatch(&mut self) -> Result<usize, XenError> {
+        let mut handled = 0;
+        let mut result = Ok(());
+
+        while result.is_ok() && self.ring.has_unconsumed_requests() {
+            let request = self.ring.get_request();
+
+            let response = match self.handle_request(&request) {
+                Ok(response) => response,
+                Err(err) => {
+                    result = Err(XenError::Event {
+                        domain_id: self.monitor.domain_id(),
+                        vcpu_id: request.vcpu_id,
+                        reason: request.reason.name(),
+                        source: Box::new(err),
+                    });
+
+                    MonitorResponse::new()
+                }
+            };
+
+            self.ring.put_response(response.into_event(request));
+            handled += 1;
+        }
+
+        if handled > 0 {
+            self.port.notify()?;
+        }
+
+        result.map(|()| handled)
+    }
+
+    /// Returns `true` if waiting failed because the loop was stopped.
+    fn is_cancelled(&self, err: &XenError) -> bool {
+        self.handle.is_stopped() && err.errno() == Some(libc::ECANCELED)
+    }
+
+    fn handle_request(&mut self, request: &VmEvent) -> Result<MonitorResponse, XenError> {
+        let reason = MonitorCapabilities::from(&request.reason);
+
+        let handler = self
+            .handlers
+            .iter_mut()
+            .rev()
+            .find(|(reasons, _)| !reason.is_empty() && reasons.contains(reason))
+            .map(|(_, handler)| handler)
+            .or(self.fallback.as_mut());
+
+        match handler {
+            Some(handler) => handler(request),
+            None => Ok(MonitorResponse::new()),
+        }
+    }
+}
+
+impl Drop for MonitorLoop<'_> {
+    fn drop(&mut self) {
+        let mut answered = false;
+
+        while self.ring.has_unconsumed_requests() {
+            let request = self.ring.get_request();
+            self.ring
+                .put_response(MonitorResponse::new().into_event(request));
+            answered = true;
+        }
+
+        if answered && let Err(err) = self.port.notify() {
+            tracing::warn!(?err, "failed to notify the monitor event channel");
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::{
+        VcpuId, XenDomain, arch::x86::Amd64, backend::mock::MockHypervisor, ctrl::VmEventReason,
+    };
+
+    fn setup(vcpus: u16) -> (MockHypervisor, XenDomain<Amd64>) {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, vcpus)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+
+        (hypervisor, domain)
+    }
+
+    fn send(hypervisor: &MockHypervisor, vcpu: u16, reason: VmEventReason) {
+        hypervisor
+            .send_event(
+                XenDomainId(1),
+                VmEvent {
+                    flags: VmEventFlag::VCPU_PAUSED,
+                    reason,
+                    vcpu_id: VcpuId(vcpu),
+                    altp2m_idx: 0,
+                    options: None,
+                    data: None,
+                },
+            )
+            .unwrap();
+    }
+
+    fn is_paused(hypervisor: &MockHypervisor, vcpu: u16) -> bool {
+        hypervisor
+            .is_vcpu_paused(XenDomainId(1), VcpuId(vcpu))
+            .unwrap()
+    }
+
+    #[test]
+    fn dispatch_by_reason() {
+        let (hypervisor, domain) = setup(1);
+        let mut event_loop = domain.monitor_loop().unwrap();
+
+        event_loop
+            .on(
+                MonitorCapabilities::GUEST_REQUEST | MonitorCapabilities::PRIVILEGED_CALL,
+                |_| Ok(MonitorResponse::new().toggle_singlestep()),
+            )
+            .on(MonitorCapabilities::GUEST_REQUEST, |_| {
+                Ok(MonitorResponse::new().deny())
+            });
+
+        send(&hypervisor, 0, VmEventReason::GuestRequest);
+        send(&hypervisor, 0, VmEventReason::PrivilegedCall);
+        assert_eq!(event_loop.dispatch().unwrap(), 2);
+
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert!(matches!(responses[0].reason, VmEventReason::GuestRequest));
+        assert_eq!(
+            responses[0].flags,
+            VmEventFlag::VCPU_PAUSED | VmEventFlag::DENY
+        );
+        assert!(matches!(responses[1].reason, VmEventReason::PrivilegedCall));
+        assert_eq!(
+            responses[1].flags,
+            VmEventFlag::VCPU_PAUSED | VmEventFlag::TOGGLE_SINGLESTEP
+        );
+        assert!(!is_paused(&hypervisor, 0));
+    }
+
+    #[test]
+    fn unhandled_events() {
+        let (hypervisor, domain) = setup(1);
+        let mut event_loop = domain.monitor_loop().unwrap();
+        event_loop.on(MonitorCapabilities::GUEST_REQUEST, |_| {
+            Ok(MonitorResponse::new().deny())
+        });
+
+        // Without a fallback, the event is answered with the default
+        // response.
+        send(&hypervisor, 0, VmEventReason::EmulUnimplemented);
+        assert_eq!(event_loop.dispatch().unwrap(), 1);
+
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(responses[0].flags, VmEventFlag::VCPU_PAUSED);
+        assert!(!is_paused(&hypervisor, 0));
+
+        event_loop.on_unhandled(|_| Ok(MonitorResponse::new().emulate()));
+
+        send(&hypervisor, 0, VmEventReason::EmulUnimplemented);
+        send(&hypervisor, 0, VmEventReason::GuestRequest);
+        assert_eq!(event_loop.dispatch().unwrap(), 2);
+
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(
+            responses[0].flags,
+            VmEventFlag::VCPU_PAUSED | VmEventFlag::EMULATE
+        );
+        assert_eq!(
+            responses[1].flags,
+            VmEventFlag::VCPU_PAUSED | VmEventFlag::DENY
+        );
+    }
+
+    #[test]
+    fn failed_handler() {
+        let (hypervisor, domain) = setup(2);
+        let mut event_loop = domain.monitor_loop().unwrap();
+        event_loop.on(MonitorCapabilities::GUEST_REQUEST, |_| {
+            Err(XenError::Other("handler failed"))
+        });
+
+        send(&hypervisor, 1, VmEventReason::GuestRequest);
+        send(&hypervisor, 0, VmEventReason::PrivilegedCall);
+
+        let err = event_loop.dispatch().unwrap_err();
+        match &err {
+            XenError::Event {
+                domain_id,
+                vcpu_id,
+                reason,
+                source,
+            } => {
+                assert_eq!(*domain_id, XenDomainId(1));
+                assert_eq!(*vcpu_id, VcpuId(1));
+                assert_eq!(*reason, VmEventReason::GuestRequest.name());
+                assert!(matches!(**source, XenError::Other("handler failed")));
+            }
+            _ => panic!("unexpected error {err:?}"),
+        }
+
+        // The failed event is answered, the next one stays on the ring.
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].flags, VmEventFlag::VCPU_PAUSED);
+        assert!(!is_paused(&hypervisor, 1));
+        assert!(is_paused(&hypervisor, 0));
+        assert_eq!(event_loop.ring.unconsumed_requests(), 1);
+
+        assert_eq!(event_loop.dispatch().unwrap(), 1);
+        assert!(!is_paused(&hypervisor, 0));
+    }
+
+    #[test]
+    fn drop_answers_requests() {
+        let (hypervisor, domain) = setup(2);
+        let event_loop = domain.monitor_loop().unwrap();
+
+        send(&hypervisor, 0, VmEventReason::GuestRequest);
+        send(&hypervisor, 1, VmEventReason::GuestRequest);
+
+        drop(event_loop);
+
+        // Disabling the monitor unpauses the vCPUs as well, the responses
+        // show the requests were answered before.
+        let responses = hypervisor.take_responses(XenDomainId(1)).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert!(
+            responses
+                .iter()
+                .all(|response| response.flags == VmEventFlag::VCPU_PAUSED)
+        );
+        assert!(!is_paused(&hypervisor, 0));
+        assert!(!is_paused(&hypervisor, 1));
+    }
+
+    #[test]
+    fn stop_wakes_run() {
+        let (hypervisor, domain) = setup(1);
+        let mut event_loop = domain.monitor_loop().unwrap();
+        let handle = event_loop.handle();
+
+        std::thread::scope(|scope| {
+            let run = scope.spawn(|| event_loop.run());
+
+            std::thread::sleep(Duration::from_millis(50));
+            handle.stop();
+            run.join().unwrap().unwrap();
+        });
+
+        // The loop runs again after it was stopped.
+        let handled = Arc::new(AtomicUsize::new(0));
+        event_loop.on(MonitorCapabilities::GUEST_REQUEST, {
+            let handled = handled.clone();
+            move |_| {
+                handled.fetch_add(1, Ordering::Relaxed);
+                handle.stop();
+                Ok(MonitorResponse::new())
+            }
+        });
+
+        send(&hypervisor, 0, VmEventReason::GuestRequest);
+        event_loop.run().unwrap();
+        assert_eq!(handled.load(Ordering::Relaxed), 1);
+        assert!(!is_paused(&hypervisor, 0));
+    }
+}
@@ -1,21 +1,23 @@
+mod capabilities;
+mod event_loop;
 mod ring;
-use xen_sys::{
-    vm_event_back_ring, xc_monitor_cpuid, xc_monitor_debug_exceptions,
-    xc_monitor_descriptor_access, xc_monitor_disable, xc_monitor_emul_unimplemented,
-    xc_monitor_emulate_each_rep, xc_monitor_enable, xc_monitor_get_capabilities,
-    xc_monitor_guest_request, xc_monitor_inguest_pagefault, xc_monitor_io, xc_monitor_mov_to_msr,
-    xc_monitor_privileged_call, xc_monitor_resume, xc_monitor_singlestep,
-    xc_monitor_software_breakpoint, xc_monitor_vmexit, xc_monitor_write_ctrlreg,
+#[cfg(feature = "tokio")]
+mod stream;
+use xen_sys::vm_event_back_ring;
+
+#[cfg(feature = "tokio")]
+pub use self::stream::VmEventStream;
+pub use self::{
+    capabilities::{IntrospectionCapabilities, MonitorCapabilities},
+    event_loop::{MonitorLoop, MonitorLoopHandle, MonitorResponse},
+    ring::VmEventRing,
 };
-
-pub use self::ring::VmEventRing;
 use crate::{
     BACK_RING_INIT, SHARED_RING_INIT, XenDomainId,
     consts::PAGE_SIZE,
     ctrl::{VmEventCtrlReg, XenInterface},
-    error::{XcError, XenError},
-    evtchn::XenEventChannelPort,
-    xc_check_error,
+    error::XenError,
+    evtchn::{XenEventChannel, XenEventChannelPort},
 };
 
 pub struct XenMonitor {
@@ -29,12 +31,8 @@ impl XenMonitor {
         interface: XenInterface,
         domain_id: XenDomainId,
     ) -> Result<(Self, VmEventRing), XenError> {
-        let mut port: u32 = 0;
-        let ring_page = unsafe { xc_monitor_enable(interface.handle.0, domain_id.0, &mut port) };
-
-        if ring_page.is_null() {
-            return Err(XcError::new(-1, 0, "Failed to enable monitor").into());
-        }
+        let (ring_page, port) = interface.backend.monitor_enable(domain_id)?;
+        let ring_page = ring_page.as_ptr();
 
         SHARED_RING_INIT!(ring_page);
 
@@ -43,16 +41,29 @@ impl XenMonitor {
 
         Ok((
             Self {
-                interface,
+                interface: interface.clone(),
                 domain_id,
                 port,
             },
-            VmEventRing::new(ring_page, back_ring),
+            VmEventRing::new(interface, ring_page, back_ring),
         ))
     }
 
+    /// Opens a new event channel and binds the monitor port on it.
     pub fn channel(&self) -> Result<XenEventChannelPort, XenError> {
-        XenEventChannelPort::bind_interdomain(self.domain_id, self.port)
+        let evtchn = XenEventChannel::with_backend(self.interface.backend.open_event_channel()?);
+        self.bind(&evtchn)
+    }
+
+    /// Binds the monitor port on an existing event channel, e.g. to wait
+    /// for the monitors of many domains with a single
+    /// [`XenEventChannel::poll`].
+    pub fn bind(&self, evtchn: &XenEventChannel) -> Result<XenEventChannelPort, XenError> {
+        evtchn.bind_interdomain(self.domain_id, self.port)
+    }
+
+    pub fn domain_id(&self) -> XenDomainId {
+        self.domain_id
     }
 
     pub fn port(&self) -> u32 {
@@ -60,22 +71,23 @@ impl XenMonitor {
     }
 
     pub fn resume(&self) -> Result<(), XenError> {
-        let rc = unsafe { xc_monitor_resume(self.interface.handle.0, self.domain_id.0) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.monitor_resume(self.domain_id)
+    }
+
+    /// Returns the event reasons the domain can be monitored for.
+    pub fn get_capabilities(&self) -> Result<MonitorCapabilities, XenError> {
+        Ok(MonitorCapabilities::from_bits_retain(
+            self.interface
+                .backend
+                .monitor_get_capabilities(self.domain_id)?,
+        ))
     }
 
-    pub fn get_capabilities(&self) -> Result<u32, XenError> {
-        let mut capabilities = 0;
-        let rc = unsafe {
-            xc_monitor_get_capabilities(
-                self.interface.handle.0,
-                self.domain_id.0,
-                &mut capabilities,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(capabilities)
+    /// Returns `true` if events of all `capabilities` can be monitored,
+    /// e.g. [`MonitorCapabilities::SINGLESTEP`] or the reason of a received
+    /// event.
+    pub fn supports(&self, capabilities: impl Into<MonitorCapabilities>) -> Result<bool, XenError> {
+        Ok(self.get_capabilities()?.contains(capabilities.into()))
     }
 
     pub fn write_ctrlreg(
@@ -86,56 +98,38 @@ impl XenMonitor {
         bitmask: u64,
         onchangeonly: bool,
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_write_ctrlreg(
-                self.interface.handle.0,
-                self.domain_id.0,
-                index as u16,
-                enable,
-                sync,
-                bitmask,
-                onchangeonly,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.monitor_write_ctrlreg(
+            self.domain_id,
+            index,
+            enable,
+            sync,
+            bitmask,
+            onchangeonly,
+        )
     }
 
     pub fn mov_to_msr(&self, msr: u32, enable: bool, onchangeonly: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_mov_to_msr(
-                self.interface.handle.0,
-                self.domain_id.0,
-                msr,
-                enable,
-                onchangeonly,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_mov_to_msr(self.domain_id, msr, enable, onchangeonly)
     }
 
     pub fn singlestep(&self, singlestep: bool) -> Result<(), XenError> {
-        let rc =
-            unsafe { xc_monitor_singlestep(self.interface.handle.0, self.domain_id.0, singlestep) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_singlestep(self.domain_id, singlestep)
     }
 
     pub fn software_breakpoint(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_software_breakpoint(self.interface.handle.0, self.domain_id.0, enable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_software_breakpoint(self.domain_id, enable)
     }
 
     pub fn descriptor_access(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_descriptor_access(self.interface.handle.0, self.domain_id.0, enable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_descriptor_access(self.domain_id, enable)
     }
 
     pub fn guest_request(
@@ -144,84 +138,59 @@ impl XenMonitor {
         sync: bool,
         allow_userspace: bool,
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_guest_request(
-                self.interface.handle.0,
-                self.domain_id.0,
-                enable,
-                sync,
-                allow_userspace,
-            )
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_guest_request(self.domain_id, enable, sync, allow_userspace)
     }
 
     pub fn inguest_pagefault(&self, disable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_inguest_pagefault(self.interface.handle.0, self.domain_id.0, disable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_inguest_pagefault(self.domain_id, disable)
     }
 
     pub fn debug_exceptions(&self, enable: bool, sync: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_debug_exceptions(self.interface.handle.0, self.domain_id.0, enable, sync)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_debug_exceptions(self.domain_id, enable, sync)
     }
 
     pub fn cpuid(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe { xc_monitor_cpuid(self.interface.handle.0, self.domain_id.0, enable) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.monitor_cpuid(self.domain_id, enable)
     }
 
     pub fn privileged_call(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_privileged_call(self.interface.handle.0, self.domain_id.0, enable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_privileged_call(self.domain_id, enable)
     }
 
     pub fn emul_unimplemented(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_emul_unimplemented(self.interface.handle.0, self.domain_id.0, enable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_emul_unimplemented(self.domain_id, enable)
     }
 
     pub fn vmexit(&self, enable: bool, sync: bool) -> Result<(), XenError> {
-        let rc =
-            unsafe { xc_monitor_vmexit(self.interface.handle.0, self.domain_id.0, enable, sync) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_vmexit(self.domain_id, enable, sync)
     }
 
     pub fn io(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe { xc_monitor_io(self.interface.handle.0, self.domain_id.0, enable) };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface.backend.monitor_io(self.domain_id, enable)
     }
 
     pub fn emulate_each_rep(&self, enable: bool) -> Result<(), XenError> {
-        let rc = unsafe {
-            xc_monitor_emulate_each_rep(self.interface.handle.0, self.domain_id.0, enable)
-        };
-        xc_check_error!(self.interface.handle.0, rc);
-        Ok(())
+        self.interface
+            .backend
+            .monitor_emulate_each_rep(self.domain_id, enable)
     }
 }
 
 impl Drop for XenMonitor {
     fn drop(&mut self) {
         tracing::trace!(?self.domain_id, "disabling monitor");
-        unsafe {
-            xc_monitor_disable(self.interface.handle.0, self.domain_id.0);
-        }
+        let _ = self.interface.backend.monitor_disable(self.domain_id);
     }
 }
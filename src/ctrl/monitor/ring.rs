@@ -1,20 +1,34 @@
-use std::ffi::c_void;
+use std::{ffi::c_void, ptr::NonNull};
 
 use xen_sys::vm_event_back_ring;
 
+#[cfg(feature = "tokio")]
+use super::VmEventStream;
 use crate::{
     RING_GET_REQUEST, RING_HAS_UNCONSUMED_REQUESTS, RING_PUSH_RESPONSES, RING_PUT_RESPONSE,
-    consts::PAGE_SIZE, ctrl::VmEvent,
+    ctrl::{VmEvent, XenInterface},
 };
+#[cfg(feature = "tokio")]
+use crate::{XenError, evtchn::XenEventChannelPort};
 
 pub struct VmEventRing {
+    interface: XenInterface,
     ring_page: *mut c_void,
     back_ring: vm_event_back_ring,
 }
 
+// SAFETY: The ring page is owned by the ring and every access to the back
+// ring goes through `&mut self`.
+unsafe impl Send for VmEventRing {}
+
 impl VmEventRing {
-    pub(crate) fn new(ring_page: *mut c_void, back_ring: vm_event_back_ring) -> Self {
+    pub(crate) fn new(
+        interface: XenInterface,
+        ring_page: *mut c_void,
+        back_ring: vm_event_back_ring,
+    ) -> Self {
         Self {
+            interface,
             ring_page,
             back_ring,
         }
@@ -54,13 +68,29 @@ impl VmEventRing {
         self.back_ring.rsp_prod_pvt = rsp_prod;
         RING_PUSH_RESPONSES!(self.back_ring);
     }
+
+    /// Turns the ring into an async stream of its requests, waiting for
+    /// `port` on the tokio reactor.
+    ///
+    /// `port` must be the monitor event channel of the ring, bound with
+    /// [`XenMonitor::channel`](crate::XenMonitor::channel), and is put into
+    /// non-blocking mode. Fails with `EBUSY` if other ports are bound on
+    /// its channel, e.g. with [`XenMonitor::bind`](crate::XenMonitor::bind).
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with I/O enabled.
+    #[cfg(feature = "tokio")]
+    pub fn into_stream(self, port: XenEventChannelPort) -> Result<VmEventStream, XenError> {
+        VmEventStream::new(self, port)
+    }
 }
 
 impl Drop for VmEventRing {
     fn drop(&mut self) {
         tracing::trace!("unmapping ring page");
-        unsafe {
-            libc::munmap(self.ring_page, PAGE_SIZE as usize);
+        if let Some(ring_page) = NonNull::new(self.ring_page) {
+            self.interface.backend.monitor_unmap_ring_page(ring_page);
         }
     }
 }
@@ -0,0 +1,137 @@
+use std::{
+    os::fd::AsRawFd,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_core::Stream;
+use tokio::io::{Interest, unix::AsyncFd};
+
+use super::VmEventRing;
+use crate::{
+    XenError,
+    ctrl::VmEvent,
+    evtchn::{XenEventChannelPort, set_nonblocking},
+};
+
+/// Requests of a [`VmEventRing`] as an async [`Stream`].
+///
+/// The stream waits for the monitor event channel on the tokio reactor
+/// instead of blocking a thread. The channel must not be shared with other
+/// ports, so the port has to be bound with
+/// [`XenMonitor::channel`](crate::XenMonitor::channel). Every request still
+/// needs a response, sent with [`respond`](Self::respond):
+///
+/// ```no_run
+/// # async fn example(domain: xen::XenDomain<xen::arch::x86::Amd64>) -> Result<(), xen::XenError> {
+/// use std::future::poll_fn;
+/// use std::pin::Pin;
+///
+/// use futures_core::Stream;
+///
+/// let (monitor, ring) = domain.monitor()?;
+///
+/// // The only supported port: the monitor port on a channel of its own.
+/// let mut events = ring.into_stream(monitor.channel()?)?;
+///
+/// while let Some(event) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+///     events.respond(event?).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct VmEventStream {
+    ring: VmEventRing,
+    port: AsyncFd<XenEventChannelPort>,
+}
+
+impl VmEventStream {
+    pub(crate) fn new(ring: VmEventRing, port: XenEventChannelPort) -> Result<Self, XenError> {
+        // Readiness of a shared channel may belong to another port, whose
+        // signal the stream would consume.
+        if port.is_shared() {
+            return Err(std::io::Error::from_raw_os_error(libc::EBUSY).into());
+        }
+
+        set_nonblocking(port.as_raw_fd())?;
+
+        // SAFETY: The port holds the event channel handle, which keeps the
+        // file descriptor open until the port is dropped.
+        let port = unsafe { AsyncFd::register_with_interest(port, Interest::READABLE) }
+            .map_err(std::io::Error::from)?;
+
+        Ok(Self { ring, port })
+    }
+
+    pub fn port(&self) -> &XenEventChannelPort {
+        self.port.get_ref()
+    }
+
+    /// Places a response on the ring and notifies the hypervisor.
+    pub async fn respond(&mut self, response: VmEvent) -> Result<(), XenError> {
+        self.ring.put_response(response);
+        self.port.get_ref().notify()
+    }
+
+    /// Returns the ring and the event channel port.
+    pub fn into_inner(self) -> (VmEventRing, XenEventChannelPort) {
+        (self.ring, self.port.into_inner())
+    }
+}
+
+impl Stream for VmEventStream {
+    type Item = Result<VmEvent, XenError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.ring.has_unconsumed_requests() {
+                return Poll::Ready(Some(Ok(this.ring.get_request())));
+            }
+
+            let mut guard = ready!(this.port.poll_read_ready(cx))?;
+
+            match guard.get_inner().try_wait() {
+                Ok(true) => {}
+                Ok(false) => guard.clear_ready(),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for VmEventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VmEventStream")
+            .field("port", self.port.get_ref())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{XenDomainId, arch::x86::Amd64, backend::mock::MockHypervisor, evtchn::Virq};
+
+    #[test]
+    fn shared_channel() {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+        let (monitor, ring) = domain.monitor().unwrap();
+
+        let evtchn = hypervisor.event_channel().unwrap();
+        let _dom_exc = evtchn.bind_virq(Virq::DomExc).unwrap();
+        let port = monitor.bind(&evtchn).unwrap();
+
+        let err = ring.into_stream(port).unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EBUSY));
+    }
+}
@@ -0,0 +1,109 @@
+use xen_sys::{
+    XEN_SYSCTL_PHYSCAP_directio, XEN_SYSCTL_PHYSCAP_gnttab_v1, XEN_SYSCTL_PHYSCAP_gnttab_v2,
+    XEN_SYSCTL_PHYSCAP_hap, XEN_SYSCTL_PHYSCAP_hvm, XEN_SYSCTL_PHYSCAP_iommu_hap_pt_share,
+    XEN_SYSCTL_PHYSCAP_pv, XEN_SYSCTL_PHYSCAP_shadow, XEN_SYSCTL_PHYSCAP_vmtrace,
+    XEN_SYSCTL_PHYSCAP_vpmu, xen_sysctl_physinfo,
+};
+
+bitflags::bitflags! {
+    /// Capabilities of the host (`XEN_SYSCTL_PHYSCAP_*`).
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct XenPhysCapabilities: u32 {
+        const HVM = XEN_SYSCTL_PHYSCAP_hvm;
+        const PV = XEN_SYSCTL_PHYSCAP_pv;
+        const DIRECTIO = XEN_SYSCTL_PHYSCAP_directio;
+        const HAP = XEN_SYSCTL_PHYSCAP_hap;
+        const SHADOW = XEN_SYSCTL_PHYSCAP_shadow;
+        const IOMMU_HAP_PT_SHARE = XEN_SYSCTL_PHYSCAP_iommu_hap_pt_share;
+        const VMTRACE = XEN_SYSCTL_PHYSCAP_vmtrace;
+        const VPMU = XEN_SYSCTL_PHYSCAP_vpmu;
+        const GNTTAB_V1 = XEN_SYSCTL_PHYSCAP_gnttab_v1;
+        const GNTTAB_V2 = XEN_SYSCTL_PHYSCAP_gnttab_v2;
+    }
+}
+
+/// Index of the CPUID leaf 1 ECX word in [`XenPhysInfo::hw_cap`].
+const FEATURESET_1C: usize = 1;
+
+/// CPUID leaf 1 ECX bit indicating Intel VT-x.
+const X86_FEATURE_VMX: u32 = 1 << 5;
+
+/// Physical host information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XenPhysInfo {
+    pub threads_per_core: u32,
+    pub cores_per_socket: u32,
+    pub nr_cpus: u32,
+    pub max_cpu_id: u32,
+    pub nr_nodes: u32,
+    pub max_node_id: u32,
+    pub cpu_khz: u32,
+    pub capabilities: XenPhysCapabilities,
+    pub arch_capabilities: u32,
+    pub total_pages: u64,
+    pub free_pages: u64,
+    pub scrub_pages: u64,
+    pub outstanding_pages: u64,
+    pub max_mfn: u64,
+
+    /// Host CPU featureset, in the order of Xen's `FEATURESET_*` words.
+    pub hw_cap: [u32; 8],
+}
+
+impl XenPhysInfo {
+    /// Returns `true` if HVM guests are supported.
+    pub fn hvm(&self) -> bool {
+        self.capabilities.contains(XenPhysCapabilities::HVM)
+    }
+
+    /// Returns `true` if hardware assisted paging (EPT/NPT) is available.
+    pub fn hap(&self) -> bool {
+        self.capabilities.contains(XenPhysCapabilities::HAP)
+    }
+
+    /// Returns `true` if the host CPU supports Intel VT-x.
+    pub fn vmx(&self) -> bool {
+        self.hw_cap[FEATURESET_1C] & X86_FEATURE_VMX != 0
+    }
+
+    /// Returns `true` if the host can run altp2m.
+    ///
+    /// Xen does not report altp2m support directly. On x86 it requires HVM
+    /// with HAP on a VT-x capable CPU, which is what this checks.
+    pub fn altp2m(&self) -> bool {
+        self.hvm() && self.hap() && self.vmx()
+    }
+
+    /// Returns `true` if processor tracing of guests is supported.
+    pub fn vmtrace(&self) -> bool {
+        self.capabilities.contains(XenPhysCapabilities::VMTRACE)
+    }
+
+    /// Returns `true` if an IOMMU is enabled, i.e. devices can be passed
+    /// through to guests.
+    pub fn iommu(&self) -> bool {
+        self.capabilities.contains(XenPhysCapabilities::DIRECTIO)
+    }
+}
+
+impl From<xen_sysctl_physinfo> for XenPhysInfo {
+    fn from(value: xen_sysctl_physinfo) -> Self {
+        Self {
+            threads_per_core: value.threads_per_core,
+            cores_per_socket: value.cores_per_socket,
+            nr_cpus: value.nr_cpus,
+            max_cpu_id: value.max_cpu_id,
+            nr_nodes: value.nr_nodes,
+            max_node_id: value.max_node_id,
+            cpu_khz: value.cpu_khz,
+            capabilities: XenPhysCapabilities::from_bits_retain(value.capabilities),
+            arch_capabilities: value.arch_capabilities,
+            total_pages: value.total_pages,
+            free_pages: value.free_pages,
+            scrub_pages: value.scrub_pages,
+            outstanding_pages: value.outstanding_pages,
+            max_mfn: value.max_mfn,
+            hw_cap: value.hw_cap,
+        }
+    }
+}
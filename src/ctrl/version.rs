@@ -0,0 +1,112 @@
+use std::ffi::{CStr, c_char};
+
+use xen_sys::{
+    XENVER_capabilities, XENVER_changeset, XENVER_compile_info, XENVER_extraversion,
+    XENVER_pagesize, XENVER_platform_parameters, XENVER_version, xen_capabilities_info_t,
+    xen_changeset_info_t, xen_compile_info, xen_extraversion_t, xen_platform_parameters,
+};
+
+use crate::{XenError, backend::XenControlBackend, macros::as_bytes_mut};
+
+/// Information about how the hypervisor was built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XenCompileInfo {
+    pub compiler: String,
+    pub compile_by: String,
+    pub compile_domain: String,
+    pub compile_date: String,
+}
+
+impl From<xen_compile_info> for XenCompileInfo {
+    fn from(value: xen_compile_info) -> Self {
+        Self {
+            compiler: to_string(&value.compiler),
+            compile_by: to_string(&value.compile_by),
+            compile_domain: to_string(&value.compile_domain),
+            compile_date: to_string(&value.compile_date),
+        }
+    }
+}
+
+/// Version of the running hypervisor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XenVersion {
+    pub major: u32,
+    pub minor: u32,
+
+    /// Extra version, e.g. `.1` or `-rc`.
+    pub extra: String,
+
+    /// Changeset the hypervisor was built from.
+    pub changeset: String,
+
+    pub compile_info: XenCompileInfo,
+
+    /// Space separated list of supported guest types, e.g.
+    /// `xen-3.0-x86_64 hvm-3.0-x86_32 hvm-3.0-x86_64`.
+    pub capabilities: String,
+
+    /// Start of the hypervisor virtual address range.
+    pub virt_start: u64,
+
+    /// Page size used by the hypervisor.
+    pub pagesize: u64,
+}
+
+impl XenVersion {
+    pub(crate) fn query(backend: &dyn XenControlBackend) -> Result<Self, XenError> {
+        let version = backend.version(XENVER_version, None)? as u32;
+
+        let mut extra: xen_extraversion_t = unsafe { std::mem::zeroed() };
+        backend.version(XENVER_extraversion, Some(as_bytes_mut(&mut extra)))?;
+
+        let mut changeset: xen_changeset_info_t = unsafe { std::mem::zeroed() };
+        backend.version(XENVER_changeset, Some(as_bytes_mut(&mut changeset)))?;
+
+        let mut compile_info: xen_compile_info = unsafe { std::mem::zeroed() };
+        backend.version(XENVER_compile_info, Some(as_bytes_mut(&mut compile_info)))?;
+
+        let mut capabilities: xen_capabilities_info_t = unsafe { std::mem::zeroed() };
+        backend.version(XENVER_capabilities, Some(as_bytes_mut(&mut capabilities)))?;
+
+        let mut platform_parameters = xen_platform_parameters::default();
+        backend.version(
+            XENVER_platform_parameters,
+            Some(as_bytes_mut(&mut platform_parameters)),
+        )?;
+
+        let pagesize = backend.version(XENVER_pagesize, None)?;
+
+        Ok(Self {
+            major: version >> 16,
+            minor: version & 0xffff,
+            extra: to_string(&extra),
+            changeset: to_string(&changeset),
+            compile_info: compile_info.into(),
+            capabilities: to_string(&capabilities),
+            virt_start: platform_parameters.virt_start,
+            pagesize: pagesize as u64,
+        })
+    }
+
+    /// Returns `true` if the hypervisor version is at least `major.minor`.
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+impl std::fmt::Display for XenVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}{}", self.major, self.minor, self.extra)
+    }
+}
+
+/// Converts a fixed-size C string, which is not necessarily nul-terminated.
+fn to_string(value: &[c_char]) -> String {
+    let bytes = unsafe { std::slice::from_raw_parts(value.as_ptr() as *const u8, value.len()) };
+
+    match CStr::from_bytes_until_nul(bytes) {
+        Ok(value) => value.to_string_lossy().into_owned(),
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
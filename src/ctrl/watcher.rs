@@ -0,0 +1,502 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use super::{ShutdownReason, XenControl, XenDomainFlags, XenDomainInfo};
+use crate::{
+    XenDomainId, XenError,
+    evtchn::{Virq, XenEventChannelCancelHandle, XenEventChannelPort, poll_fds},
+    store::XenStore,
+};
+
+/// Token of the store watches registered by a [`DomainWatcher`].
+const WATCH_TOKEN: &str = "xen-rs-domain-watcher";
+
+/// Change of the state of a domain, reported by a [`DomainWatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainEvent {
+    /// The domain appeared.
+    Created(XenDomainInfo),
+
+    /// The domain was paused by the toolstack.
+    Paused(XenDomainId),
+
+    /// The domain was unpaused by the toolstack.
+    Unpaused(XenDomainId),
+
+    /// The domain has shut down for a reason other than a crash.
+    Shutdown {
+        domain_id: XenDomainId,
+        reason: ShutdownReason,
+    },
+
+    /// The domain has shut down because it crashed.
+    Crashed(XenDomainId),
+
+    /// The domain is being destroyed. Its monitors should be disabled and
+    /// its memory unmapped, so that the hypervisor can release it.
+    Dying(XenDomainId),
+
+    /// The domain is gone.
+    Destroyed(XenDomainId),
+}
+
+impl DomainEvent {
+    pub fn domain_id(&self) -> XenDomainId {
+        match *self {
+            Self::Created(info) => info.domain_id,
+            Self::Paused(domain_id)
+            | Self::Unpaused(domain_id)
+            | Self::Shutdown { domain_id, .. }
+            | Self::Crashed(domain_id)
+            | Self::Dying(domain_id)
+            | Self::Destroyed(domain_id) => domain_id,
+        }
+    }
+}
+
+/// Reports domains being created, paused, shut down and destroyed.
+///
+/// The watcher keeps a list of the domains and compares it with the
+/// domains reported by the hypervisor whenever it is notified of a change.
+/// Notifications come from `VIRQ_DOM_EXC`, which the hypervisor raises
+/// when a domain shuts down or dies, and from the `@introduceDomain` and
+/// `@releaseDomain` store watches. Pausing a domain raises neither, so
+/// the domains are also compared every
+/// [`rescan interval`](Self::set_rescan_interval).
+///
+/// Domains that exist when the watcher is created are not reported, they
+/// are available through [`domains`](Self::domains).
+///
+/// ```no_run
+/// # fn main() -> Result<(), xen::XenError> {
+/// use xen::{XenControl, ctrl::DomainEvent};
+///
+/// let control = XenControl::new()?;
+/// let mut watcher = control.domain_watcher()?;
+///
+/// loop {
+///     match watcher.next_event()? {
+///         DomainEvent::Created(info) => println!("domain {} created", info.domain_id),
+///         DomainEvent::Dying(domain_id) => println!("domain {domain_id} is dying"),
+///         _ => {}
+///     }
+/// }
+/// # }
+/// ```
+pub struct DomainWatcher {
+    control: XenControl,
+    port: Option<XenEventChannelPort>,
+    store: Option<XenStore>,
+    cancel: XenEventChannelCancelHandle,
+    domains: BTreeMap<XenDomainId, XenDomainInfo>,
+    events: VecDeque<DomainEvent>,
+    rescan_interval: Option<Duration>,
+}
+
+impl DomainWatcher {
+    /// Creates a watcher notified through `VIRQ_DOM_EXC`.
+    ///
+    /// Only one event channel of the control domain can bind the
+    /// interrupt. If it is already bound, e.g. by xenstored, binding
+    /// fails with `EEXIST`; use [`with_store`](Self::with_store) then.
+    pub fn new(control: &XenControl) -> Result<Self, XenError> {
+        let mut watcher = Self::unnotified(control)?;
+        watcher.port = Some(control.event_channel()?.bind_virq(Virq::DomExc)?);
+        Ok(watcher)
+    }
+
+    /// Creates a watcher notified through the `@introduceDomain` and
+    /// `@releaseDomain` watches of `store`.
+    ///
+    /// The watcher consumes all watch events of the store, which should not
+    /// be used for other watches.
+    pub fn with_store(control: &XenControl, store: XenStore) -> Result<Self, XenError> {
+        let mut watcher = Self::unnotified(control)?;
+        watcher.watch_store(store)?;
+        Ok(watcher)
+    }
+
+    fn unnotified(control: &XenControl) -> Result<Self, XenError> {
+        let control = XenControl::attach(control.interface.clone())?;
+        let domains = control
+            .domains()?
+            .into_iter()
+            .map(|info| (info.domain_id, info))
+            .collect();
+
+        Ok(Self {
+            control,
+            port: None,
+            store: None,
+            cancel: XenEventChannelCancelHandle::new()?,
+            domains,
+            events: VecDeque::new(),
+            rescan_interval: Some(Duration::from_secs(1)),
+        })
+    }
+
+    /// Also notifies the watcher through the `@introduceDomain` and
+    /// `@releaseDomain` watches of `store`, replacing a previously watched
+    /// store.
+    ///
+    /// The watcher consumes all watch events of the store, which should not
+    /// be used for other watches.
+    pub fn watch_store(&mut self, store: XenStore) -> Result<(), XenError> {
+        store.watch("@introduceDomain", WATCH_TOKEN)?;
+        store.watch("@releaseDomain", WATCH_TOKEN)?;
+
+        if let Some(previous) = self.store.replace(store) {
+            unwatch(&previous);
+        }
+
+        Ok(())
+    }
+
+    /// Sets how often the domains are compared without a notification.
+    ///
+    /// With `None`, only notifications trigger a comparison, and pausing
+    /// and unpausing of domains is reported with the next notification.
+    /// Defaults to one second.
+    pub fn set_rescan_interval(&mut self, interval: Option<Duration>) {
+        self.rescan_interval = interval;
+    }
+
+    /// Returns the domains as of the last comparison.
+    pub fn domains(&self) -> impl Iterator<Item = &XenDomainInfo> {
+        self.domains.values()
+    }
+
+    /// Returns a handle that wakes up the watcher from another thread,
+    /// making waits fail with `ECANCELED`.
+    pub fn cancel_handle(&self) -> XenEventChannelCancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Blocks until a domain changes its state.
+    pub fn next_event(&mut self) -> Result<DomainEvent, XenError> {
+        loop {
+            if let Some(event) = self.wait(None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Blocks until a domain changes its state or `timeout` elapses.
+    ///
+    /// Returns `None` on timeout.
+    pub fn next_event_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DomainEvent>, XenError> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    /// Compares the domains without waiting and returns the next change.
+    pub fn try_next_event(&mut self) -> Result<Option<DomainEvent>, XenError> {
+        self.wait(Some(Instant::now()))
+    }
+
+    /// Waits for a notification or the rescan interval until `deadline`,
+    /// and compares the domains.
+    fn wait(&mut self, deadline: Option<Instant>) -> Result<Option<DomainEvent>, XenError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            let rescan = self
+                .rescan_interval
+                .map(|interval| Instant::now() + interval);
+            let wakeup = match (deadline, rescan) {
+                (Some(deadline), Some(rescan)) => Some(deadline.min(rescan)),
+                (deadline, rescan) => deadline.or(rescan),
+            };
+
+            let mut fds = vec![libc::pollfd {
+                fd: self.cancel.fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+
+            if let Some(port) = &self.port {
+                fds.push(libc::pollfd {
+                    fd: port.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+
+            if let Some(store) = &self.store {
+                fds.push(libc::pollfd {
+                    fd: store.watch_fd()?.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+
+            poll_fds(&mut fds, wakeup)?;
+
+            if fds[0].revents != 0 {
+                return Err(std::io::Error::from_raw_os_error(libc::ECANCELED).into());
+            }
+
+            if let Some(port) = &self.port {
+                port.try_wait()?;
+            }
+
+            if let Some(store) = &self.store {
+                while store.check_watch()?.is_some() {}
+            }
+
+            self.rescan()?;
+
+            if self.events.is_empty()
+                && let Some(deadline) = deadline
+                && Instant::now() >= deadline
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Compares the domains reported by the hypervisor with the known
+    /// ones and queues the changes.
+    fn rescan(&mut self) -> Result<(), XenError> {
+        let domains = self
+            .control
+            .domains()?
+            .into_iter()
+            .map(|info| (info.domain_id, info))
+            .collect::<BTreeMap<_, _>>();
+
+        for (domain_id, old) in &self.domains {
+            match domains.get(domain_id) {
+                // A domain ID can be reused before the watcher notices that
+                // the previous domain is gone.
+                Some(new) if new.handle == old.handle => compare(&mut self.events, old, new),
+                Some(new) => {
+                    self.events.push_back(DomainEvent::Destroyed(*domain_id));
+                    self.events.push_back(DomainEvent::Created(*new));
+                }
+                None => self.events.push_back(DomainEvent::Destroyed(*domain_id)),
+            }
+        }
+
+        for (domain_id, new) in &domains {
+            if !self.domains.contains_key(domain_id) {
+                self.events.push_back(DomainEvent::Created(*new));
+            }
+        }
+
+        self.domains = domains;
+        Ok(())
+    }
+}
+
+/// Queues the changes between two states of the same domain.
+fn compare(events: &mut VecDeque<DomainEvent>, old: &XenDomainInfo, new: &XenDomainInfo) {
+    let domain_id = new.domain_id;
+    let appeared = new.flags.difference(old.flags);
+    let disappeared = old.flags.difference(new.flags);
+
+    if appeared.contains(XenDomainFlags::PAUSED) {
+        events.push_back(DomainEvent::Paused(domain_id));
+    }
+
+    if disappeared.contains(XenDomainFlags::PAUSED) {
+        events.push_back(DomainEvent::Unpaused(domain_id));
+    }
+
+    if appeared.contains(XenDomainFlags::SHUTDOWN) {
+        match new.shutdown_reason {
+            Some(ShutdownReason::Crash) => events.push_back(DomainEvent::Crashed(domain_id)),
+            Some(reason) => events.push_back(DomainEvent::Shutdown { domain_id, reason }),
+            None => {}
+        }
+    }
+
+    if appeared.contains(XenDomainFlags::DYING) {
+        events.push_back(DomainEvent::Dying(domain_id));
+    }
+}
+
+impl Drop for DomainWatcher {
+    fn drop(&mut self) {
+        if let Some(store) = &self.store {
+            unwatch(store);
+        }
+    }
+}
+
+fn unwatch(store: &XenStore) {
+    for path in ["@introduceDomain", "@releaseDomain"] {
+        if let Err(err) = store.unwatch(path, WATCH_TOKEN) {
+            tracing::warn!(?err, path, "failed to remove store watch");
+        }
+    }
+}
+
+impl std::fmt::Debug for DomainWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DomainWatcher")
+            .field("port", &self.port)
+            .field("store", &self.store)
+            .field("domains", &self.domains.len())
+            .field("rescan_interval", &self.rescan_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{XenDomain, arch::x86::Amd64, backend::mock::MockHypervisor, ctrl::XenDomainUuid};
+
+    fn setup() -> (MockHypervisor, XenControl, DomainWatcher) {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        let control = hypervisor.control().unwrap();
+        let mut watcher = control.domain_watcher().unwrap();
+        watcher.set_rescan_interval(None);
+
+        (hypervisor, control, watcher)
+    }
+
+    fn domain(control: &XenControl, domain_id: u32) -> XenDomain<Amd64> {
+        control.domain::<Amd64>(XenDomainId(domain_id)).unwrap()
+    }
+
+    fn events(watcher: &mut DomainWatcher) -> Vec<DomainEvent> {
+        std::iter::from_fn(|| watcher.try_next_event().unwrap()).collect()
+    }
+
+    #[test]
+    fn existing_domains_are_not_reported() {
+        let (_hypervisor, _control, mut watcher) = setup();
+
+        assert_eq!(events(&mut watcher), []);
+        assert_eq!(
+            watcher
+                .domains()
+                .map(|info| info.domain_id)
+                .collect::<Vec<_>>(),
+            [XenDomainId(1)]
+        );
+    }
+
+    #[test]
+    fn created() {
+        let (hypervisor, control, mut watcher) = setup();
+        hypervisor
+            .create_domain(XenDomainId(2), "other", 16, 1)
+            .unwrap();
+
+        let info = domain(&control, 2).info().unwrap();
+        assert_eq!(events(&mut watcher), [DomainEvent::Created(info)]);
+    }
+
+    #[test]
+    fn paused_and_unpaused() {
+        let (_hypervisor, control, mut watcher) = setup();
+        let domain = domain(&control, 1);
+
+        domain.pause().unwrap();
+        assert_eq!(events(&mut watcher), [DomainEvent::Paused(XenDomainId(1))]);
+
+        domain.unpause().unwrap();
+        assert_eq!(
+            events(&mut watcher),
+            [DomainEvent::Unpaused(XenDomainId(1))]
+        );
+    }
+
+    #[test]
+    fn shutdown() {
+        let (_hypervisor, control, mut watcher) = setup();
+        domain(&control, 1)
+            .shutdown(ShutdownReason::Poweroff)
+            .unwrap();
+
+        assert_eq!(
+            events(&mut watcher),
+            [DomainEvent::Shutdown {
+                domain_id: XenDomainId(1),
+                reason: ShutdownReason::Poweroff,
+            }]
+        );
+    }
+
+    #[test]
+    fn crashed() {
+        let (_hypervisor, control, mut watcher) = setup();
+        domain(&control, 1).shutdown(ShutdownReason::Crash).unwrap();
+
+        assert_eq!(events(&mut watcher), [DomainEvent::Crashed(XenDomainId(1))]);
+    }
+
+    #[test]
+    fn dying() {
+        // The mock destroys domains immediately, so the transition is
+        // compared directly.
+        let (_hypervisor, control, _watcher) = setup();
+        let old = domain(&control, 1).info().unwrap();
+        let new = XenDomainInfo {
+            flags: old.flags | XenDomainFlags::DYING,
+            ..old
+        };
+
+        let mut events = VecDeque::new();
+        compare(&mut events, &old, &new);
+        assert_eq!(events, [DomainEvent::Dying(XenDomainId(1))]);
+    }
+
+    #[test]
+    fn destroyed() {
+        let (hypervisor, _control, mut watcher) = setup();
+        hypervisor.destroy_domain(XenDomainId(1)).unwrap();
+
+        assert_eq!(
+            events(&mut watcher),
+            [DomainEvent::Destroyed(XenDomainId(1))]
+        );
+        assert_eq!(watcher.domains().count(), 0);
+    }
+
+    #[test]
+    fn domain_id_reused_between_scans() {
+        let (hypervisor, control, mut watcher) = setup();
+        hypervisor.destroy_domain(XenDomainId(1)).unwrap();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        // Domains created by the mock have a nil handle, like the previous one.
+        domain(&control, 1)
+            .set_handle(XenDomainUuid([2; 16]))
+            .unwrap();
+
+        let info = domain(&control, 1).info().unwrap();
+        assert_eq!(
+            events(&mut watcher),
+            [
+                DomainEvent::Destroyed(XenDomainId(1)),
+                DomainEvent::Created(info),
+            ]
+        );
+    }
+
+    #[test]
+    fn cancelled() {
+        let (_hypervisor, _control, mut watcher) = setup();
+        watcher.cancel_handle().cancel();
+
+        let err = watcher.next_event().unwrap_err();
+        assert_eq!(err.errno(), Some(libc::ECANCELED));
+    }
+}
@@ -1,30 +1,46 @@
-use xen_sys::{
-    xendevicemodel_close, xendevicemodel_handle, xendevicemodel_open, xentoollog_logger,
-};
+use xen_sys::{xendevicemodel_handle, xentoollog_logger};
 
-use crate::XenError;
+use super::{XenX86EventType, XenX86ExceptionVector};
+use crate::{
+    VcpuId, XenDomainId, XenError,
+    backend::XenDeviceModelBackend,
+    check_errno,
+    error::{XcError, XenErrorContext},
+    ffi::xendevicemodel::{
+        self, xendevicemodel_close, xendevicemodel_inject_event, xendevicemodel_open,
+    },
+    logger::logger_or_default,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct XenDeviceModelHandle(pub(crate) *mut xendevicemodel_handle);
 
+// SAFETY: libxendevicemodel reports errors through the thread-local errno and
+// keeps no other mutable state in the handle.
+unsafe impl Send for XenDeviceModelHandle {}
+unsafe impl Sync for XenDeviceModelHandle {}
+
 impl XenDeviceModelHandle {
     pub fn new() -> Result<Self, XenError> {
         Self::new_with_options(None, 0)
     }
 
+    /// Opens the handle with a custom `logger`.
+    ///
+    /// Messages of the library are forwarded to the global
+    /// [`TracingLogger`](crate::TracingLogger) if `logger` is `None`.
     pub fn new_with_options(
         logger: Option<&mut xentoollog_logger>,
         flags: u32,
     ) -> Result<Self, XenError> {
-        let handle = unsafe {
-            xendevicemodel_open(
-                logger.map_or_else(std::ptr::null_mut, |p| p as *mut _),
-                flags,
-            )
-        };
+        xendevicemodel::load()?;
+
+        let handle = unsafe { xendevicemodel_open(logger_or_default(logger), flags) };
 
         if handle.is_null() {
-            return Err(XenError::Other("Failed to open Xen event channel"));
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xendevicemodel_open")).into(),
+            );
         }
 
         Ok(Self(handle))
@@ -33,9 +49,37 @@ impl XenDeviceModelHandle {
 
 impl Drop for XenDeviceModelHandle {
     fn drop(&mut self) {
-        tracing::trace!("closing Xen foreign memory handle");
+        tracing::trace!("closing Xen device model handle");
         unsafe {
             xendevicemodel_close(self.0);
         }
     }
 }
+
+impl XenDeviceModelBackend for XenDeviceModelHandle {
+    fn inject_event(
+        &self,
+        domain_id: XenDomainId,
+        vcpu: VcpuId,
+        vector: XenX86ExceptionVector,
+        event_type: XenX86EventType,
+        error_code: u32,
+        instruction_length: u8,
+        extra: u64,
+    ) -> Result<(), XenError> {
+        let rc = unsafe {
+            xendevicemodel_inject_event(
+                self.0,
+                domain_id.0 as _,
+                vcpu.0 as _,
+                vector.0,
+                event_type as _,
+                error_code,
+                instruction_length,
+                extra,
+            )
+        };
+        check_errno!(rc, "xendevicemodel_inject_event", domain_id: domain_id, vcpu: vcpu);
+        Ok(())
+    }
+}
@@ -1,18 +1,8 @@
 mod handle;
-use std::rc::Rc;
-
-use xen_sys::xendevicemodel_inject_event;
+use std::sync::Arc;
 
 pub use self::handle::XenDeviceModelHandle;
-use crate::{VcpuId, XenDomainId, XenError};
-
-macro_rules! xc_check_error {
-    ($rc:ident) => {
-        if $rc < 0 {
-            return Err(XenError::Io(std::io::Error::last_os_error()));
-        }
-    };
-}
+use crate::{VcpuId, XenDomainId, XenError, backend::XenDeviceModelBackend};
 
 /*
  * x86 event types. This enumeration is valid for:
@@ -79,16 +69,16 @@ impl XenX86ExceptionVector {
 
 #[derive(Debug, Clone)]
 pub struct XenDeviceModel {
-    pub(crate) handle: Rc<XenDeviceModelHandle>,
+    pub(crate) backend: Arc<dyn XenDeviceModelBackend>,
     domain_id: XenDomainId,
 }
 
 impl XenDeviceModel {
-    pub(crate) fn new(domain_id: XenDomainId) -> Result<Self, XenError> {
-        Ok(Self {
-            handle: Rc::new(XenDeviceModelHandle::new()?),
-            domain_id,
-        })
+    pub(crate) fn new(
+        backend: Arc<dyn XenDeviceModelBackend>,
+        domain_id: XenDomainId,
+    ) -> Result<Self, XenError> {
+        Ok(Self { backend, domain_id })
     }
 
     /// This function injects an event into a vCPU to take effect the next time
@@ -107,19 +97,14 @@ impl XenDeviceModel {
         instruction_length: u8,
         extra: u64,
     ) -> Result<(), XenError> {
-        let rc = unsafe {
-            xendevicemodel_inject_event(
-                self.handle.0,
-                self.domain_id.0 as _,
-                vcpu.0 as _,
-                vector.0,
-                event_type as _,
-                error_code,
-                instruction_length,
-                extra,
-            )
-        };
-        xc_check_error!(rc);
-        Ok(())
+        self.backend.inject_event(
+            self.domain_id,
+            vcpu,
+            vector,
+            event_type,
+            error_code,
+            instruction_length,
+            extra,
+        )
     }
 }
@@ -1,23 +1,314 @@
+use std::borrow::Cow;
+
+use xen_sys::{
+    xc_error_code_XC_ERROR_NONE, xc_error_code_XC_INTERNAL_ERROR, xc_error_code_XC_INVALID_KERNEL,
+    xc_error_code_XC_INVALID_PARAM, xc_error_code_XC_OUT_OF_MEMORY, xc_interface,
+};
+
+use crate::{
+    VcpuId, XenDomainId,
+    arch::x86::{PageFault, PageFaultReason},
+    ffi::xencontrol::xc_get_last_error,
+    foreignmemory::FailedRange,
+};
+
 #[derive(thiserror::Error, Debug)]
 pub enum XenError {
     #[error(transparent)]
-    Xen(#[from] XcError),
+    Xen(#[from] Box<XcError>),
 
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    /// A Xen library could not be loaded at runtime.
+    #[error("failed to load {library}: {reason}")]
+    Library {
+        /// Name of the library, e.g. `xencontrol`.
+        library: &'static str,
+
+        /// Why the library or one of its functions could not be loaded.
+        reason: String,
+    },
+
+    /// Some pages of a guest physical memory access could not be mapped,
+    /// the other pages were accessed.
+    #[error("failed to access guest physical memory{}", failed_address(.0))]
+    PartialAccess(Vec<FailedRange>),
+
+    /// A guest linear address could not be translated.
+    #[error(transparent)]
+    PageFault(#[from] PageFault),
+
+    /// A [`MonitorLoop`](crate::ctrl::MonitorLoop) handler failed. The
+    /// event was still answered, resuming the vCPU.
+    #[error("failed to handle {reason} event of domain {domain_id} vCPU {vcpu_id}")]
+    Event {
+        /// Domain that sent the event.
+        domain_id: XenDomainId,
+
+        /// vCPU that sent the event.
+        vcpu_id: VcpuId,
+
+        /// [`VmEventReason::name`](crate::ctrl::VmEventReason::name) of the
+        /// event.
+        reason: &'static str,
+
+        /// The error returned by the handler.
+        #[source]
+        source: Box<XenError>,
+    },
+
     #[error("{0}")]
     Other(&'static str),
 }
 
+/// Formats the start of the first failed range of a partial access.
+fn failed_address(failed: &[FailedRange]) -> String {
+    match failed.first() {
+        Some(first) => format!(" at {:#x}", first.range.start),
+        None => String::new(),
+    }
+}
+
+impl From<XcError> for XenError {
+    fn from(value: XcError) -> Self {
+        Self::Xen(Box::new(value))
+    }
+}
+
+impl XenError {
+    /// Returns the classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Xen(err) => err.kind(),
+            Self::Io(err) => match err.raw_os_error() {
+                Some(errno) => ErrorKind::from_errno(errno),
+                None => ErrorKind::from(err.kind()),
+            },
+            Self::Library { .. } => ErrorKind::NotFound,
+            Self::PartialAccess(failed) => match failed.first() {
+                Some(first) => ErrorKind::from_errno(first.errno),
+                None => ErrorKind::Other,
+            },
+            Self::PageFault(fault) => match fault.reason {
+                PageFaultReason::NotPresent => ErrorKind::NotFound,
+                PageFaultReason::ReservedBit => ErrorKind::Other,
+                _ => ErrorKind::PermissionDenied,
+            },
+            Self::Event { source, .. } => source.kind(),
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Returns the `errno` value of the failed call, if any.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            Self::Xen(err) => err.errno(),
+            Self::Io(err) => err.raw_os_error(),
+            Self::PartialAccess(failed) => failed.first().map(|first| first.errno),
+            Self::Event { source, .. } => source.errno(),
+            Self::Library { .. } | Self::PageFault(_) | Self::Other(_) => None,
+        }
+    }
+
+    /// Returns the operation and the arguments the error was raised for,
+    /// if known.
+    pub fn context(&self) -> Option<&XenErrorContext> {
+        match self {
+            Self::Xen(err) => Some(err.context()),
+            Self::Event { source, .. } => source.context(),
+            _ => None,
+        }
+    }
+}
+
+/// A list specifying general categories of Xen errors.
+///
+/// The classification is derived from `errno`, so it works the same way for
+/// errors reported by all Xen libraries.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The domain, page or entry does not exist (`ENOENT`, `ESRCH`,
+    /// `ENODATA`, `ENXIO`).
+    NotFound,
+
+    /// The resource is busy and the operation may succeed when retried
+    /// (`EBUSY`, `EAGAIN`, `EINTR`).
+    Busy,
+
+    /// The caller lacks the privileges for the operation (`EPERM`, `EACCES`).
+    PermissionDenied,
+
+    /// The hypervisor or the domain does not support the operation
+    /// (`EOPNOTSUPP`, `ENOSYS`, `ENODEV`).
+    NotSupported,
+
+    /// An argument was invalid (`EINVAL`, `E2BIG`, `ERANGE`).
+    InvalidInput,
+
+    /// Memory could not be allocated (`ENOMEM`, `ENOSPC`).
+    OutOfMemory,
+
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies an `errno` value.
+    pub fn from_errno(errno: i32) -> Self {
+        match errno {
+            libc::ENOENT | libc::ESRCH | libc::ENODATA | libc::ENXIO => Self::NotFound,
+            libc::EBUSY | libc::EAGAIN | libc::EINTR => Self::Busy,
+            libc::EPERM | libc::EACCES => Self::PermissionDenied,
+            libc::EOPNOTSUPP | libc::ENOSYS | libc::ENODEV => Self::NotSupported,
+            libc::EINVAL | libc::E2BIG | libc::ERANGE => Self::InvalidInput,
+            libc::ENOMEM | libc::ENOSPC => Self::OutOfMemory,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<std::io::ErrorKind> for ErrorKind {
+    fn from(value: std::io::ErrorKind) -> Self {
+        match value {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::ResourceBusy
+            | std::io::ErrorKind::Interrupted => Self::Busy,
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::Unsupported => Self::NotSupported,
+            std::io::ErrorKind::InvalidInput => Self::InvalidInput,
+            std::io::ErrorKind::OutOfMemory => Self::OutOfMemory,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Error code reported by libxenctrl (`xc_error_code`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XcErrorCode {
+    /// No error details.
+    None,
+
+    /// Internal error.
+    InternalError,
+
+    /// Invalid kernel.
+    InvalidKernel,
+
+    /// Invalid configuration.
+    InvalidParam,
+
+    /// Out of memory.
+    OutOfMemory,
+
+    /// Unknown error code.
+    Unknown(u32),
+}
+
+impl XcErrorCode {
+    /// Returns the description libxenctrl uses for the code.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::None => "No error details",
+            Self::InternalError => "Internal error",
+            Self::InvalidKernel => "Invalid kernel",
+            Self::InvalidParam => "Invalid configuration",
+            Self::OutOfMemory => "Out of memory",
+            Self::Unknown(_) => "Unknown error code",
+        }
+    }
+}
+
+impl From<u32> for XcErrorCode {
+    #[expect(non_upper_case_globals)]
+    fn from(value: u32) -> Self {
+        match value {
+            xc_error_code_XC_ERROR_NONE => Self::None,
+            xc_error_code_XC_INTERNAL_ERROR => Self::InternalError,
+            xc_error_code_XC_INVALID_KERNEL => Self::InvalidKernel,
+            xc_error_code_XC_INVALID_PARAM => Self::InvalidParam,
+            xc_error_code_XC_OUT_OF_MEMORY => Self::OutOfMemory,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<XcErrorCode> for u32 {
+    fn from(value: XcErrorCode) -> Self {
+        match value {
+            XcErrorCode::None => xc_error_code_XC_ERROR_NONE,
+            XcErrorCode::InternalError => xc_error_code_XC_INTERNAL_ERROR,
+            XcErrorCode::InvalidKernel => xc_error_code_XC_INVALID_KERNEL,
+            XcErrorCode::InvalidParam => xc_error_code_XC_INVALID_PARAM,
+            XcErrorCode::OutOfMemory => xc_error_code_XC_OUT_OF_MEMORY,
+            XcErrorCode::Unknown(value) => value,
+        }
+    }
+}
+
+/// The failed operation and the arguments it was called with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XenErrorContext {
+    /// Name of the library function that failed, e.g. `xc_domain_pause`.
+    pub operation: &'static str,
+
+    /// Domain the operation was performed on.
+    pub domain_id: Option<XenDomainId>,
+
+    /// vCPU the operation was performed on.
+    pub vcpu: Option<VcpuId>,
+
+    /// Guest frame number the operation was performed on.
+    pub gfn: Option<u64>,
+}
+
+impl XenErrorContext {
+    /// Creates a context naming only the failed operation.
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for XenErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut separator = " (";
+
+        if let Some(domain_id) = self.domain_id {
+            write!(f, "{separator}domain {domain_id}")?;
+            separator = ", ";
+        }
+
+        if let Some(vcpu) = self.vcpu {
+            write!(f, "{separator}vcpu {vcpu}")?;
+            separator = ", ";
+        }
+
+        if let Some(gfn) = self.gfn {
+            write!(f, "{separator}gfn {gfn:#x}")?;
+            separator = ", ";
+        }
+
+        if separator == ", " {
+            write!(f, ")")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error reported by a Xen library call.
 #[derive(Debug)]
 pub struct XcError {
-    #[expect(unused)]
     pub(crate) rc: i32,
-    #[expect(unused)]
-    pub(crate) code: u32,
-    pub(crate) desc: &'static str,
-    #[expect(unused)]
+    pub(crate) code: XcErrorCode,
+    pub(crate) errno: Option<i32>,
+    pub(crate) desc: Cow<'static, str>,
+    pub(crate) context: XenErrorContext,
     pub(crate) backtrace: std::backtrace::Backtrace,
 }
 
@@ -25,7 +316,24 @@ impl std::error::Error for XcError {}
 
 impl std::fmt::Display for XcError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.desc)
+        if !self.context.operation.is_empty() {
+            write!(f, "{} failed: ", self.context.operation)?;
+        }
+
+        match self.errno {
+            Some(errno) if self.desc.is_empty() => {
+                write!(f, "{}", std::io::Error::from_raw_os_error(errno))?
+            }
+            Some(errno) => write!(
+                f,
+                "{}: {}",
+                self.desc,
+                std::io::Error::from_raw_os_error(errno)
+            )?,
+            None => write!(f, "{}", self.desc)?,
+        }
+
+        write!(f, "{}", self.context)
     }
 }
 
@@ -33,9 +341,144 @@ impl XcError {
     pub fn new(rc: i32, code: u32, desc: &'static str) -> Self {
         Self {
             rc,
-            code,
-            desc,
+            code: code.into(),
+            errno: None,
+            desc: Cow::Borrowed(desc),
+            context: XenErrorContext::default(),
             backtrace: std::backtrace::Backtrace::capture(),
         }
     }
+
+    /// Creates an error from `errno` of the calling thread.
+    ///
+    /// Must be called right after the failed call, before anything else
+    /// can overwrite `errno`.
+    pub(crate) fn last_os_error(rc: i32, context: XenErrorContext) -> Self {
+        Self {
+            rc,
+            code: XcErrorCode::None,
+            errno: std::io::Error::last_os_error()
+                .raw_os_error()
+                .filter(|&errno| errno != 0),
+            desc: Cow::Borrowed(""),
+            context,
+            backtrace: std::backtrace::Backtrace::capture(),
+        }
+    }
+
+    /// Creates an error from `errno` and the last error recorded in the
+    /// libxenctrl handle.
+    ///
+    /// # Safety
+    ///
+    /// `xch` must be a valid handle that is not used concurrently.
+    pub(crate) unsafe fn last_error(
+        xch: *mut xc_interface,
+        rc: i32,
+        context: XenErrorContext,
+    ) -> Self {
+        let mut result = Self::last_os_error(rc, context);
+
+        let err = unsafe { &*xc_get_last_error(xch) };
+        let message = unsafe {
+            std::slice::from_raw_parts(err.message.as_ptr() as *const u8, err.message.len())
+        };
+        let message = std::ffi::CStr::from_bytes_until_nul(message)
+            .map(|message| message.to_string_lossy().trim_end().to_owned())
+            .unwrap_or_default();
+
+        result.code = XcErrorCode::from(err.code);
+        result.desc = match (result.code, message.is_empty()) {
+            (_, false) => Cow::Owned(message),
+            (XcErrorCode::None, true) => Cow::Borrowed(""),
+            (code, true) => Cow::Borrowed(code.description()),
+        };
+
+        result
+    }
+
+    /// Returns the return code of the failed call.
+    pub fn rc(&self) -> i32 {
+        self.rc
+    }
+
+    /// Returns the error code recorded by libxenctrl.
+    pub fn code(&self) -> XcErrorCode {
+        self.code
+    }
+
+    /// Returns the `errno` value of the failed call, if any.
+    pub fn errno(&self) -> Option<i32> {
+        self.errno
+    }
+
+    /// Returns the description of the error, without the `errno` message.
+    pub fn description(&self) -> &str {
+        &self.desc
+    }
+
+    /// Returns the operation and the arguments the error was raised for.
+    pub fn context(&self) -> &XenErrorContext {
+        &self.context
+    }
+
+    /// Returns the name of the library function that failed.
+    pub fn operation(&self) -> &'static str {
+        self.context.operation
+    }
+
+    /// Returns the domain the operation was performed on.
+    pub fn domain_id(&self) -> Option<XenDomainId> {
+        self.context.domain_id
+    }
+
+    /// Returns the vCPU the operation was performed on.
+    pub fn vcpu(&self) -> Option<VcpuId> {
+        self.context.vcpu
+    }
+
+    /// Returns the guest frame number the operation was performed on.
+    pub fn gfn(&self) -> Option<u64> {
+        self.context.gfn
+    }
+
+    /// Returns the classification of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match (self.errno, self.code) {
+            (Some(errno), _) => ErrorKind::from_errno(errno),
+            (None, XcErrorCode::OutOfMemory) => ErrorKind::OutOfMemory,
+            (None, XcErrorCode::InvalidParam) => ErrorKind::InvalidInput,
+            (None, _) => ErrorKind::Other,
+        }
+    }
+
+    /// Returns the backtrace captured when the error was created.
+    pub fn backtrace(&self) -> &std::backtrace::Backtrace {
+        &self.backtrace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_access() {
+        let err = XenError::PartialAccess(vec![FailedRange {
+            range: 0x1000..0x3000,
+            errno: libc::ENOENT,
+        }]);
+
+        assert_eq!(
+            err.to_string(),
+            "failed to access guest physical memory at 0x1000"
+        );
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(err.errno(), Some(libc::ENOENT));
+
+        let err = XenError::PartialAccess(Vec::new());
+        assert_eq!(err.to_string(), "failed to access guest physical memory");
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.errno(), None);
+    }
 }
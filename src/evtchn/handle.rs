@@ -0,0 +1,134 @@
+use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use xen_sys::{xenevtchn_handle, xentoollog_logger};
+
+use crate::{
+    XenDomainId, XenError,
+    backend::XenEventChannelBackend,
+    check_errno,
+    error::{XcError, XenErrorContext},
+    ffi::xenevtchn::{
+        self, xenevtchn_bind_interdomain, xenevtchn_bind_unbound_port, xenevtchn_bind_virq,
+        xenevtchn_close, xenevtchn_fd, xenevtchn_fdopen, xenevtchn_notify, xenevtchn_open,
+        xenevtchn_pending, xenevtchn_restrict, xenevtchn_unbind, xenevtchn_unmask,
+    },
+    logger::logger_or_default,
+};
+
+#[derive(Debug)]
+pub struct XenEventChannelHandle(pub(crate) *mut xenevtchn_handle);
+
+// SAFETY: libxenevtchn operations are plain syscalls on the underlying fd and
+// report errors through the thread-local errno.
+unsafe impl Send for XenEventChannelHandle {}
+unsafe impl Sync for XenEventChannelHandle {}
+
+impl XenEventChannelHandle {
+    pub fn new() -> Result<Self, XenError> {
+        Self::new_with_options(None, 0)
+    }
+
+    /// Opens the handle with a custom `logger`.
+    ///
+    /// Messages of the library are forwarded to the global
+    /// [`TracingLogger`](crate::TracingLogger) if `logger` is `None`.
+    pub fn new_with_options(
+        logger: Option<&mut xentoollog_logger>,
+        flags: u32,
+    ) -> Result<Self, XenError> {
+        xenevtchn::load()?;
+
+        let handle = unsafe { xenevtchn_open(logger_or_default(logger), flags) };
+
+        if handle.is_null() {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xenevtchn_open")).into());
+        }
+
+        Ok(Self(handle))
+    }
+
+    /// Opens the handle from an open file descriptor of `/dev/xen/evtchn`.
+    ///
+    /// The handle takes ownership of `fd` and closes it when it is dropped.
+    pub fn fdopen(
+        logger: Option<&mut xentoollog_logger>,
+        fd: OwnedFd,
+        flags: u32,
+    ) -> Result<Self, XenError> {
+        xenevtchn::load()?;
+
+        let handle = unsafe { xenevtchn_fdopen(logger_or_default(logger), fd.as_raw_fd(), flags) };
+
+        if handle.is_null() {
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xenevtchn_fdopen")).into(),
+            );
+        }
+
+        let _ = fd.into_raw_fd();
+        Ok(Self(handle))
+    }
+}
+
+impl Drop for XenEventChannelHandle {
+    fn drop(&mut self) {
+        tracing::trace!("closing Xen event channel");
+        unsafe {
+            xenevtchn_close(self.0);
+        }
+    }
+}
+
+impl XenEventChannelBackend for XenEventChannelHandle {
+    fn fd(&self) -> RawFd {
+        unsafe { xenevtchn_fd(self.0) }
+    }
+
+    fn bind_interdomain(&self, domain_id: XenDomainId, remote_port: u32) -> Result<u32, XenError> {
+        let rc = unsafe { xenevtchn_bind_interdomain(self.0, domain_id.0, remote_port) };
+        check_errno!(rc, "xenevtchn_bind_interdomain", domain_id: domain_id);
+        Ok(rc as u32)
+    }
+
+    fn bind_unbound_port(&self, domain_id: XenDomainId) -> Result<u32, XenError> {
+        let rc = unsafe { xenevtchn_bind_unbound_port(self.0, domain_id.0) };
+        check_errno!(rc, "xenevtchn_bind_unbound_port", domain_id: domain_id);
+        Ok(rc as u32)
+    }
+
+    fn bind_virq(&self, virq: u32) -> Result<u32, XenError> {
+        let rc = unsafe { xenevtchn_bind_virq(self.0, virq) };
+        check_errno!(rc, "xenevtchn_bind_virq");
+        Ok(rc as u32)
+    }
+
+    fn restrict(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        let rc = unsafe { xenevtchn_restrict(self.0, domain_id.0 as u16) };
+        check_errno!(rc, "xenevtchn_restrict", domain_id: domain_id);
+        Ok(())
+    }
+
+    fn unbind(&self, port: u32) -> Result<(), XenError> {
+        let rc = unsafe { xenevtchn_unbind(self.0, port) };
+        check_errno!(rc, "xenevtchn_unbind");
+        Ok(())
+    }
+
+    fn notify(&self, port: u32) -> Result<(), XenError> {
+        let rc = unsafe { xenevtchn_notify(self.0, port) };
+        check_errno!(rc, "xenevtchn_notify");
+        Ok(())
+    }
+
+    fn pending(&self) -> Result<u32, XenError> {
+        let rc = unsafe { xenevtchn_pending(self.0) };
+        check_errno!(rc, "xenevtchn_pending");
+        Ok(rc as u32)
+    }
+
+    fn unmask(&self, port: u32) -> Result<(), XenError> {
+        let rc = unsafe { xenevtchn_unmask(self.0, port) };
+        check_errno!(rc, "xenevtchn_unmask");
+        Ok(())
+    }
+}
@@ -1,50 +1,274 @@
+mod handle;
+pub use self::handle::XenEventChannelHandle;
+
 mod port;
+mod status;
+mod virq;
 use std::{
-    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
-    rc::Rc,
+    collections::BTreeSet,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use xen_sys::{xenevtchn_close, xenevtchn_fd, xenevtchn_handle, xenevtchn_open, xentoollog_logger};
+use xen_sys::xentoollog_logger;
 
-pub use self::port::XenEventChannelPort;
-use crate::XenError;
+pub use self::{
+    port::{XenEventChannelBinding, XenEventChannelCancelHandle, XenEventChannelPort},
+    status::{XenEventChannelState, XenEventChannelStatus},
+    virq::Virq,
+};
+use crate::{XenDomainId, XenError, backend::XenEventChannelBackend};
 
+/// Handle to `/dev/xen/evtchn`.
+///
+/// A single channel can bind any number of ports, e.g. the monitor rings
+/// of many domains through [`XenMonitor::bind`](crate::XenMonitor::bind).
+/// The ports share the file descriptor of the channel, and [`poll`](Self::poll)
+/// waits for all of them at once. Cloning is cheap, all clones share the
+/// same handle.
 #[derive(Debug, Clone)]
-pub struct XenEventChannel(pub(crate) Rc<*mut xenevtchn_handle>);
+pub struct XenEventChannel {
+    pub(crate) backend: Arc<dyn XenEventChannelBackend>,
+
+    /// Number of ports bound on the channel.
+    ports: Arc<AtomicUsize>,
+
+    /// Wakes up [`poll`](Self::poll), created on first use.
+    cancel: Arc<OnceLock<XenEventChannelCancelHandle>>,
+}
 
 impl XenEventChannel {
     pub fn new() -> Result<Self, XenError> {
         Self::new_with_options(None, 0)
     }
 
+    /// Opens the handle with a custom `logger`.
+    ///
+    /// Messages of the library are forwarded to the global
+    /// [`TracingLogger`](crate::TracingLogger) if `logger` is `None`.
     pub fn new_with_options(
         logger: Option<&mut xentoollog_logger>,
         flags: u32,
     ) -> Result<Self, XenError> {
-        let handle = unsafe {
-            xenevtchn_open(
-                logger.map_or_else(std::ptr::null_mut, |p| p as *mut _),
-                flags,
-            )
-        };
+        Ok(Self::with_backend(Arc::new(
+            XenEventChannelHandle::new_with_options(logger, flags)?,
+        )))
+    }
+
+    /// Creates an event channel from an open file descriptor of
+    /// `/dev/xen/evtchn`, e.g. one inherited from another process.
+    ///
+    /// The channel takes ownership of `fd`.
+    pub fn fdopen(fd: OwnedFd) -> Result<Self, XenError> {
+        Self::fdopen_with_options(None, fd, 0)
+    }
+
+    /// Creates an event channel from an open file descriptor with a custom
+    /// `logger`.
+    pub fn fdopen_with_options(
+        logger: Option<&mut xentoollog_logger>,
+        fd: OwnedFd,
+        flags: u32,
+    ) -> Result<Self, XenError> {
+        Ok(Self::with_backend(Arc::new(XenEventChannelHandle::fdopen(
+            logger, fd, flags,
+        )?)))
+    }
+
+    /// Creates an event channel that routes all operations through `backend`.
+    pub fn with_backend(backend: Arc<dyn XenEventChannelBackend>) -> Self {
+        Self {
+            backend,
+            ports: Arc::default(),
+            cancel: Arc::default(),
+        }
+    }
+
+    /// Binds a local port to `remote_port` of `domain_id`.
+    pub fn bind_interdomain(
+        &self,
+        domain_id: XenDomainId,
+        remote_port: u32,
+    ) -> Result<XenEventChannelPort, XenError> {
+        let local_port = self.backend.bind_interdomain(domain_id, remote_port)?;
+        XenEventChannelPort::new(
+            self.clone(),
+            XenEventChannelBinding::Interdomain {
+                domain_id,
+                remote_port,
+            },
+            local_port,
+        )
+    }
+
+    /// Allocates a local port that `domain_id` can bind to.
+    pub fn bind_unbound_port(
+        &self,
+        domain_id: XenDomainId,
+    ) -> Result<XenEventChannelPort, XenError> {
+        let local_port = self.backend.bind_unbound_port(domain_id)?;
+        XenEventChannelPort::new(
+            self.clone(),
+            XenEventChannelBinding::Unbound { domain_id },
+            local_port,
+        )
+    }
+
+    /// Binds a local port to a virtual interrupt of the hypervisor, e.g.
+    /// [`Virq::DomExc`] to learn about domain state changes.
+    pub fn bind_virq(&self, virq: Virq) -> Result<XenEventChannelPort, XenError> {
+        let local_port = self.backend.bind_virq(virq.into())?;
+        XenEventChannelPort::new(self.clone(), XenEventChannelBinding::Virq(virq), local_port)
+    }
+
+    /// Restricts the channel to ports of `domain_id`.
+    ///
+    /// Afterwards, only interdomain bindings to `domain_id` are allowed.
+    /// The restriction cannot be lifted.
+    pub fn restrict(&self, domain_id: XenDomainId) -> Result<(), XenError> {
+        self.backend.restrict(domain_id)
+    }
+
+    /// Returns a handle that wakes up [`poll`](Self::poll) from another
+    /// thread.
+    ///
+    /// The handle only cancels polls of the whole channel, waits on its
+    /// ports are cancelled through [`XenEventChannelPort::cancel_handle`].
+    pub fn cancel_handle(&self) -> Result<XenEventChannelCancelHandle, XenError> {
+        if let Some(cancel) = self.cancel.get() {
+            return Ok(cancel.clone());
+        }
+
+        let cancel = XenEventChannelCancelHandle::new()?;
+        Ok(self.cancel.get_or_init(|| cancel).clone())
+    }
+
+    /// Waits until ports of the channel are signalled, or `timeout`
+    /// elapses, and returns all pending local ports.
+    ///
+    /// The pending ports stay masked, and are not reported again, until
+    /// they are acknowledged with [`acknowledge`](Self::acknowledge) or
+    /// [`XenEventChannelPort::acknowledge`]. An empty set is returned on
+    /// timeout. Waits without a timeout if `timeout` is `None`.
+    ///
+    /// Fails with `ECANCELED` if the channel is cancelled through its
+    /// [`cancel_handle`](Self::cancel_handle). The file descriptor of the
+    /// channel is put into non-blocking mode.
+    pub fn poll(&self, timeout: Option<Duration>) -> Result<BTreeSet<u32>, XenError> {
+        set_nonblocking(self.as_raw_fd())?;
+
+        let cancel = self.cancel_handle()?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut pending = BTreeSet::new();
+
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: cancel.fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+
+            let ready = poll_fds(&mut fds, deadline)?;
 
-        if handle.is_null() {
-            return Err(XenError::Other("Failed to open Xen event channel"));
+            if fds[1].revents != 0 {
+                return Err(std::io::Error::from_raw_os_error(libc::ECANCELED).into());
+            }
+
+            if ready == 0 {
+                return Ok(pending);
+            }
+
+            loop {
+                match self.backend.pending() {
+                    Ok(port) => pending.insert(port),
+                    Err(err) if err.errno() == Some(libc::EAGAIN) => break,
+                    Err(err) => return Err(err),
+                };
+            }
+
+            if !pending.is_empty() {
+                return Ok(pending);
+            }
         }
+    }
+
+    /// Unmasks a port reported by [`poll`](Self::poll), so that it can be
+    /// signalled again.
+    pub fn acknowledge(&self, port: u32) -> Result<(), XenError> {
+        self.backend.unmask(port)
+    }
 
-        Ok(Self(Rc::new(handle)))
+    /// Returns the number of ports bound on the channel.
+    pub(crate) fn ports(&self) -> usize {
+        self.ports.load(Ordering::Acquire)
     }
 }
 
-impl Drop for XenEventChannel {
-    fn drop(&mut self) {
-        tracing::trace!("closing Xen event channel");
-        unsafe {
-            xenevtchn_close(*self.0);
+/// Polls `fds` until one of them is ready or `deadline` passes, retrying
+/// on `EINTR`.
+///
+/// Returns the number of ready descriptors, zero on timeout.
+pub(crate) fn poll_fds(
+    fds: &mut [libc::pollfd],
+    deadline: Option<Instant>,
+) -> Result<usize, XenError> {
+    loop {
+        let timeout_ms = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                remaining
+                    .as_nanos()
+                    .div_ceil(1_000_000)
+                    .min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+
+        let rc = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout_ms) };
+
+        if rc < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(err.into());
         }
+
+        return Ok(rc as usize);
     }
 }
 
+/// Makes reading a pending port fail with `EAGAIN` instead of blocking.
+pub(crate) fn set_nonblocking(fd: RawFd) -> Result<(), XenError> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    if flags & libc::O_NONBLOCK != 0 {
+        return Ok(());
+    }
+
+    let rc = unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+    if rc < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
 impl AsFd for XenEventChannel {
     fn as_fd(&self) -> BorrowedFd<'_> {
         unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
@@ -53,6 +277,6 @@ impl AsFd for XenEventChannel {
 
 impl AsRawFd for XenEventChannel {
     fn as_raw_fd(&self) -> RawFd {
-        unsafe { xenevtchn_fd(*self.0) }
+        self.backend.fd()
     }
 }
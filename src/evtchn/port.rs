@@ -1,91 +1,292 @@
-use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
-
-use xen_sys::{
-    xenevtchn_bind_interdomain, xenevtchn_notify, xenevtchn_pending, xenevtchn_unbind,
-    xenevtchn_unmask,
+use std::{
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
 };
 
-use super::XenEventChannel;
+use super::{Virq, XenEventChannel, poll_fds, set_nonblocking};
 use crate::{XenDomainId, XenError};
 
-macro_rules! xc_check_error {
-    ($self:ident, $rc:ident) => {
-        if $rc < 0 {
-            return Err(XenError::Io(std::io::Error::last_os_error()));
+/// Wakes up threads waiting on a [`XenEventChannelPort`] or polling a
+/// [`XenEventChannel`].
+///
+/// Cancellation is sticky: once [`cancel`](Self::cancel) was called, every
+/// wait on the port or poll of the channel fails with `ECANCELED` until
+/// [`reset`](Self::reset) is called. Cloning is cheap, all clones cancel
+/// the same waits.
+#[derive(Debug, Clone)]
+pub struct XenEventChannelCancelHandle {
+    eventfd: Arc<OwnedFd>,
+}
+
+impl XenEventChannelCancelHandle {
+    pub(crate) fn new() -> Result<Self, XenError> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
         }
-    };
+
+        Ok(Self {
+            eventfd: Arc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+        })
+    }
+
+    pub(crate) fn fd(&self) -> RawFd {
+        self.eventfd.as_raw_fd()
+    }
+
+    /// Wakes up all current and future waiters.
+    pub fn cancel(&self) {
+        let value = 1u64;
+        unsafe {
+            libc::write(
+                self.eventfd.as_raw_fd(),
+                &value as *const u64 as *const _,
+                size_of::<u64>(),
+            );
+        }
+    }
+
+    /// Returns `true` if the port was cancelled and not reset since.
+    pub fn is_cancelled(&self) -> bool {
+        let mut fd = libc::pollfd {
+            fd: self.eventfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        unsafe { libc::poll(&mut fd, 1, 0) > 0 }
+    }
+
+    /// Lets waits block again.
+    pub fn reset(&self) {
+        let mut value = 0u64;
+        unsafe {
+            libc::read(
+                self.eventfd.as_raw_fd(),
+                &mut value as *mut u64 as *mut _,
+                size_of::<u64>(),
+            );
+        }
+    }
 }
 
+/// What a [`XenEventChannelPort`] is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XenEventChannelBinding {
+    /// Connected to `remote_port` of `domain_id`.
+    Interdomain {
+        domain_id: XenDomainId,
+        remote_port: u32,
+    },
+
+    /// Allocated for `domain_id` to connect to.
+    Unbound { domain_id: XenDomainId },
+
+    /// Bound to a virtual interrupt of the hypervisor.
+    Virq(Virq),
+}
+
+/// A local port bound on a [`XenEventChannel`].
+///
+/// The port keeps its channel open and is unbound when the last of its
+/// clones is dropped. Cloning is cheap, all clones share the binding and
+/// the cancellation handle.
 #[derive(Debug, Clone)]
 pub struct XenEventChannelPort {
+    inner: Arc<PortInner>,
+}
+
+#[derive(Debug)]
+struct PortInner {
     evtchn: XenEventChannel,
-    remote_port: u32,
+    binding: XenEventChannelBinding,
     local_port: u32,
+    cancel: XenEventChannelCancelHandle,
 }
 
 impl XenEventChannelPort {
-    pub(crate) fn bind_interdomain(
-        domain_id: XenDomainId,
-        remote_port: u32,
+    /// Takes ownership of the bound `local_port`, unbinding it on failure.
+    pub(crate) fn new(
+        evtchn: XenEventChannel,
+        binding: XenEventChannelBinding,
+        local_port: u32,
     ) -> Result<Self, XenError> {
-        let evtchn = XenEventChannel::new()?;
-        let rc = unsafe { xenevtchn_bind_interdomain(*evtchn.0, domain_id.0, remote_port) };
-        xc_check_error!(self, rc);
+        let cancel = match XenEventChannelCancelHandle::new() {
+            Ok(cancel) => cancel,
+            Err(err) => {
+                let _ = evtchn.backend.unbind(local_port);
+                return Err(err);
+            }
+        };
+
+        evtchn.ports.fetch_add(1, Ordering::AcqRel);
 
-        let local_port = rc as u32;
         Ok(Self {
-            evtchn,
-            remote_port,
-            local_port,
+            inner: Arc::new(PortInner {
+                evtchn,
+                binding,
+                local_port,
+                cancel,
+            }),
         })
     }
 
     pub fn local_port(&self) -> u32 {
-        self.local_port
+        self.inner.local_port
+    }
+
+    /// Returns the port of the remote domain for interdomain bindings.
+    pub fn remote_port(&self) -> Option<u32> {
+        match self.inner.binding {
+            XenEventChannelBinding::Interdomain { remote_port, .. } => Some(remote_port),
+            _ => None,
+        }
+    }
+
+    pub fn binding(&self) -> XenEventChannelBinding {
+        self.inner.binding
     }
 
-    pub fn remote_port(&self) -> u32 {
-        self.remote_port
+    /// Returns a handle that wakes up waiters of the port from another
+    /// thread.
+    pub fn cancel_handle(&self) -> XenEventChannelCancelHandle {
+        self.inner.cancel.clone()
     }
 
+    /// Unmasks the port after it was reported by
+    /// [`XenEventChannel::poll`].
+    pub fn acknowledge(&self) -> Result<(), XenError> {
+        self.inner.evtchn.backend.unmask(self.inner.local_port)
+    }
+
+    /// Returns `true` if other ports are bound on the channel of the port.
+    ///
+    /// Ports on shared channels cannot be waited for on their own, use
+    /// [`XenEventChannel::poll`] instead.
+    pub fn is_shared(&self) -> bool {
+        self.inner.evtchn.ports() > 1
+    }
+
+    /// Blocks until the port is signalled.
+    ///
+    /// The wait methods read the signals of all ports of the channel, so
+    /// they fail with `EBUSY` if other ports are bound on the channel, see
+    /// [`is_shared`](Self::is_shared). The file descriptor of the channel
+    /// is put into non-blocking mode.
+    ///
+    /// Fails with `ECANCELED` if the port is cancelled through its
+    /// [`cancel_handle`](Self::cancel_handle).
     pub fn wait(&self) -> Result<(), XenError> {
-        let port = self.pending()?;
-        assert_eq!(port, self.local_port);
-        self.unmask()?;
-        Ok(())
+        self.poll(None).map(|_| ())
+    }
+
+    /// Blocks until the port is signalled or `timeout` elapses.
+    ///
+    /// Returns `false` on timeout. Fails with `ECANCELED` if the port is
+    /// cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool, XenError> {
+        self.poll(Some(timeout))
+    }
+
+    /// Consumes a pending signal of the port without blocking.
+    ///
+    /// Returns `false` if the port is not signalled. Fails with
+    /// `ECANCELED` if the port is cancelled.
+    pub fn try_wait(&self) -> Result<bool, XenError> {
+        self.poll(Some(Duration::ZERO))
     }
 
     pub fn notify(&self) -> Result<(), XenError> {
-        let rc = unsafe { xenevtchn_notify(*self.evtchn.0, self.local_port) };
-        xc_check_error!(self, rc);
-        Ok(())
+        self.inner.evtchn.backend.notify(self.inner.local_port)
     }
 
-    fn pending(&self) -> Result<u32, XenError> {
-        let rc = unsafe { xenevtchn_pending(*self.evtchn.0) };
-        xc_check_error!(self, rc);
+    /// Polls the event channel and the cancellation eventfd, then consumes
+    /// the pending signal.
+    ///
+    /// The descriptor is put into non-blocking mode, so that a waiter that
+    /// loses the signal to another one polls again instead of blocking in
+    /// the read, where cancellation could not wake it up.
+    fn poll(&self, timeout: Option<Duration>) -> Result<bool, XenError> {
+        self.ensure_exclusive()?;
+        set_nonblocking(self.as_raw_fd())?;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: self.inner.cancel.eventfd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+
+            let ready = poll_fds(&mut fds, deadline)?;
+
+            if fds[1].revents != 0 {
+                return Err(std::io::Error::from_raw_os_error(libc::ECANCELED).into());
+            }
+
+            if ready == 0 {
+                return Ok(false);
+            }
+
+            // A port bound while waiting could be signalled as well.
+            self.ensure_exclusive()?;
+
+            let port = match self.pending() {
+                Ok(port) => port,
+                // Another waiter consumed the signal, or the readiness was
+                // spurious.
+                Err(err) if err.errno() == Some(libc::EAGAIN) => continue,
+                Err(err) => return Err(err),
+            };
+            self.inner.evtchn.backend.unmask(port)?;
+
+            if port != self.inner.local_port {
+                tracing::warn!(
+                    port,
+                    local_port = self.inner.local_port,
+                    "unexpected event channel port"
+                );
+
+                return Err(XenError::Other("Unexpected event channel port"));
+            }
 
-        let port = rc as u32;
-        Ok(port)
+            return Ok(true);
+        }
+    }
+
+    fn pending(&self) -> Result<u32, XenError> {
+        self.inner.evtchn.backend.pending()
     }
 
-    fn unmask(&self) -> Result<(), XenError> {
-        let rc = unsafe { xenevtchn_unmask(*self.evtchn.0, self.local_port) };
-        xc_check_error!(self, rc);
+    /// Fails with `EBUSY` if reading the pending port could consume the
+    /// signal of another port.
+    fn ensure_exclusive(&self) -> Result<(), XenError> {
+        if self.is_shared() {
+            return Err(std::io::Error::from_raw_os_error(libc::EBUSY).into());
+        }
+
         Ok(())
     }
 }
 
-impl Drop for XenEventChannelPort {
+impl Drop for PortInner {
     fn drop(&mut self) {
         tracing::trace!(
             local_port = self.local_port,
-            remote_port = self.remote_port,
+            binding = ?self.binding,
             "unbinding Xen event channel port"
         );
-        unsafe {
-            xenevtchn_unbind(*self.evtchn.0, self.local_port);
-        }
+        let _ = self.evtchn.backend.unbind(self.local_port);
+        self.evtchn.ports.fetch_sub(1, Ordering::AcqRel);
     }
 }
 
@@ -97,6 +298,101 @@ impl AsFd for XenEventChannelPort {
 
 impl AsRawFd for XenEventChannelPort {
     fn as_raw_fd(&self) -> RawFd {
-        self.evtchn.as_raw_fd()
+        self.inner.evtchn.as_raw_fd()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockHypervisor;
+
+    #[test]
+    fn wait_is_nonblocking() {
+        let hypervisor = MockHypervisor::new();
+        let port = hypervisor
+            .event_channel()
+            .unwrap()
+            .bind_virq(Virq::DomExc)
+            .unwrap();
+
+        assert!(!port.try_wait().unwrap());
+
+        // A waiter that loses the signal to another one must not block in
+        // the read.
+        let flags = unsafe { libc::fcntl(port.as_raw_fd(), libc::F_GETFL) };
+        assert_ne!(flags & libc::O_NONBLOCK, 0);
+
+        hypervisor.send_virq(Virq::DomExc);
+        assert!(port.wait_timeout(Duration::from_secs(5)).unwrap());
+        assert!(!port.try_wait().unwrap());
+
+        port.cancel_handle().cancel();
+        let err = port.wait().unwrap_err();
+        assert_eq!(err.errno(), Some(libc::ECANCELED));
+    }
+
+    #[test]
+    fn unbound_with_last_clone() {
+        let hypervisor = MockHypervisor::new();
+        let evtchn = hypervisor.event_channel().unwrap();
+        let port = evtchn.bind_virq(Virq::DomExc).unwrap();
+        let local_port = port.local_port();
+
+        drop(port.clone());
+        port.notify().unwrap();
+        hypervisor.send_virq(Virq::DomExc);
+        assert!(port.try_wait().unwrap());
+
+        drop(port);
+        let err = evtchn.backend.notify(local_port).unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EINVAL));
+    }
+
+    #[test]
+    fn wait_on_shared_channel() {
+        let hypervisor = MockHypervisor::new();
+        let evtchn = hypervisor.event_channel().unwrap();
+        let dom_exc = evtchn.bind_virq(Virq::DomExc).unwrap();
+        assert!(!dom_exc.is_shared());
+
+        let console = evtchn.bind_virq(Virq::Console).unwrap();
+        assert!(dom_exc.is_shared());
+
+        // Waiting on a port must not consume the signal of another one.
+        hypervisor.send_virq(Virq::Console);
+        let err = dom_exc.try_wait().unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EBUSY));
+
+        let pending = evtchn.poll(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(
+            pending.into_iter().collect::<Vec<_>>(),
+            [console.local_port()]
+        );
+
+        drop(console);
+        assert!(!dom_exc.is_shared());
+        assert!(!dom_exc.try_wait().unwrap());
+    }
+
+    #[test]
+    fn cancel_channel_poll() {
+        let hypervisor = MockHypervisor::new();
+        let evtchn = hypervisor.event_channel().unwrap();
+        let _port = evtchn.bind_virq(Virq::DomExc).unwrap();
+        let cancel = evtchn.cancel_handle().unwrap();
+
+        std::thread::scope(|scope| {
+            let poll = scope.spawn(|| evtchn.poll(None));
+
+            std::thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+
+            let err = poll.join().unwrap().unwrap_err();
+            assert_eq!(err.errno(), Some(libc::ECANCELED));
+        });
+
+        cancel.reset();
+        assert!(evtchn.poll(Some(Duration::ZERO)).unwrap().is_empty());
     }
 }
@@ -0,0 +1,71 @@
+use xen_sys::{
+    EVTCHNSTAT_closed, EVTCHNSTAT_interdomain, EVTCHNSTAT_ipi, EVTCHNSTAT_pirq, EVTCHNSTAT_unbound,
+    EVTCHNSTAT_virq, evtchn_status,
+};
+
+use super::Virq;
+use crate::{VcpuId, XenDomainId, XenError};
+
+/// What an event channel port of a domain is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XenEventChannelState {
+    /// The port is not in use.
+    Closed,
+
+    /// The port is allocated and waits for `remote_domain_id` to bind it.
+    Unbound { remote_domain_id: XenDomainId },
+
+    /// The port is connected to `remote_port` of `remote_domain_id`.
+    Interdomain {
+        remote_domain_id: XenDomainId,
+        remote_port: u32,
+    },
+
+    /// The port is bound to a physical IRQ.
+    Pirq(u32),
+
+    /// The port is bound to a virtual IRQ.
+    Virq(Virq),
+
+    /// The port is bound to an inter-processor interrupt.
+    Ipi,
+}
+
+/// Status of an event channel port (`xc_evtchn_status`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XenEventChannelStatus {
+    /// What the port is connected to.
+    pub state: XenEventChannelState,
+
+    /// vCPU notified when the port is signalled.
+    pub vcpu: VcpuId,
+}
+
+#[expect(non_upper_case_globals)]
+impl TryFrom<evtchn_status> for XenEventChannelStatus {
+    type Error = XenError;
+
+    fn try_from(value: evtchn_status) -> Result<Self, Self::Error> {
+        let state = unsafe {
+            match value.status {
+                EVTCHNSTAT_closed => XenEventChannelState::Closed,
+                EVTCHNSTAT_unbound => XenEventChannelState::Unbound {
+                    remote_domain_id: XenDomainId(value.u.unbound.dom as u32),
+                },
+                EVTCHNSTAT_interdomain => XenEventChannelState::Interdomain {
+                    remote_domain_id: XenDomainId(value.u.interdomain.dom as u32),
+                    remote_port: value.u.interdomain.port,
+                },
+                EVTCHNSTAT_pirq => XenEventChannelState::Pirq(value.u.pirq),
+                EVTCHNSTAT_virq => XenEventChannelState::Virq(Virq::try_from(value.u.virq)?),
+                EVTCHNSTAT_ipi => XenEventChannelState::Ipi,
+                _ => return Err(XenError::Other("unknown event channel status")),
+            }
+        };
+
+        Ok(Self {
+            state,
+            vcpu: VcpuId(value.vcpu as u16),
+        })
+    }
+}
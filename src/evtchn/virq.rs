@@ -0,0 +1,102 @@
+use xen_sys::{
+    NR_VIRQS, VIRQ_ARCH_0, VIRQ_ARGO, VIRQ_CON_RING, VIRQ_CONSOLE, VIRQ_DEBUG, VIRQ_DEBUGGER,
+    VIRQ_DOM_EXC, VIRQ_ENOMEM, VIRQ_MEM_EVENT, VIRQ_PCPU_STATE, VIRQ_TBUF, VIRQ_TIMER,
+    VIRQ_XENOPROF, VIRQ_XENPMU,
+};
+
+use crate::XenError;
+
+/// Virtual interrupt raised by the hypervisor (`VIRQ_*`).
+///
+/// Except for [`Virq::Timer`], [`Virq::Debug`] and [`Virq::Xenoprof`], the
+/// interrupts are global and can only be bound by the control domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Virq {
+    /// Timer of the vCPU expired.
+    Timer,
+
+    /// Request to dump debug information.
+    Debug,
+
+    /// Bytes were received on the emergency console.
+    Console,
+
+    /// A domain was created, paused, shut down, crashed or destroyed.
+    DomExc,
+
+    /// The trace buffer is half full.
+    Tbuf,
+
+    /// A domain being debugged changed its state.
+    Debugger,
+
+    /// An oprofile sample buffer needs to be flushed.
+    Xenoprof,
+
+    /// Bytes were written to the hypervisor console ring.
+    ConRing,
+
+    /// A physical CPU was brought online or offline.
+    PcpuState,
+
+    /// A `vm_event` ring has free slots again.
+    MemEvent,
+
+    /// An Argo message was delivered.
+    Argo,
+
+    /// The hypervisor ran low on memory.
+    Enomem,
+
+    /// A performance monitoring interrupt occurred.
+    Xenpmu,
+
+    /// Architecture-specific interrupt `VIRQ_ARCH_0 + n`, e.g.
+    /// `VIRQ_MCA` on x86.
+    Arch(u8),
+}
+
+impl TryFrom<u32> for Virq {
+    type Error = XenError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            VIRQ_TIMER => Self::Timer,
+            VIRQ_DEBUG => Self::Debug,
+            VIRQ_CONSOLE => Self::Console,
+            VIRQ_DOM_EXC => Self::DomExc,
+            VIRQ_TBUF => Self::Tbuf,
+            VIRQ_DEBUGGER => Self::Debugger,
+            VIRQ_XENOPROF => Self::Xenoprof,
+            VIRQ_CON_RING => Self::ConRing,
+            VIRQ_PCPU_STATE => Self::PcpuState,
+            VIRQ_MEM_EVENT => Self::MemEvent,
+            VIRQ_ARGO => Self::Argo,
+            VIRQ_ENOMEM => Self::Enomem,
+            VIRQ_XENPMU => Self::Xenpmu,
+            VIRQ_ARCH_0..NR_VIRQS => Self::Arch((value - VIRQ_ARCH_0) as u8),
+            _ => return Err(XenError::Other("unknown VIRQ")),
+        })
+    }
+}
+
+impl From<Virq> for u32 {
+    fn from(value: Virq) -> Self {
+        match value {
+            Virq::Timer => VIRQ_TIMER,
+            Virq::Debug => VIRQ_DEBUG,
+            Virq::Console => VIRQ_CONSOLE,
+            Virq::DomExc => VIRQ_DOM_EXC,
+            Virq::Tbuf => VIRQ_TBUF,
+            Virq::Debugger => VIRQ_DEBUGGER,
+            Virq::Xenoprof => VIRQ_XENOPROF,
+            Virq::ConRing => VIRQ_CON_RING,
+            Virq::PcpuState => VIRQ_PCPU_STATE,
+            Virq::MemEvent => VIRQ_MEM_EVENT,
+            Virq::Argo => VIRQ_ARGO,
+            Virq::Enomem => VIRQ_ENOMEM,
+            Virq::Xenpmu => VIRQ_XENPMU,
+            Virq::Arch(index) => VIRQ_ARCH_0 + index as u32,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Functions of the Xen libraries used by this crate.
+//!
+//! By default the functions are linked at build time. With the `dlopen`
+//! feature, each library is opened with `dlopen` the first time a handle
+//! needing it is created, and all its functions are resolved with `dlsym`.
+//! Missing libraries or symbols are reported as
+//! [`XenError::Library`](crate::XenError::Library) by the
+//! handle constructors.
+//!
+//! Every handle constructor must call the `load` function of its library
+//! before calling any other function of it.
+//!
+//! `libxenctrl` has no stable ABI, so only the release the bindings were
+//! generated for, [`xen_sys::XENCTRL_SONAME`], is loaded.
+
+#[cfg(feature = "dlopen")]
+use std::ffi::{CStr, CString, c_void};
+
+macro_rules! libraries {
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis mod $library:ident($($soname:expr),+ $(,)?) {
+            $(fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*
+        }
+    )*) => {$(
+        $(#[$meta])*
+        $vis mod $library {
+            #[cfg(not(feature = "dlopen"))]
+            pub(crate) use xen_sys::{$($name),*};
+
+            /// Makes sure the library is available.
+            #[cfg(not(feature = "dlopen"))]
+            pub(crate) fn load() -> Result<(), crate::XenError> {
+                Ok(())
+            }
+
+            #[cfg(feature = "dlopen")]
+            #[allow(unused_imports)]
+            use std::ffi::{c_char, c_int, c_uint, c_ulong, c_ulonglong, c_void};
+
+            #[cfg(feature = "dlopen")]
+            #[allow(unused_imports)]
+            use xen_sys::*;
+
+            #[cfg(feature = "dlopen")]
+            struct Functions {
+                $($name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+            }
+
+            #[cfg(feature = "dlopen")]
+            static FUNCTIONS: std::sync::OnceLock<Result<Functions, String>> =
+                std::sync::OnceLock::new();
+
+            /// Opens the library and resolves its functions.
+            #[cfg(feature = "dlopen")]
+            pub(crate) fn load() -> Result<(), crate::XenError> {
+                let functions = FUNCTIONS.get_or_init(|| unsafe {
+                    let handle = super::open(&[$($soname),+])?;
+
+                    Ok(Functions {$(
+                        $name: std::mem::transmute::<
+                            *mut c_void,
+                            unsafe extern "C" fn($($ty),*) $(-> $ret)?,
+                        >(
+                            super::symbol(handle, concat!(stringify!($name), "\0"))?
+                        ),
+                    )*})
+                });
+
+                match functions {
+                    Ok(_) => Ok(()),
+                    Err(reason) => Err(crate::XenError::Library {
+                        library: stringify!($library),
+                        reason: reason.clone(),
+                    }),
+                }
+            }
+
+            #[cfg(feature = "dlopen")]
+            fn functions() -> &'static Functions {
+                match FUNCTIONS.get() {
+                    Some(Ok(functions)) => functions,
+                    _ => panic!(concat!(stringify!($library), " used before it was loaded")),
+                }
+            }
+
+            $(
+                #[cfg(feature = "dlopen")]
+                #[allow(clippy::too_many_arguments)]
+                pub(crate) unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                    unsafe { (functions().$name)($($arg),*) }
+                }
+            )*
+        }
+    )*};
+}
+
+/// Opens the first library of `sonames` that can be found.
+#[cfg(feature = "dlopen")]
+unsafe fn open(sonames: &[&'static str]) -> Result<*mut c_void, String> {
+    let mut errors = Vec::new();
+
+    for soname in sonames {
+        let name = CString::new(*soname).map_err(|err| err.to_string())?;
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+
+        if !handle.is_null() {
+            return Ok(handle);
+        }
+
+        errors.push(unsafe { last_error() });
+    }
+
+    Err(format!(
+        "{} (the bindings require {})",
+        errors.join(", "),
+        sonames.join(" or ")
+    ))
+}
+
+/// Resolves the nul-terminated symbol `name`.
+#[cfg(feature = "dlopen")]
+unsafe fn symbol(handle: *mut c_void, name: &'static str) -> Result<*mut c_void, String> {
+    let symbol = unsafe { libc::dlsym(handle, name.as_ptr() as _) };
+
+    match symbol.is_null() {
+        true => Err(unsafe { last_error() }),
+        false => Ok(symbol),
+    }
+}
+
+#[cfg(feature = "dlopen")]
+unsafe fn last_error() -> String {
+    let error = unsafe { libc::dlerror() };
+
+    match error.is_null() {
+        true => String::from("unknown error"),
+        false => unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+libraries! {
+    /// `libxenctrl`.
+    pub(crate) mod xencontrol(xen_sys::XENCTRL_SONAME) {
+        fn xc_altp2m_change_gfn(handle: *mut xc_interface, domid: u32, view_id: u16, old_gfn: xen_pfn_t, new_gfn: xen_pfn_t) -> c_int;
+        fn xc_altp2m_create_view(handle: *mut xc_interface, domid: u32, default_access: xenmem_access_t, view_id: *mut u16) -> c_int;
+        fn xc_altp2m_destroy_view(handle: *mut xc_interface, domid: u32, view_id: u16) -> c_int;
+        fn xc_altp2m_get_mem_access(handle: *mut xc_interface, domid: u32, view_id: u16, gfn: xen_pfn_t, access: *mut xenmem_access_t) -> c_int;
+        fn xc_altp2m_get_domain_state(handle: *mut xc_interface, dom: u32, state: *mut bool) -> c_int;
+        fn xc_altp2m_set_domain_state(handle: *mut xc_interface, dom: u32, state: bool) -> c_int;
+        fn xc_altp2m_set_mem_access(handle: *mut xc_interface, domid: u32, view_id: u16, gfn: xen_pfn_t, access: xenmem_access_t) -> c_int;
+        fn xc_altp2m_set_mem_access_multi(handle: *mut xc_interface, domid: u32, view_id: u16, access: *mut u8, gfns: *mut u64, nr: u32) -> c_int;
+        fn xc_altp2m_switch_to_view(handle: *mut xc_interface, domid: u32, view_id: u16) -> c_int;
+        fn xc_domain_create(xch: *mut xc_interface, pdomid: *mut u32, config: *mut xen_domctl_createdomain) -> c_int;
+        fn xc_domain_debug_control(xch: *mut xc_interface, domid: u32, sop: u32, vcpu: u32) -> c_int;
+        fn xc_domain_decrease_reservation(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_decrease_reservation_exact(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_destroy(xch: *mut xc_interface, domid: u32) -> c_int;
+        fn xc_domain_getinfolist(xch: *mut xc_interface, first_domain: u32, max_domains: c_uint, info: *mut xc_domaininfo_t) -> c_int;
+        fn xc_domain_hvm_getcontext(xch: *mut xc_interface, domid: u32, ctxt_buf: *mut u8, size: u32) -> c_int;
+        fn xc_domain_hvm_getcontext_partial(xch: *mut xc_interface, domid: u32, typecode: u16, instance: u16, ctxt_buf: *mut c_void, size: u32) -> c_int;
+        fn xc_domain_hvm_setcontext(xch: *mut xc_interface, domid: u32, hvm_ctxt: *mut u8, size: u32) -> c_int;
+        fn xc_domain_increase_reservation(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, mem_flags: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_increase_reservation_exact(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, mem_flags: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_max_vcpus(xch: *mut xc_interface, domid: u32, max: c_uint) -> c_int;
+        fn xc_domain_maximum_gpfn(xch: *mut xc_interface, domid: u32, gpfns: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_node_getaffinity(xch: *mut xc_interface, domind: u32, nodemap: xc_nodemap_t) -> c_int;
+        fn xc_domain_node_setaffinity(xch: *mut xc_interface, domind: u32, nodemap: xc_nodemap_t) -> c_int;
+        fn xc_domain_pause(xch: *mut xc_interface, domid: u32) -> c_int;
+        fn xc_domain_populate_physmap(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, mem_flags: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_populate_physmap_exact(xch: *mut xc_interface, domid: u32, nr_extents: c_ulong, extent_order: c_uint, mem_flags: c_uint, extent_start: *mut xen_pfn_t) -> c_int;
+        fn xc_domain_set_access_required(xch: *mut xc_interface, domid: u32, required: c_uint) -> c_int;
+        fn xc_domain_sethandle(xch: *mut xc_interface, domid: u32, handle: *mut u8) -> c_int;
+        fn xc_domain_setmaxmem(xch: *mut xc_interface, domid: u32, max_memkb: u64) -> c_int;
+        fn xc_domain_shutdown(xch: *mut xc_interface, domid: u32, reason: c_int) -> c_int;
+        fn xc_domain_soft_reset(xch: *mut xc_interface, domid: u32) -> c_int;
+        fn xc_domain_unpause(xch: *mut xc_interface, domid: u32) -> c_int;
+        fn xc_evtchn_alloc_unbound(xch: *mut xc_interface, dom: u32, remote_dom: u32) -> xc_evtchn_port_or_error_t;
+        fn xc_evtchn_reset(xch: *mut xc_interface, dom: u32) -> c_int;
+        fn xc_evtchn_status(xch: *mut xc_interface, status: *mut xc_evtchn_status_t) -> c_int;
+        fn xc_get_cpumap_size(xch: *mut xc_interface) -> c_int;
+        fn xc_get_last_error(handle: *mut xc_interface) -> *const xc_error;
+        fn xc_get_mem_access(xch: *mut xc_interface, domain_id: u32, pfn: u64, access: *mut xenmem_access_t) -> c_int;
+        fn xc_get_nodemap_size(xch: *mut xc_interface) -> c_int;
+        fn xc_hvm_param_get(handle: *mut xc_interface, dom: u32, param: u32, value: *mut u64) -> c_int;
+        fn xc_hvm_param_set(handle: *mut xc_interface, dom: u32, param: u32, value: u64) -> c_int;
+        fn xc_interface_close(xch: *mut xc_interface) -> c_int;
+        fn xc_interface_open(logger: *mut xentoollog_logger, dombuild_logger: *mut xentoollog_logger, open_flags: c_uint) -> *mut xc_interface;
+        fn xc_monitor_cpuid(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_debug_exceptions(xch: *mut xc_interface, domain_id: u32, enable: bool, sync: bool) -> c_int;
+        fn xc_monitor_descriptor_access(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_disable(xch: *mut xc_interface, domain_id: u32) -> c_int;
+        fn xc_monitor_emul_unimplemented(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_emulate_each_rep(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_enable(xch: *mut xc_interface, domain_id: u32, port: *mut u32) -> *mut c_void;
+        fn xc_monitor_get_capabilities(xch: *mut xc_interface, domain_id: u32, capabilities: *mut u32) -> c_int;
+        fn xc_monitor_guest_request(xch: *mut xc_interface, domain_id: u32, enable: bool, sync: bool, allow_userspace: bool) -> c_int;
+        fn xc_monitor_inguest_pagefault(xch: *mut xc_interface, domain_id: u32, disable: bool) -> c_int;
+        fn xc_monitor_io(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_mov_to_msr(xch: *mut xc_interface, domain_id: u32, msr: u32, enable: bool, onchangeonly: bool) -> c_int;
+        fn xc_monitor_privileged_call(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_resume(xch: *mut xc_interface, domain_id: u32) -> c_int;
+        fn xc_monitor_singlestep(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_software_breakpoint(xch: *mut xc_interface, domain_id: u32, enable: bool) -> c_int;
+        fn xc_monitor_vmexit(xch: *mut xc_interface, domain_id: u32, enable: bool, sync: bool) -> c_int;
+        fn xc_monitor_write_ctrlreg(xch: *mut xc_interface, domain_id: u32, index: u16, enable: bool, sync: bool, bitmask: u64, onchangeonly: bool) -> c_int;
+        fn xc_physinfo(xch: *mut xc_interface, info: *mut xc_physinfo_t) -> c_int;
+        fn xc_set_mem_access(xch: *mut xc_interface, domain_id: u32, access: xenmem_access_t, first_pfn: u64, nr: u32) -> c_int;
+        fn xc_translate_foreign_address(xch: *mut xc_interface, dom: u32, vcpu: c_int, virt: c_ulonglong) -> c_ulong;
+        fn xc_version(xch: *mut xc_interface, cmd: c_int, arg: *mut c_void) -> c_int;
+        fn xc_vm_event_get_version(xch: *mut xc_interface) -> c_int;
+        fn xc_vcpu_get_extstate(xch: *mut xc_interface, domid: u32, vcpu: u32, extstate: *mut xc_vcpu_extstate_t) -> c_int;
+        fn xc_vcpu_getaffinity(xch: *mut xc_interface, domid: u32, vcpu: c_int, cpumap_hard: xc_cpumap_t, cpumap_soft: xc_cpumap_t, flags: u32) -> c_int;
+        fn xc_vcpu_getinfo(xch: *mut xc_interface, domid: u32, vcpu: u32, info: *mut xc_vcpuinfo_t) -> c_int;
+        fn xc_vcpu_setaffinity(xch: *mut xc_interface, domid: u32, vcpu: c_int, cpumap_hard_inout: xc_cpumap_t, cpumap_soft_inout: xc_cpumap_t, flags: u32) -> c_int;
+    }
+
+    /// `libxendevicemodel`.
+    pub(crate) mod xendevicemodel("libxendevicemodel.so.1", "libxendevicemodel.so") {
+        fn xendevicemodel_close(dmod: *mut xendevicemodel_handle) -> c_int;
+        fn xendevicemodel_inject_event(dmod: *mut xendevicemodel_handle, domid: domid_t, vcpu: c_int, vector: u8, type_: u8, error_code: u32, insn_len: u8, extra: u64) -> c_int;
+        fn xendevicemodel_open(logger: *mut xentoollog_logger, open_flags: c_uint) -> *mut xendevicemodel_handle;
+    }
+
+    /// `libxenevtchn`.
+    pub(crate) mod xenevtchn("libxenevtchn.so.1", "libxenevtchn.so") {
+        fn xenevtchn_bind_interdomain(xce: *mut xenevtchn_handle, domid: u32, remote_port: evtchn_port_t) -> xenevtchn_port_or_error_t;
+        fn xenevtchn_bind_unbound_port(xce: *mut xenevtchn_handle, domid: u32) -> xenevtchn_port_or_error_t;
+        fn xenevtchn_bind_virq(xce: *mut xenevtchn_handle, virq: c_uint) -> xenevtchn_port_or_error_t;
+        fn xenevtchn_close(xce: *mut xenevtchn_handle) -> c_int;
+        fn xenevtchn_fd(xce: *mut xenevtchn_handle) -> c_int;
+        fn xenevtchn_fdopen(logger: *mut xentoollog_logger, fd: c_int, open_flags: c_uint) -> *mut xenevtchn_handle;
+        fn xenevtchn_notify(xce: *mut xenevtchn_handle, port: evtchn_port_t) -> c_int;
+        fn xenevtchn_open(logger: *mut xentoollog_logger, flags: c_uint) -> *mut xenevtchn_handle;
+        fn xenevtchn_pending(xce: *mut xenevtchn_handle) -> xenevtchn_port_or_error_t;
+        fn xenevtchn_restrict(xce: *mut xenevtchn_handle, domid: domid_t) -> c_int;
+        fn xenevtchn_unbind(xce: *mut xenevtchn_handle, port: evtchn_port_t) -> c_int;
+        fn xenevtchn_unmask(xce: *mut xenevtchn_handle, port: evtchn_port_t) -> c_int;
+    }
+
+    /// `libxenforeignmemory`.
+    pub(crate) mod xenforeignmemory("libxenforeignmemory.so.1", "libxenforeignmemory.so") {
+        fn xenforeignmemory_close(fmem: *mut xenforeignmemory_handle) -> c_int;
+        fn xenforeignmemory_map(fmem: *mut xenforeignmemory_handle, dom: u32, prot: c_int, pages: usize, arr: *const xen_pfn_t, err: *mut c_int) -> *mut c_void;
+        fn xenforeignmemory_open(logger: *mut xentoollog_logger, open_flags: c_uint) -> *mut xenforeignmemory_handle;
+        fn xenforeignmemory_unmap(fmem: *mut xenforeignmemory_handle, addr: *mut c_void, pages: usize) -> c_int;
+    }
+
+    /// `libxenstore`.
+    pub(crate) mod xenstore("libxenstore.so.4", "libxenstore.so") {
+        fn xs_check_watch(h: *mut xs_handle) -> *mut *mut c_char;
+        fn xs_close(xsh: *mut xs_handle);
+        fn xs_directory(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char, num: *mut c_uint) -> *mut *mut c_char;
+        fn xs_fileno(h: *mut xs_handle) -> c_int;
+        fn xs_get_domain_path(h: *mut xs_handle, domid: c_uint) -> *mut c_char;
+        fn xs_get_permissions(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char, num: *mut c_uint) -> *mut xs_permissions;
+        fn xs_is_domain_introduced(h: *mut xs_handle, domid: c_uint) -> bool;
+        fn xs_mkdir(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char) -> bool;
+        fn xs_open(flags: c_ulong) -> *mut xs_handle;
+        fn xs_read(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char, len: *mut c_uint) -> *mut c_void;
+        fn xs_rm(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char) -> bool;
+        fn xs_set_permissions(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char, perms: *mut xs_permissions, num_perms: c_uint) -> bool;
+        fn xs_unwatch(h: *mut xs_handle, path: *const c_char, token: *const c_char) -> bool;
+        fn xs_watch(h: *mut xs_handle, path: *const c_char, token: *const c_char) -> bool;
+        fn xs_write(h: *mut xs_handle, t: xs_transaction_t, path: *const c_char, data: *const c_void, len: c_uint) -> bool;
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::{Deref, Range},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use super::{XenForeignMemory, XenForeignMemoryMapped, XenForeignMemoryProtection};
+use crate::{
+    XenDomainId, XenError,
+    arch::x86::PhysicalMemory,
+    consts::{PAGE_SHIFT, PAGE_SIZE},
+};
+
+/// Hit and miss counters of a [`XenForeignMemoryCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct XenForeignMemoryCacheStats {
+    /// Lookups served from the cache.
+    pub hits: u64,
+
+    /// Lookups that had to map the page.
+    pub misses: u64,
+
+    /// Pages dropped to stay within the capacity.
+    pub evictions: u64,
+}
+
+/// A read-only page held by a [`XenForeignMemoryCache`].
+///
+/// The page stays mapped as long as it is referenced, even after it was
+/// evicted or invalidated.
+#[derive(Clone)]
+pub struct XenForeignMemoryCachedPage {
+    mapping: Arc<XenForeignMemoryMapped>,
+    offset: usize,
+}
+
+impl Deref for XenForeignMemoryCachedPage {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.mapping[self.offset..self.offset + PAGE_SIZE as usize]
+    }
+}
+
+impl AsRef<[u8]> for XenForeignMemoryCachedPage {
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl std::fmt::Debug for XenForeignMemoryCachedPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("XenForeignMemoryCachedPage")
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct CacheState {
+    capacity: usize,
+
+    /// Cached pages and the tick of their last use.
+    entries: HashMap<u64, (XenForeignMemoryCachedPage, u64)>,
+
+    /// Cached gfns by the tick of their last use, least recent first.
+    lru: BTreeMap<u64, u64>,
+
+    tick: u64,
+
+    /// Incremented whenever pages are invalidated. Pages mapped while it
+    /// changed may hold the old frames and are not inserted.
+    generation: u64,
+
+    stats: XenForeignMemoryCacheStats,
+}
+
+impl CacheState {
+    fn touch(&mut self, gfn: u64) -> Option<XenForeignMemoryCachedPage> {
+        let tick = self.tick;
+        let (page, last_use) = self.entries.get_mut(&gfn)?;
+
+        self.lru.remove(last_use);
+        self.lru.insert(tick, gfn);
+        *last_use = tick;
+        self.tick += 1;
+
+        Some(page.clone())
+    }
+
+    fn insert(&mut self, gfn: u64, page: XenForeignMemoryCachedPage) {
+        if let Some((_, last_use)) = self.entries.insert(gfn, (page, self.tick)) {
+            self.lru.remove(&last_use);
+        }
+
+        self.lru.insert(self.tick, gfn);
+        self.tick += 1;
+        self.evict();
+    }
+
+    fn remove(&mut self, gfn: u64) {
+        if let Some((_, last_use)) = self.entries.remove(&gfn) {
+            self.lru.remove(&last_use);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.generation += 1;
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let Some((_, gfn)) = self.lru.pop_first()
+            else {
+                break;
+            };
+
+            self.entries.remove(&gfn);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CacheShared {
+    foreignmemory: XenForeignMemory,
+    domain_id: XenDomainId,
+    state: Mutex<CacheState>,
+}
+
+impl CacheShared {
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn domain_id(&self) -> XenDomainId {
+        self.domain_id
+    }
+
+    pub(crate) fn invalidate_range(&self, gfns: Range<u64>) {
+        let mut state = self.lock();
+        state.generation += 1;
+
+        if gfns.end.saturating_sub(gfns.start) <= state.entries.len() as u64 {
+            for gfn in gfns {
+                state.remove(gfn);
+            }
+        }
+        else {
+            let cached = state
+                .entries
+                .keys()
+                .copied()
+                .filter(|gfn| gfns.contains(gfn))
+                .collect::<Vec<_>>();
+
+            for gfn in cached {
+                state.remove(gfn);
+            }
+        }
+    }
+}
+
+/// Least recently used cache of read-only mappings of guest pages.
+///
+/// Pages are unmapped once they are evicted and no
+/// [`XenForeignMemoryCachedPage`] references them anymore. Pages mapped
+/// together by [`prefetch`](Self::prefetch) share a single mapping, which
+/// is unmapped with the last of them.
+///
+/// Caches are created with
+/// [`XenDomain::foreign_memory_cache`](crate::XenDomain::foreign_memory_cache),
+/// which has them invalidated by
+/// [`decrease_reservation`](crate::XenDomain::decrease_reservation) and
+/// [`change_gfn`](crate::XenAltP2MView::change_gfn). Call
+/// [`invalidate`](Self::invalidate) when the mapping changes otherwise.
+///
+/// Cloning is cheap, all clones share the same pages.
+#[derive(Debug, Clone)]
+pub struct XenForeignMemoryCache {
+    pub(crate) shared: Arc<CacheShared>,
+}
+
+impl XenForeignMemoryCache {
+    pub(crate) fn new(
+        foreignmemory: XenForeignMemory,
+        domain_id: XenDomainId,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            shared: Arc::new(CacheShared {
+                foreignmemory,
+                domain_id,
+                state: Mutex::new(CacheState {
+                    capacity,
+                    entries: HashMap::new(),
+                    lru: BTreeMap::new(),
+                    tick: 0,
+                    generation: 0,
+                    stats: XenForeignMemoryCacheStats::default(),
+                }),
+            }),
+        }
+    }
+
+    pub fn domain_id(&self) -> XenDomainId {
+        self.shared.domain_id
+    }
+
+    /// Returns the maximum number of cached pages.
+    pub fn capacity(&self) -> usize {
+        self.shared.lock().capacity
+    }
+
+    /// Changes the maximum number of cached pages, evicting the least
+    /// recently used pages if needed.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.shared.lock();
+        state.capacity = capacity;
+        state.evict();
+    }
+
+    /// Returns the number of cached pages.
+    pub fn len(&self) -> usize {
+        self.shared.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> XenForeignMemoryCacheStats {
+        self.shared.lock().stats
+    }
+
+    pub fn reset_stats(&self) {
+        self.shared.lock().stats = XenForeignMemoryCacheStats::default();
+    }
+
+    /// Returns a page, mapping it on a miss.
+    ///
+    /// A page mapped while the cache was invalidated is returned, but not
+    /// cached.
+    pub fn page(&self, gfn: u64) -> Result<XenForeignMemoryCachedPage, XenError> {
+        let generation = {
+            let mut state = self.shared.lock();
+            if let Some(page) = state.touch(gfn) {
+                state.stats.hits += 1;
+                return Ok(page);
+            }
+
+            state.stats.misses += 1;
+            state.generation
+        };
+
+        let mapping = self.shared.foreignmemory.map(
+            self.shared.domain_id,
+            XenForeignMemoryProtection::READ,
+            &[gfn],
+            None,
+        )?;
+
+        let page = XenForeignMemoryCachedPage {
+            mapping: Arc::new(mapping),
+            offset: 0,
+        };
+
+        let mut state = self.shared.lock();
+        if state.generation == generation {
+            state.insert(gfn, page.clone());
+        }
+
+        Ok(page)
+    }
+
+    /// Maps all pages of `gfns` that are not cached yet with a single call.
+    ///
+    /// Pages that cannot be mapped are skipped, a later
+    /// [`page`](Self::page) reports their error. Prefetching does not
+    /// count as hits or misses.
+    pub fn prefetch(&self, gfns: &[u64]) -> Result<(), XenError> {
+        let (mut missing, generation) = {
+            let state = self.shared.lock();
+            let missing = gfns
+                .iter()
+                .copied()
+                .filter(|gfn| !state.entries.contains_key(gfn))
+                .collect::<Vec<_>>();
+
+            (missing, state.generation)
+        };
+
+        missing.sort_unstable();
+        missing.dedup();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut err = vec![0; missing.len()];
+        let mapping = Arc::new(self.shared.foreignmemory.map(
+            self.shared.domain_id,
+            XenForeignMemoryProtection::READ,
+            &missing,
+            Some(&mut err),
+        )?);
+
+        let mut state = self.shared.lock();
+        if state.generation != generation {
+            return Ok(());
+        }
+
+        for (index, (gfn, err)) in missing.into_iter().zip(err).enumerate() {
+            if err != 0 {
+                continue;
+            }
+
+            let page = XenForeignMemoryCachedPage {
+                mapping: mapping.clone(),
+                offset: index * PAGE_SIZE as usize,
+            };
+
+            state.insert(gfn, page);
+        }
+
+        Ok(())
+    }
+
+    /// Drops a page from the cache.
+    pub fn invalidate(&self, gfn: u64) {
+        let mut state = self.shared.lock();
+        state.generation += 1;
+        state.remove(gfn);
+    }
+
+    /// Drops all pages within `gfns` from the cache.
+    pub fn invalidate_range(&self, gfns: Range<u64>) {
+        self.shared.invalidate_range(gfns);
+    }
+
+    /// Drops all pages from the cache.
+    pub fn clear(&self) {
+        self.shared.lock().clear();
+    }
+}
+
+impl PhysicalMemory for XenForeignMemoryCache {
+    fn read_physical(&self, address: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        let mut offset = 0;
+
+        while offset < buffer.len() {
+            let address = address + offset as u64;
+            let page_offset = (address & (PAGE_SIZE - 1)) as usize;
+            let size = (buffer.len() - offset).min(PAGE_SIZE as usize - page_offset);
+
+            let page = self.page(address >> PAGE_SHIFT)?;
+            buffer[offset..offset + size].copy_from_slice(&page[page_offset..page_offset + size]);
+            offset += size;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        MemoryAccess, XenDomainId, arch::x86::Amd64, backend::mock::MockHypervisor,
+        consts::INVALID_GFN,
+    };
+
+    #[test]
+    fn invalidated_by_domain() {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 16, 1)
+            .unwrap();
+
+        let domain = hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap();
+        let cache = domain.foreign_memory_cache(&hypervisor.foreign_memory(), 8);
+
+        cache.prefetch(&[1, 2, 3]).unwrap();
+        assert_eq!(cache.len(), 3);
+
+        domain.decrease_reservation(0, &[2]).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.page(2).is_err());
+
+        let altp2m = domain.altp2m().unwrap();
+        let view = altp2m.create_view(MemoryAccess::RWX).unwrap();
+
+        view.change_gfn(1, 3).unwrap();
+        assert_eq!(cache.len(), 0);
+
+        cache.page(1).unwrap();
+        view.change_gfn(1, INVALID_GFN).unwrap();
+        assert!(cache.is_empty());
+
+        // A failed remapping leaves the cache alone.
+        cache.page(1).unwrap();
+        assert!(view.change_gfn(1, 2).is_err());
+        assert_eq!(cache.len(), 1);
+    }
+}
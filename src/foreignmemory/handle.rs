@@ -1,30 +1,48 @@
-use xen_sys::{
-    xenforeignmemory_close, xenforeignmemory_handle, xenforeignmemory_open, xentoollog_logger,
-};
+use std::{ffi::c_void, ptr::NonNull};
+
+use xen_sys::{xenforeignmemory_handle, xentoollog_logger};
 
-use crate::XenError;
+use super::XenForeignMemoryProtection;
+use crate::{
+    XenDomainId, XenError,
+    backend::XenForeignMemoryBackend,
+    error::{XcError, XenErrorContext},
+    ffi::xenforeignmemory::{
+        self, xenforeignmemory_close, xenforeignmemory_map, xenforeignmemory_open,
+        xenforeignmemory_unmap,
+    },
+    logger::logger_or_default,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct XenForeignMemoryHandle(pub(crate) *mut xenforeignmemory_handle);
 
+// SAFETY: libxenforeignmemory keeps no per-call state in the handle, mapping
+// and unmapping may be done concurrently from multiple threads.
+unsafe impl Send for XenForeignMemoryHandle {}
+unsafe impl Sync for XenForeignMemoryHandle {}
+
 impl XenForeignMemoryHandle {
     pub fn new() -> Result<Self, XenError> {
         Self::new_with_options(None, 0)
     }
 
+    /// Opens the handle with a custom `logger`.
+    ///
+    /// Messages of the library are forwarded to the global
+    /// [`TracingLogger`](crate::TracingLogger) if `logger` is `None`.
     pub fn new_with_options(
         logger: Option<&mut xentoollog_logger>,
         flags: u32,
     ) -> Result<Self, XenError> {
-        let handle = unsafe {
-            xenforeignmemory_open(
-                logger.map_or_else(std::ptr::null_mut, |p| p as *mut _),
-                flags,
-            )
-        };
+        xenforeignmemory::load()?;
+
+        let handle = unsafe { xenforeignmemory_open(logger_or_default(logger), flags) };
 
         if handle.is_null() {
-            return Err(XenError::Other("Failed to open Xen event channel"));
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xenforeignmemory_open")).into(),
+            );
         }
 
         Ok(Self(handle))
@@ -39,3 +57,44 @@ impl Drop for XenForeignMemoryHandle {
         }
     }
 }
+
+impl XenForeignMemoryBackend for XenForeignMemoryHandle {
+    fn map(
+        &self,
+        domain_id: XenDomainId,
+        protection: XenForeignMemoryProtection,
+        gfns: &[u64],
+        err: Option<&mut [i32]>,
+    ) -> Result<NonNull<c_void>, XenError> {
+        if err.as_ref().is_some_and(|err| err.len() != gfns.len()) {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
+        }
+
+        let ptr = unsafe {
+            xenforeignmemory_map(
+                self.0,
+                domain_id.0,
+                protection.bits(),
+                gfns.len(),
+                gfns.as_ptr() as *const _,
+                err.map_or_else(std::ptr::null_mut, <[_]>::as_mut_ptr),
+            )
+        };
+
+        NonNull::new(ptr).ok_or_else(|| {
+            let context = XenErrorContext {
+                domain_id: Some(domain_id),
+                gfn: gfns.first().copied(),
+                ..XenErrorContext::new("xenforeignmemory_map")
+            };
+
+            XcError::last_os_error(-1, context).into()
+        })
+    }
+
+    fn unmap(&self, ptr: NonNull<c_void>, pages: usize) {
+        unsafe {
+            xenforeignmemory_unmap(self.0, ptr.as_ptr(), pages);
+        }
+    }
+}
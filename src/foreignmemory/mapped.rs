@@ -1,19 +1,23 @@
 use std::{
     ffi::c_void,
     ops::{Deref, DerefMut},
+    ptr::NonNull,
 };
 
-use xen_sys::{xenforeignmemory_map, xenforeignmemory_unmap};
-
 use super::{XenForeignMemory, XenForeignMemoryProtection};
 use crate::{XenDomainId, XenError, consts::PAGE_SIZE};
 
 pub struct XenForeignMemoryMapped {
     foreignmemory: XenForeignMemory,
-    ptr: *mut c_void,
+    ptr: NonNull<c_void>,
     pages: usize,
 }
 
+// SAFETY: The mapping is exclusively owned, shared access only hands out
+// `&[u8]` and mutable access requires `&mut self`.
+unsafe impl Send for XenForeignMemoryMapped {}
+unsafe impl Sync for XenForeignMemoryMapped {}
+
 impl XenForeignMemoryMapped {
     pub(crate) fn new(
         foreignmemory: XenForeignMemory,
@@ -22,24 +26,11 @@ impl XenForeignMemoryMapped {
         arr: &[u64],
         err: Option<&mut [i32]>,
     ) -> Result<Self, XenError> {
-        if let Some(err) = &err {
-            debug_assert_eq!(arr.len(), err.len());
+        if err.as_ref().is_some_and(|err| err.len() != arr.len()) {
+            return Err(std::io::Error::from_raw_os_error(libc::EINVAL).into());
         }
 
-        let ptr = unsafe {
-            xenforeignmemory_map(
-                foreignmemory.handle.0,
-                domain_id.0,
-                protection.bits(),
-                arr.len(),
-                arr.as_ptr() as *const _,
-                err.map_or_else(std::ptr::null_mut, <[_]>::as_mut_ptr),
-            )
-        };
-
-        if ptr.is_null() {
-            return Err(XenError::Io(std::io::Error::last_os_error()));
-        }
+        let ptr = foreignmemory.backend.map(domain_id, protection, arr, err)?;
 
         Ok(Self {
             foreignmemory,
@@ -52,9 +43,7 @@ impl XenForeignMemoryMapped {
 impl Drop for XenForeignMemoryMapped {
     fn drop(&mut self) {
         //tracing::trace!("unmapping foreign memory");
-        unsafe {
-            xenforeignmemory_unmap(self.foreignmemory.handle.0, self.ptr, self.pages);
-        }
+        self.foreignmemory.backend.unmap(self.ptr, self.pages);
     }
 }
 
@@ -64,7 +53,10 @@ impl Deref for XenForeignMemoryMapped {
     #[inline]
     fn deref(&self) -> &Self::Target {
         unsafe {
-            std::slice::from_raw_parts(self.ptr as *const u8, self.pages * PAGE_SIZE as usize)
+            std::slice::from_raw_parts(
+                self.ptr.as_ptr() as *const u8,
+                self.pages * PAGE_SIZE as usize,
+            )
         }
     }
 }
@@ -73,7 +65,10 @@ impl DerefMut for XenForeignMemoryMapped {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe {
-            std::slice::from_raw_parts_mut(self.ptr as *mut u8, self.pages * PAGE_SIZE as usize)
+            std::slice::from_raw_parts_mut(
+                self.ptr.as_ptr() as *mut u8,
+                self.pages * PAGE_SIZE as usize,
+            )
         }
     }
 }
@@ -1,11 +1,18 @@
 mod handle;
 pub use self::handle::XenForeignMemoryHandle;
 
+mod cache;
 mod mapped;
-use std::rc::Rc;
+mod physical;
+use std::sync::Arc;
 
-pub use self::mapped::XenForeignMemoryMapped;
-use crate::{XenDomainId, XenError};
+pub(crate) use self::cache::CacheShared;
+pub use self::{
+    cache::{XenForeignMemoryCache, XenForeignMemoryCacheStats, XenForeignMemoryCachedPage},
+    mapped::XenForeignMemoryMapped,
+    physical::{FailedRange, GuestPhysicalMemory},
+};
+use crate::{XenDomainId, XenError, backend::XenForeignMemoryBackend};
 
 bitflags::bitflags! {
     #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -18,14 +25,17 @@ bitflags::bitflags! {
 
 #[derive(Debug, Clone)]
 pub struct XenForeignMemory {
-    pub(crate) handle: Rc<XenForeignMemoryHandle>,
+    pub(crate) backend: Arc<dyn XenForeignMemoryBackend>,
 }
 
 impl XenForeignMemory {
     pub fn new() -> Result<Self, XenError> {
-        Ok(Self {
-            handle: Rc::new(XenForeignMemoryHandle::new()?),
-        })
+        Ok(Self::with_backend(Arc::new(XenForeignMemoryHandle::new()?)))
+    }
+
+    /// Creates a foreign memory interface that maps pages through `backend`.
+    pub fn with_backend(backend: Arc<dyn XenForeignMemoryBackend>) -> Self {
+        Self { backend }
     }
 
     pub fn map(
@@ -37,4 +47,9 @@ impl XenForeignMemory {
     ) -> Result<XenForeignMemoryMapped, XenError> {
         XenForeignMemoryMapped::new(self.clone(), domain_id, protection, arr, err)
     }
+
+    /// Returns the guest physical address space of a domain.
+    pub fn physical_memory(&self, domain_id: XenDomainId) -> GuestPhysicalMemory {
+        GuestPhysicalMemory::new(self.clone(), domain_id)
+    }
 }
@@ -0,0 +1,333 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+use super::{XenForeignMemory, XenForeignMemoryProtection};
+use crate::{XenDomainId, XenError, arch::x86::PhysicalMemory, consts::PAGE_SHIFT};
+
+/// A range of guest physical memory that could not be mapped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FailedRange {
+    /// Guest physical addresses of the range.
+    pub range: Range<u64>,
+
+    /// The `errno` value reported for the pages of the range.
+    pub errno: i32,
+}
+
+/// Guest physical address space of a domain.
+///
+/// Accesses may span any number of pages. Pages that cannot be mapped do
+/// not fail the whole access: all other pages are still read or written,
+/// and the failed ranges are reported in [`XenError::PartialAccess`].
+///
+/// The [`Read`], [`Write`] and [`Seek`] implementations access the memory
+/// at the current position and stop short of the first failed page, the
+/// pages following it are not accessed.
+#[derive(Debug, Clone)]
+pub struct GuestPhysicalMemory {
+    foreignmemory: XenForeignMemory,
+    domain_id: XenDomainId,
+    position: u64,
+}
+
+/// Defines a little-endian read and write method for each integer type.
+macro_rules! impl_integer_access {
+    ($($ty:ty => $read:ident, $write:ident;)*) => {
+        $(
+            #[doc = concat!("Reads a little-endian `", stringify!($ty), "`.")]
+            pub fn $read(&self, gpa: u64) -> Result<$ty, XenError> {
+                let mut buffer = [0; size_of::<$ty>()];
+                self.read_at(gpa, &mut buffer)?;
+                Ok(<$ty>::from_le_bytes(buffer))
+            }
+
+            #[doc = concat!("Writes a little-endian `", stringify!($ty), "`.")]
+            pub fn $write(&self, gpa: u64, value: $ty) -> Result<(), XenError> {
+                self.write_at(gpa, &value.to_le_bytes())
+            }
+        )*
+    };
+}
+
+impl GuestPhysicalMemory {
+    pub(crate) fn new(foreignmemory: XenForeignMemory, domain_id: XenDomainId) -> Self {
+        Self {
+            foreignmemory,
+            domain_id,
+            position: 0,
+        }
+    }
+
+    pub fn domain_id(&self) -> XenDomainId {
+        self.domain_id
+    }
+
+    /// Reads `buffer.len()` bytes at `gpa`.
+    ///
+    /// The bytes of failed ranges are left unchanged.
+    pub fn read_at(&self, gpa: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        self.read_pages(gpa, buffer, false)
+    }
+
+    /// Writes `data` at `gpa`.
+    pub fn write_at(&self, gpa: u64, data: &[u8]) -> Result<(), XenError> {
+        self.write_pages(gpa, data, false)
+    }
+
+    impl_integer_access! {
+        u8 => read_u8, write_u8;
+        u16 => read_u16, write_u16;
+        u32 => read_u32, write_u32;
+        u64 => read_u64, write_u64;
+    }
+
+    fn read_pages(
+        &self,
+        gpa: u64,
+        buffer: &mut [u8],
+        stop_at_failure: bool,
+    ) -> Result<(), XenError> {
+        self.access(
+            gpa,
+            buffer.len(),
+            XenForeignMemoryProtection::READ,
+            stop_at_failure,
+            |offset, page| {
+                buffer[offset..offset + page.len()].copy_from_slice(page);
+            },
+        )
+    }
+
+    fn write_pages(&self, gpa: u64, data: &[u8], stop_at_failure: bool) -> Result<(), XenError> {
+        self.access(
+            gpa,
+            data.len(),
+            XenForeignMemoryProtection::READ | XenForeignMemoryProtection::WRITE,
+            stop_at_failure,
+            |offset, page| {
+                page.copy_from_slice(&data[offset..offset + page.len()]);
+            },
+        )
+    }
+
+    /// Maps the pages covering `size` bytes at `gpa` at once and calls `f`
+    /// with the offset into the access and the accessed part of each mapped
+    /// page.
+    ///
+    /// With `stop_at_failure`, the pages following the first failed page
+    /// are not accessed.
+    fn access(
+        &self,
+        gpa: u64,
+        size: usize,
+        protection: XenForeignMemoryProtection,
+        stop_at_failure: bool,
+        mut f: impl FnMut(usize, &mut [u8]),
+    ) -> Result<(), XenError> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        let end = gpa
+            .checked_add(size as u64)
+            .ok_or(XenError::Other("Access wraps around the address space"))?;
+
+        let first_gfn = gpa >> PAGE_SHIFT;
+        let gfns = (first_gfn..=(end - 1) >> PAGE_SHIFT).collect::<Vec<_>>();
+        let mut err = vec![0; gfns.len()];
+
+        let mut mapped =
+            self.foreignmemory
+                .map(self.domain_id, protection, &gfns, Some(&mut err))?;
+
+        let mut failed = Vec::<FailedRange>::new();
+
+        for (gfn, err) in gfns.iter().zip(err) {
+            let start = gpa.max(gfn << PAGE_SHIFT);
+            let stop = end.min((gfn + 1) << PAGE_SHIFT);
+
+            if err != 0 {
+                match failed.last_mut() {
+                    Some(last) if last.range.end == start && last.errno == -err => {
+                        last.range.end = stop;
+                    }
+                    _ => failed.push(FailedRange {
+                        range: start..stop,
+                        errno: -err,
+                    }),
+                }
+
+                if stop_at_failure {
+                    break;
+                }
+
+                continue;
+            }
+
+            let mapped_offset = (start - (first_gfn << PAGE_SHIFT)) as usize;
+            let page = &mut mapped[mapped_offset..mapped_offset + (stop - start) as usize];
+            f((start - gpa) as usize, page);
+        }
+
+        if !failed.is_empty() {
+            return Err(XenError::PartialAccess(failed));
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` at the current position and advances it by the number of
+    /// bytes accessed before the first failed range.
+    fn transfer(
+        &mut self,
+        size: usize,
+        f: impl FnOnce(&Self, u64) -> Result<(), XenError>,
+    ) -> std::io::Result<usize> {
+        let accessed = match f(self, self.position) {
+            Ok(()) => size,
+            Err(XenError::PartialAccess(failed)) => match failed.first() {
+                Some(first) if first.range.start == self.position => {
+                    return Err(std::io::Error::from_raw_os_error(first.errno));
+                }
+                Some(first) => (first.range.start - self.position) as usize,
+                None => size,
+            },
+            Err(XenError::Io(err)) => return Err(err),
+            Err(err) => return Err(std::io::Error::other(err)),
+        };
+
+        self.position += accessed as u64;
+        Ok(accessed)
+    }
+}
+
+impl Read for GuestPhysicalMemory {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.transfer(buf.len(), |memory, position| {
+            memory.read_pages(position, buf, true)
+        })
+    }
+}
+
+impl Write for GuestPhysicalMemory {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.transfer(buf.len(), |memory, position| {
+            memory.write_pages(position, buf, true)
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for GuestPhysicalMemory {
+    /// Seeking relative to the end is not supported, the guest physical
+    /// address space has no defined end.
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "guest physical memory has no end",
+                ));
+            }
+        };
+
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+impl PhysicalMemory for GuestPhysicalMemory {
+    fn read_physical(&self, address: u64, buffer: &mut [u8]) -> Result<(), XenError> {
+        self.read_at(address, buffer)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{arch::x86::Amd64, backend::mock::MockHypervisor, consts::PAGE_SIZE};
+
+    /// Returns the memory of a domain with 4 pages, where page 1 is
+    /// missing.
+    fn memory() -> (MockHypervisor, GuestPhysicalMemory) {
+        let hypervisor = MockHypervisor::new();
+        hypervisor
+            .create_domain(XenDomainId(1), "guest", 4, 1)
+            .unwrap();
+        hypervisor
+            .control()
+            .unwrap()
+            .domain::<Amd64>(XenDomainId(1))
+            .unwrap()
+            .decrease_reservation(0, &[1])
+            .unwrap();
+
+        let memory = hypervisor.foreign_memory().physical_memory(XenDomainId(1));
+        (hypervisor, memory)
+    }
+
+    #[test]
+    fn partial_access() {
+        let (hypervisor, memory) = memory();
+        let page = PAGE_SIZE;
+
+        match memory.write_at(0x800, &[0xaa; 3 * PAGE_SIZE as usize]) {
+            Err(XenError::PartialAccess(failed)) => {
+                assert_eq!(
+                    failed,
+                    [FailedRange {
+                        range: page..2 * page,
+                        errno: libc::ENOENT,
+                    }]
+                );
+            }
+            result => panic!("expected a partial access, got {result:?}"),
+        }
+
+        // The pages around the failed one are written.
+        let mut buffer = [0; 2];
+        hypervisor
+            .read_physical(XenDomainId(1), page - 1, &mut buffer[..1])
+            .unwrap();
+        hypervisor
+            .read_physical(XenDomainId(1), 2 * page, &mut buffer[1..])
+            .unwrap();
+        assert_eq!(buffer, [0xaa; 2]);
+    }
+
+    #[test]
+    fn write_stops_at_failure() {
+        let (hypervisor, mut memory) = memory();
+        let page = PAGE_SIZE;
+
+        memory.seek(SeekFrom::Start(0x800)).unwrap();
+        assert_eq!(
+            memory.write(&[0xaa; 3 * PAGE_SIZE as usize]).unwrap(),
+            0x800
+        );
+        assert_eq!(memory.stream_position().unwrap(), page);
+
+        // The page following the failed one is not written.
+        let mut buffer = [0xff; 1];
+        hypervisor
+            .read_physical(XenDomainId(1), 2 * page, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, [0]);
+
+        let err = memory.write(&[0xaa; 1]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+    }
+}
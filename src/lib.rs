@@ -1,11 +1,14 @@
 pub mod arch;
+pub mod backend;
 pub mod consts;
 pub mod core;
 pub mod ctrl;
 pub mod devicemodel;
 pub mod error;
 pub mod evtchn;
+mod ffi;
 pub mod foreignmemory;
+pub mod logger;
 pub mod macros;
 pub mod store;
 
@@ -15,11 +18,16 @@ pub use self::{
     arch::Architecture,
     core::{MemoryAccess, VcpuId, XenDomainId},
     ctrl::{
-        XenAltP2M, XenAltP2MView, XenControl, XenDomain, XenDomainInfo, XenInterface, XenMonitor,
+        DomainWatcher, MonitorLoop, XenAltP2M, XenAltP2MView, XenControl, XenDomain, XenDomainInfo,
+        XenInterface, XenMonitor,
     },
     devicemodel::{XenDeviceModel, XenX86EventType, XenX86ExceptionVector},
     error::XenError,
     evtchn::XenEventChannelPort,
-    foreignmemory::{XenForeignMemory, XenForeignMemoryMapped, XenForeignMemoryProtection},
+    foreignmemory::{
+        GuestPhysicalMemory, XenForeignMemory, XenForeignMemoryCache, XenForeignMemoryMapped,
+        XenForeignMemoryProtection,
+    },
+    logger::TracingLogger,
     store::XenStore,
 };
@@ -0,0 +1,191 @@
+use std::{
+    cell::UnsafeCell,
+    ffi::{CStr, c_char, c_int, c_ulong},
+};
+
+use xen_sys::{
+    __va_list_tag, xentoollog_level, xentoollog_level_XTL_CRITICAL, xentoollog_level_XTL_DEBUG,
+    xentoollog_level_XTL_DETAIL, xentoollog_level_XTL_ERROR, xentoollog_level_XTL_INFO,
+    xentoollog_level_XTL_NOTICE, xentoollog_level_XTL_PROGRESS, xentoollog_level_XTL_VERBOSE,
+    xentoollog_level_XTL_WARN, xentoollog_logger,
+};
+
+/// Maximum length of a single formatted message, longer messages are
+/// truncated.
+const MESSAGE_SIZE: usize = 2048;
+
+unsafe extern "C" {
+    fn vsnprintf(
+        buffer: *mut c_char,
+        size: usize,
+        format: *const c_char,
+        args: *mut __va_list_tag,
+    ) -> c_int;
+}
+
+/// `xentoollog` logger that forwards messages of the Xen libraries to
+/// [`tracing`].
+///
+/// The xentoollog levels are mapped as follows:
+///
+/// | xentoollog                               | tracing |
+/// |------------------------------------------|---------|
+/// | `XTL_DEBUG`                              | `TRACE` |
+/// | `XTL_VERBOSE`, `XTL_DETAIL`              | `DEBUG` |
+/// | `XTL_PROGRESS`, `XTL_INFO`, `XTL_NOTICE` | `INFO`  |
+/// | `XTL_WARN`                               | `WARN`  |
+/// | `XTL_ERROR`, `XTL_CRITICAL`              | `ERROR` |
+///
+/// Handles created without an explicit logger use the shared
+/// [`TracingLogger::global`] instance.
+#[repr(transparent)]
+pub struct TracingLogger(UnsafeCell<xentoollog_logger>);
+
+// SAFETY: The Xen libraries only read the function pointers of the logger,
+// the callbacks themselves are stateless.
+unsafe impl Send for TracingLogger {}
+unsafe impl Sync for TracingLogger {}
+
+static GLOBAL: TracingLogger = TracingLogger::new();
+
+impl TracingLogger {
+    pub const fn new() -> Self {
+        Self(UnsafeCell::new(xentoollog_logger {
+            vmessage: Some(vmessage),
+            progress: Some(progress),
+            destroy: Some(destroy),
+        }))
+    }
+
+    /// Returns the logger shared by all handles.
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    /// Returns the raw logger to be passed to the Xen libraries.
+    pub fn as_ptr(&self) -> *mut xentoollog_logger {
+        self.0.get()
+    }
+}
+
+impl Default for TracingLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for TracingLogger {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TracingLogger").finish()
+    }
+}
+
+impl AsMut<xentoollog_logger> for TracingLogger {
+    fn as_mut(&mut self) -> &mut xentoollog_logger {
+        self.0.get_mut()
+    }
+}
+
+/// Returns the logger to be passed to the Xen libraries, falling back to
+/// the global [`TracingLogger`].
+pub(crate) fn logger_or_default(logger: Option<&mut xentoollog_logger>) -> *mut xentoollog_logger {
+    logger.map_or_else(|| TracingLogger::global().as_ptr(), |p| p as *mut _)
+}
+
+/// Formats a `printf`-style message.
+///
+/// # Safety
+///
+/// `format` and `args` must be the arguments of a `vmessage` call.
+unsafe fn format(format: *const c_char, args: *mut __va_list_tag) -> String {
+    let mut buffer = [0u8; MESSAGE_SIZE];
+    let rc = unsafe {
+        vsnprintf(
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+            format,
+            args,
+        )
+    };
+
+    if rc < 0 {
+        return String::new();
+    }
+
+    CStr::from_bytes_until_nul(&buffer)
+        .map(|message| message.to_string_lossy().trim_end().to_owned())
+        .unwrap_or_default()
+}
+
+/// Converts a possibly null C string.
+///
+/// # Safety
+///
+/// `value` must be null or point to a nul-terminated string.
+unsafe fn to_str<'a>(value: *const c_char) -> std::borrow::Cow<'a, str> {
+    match value.is_null() {
+        true => "".into(),
+        false => unsafe { CStr::from_ptr(value) }.to_string_lossy(),
+    }
+}
+
+#[expect(non_upper_case_globals)]
+unsafe extern "C" fn vmessage(
+    _logger: *mut xentoollog_logger,
+    level: xentoollog_level,
+    errnoval: c_int,
+    context: *const c_char,
+    format: *const c_char,
+    args: *mut __va_list_tag,
+) {
+    macro_rules! log {
+        ($level:expr) => {
+            if tracing::enabled!($level) {
+                let context = unsafe { to_str(context) };
+                let message = unsafe { self::format(format, args) };
+
+                match errnoval {
+                    errno if errno >= 0 => tracing::event!(
+                        $level,
+                        context = &*context,
+                        errno = %std::io::Error::from_raw_os_error(errno),
+                        "{message}"
+                    ),
+                    _ => tracing::event!($level, context = &*context, "{message}"),
+                }
+            }
+        };
+    }
+
+    match level {
+        xentoollog_level_XTL_DEBUG => log!(tracing::Level::TRACE),
+        xentoollog_level_XTL_VERBOSE | xentoollog_level_XTL_DETAIL => {
+            log!(tracing::Level::DEBUG)
+        }
+        xentoollog_level_XTL_PROGRESS | xentoollog_level_XTL_INFO | xentoollog_level_XTL_NOTICE => {
+            log!(tracing::Level::INFO)
+        }
+        xentoollog_level_XTL_WARN => log!(tracing::Level::WARN),
+        xentoollog_level_XTL_ERROR | xentoollog_level_XTL_CRITICAL => {
+            log!(tracing::Level::ERROR)
+        }
+        _ => {}
+    }
+}
+
+unsafe extern "C" fn progress(
+    _logger: *mut xentoollog_logger,
+    context: *const c_char,
+    doing_what: *const c_char,
+    percent: c_int,
+    done: c_ulong,
+    total: c_ulong,
+) {
+    if tracing::enabled!(tracing::Level::INFO) {
+        let context = unsafe { to_str(context) };
+        let doing_what = unsafe { to_str(doing_what) };
+        tracing::info!(context = &*context, percent, done, total, "{doing_what}");
+    }
+}
+
+unsafe extern "C" fn destroy(_logger: *mut xentoollog_logger) {}
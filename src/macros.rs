@@ -18,23 +18,79 @@ pub fn wmb() {
     core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Release);
 }
 
+/// Views a plain-old-data structure (e.g. an HVM save record) as bytes.
+pub(crate) fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// Views a plain-old-data structure (e.g. an HVM save record) as bytes.
+pub(crate) fn as_bytes_mut<T: Copy>(value: &mut T) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(value as *mut T as *mut u8, size_of::<T>()) }
+}
+
+/// Reads a plain-old-data structure from the start of `bytes`.
+///
+/// # Panics
+///
+/// Panics if `bytes` is shorter than the structure.
+pub(crate) fn from_bytes<T: Copy>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+/// Returns an [`XcError`](crate::error::XcError) built from `errno` and the
+/// last error of the libxenctrl handle if `rc` is negative.
+///
+/// The operation name is mandatory, the domain, vCPU and gfn the operation
+/// was performed on can be added as `field: value` pairs:
+///
+/// ```ignore
+/// xc_check_error!(*xch, rc, "xc_domain_pause", domain_id: domain_id);
+/// ```
 #[macro_export]
 macro_rules! xc_check_error {
-    ($handle:expr, $rc:expr) => {
-        if $rc < 0 {
+    ($handle:expr, $rc:expr, $operation:expr $(, $field:ident: $value:expr)* $(,)?) => {
+        let rc = $rc;
+
+        if rc < 0 {
             let handle = $handle;
-            let rc = $rc;
-
-            unsafe {
-                let err = ::xen_sys::xc_get_last_error(handle);
-                let desc = ::xen_sys::xc_error_code_to_desc((*err).code as _);
-                return Err($crate::error::XenError::Xen($crate::error::XcError {
-                    rc,
-                    code: (*err).code as _,
-                    desc: ::std::ffi::CStr::from_ptr(desc).to_str().unwrap(),
-                    backtrace: ::std::backtrace::Backtrace::capture(),
-                }));
-            }
+            let context = $crate::error::XenErrorContext {
+                operation: $operation,
+                $($field: Some($value),)*
+                ..Default::default()
+            };
+
+            return Err($crate::error::XenError::from(unsafe {
+                $crate::error::XcError::last_error(handle, rc as i32, context)
+            }));
+        }
+    };
+}
+
+/// Returns an [`XcError`](crate::error::XcError) built from `errno` if `rc`
+/// is negative.
+///
+/// Used by the libraries that report errors only through `errno`, the
+/// arguments are the same as for [`xc_check_error!`] without the handle:
+///
+/// ```ignore
+/// check_errno!(rc, "xenevtchn_bind_interdomain", domain_id: domain_id);
+/// ```
+#[macro_export]
+macro_rules! check_errno {
+    ($rc:expr, $operation:expr $(, $field:ident: $value:expr)* $(,)?) => {
+        let rc = $rc;
+
+        if rc < 0 {
+            let context = $crate::error::XenErrorContext {
+                operation: $operation,
+                $($field: Some($value),)*
+                ..Default::default()
+            };
+
+            return Err($crate::error::XenError::from(
+                $crate::error::XcError::last_os_error(rc, context),
+            ));
         }
     };
 }
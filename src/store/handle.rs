@@ -1,16 +1,38 @@
-use xen_sys::{xs_close, xs_handle, xs_open};
+use std::{
+    ffi::{CStr, CString, c_void},
+    os::fd::RawFd,
+};
 
-use crate::XenError;
+use xen_sys::{
+    XBT_NULL, xs_handle, xs_permissions, xs_watch_type_XS_WATCH_PATH, xs_watch_type_XS_WATCH_TOKEN,
+};
 
-#[derive(Debug, Clone)]
+use crate::{
+    XenDomainId, XenError,
+    backend::XenStoreBackend,
+    error::{XcError, XenErrorContext},
+    ffi::xenstore::{
+        self, xs_check_watch, xs_close, xs_directory, xs_fileno, xs_get_domain_path,
+        xs_get_permissions, xs_is_domain_introduced, xs_mkdir, xs_open, xs_read, xs_rm,
+        xs_set_permissions, xs_unwatch, xs_watch, xs_write,
+    },
+};
+
+#[derive(Debug)]
 pub struct XenStoreHandle(pub(crate) *mut xs_handle);
 
+// SAFETY: libxenstore serializes requests on a handle internally.
+unsafe impl Send for XenStoreHandle {}
+unsafe impl Sync for XenStoreHandle {}
+
 impl XenStoreHandle {
     pub fn new() -> Result<Self, XenError> {
+        xenstore::load()?;
+
         let handle = unsafe { xs_open(0) };
 
         if handle.is_null() {
-            return Err(XenError::Other("Failed to open xen store"));
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_open")).into());
         }
 
         Ok(Self(handle))
@@ -24,3 +46,223 @@ impl Drop for XenStoreHandle {
         }
     }
 }
+
+/// Converts a path or token, which cannot contain NUL bytes.
+fn c_string(value: &str) -> Result<CString, XenError> {
+    CString::new(value).map_err(|_| std::io::Error::from_raw_os_error(libc::EINVAL).into())
+}
+
+impl XenStoreBackend for XenStoreHandle {
+    fn directory(&self, path: &str) -> Result<Vec<String>, XenError> {
+        let path = c_string(path)?;
+        let mut num = 0;
+        let result = unsafe { xs_directory(self.0, XBT_NULL, path.as_ptr(), &mut num) };
+
+        if result.is_null() {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_directory")).into());
+        }
+
+        let mut entries = Vec::with_capacity(num as usize);
+        for i in 0..num {
+            let entry = unsafe { CStr::from_ptr(*result.offset(i as isize)) };
+            entries.push(entry.to_string_lossy().into());
+        }
+
+        unsafe {
+            libc::free(result as *mut c_void);
+        }
+
+        Ok(entries)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, XenError> {
+        let mut len = 0;
+        let path = c_string(path)?;
+        let result = unsafe { xs_read(self.0, XBT_NULL, path.as_ptr(), &mut len) };
+        if result.is_null() {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_read")).into());
+        }
+
+        let value = unsafe { std::slice::from_raw_parts(result as *const u8, len as usize) };
+        let value = value.to_vec();
+
+        unsafe {
+            libc::free(result);
+        }
+
+        Ok(value)
+    }
+
+    fn write(&self, path: &str, value: &[u8]) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        let rc = unsafe {
+            xs_write(
+                self.0,
+                XBT_NULL,
+                path.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len() as u32,
+            )
+        };
+
+        if !rc {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_write")).into());
+        }
+
+        Ok(())
+    }
+
+    fn mkdir(&self, path: &str) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        if !unsafe { xs_mkdir(self.0, XBT_NULL, path.as_ptr()) } {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_mkdir")).into());
+        }
+
+        Ok(())
+    }
+
+    fn rm(&self, path: &str) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        if !unsafe { xs_rm(self.0, XBT_NULL, path.as_ptr()) } {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_rm")).into());
+        }
+
+        Ok(())
+    }
+
+    fn get_permissions(&self, path: &str) -> Result<Vec<xs_permissions>, XenError> {
+        let path = c_string(path)?;
+        let mut num = 0;
+        let result = unsafe { xs_get_permissions(self.0, XBT_NULL, path.as_ptr(), &mut num) };
+
+        if result.is_null() {
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xs_get_permissions")).into(),
+            );
+        }
+
+        let permissions = unsafe { std::slice::from_raw_parts(result, num as usize) }.to_vec();
+
+        unsafe {
+            libc::free(result as *mut c_void);
+        }
+
+        Ok(permissions)
+    }
+
+    fn set_permissions(&self, path: &str, permissions: &[xs_permissions]) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        let mut permissions = permissions.to_vec();
+        let rc = unsafe {
+            xs_set_permissions(
+                self.0,
+                XBT_NULL,
+                path.as_ptr(),
+                permissions.as_mut_ptr(),
+                permissions.len() as u32,
+            )
+        };
+
+        if !rc {
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xs_set_permissions")).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn get_domain_path(&self, domain_id: XenDomainId) -> Result<String, XenError> {
+        let result = unsafe { xs_get_domain_path(self.0, domain_id.0) };
+        if result.is_null() {
+            return Err(
+                XcError::last_os_error(-1, XenErrorContext::new("xs_get_domain_path")).into(),
+            );
+        }
+
+        let path = unsafe { CStr::from_ptr(result) };
+        let path = path.to_string_lossy().into_owned();
+
+        unsafe {
+            libc::free(result as *mut c_void);
+        }
+
+        Ok(path)
+    }
+
+    fn is_domain_introduced(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        // libxenstore reports failures as `false`.
+        Ok(unsafe { xs_is_domain_introduced(self.0, domain_id.0) })
+    }
+
+    fn watch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        let token = c_string(token)?;
+        if !unsafe { xs_watch(self.0, path.as_ptr(), token.as_ptr()) } {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_watch")).into());
+        }
+
+        Ok(())
+    }
+
+    fn unwatch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        let path = c_string(path)?;
+        let token = c_string(token)?;
+        if !unsafe { xs_unwatch(self.0, path.as_ptr(), token.as_ptr()) } {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_unwatch")).into());
+        }
+
+        Ok(())
+    }
+
+    fn watch_fd(&self) -> Result<RawFd, XenError> {
+        let fd = unsafe { xs_fileno(self.0) };
+        if fd < 0 {
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_fileno")).into());
+        }
+
+        Ok(fd)
+    }
+
+    fn check_watch(&self) -> Result<Option<(String, String)>, XenError> {
+        let result = unsafe { xs_check_watch(self.0) };
+        if result.is_null() {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EAGAIN) {
+                return Ok(None);
+            }
+
+            return Err(XcError::last_os_error(-1, XenErrorContext::new("xs_check_watch")).into());
+        }
+
+        let entry = |index: u32| {
+            let entry = unsafe { CStr::from_ptr(*result.add(index as usize)) };
+            entry.to_string_lossy().into_owned()
+        };
+
+        let path = entry(xs_watch_type_XS_WATCH_PATH);
+        let token = entry(xs_watch_type_XS_WATCH_TOKEN);
+
+        unsafe {
+            libc::free(result as *mut c_void);
+        }
+
+        Ok(Some((path, token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_in_path() {
+        assert_eq!(
+            c_string("/local/domain").unwrap().as_bytes(),
+            b"/local/domain"
+        );
+
+        let err = c_string("/local\0/domain").unwrap_err();
+        assert_eq!(err.errno(), Some(libc::EINVAL));
+    }
+}
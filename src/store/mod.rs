@@ -1,24 +1,36 @@
 mod handle;
-use std::{
-    ffi::{CStr, CString, c_char, c_void},
-    rc::Rc,
+mod permission;
+use std::{os::fd::BorrowedFd, sync::Arc};
+
+pub use self::{
+    handle::XenStoreHandle,
+    permission::{XsAccess, XsPermission},
 };
+use crate::{XenDomainId, XenError, backend::XenStoreBackend};
 
-use xen_sys::{XBT_NULL, xs_directory, xs_read};
+/// A change reported by a watch registered with [`XenStore::watch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XenStoreWatchEvent {
+    /// Path that changed, the watched path itself or one of its children.
+    pub path: String,
 
-pub use self::handle::XenStoreHandle;
-use crate::{XenDomainId, XenError};
+    /// Token the watch was registered with.
+    pub token: String,
+}
 
 #[derive(Debug, Clone)]
 pub struct XenStore {
-    pub(crate) handle: Rc<XenStoreHandle>,
+    pub(crate) backend: Arc<dyn XenStoreBackend>,
 }
 
 impl XenStore {
     pub fn new() -> Result<Self, XenError> {
-        Ok(Self {
-            handle: Rc::new(XenStoreHandle::new()?),
-        })
+        Ok(Self::with_backend(Arc::new(XenStoreHandle::new()?)))
+    }
+
+    /// Creates a store that routes all operations through `backend`.
+    pub fn with_backend(backend: Arc<dyn XenStoreBackend>) -> Self {
+        Self { backend }
     }
 
     pub fn domain_id_from_name(&self, name: &str) -> Result<Option<XenDomainId>, XenError> {
@@ -36,42 +48,114 @@ impl XenStore {
     }
 
     pub fn directory(&self, path: &str) -> Result<Vec<String>, XenError> {
-        let path = CString::new(path).unwrap();
-        let mut num = 0;
-        let result = unsafe { xs_directory(self.handle.0, XBT_NULL, path.as_ptr(), &mut num) };
+        self.backend.directory(path)
+    }
 
-        if result.is_null() {
-            return Err(XenError::Other("Failed to read xen store directory"));
-        }
+    /// Reads the value of `path` as a string, replacing invalid UTF-8.
+    pub fn read(&self, path: &str) -> Result<String, XenError> {
+        let value = self.backend.read(path)?;
+        Ok(String::from_utf8(value)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()))
+    }
 
-        let mut entries = Vec::with_capacity(num as usize);
-        for i in 0..num {
-            let entry = unsafe { CStr::from_ptr(*result.offset(i as isize)) };
-            entries.push(entry.to_string_lossy().into());
-        }
+    /// Reads the value of `path`, which may contain any bytes.
+    pub fn read_bytes(&self, path: &str) -> Result<Vec<u8>, XenError> {
+        self.backend.read(path)
+    }
 
-        unsafe {
-            libc::free(result as *mut c_void);
-        }
+    /// Writes `value` to `path`, creating the node and its missing
+    /// parents.
+    pub fn write(&self, path: &str, value: &[u8]) -> Result<(), XenError> {
+        self.backend.write(path, value)
+    }
 
-        Ok(entries)
+    /// Creates the node `path` with an empty value, if it does not exist.
+    pub fn mkdir(&self, path: &str) -> Result<(), XenError> {
+        self.backend.mkdir(path)
     }
 
-    pub fn read(&self, path: &str) -> Result<String, XenError> {
-        let mut len = 0;
-        let path = CString::new(path).unwrap();
-        let result = unsafe { xs_read(self.handle.0, XBT_NULL, path.as_ptr(), &mut len) };
-        if result.is_null() {
-            return Err(XenError::Other("Failed to read xen store"));
-        }
+    /// Removes the node `path` together with its children.
+    pub fn rm(&self, path: &str) -> Result<(), XenError> {
+        self.backend.rm(path)
+    }
 
-        let value = unsafe { CStr::from_ptr(result as *const c_char) };
-        let value = value.to_string_lossy().into_owned();
+    /// Returns the permissions of `path`, see [`XsPermission`].
+    pub fn get_permissions(&self, path: &str) -> Result<Vec<XsPermission>, XenError> {
+        Ok(self
+            .backend
+            .get_permissions(path)?
+            .into_iter()
+            .map(XsPermission::from)
+            .collect())
+    }
+
+    /// Replaces the permissions of `path`, see [`XsPermission`].
+    ///
+    /// The first entry names the owner of the node.
+    pub fn set_permissions(
+        &self,
+        path: &str,
+        permissions: &[XsPermission],
+    ) -> Result<(), XenError> {
+        let permissions = permissions
+            .iter()
+            .map(|&permission| permission.into())
+            .collect::<Vec<_>>();
+
+        self.backend.set_permissions(path, &permissions)
+    }
+
+    /// Returns the home path of `domain_id`, usually
+    /// `/local/domain/<domain_id>`.
+    pub fn get_domain_path(&self, domain_id: XenDomainId) -> Result<String, XenError> {
+        self.backend.get_domain_path(domain_id)
+    }
+
+    /// Returns `true` if `domain_id` was introduced to the store, i.e. the
+    /// domain can talk to the store.
+    pub fn is_domain_introduced(&self, domain_id: XenDomainId) -> Result<bool, XenError> {
+        self.backend.is_domain_introduced(domain_id)
+    }
 
-        unsafe {
-            libc::free(result);
+    /// Returns `true` if `child` is `parent` or one of its descendants
+    /// (`xs_path_is_subpath`).
+    pub fn path_is_subpath(parent: &str, child: &str) -> bool {
+        match child.strip_prefix(parent) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
         }
+    }
+
+    /// Watches `path` and its children.
+    ///
+    /// Changes are reported with `token` by
+    /// [`check_watch`](Self::check_watch). The store reports one event
+    /// for the path right after the watch is registered. Besides regular
+    /// paths, the special paths `@introduceDomain` and `@releaseDomain`
+    /// report domains being introduced to and released from the store.
+    ///
+    /// Watch events are shared by all clones of the store, use a separate
+    /// store for each consumer of events.
+    pub fn watch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        self.backend.watch(path, token)
+    }
+
+    pub fn unwatch(&self, path: &str, token: &str) -> Result<(), XenError> {
+        self.backend.unwatch(path, token)
+    }
+
+    /// Returns a file descriptor that is readable while watch events are
+    /// pending, e.g. to wait for them with `poll`.
+    pub fn watch_fd(&self) -> Result<BorrowedFd<'_>, XenError> {
+        let fd = self.backend.watch_fd()?;
+        Ok(unsafe { BorrowedFd::borrow_raw(fd) })
+    }
 
-        Ok(value)
+    /// Returns the next pending watch event without blocking.
+    pub fn check_watch(&self) -> Result<Option<XenStoreWatchEvent>, XenError> {
+        Ok(self
+            .backend
+            .check_watch()?
+            .map(|(path, token)| XenStoreWatchEvent { path, token }))
     }
 }
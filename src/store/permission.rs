@@ -0,0 +1,63 @@
+use xen_sys::{XS_PERM_NONE, XS_PERM_READ, XS_PERM_WRITE, xs_permissions};
+
+use crate::XenDomainId;
+
+/// Access a domain has to a store node (`XS_PERM_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XsAccess {
+    None,
+    Read,
+    Write,
+
+    /// Read and write access.
+    Both,
+}
+
+/// Access of a domain to a store node.
+///
+/// The permissions of a node are a list. The first entry names the owner
+/// of the node, which always has full access, and the access of all
+/// domains not listed. The other entries grant access to single domains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XsPermission {
+    pub domain_id: XenDomainId,
+    pub access: XsAccess,
+}
+
+impl XsPermission {
+    pub fn new(domain_id: XenDomainId, access: XsAccess) -> Self {
+        Self { domain_id, access }
+    }
+}
+
+impl From<xs_permissions> for XsPermission {
+    fn from(value: xs_permissions) -> Self {
+        let access = match value.perms & (XS_PERM_READ | XS_PERM_WRITE) {
+            XS_PERM_READ => XsAccess::Read,
+            XS_PERM_WRITE => XsAccess::Write,
+            XS_PERM_NONE => XsAccess::None,
+            _ => XsAccess::Both,
+        };
+
+        Self {
+            domain_id: XenDomainId(value.id),
+            access,
+        }
+    }
+}
+
+impl From<XsPermission> for xs_permissions {
+    fn from(value: XsPermission) -> Self {
+        let perms = match value.access {
+            XsAccess::None => XS_PERM_NONE,
+            XsAccess::Read => XS_PERM_READ,
+            XsAccess::Write => XS_PERM_WRITE,
+            XsAccess::Both => XS_PERM_READ | XS_PERM_WRITE,
+        };
+
+        Self {
+            id: value.domain_id.0,
+            perms,
+        }
+    }
+}